@@ -0,0 +1,365 @@
+//! Pitting several policies of one species against each other
+//!
+//! Load a challenger next to a species' primary policy with
+//! [`crate::PolicyRegistry::assign_variant`], then give the species an
+//! [`AssignmentRule`] in [`PolicyAssignment`]. Every organism of that species
+//! gets a [`PolicyVariant`] once, when first seen alive, and keeps it for
+//! life; [`VariantPopulations`] counts how each variant fares.
+
+use std::fmt;
+use std::fmt::Write as _;
+
+use bevy_ecs::prelude::*;
+use bevy_utils::HashMap;
+use ecosystem_components::prelude::*;
+use serde::Serialize;
+
+/// Which of its species' loaded policies drives an organism
+///
+/// Organisms without one, or whose variant is not loaded, run the primary
+/// policy.
+#[derive(
+    Component, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default, Serialize,
+)]
+pub struct PolicyVariant(pub u8);
+
+impl PolicyVariant {
+    /// The policy assigned with [`crate::PolicyRegistry::assign`]
+    pub const PRIMARY: Self = Self(0);
+    /// Conventional slot for a single competitor
+    pub const CHALLENGER: Self = Self(1);
+}
+
+impl fmt::Display for PolicyVariant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+/// How organisms of a species get their [`PolicyVariant`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum AssignmentRule {
+    /// Split by these weights: each organism gets the variant furthest below
+    /// its share of the species' assignments so far (ties go to the earlier
+    /// entry), so a 1:1 ratio alternates exactly
+    Ratio(Vec<(PolicyVariant, f32)>),
+    /// The first region containing the organism's position, else `fallback`
+    Region {
+        regions: Vec<(Rect, PolicyVariant)>,
+        fallback: PolicyVariant,
+    },
+    /// The parent's variant for [`Offspring`] (e.g. spawned with
+    /// [`OrganismFactory::spawn_offspring`]) whose parent still has one, else
+    /// the inner rule
+    Inherit(Box<AssignmentRule>),
+}
+
+impl AssignmentRule {
+    /// Equal shares of the given variants
+    pub fn even(variants: impl IntoIterator<Item = PolicyVariant>) -> Self {
+        Self::Ratio(variants.into_iter().map(|variant| (variant, 1.0)).collect())
+    }
+
+    /// Let offspring inherit their parent's variant, using this rule otherwise
+    pub fn inherited(self) -> Self {
+        Self::Inherit(Box::new(self))
+    }
+
+    fn choose(
+        &self,
+        position: Vec2,
+        parent: Option<PolicyVariant>,
+        counts: &HashMap<PolicyVariant, u64>,
+    ) -> PolicyVariant {
+        match self {
+            Self::Ratio(weights) => {
+                let total_weight: f32 = weights.iter().map(|(_, weight)| weight.max(0.0)).sum();
+                let assigned = counts.values().sum::<u64>() + 1;
+                let deficit = |(variant, weight): &(PolicyVariant, f32)| {
+                    let share = weight.max(0.0) / total_weight.max(f32::EPSILON);
+                    share * assigned as f32 - counts.get(variant).copied().unwrap_or(0) as f32
+                };
+                weights
+                    .iter()
+                    .reduce(|best, candidate| {
+                        if deficit(candidate) > deficit(best) {
+                            candidate
+                        } else {
+                            best
+                        }
+                    })
+                    .map_or(PolicyVariant::PRIMARY, |(variant, _)| *variant)
+            }
+            Self::Region { regions, fallback } => regions
+                .iter()
+                .find(|(region, _)| region.contains(position))
+                .map_or(*fallback, |(_, variant)| *variant),
+            Self::Inherit(otherwise) => {
+                parent.unwrap_or_else(|| otherwise.choose(position, None, counts))
+            }
+        }
+    }
+}
+
+/// Assignment rules per species
+///
+/// Species without a rule are left alone; their organisms run the primary
+/// policy unless given a [`PolicyVariant`] by hand.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct PolicyAssignment {
+    rules: Vec<(OrganismType, AssignmentRule)>,
+    /// Assignments made so far, per species and variant
+    counts: HashMap<OrganismType, HashMap<PolicyVariant, u64>>,
+}
+
+impl PolicyAssignment {
+    pub fn with_rule(mut self, species: OrganismType, rule: AssignmentRule) -> Self {
+        self.rules.retain(|(kind, _)| *kind != species);
+        self.rules.push((species, rule));
+        self
+    }
+
+    pub fn rule(&self, species: OrganismType) -> Option<&AssignmentRule> {
+        self.rules
+            .iter()
+            .find(|(kind, _)| *kind == species)
+            .map(|(_, rule)| rule)
+    }
+
+    /// Pick the variant of a new organism, or `None` without a rule
+    pub fn assign(
+        &mut self,
+        species: OrganismType,
+        position: Vec2,
+        parent: Option<PolicyVariant>,
+    ) -> Option<PolicyVariant> {
+        let (_, rule) = self.rules.iter().find(|(kind, _)| *kind == species)?;
+        let counts = self.counts.entry(species).or_default();
+        let variant = rule.choose(position, parent, counts);
+        *counts.entry(variant).or_default() += 1;
+        Some(variant)
+    }
+}
+
+type UnassignedQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static OrganismType,
+        &'static Position,
+        Option<&'static Offspring>,
+    ),
+    (With<Alive>, Without<PolicyVariant>),
+>;
+
+/// Give every living organism of a species with a rule its [`PolicyVariant`]
+///
+/// Runs at the start of [`crate::AiSet`] when a [`PolicyAssignment`] exists,
+/// followed by a command flush so the variants drive this tick's decisions.
+pub fn assign_policy_variants(
+    mut commands: Commands,
+    mut assignment: ResMut<PolicyAssignment>,
+    unassigned: UnassignedQuery,
+    variants: Query<&PolicyVariant>,
+) {
+    let mut newcomers: Vec<_> = unassigned.iter().collect();
+    newcomers.sort_by_key(|(entity, ..)| *entity);
+    for (entity, species, position, offspring) in newcomers {
+        let parent = offspring.and_then(|offspring| variants.get(offspring.parent).ok().copied());
+        if let Some(variant) = assignment.assign(*species, position.to_vec2(), parent) {
+            commands.entity(entity).insert(variant);
+        }
+    }
+}
+
+/// How the organisms running one policy variant are doing
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct VariantStats {
+    /// Organisms ever seen with this variant
+    pub assigned: u64,
+    pub alive: usize,
+    /// Largest `alive` so far
+    pub peak: usize,
+    pub deaths: u64,
+    /// Summed lifespan, in ticks, of the organisms that died
+    pub lifespan_ticks: u64,
+    /// Mean energy ratio of the living
+    pub mean_energy: f32,
+}
+
+impl VariantStats {
+    /// Mean lifespan of the dead, in ticks
+    pub fn mean_lifespan(&self) -> f32 {
+        if self.deaths == 0 {
+            0.0
+        } else {
+            self.lifespan_ticks as f32 / self.deaths as f32
+        }
+    }
+}
+
+/// Population statistics per species and [`PolicyVariant`]
+///
+/// Refreshed every tick; an organism counts from the first tick it is seen
+/// with a variant until the tick after it died.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct VariantPopulations {
+    tick: u64,
+    stats: Vec<((OrganismType, PolicyVariant), VariantStats)>,
+    /// Species, variant and first tick of every living tracked organism
+    tracked: HashMap<Entity, (OrganismType, PolicyVariant, u64)>,
+}
+
+impl VariantPopulations {
+    /// Ticks observed so far
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    pub fn get(&self, species: OrganismType, variant: PolicyVariant) -> Option<&VariantStats> {
+        self.stats
+            .iter()
+            .find(|(key, _)| *key == (species, variant))
+            .map(|(_, stats)| stats)
+    }
+
+    /// Every tracked species and variant, Blue before Red, variants ascending
+    pub fn iter(&self) -> impl Iterator<Item = (OrganismType, PolicyVariant, &VariantStats)> {
+        self.stats
+            .iter()
+            .map(|((species, variant), stats)| (*species, *variant, stats))
+    }
+
+    /// One-line report, e.g. for periodic logging
+    pub fn summary(&self) -> String {
+        let mut line = String::from("variants");
+        for (species, variant, stats) in self.iter() {
+            let _ = write!(
+                line,
+                " | {}{} {} alive, {} dead, lifespan {:.0}",
+                species.short_name(),
+                variant,
+                stats.alive,
+                stats.deaths,
+                stats.mean_lifespan()
+            );
+        }
+        line
+    }
+
+    fn stats_mut(&mut self, species: OrganismType, variant: PolicyVariant) -> &mut VariantStats {
+        let index = match self
+            .stats
+            .iter()
+            .position(|(key, _)| *key == (species, variant))
+        {
+            Some(index) => index,
+            None => {
+                self.stats
+                    .push(((species, variant), VariantStats::default()));
+                self.stats
+                    .sort_by_key(|((species, variant), _)| (*species as usize, *variant));
+                self.stats
+                    .iter()
+                    .position(|(key, _)| *key == (species, variant))
+                    .expect("just inserted")
+            }
+        };
+        &mut self.stats[index].1
+    }
+}
+
+/// Refresh [`VariantPopulations`]; runs after [`assign_policy_variants`]
+pub fn track_variant_populations(
+    mut populations: ResMut<VariantPopulations>,
+    organisms: Query<(Entity, &OrganismType, &PolicyVariant, &Energy), With<Alive>>,
+) {
+    let populations = populations.as_mut();
+    populations.tick += 1;
+    let tick = populations.tick;
+
+    let mut dead = Vec::new();
+    populations
+        .tracked
+        .retain(|entity, (species, variant, born)| {
+            let alive = organisms.contains(*entity);
+            if !alive {
+                dead.push((*species, *variant, tick - *born));
+            }
+            alive
+        });
+    for (species, variant, lifespan) in dead {
+        let stats = populations.stats_mut(species, variant);
+        stats.deaths += 1;
+        stats.lifespan_ticks += lifespan;
+    }
+
+    let mut energy: HashMap<(OrganismType, PolicyVariant), f32> = HashMap::default();
+    for (_, stats) in &mut populations.stats {
+        stats.alive = 0;
+    }
+    for (entity, species, variant, organism_energy) in &organisms {
+        let newcomer = !populations.tracked.contains_key(&entity);
+        if newcomer {
+            populations
+                .tracked
+                .insert(entity, (*species, *variant, tick));
+        }
+        let stats = populations.stats_mut(*species, *variant);
+        stats.assigned += newcomer as u64;
+        stats.alive += 1;
+        *energy.entry((*species, *variant)).or_default() += organism_energy.ratio();
+    }
+    for ((species, variant), stats) in &mut populations.stats {
+        stats.peak = stats.peak.max(stats.alive);
+        stats.mean_energy = match stats.alive {
+            0 => 0.0,
+            alive => energy.get(&(*species, *variant)).copied().unwrap_or(0.0) / alive as f32,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rules_pick_variants() {
+        let a = PolicyVariant::PRIMARY;
+        let b = PolicyVariant::CHALLENGER;
+        let mut assignment = PolicyAssignment::default()
+            .with_rule(
+                OrganismType::Blue,
+                AssignmentRule::Ratio(vec![(a, 2.0), (b, 1.0)]).inherited(),
+            )
+            .with_rule(
+                OrganismType::Red,
+                AssignmentRule::Region {
+                    regions: vec![(Rect::new(0.0, -10.0, 10.0, 10.0), b)],
+                    fallback: a,
+                },
+            );
+
+        let blues: Vec<_> = (0..6)
+            .map(|_| assignment.assign(OrganismType::Blue, Vec2::ZERO, None))
+            .collect();
+        assert_eq!(blues, [a, b, a, a, b, a].map(Some));
+        assert_eq!(
+            assignment.assign(OrganismType::Blue, Vec2::ZERO, Some(b)),
+            Some(b)
+        );
+        assert_eq!(
+            assignment.assign(OrganismType::Red, Vec2::new(5.0, 0.0), Some(a)),
+            Some(b)
+        );
+        assert_eq!(
+            assignment.assign(OrganismType::Red, Vec2::new(-5.0, 0.0), None),
+            Some(a)
+        );
+        assert_eq!(
+            assignment.assign(OrganismType::Plant, Vec2::ZERO, None),
+            None
+        );
+    }
+}
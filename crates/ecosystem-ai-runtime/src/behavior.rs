@@ -0,0 +1,414 @@
+//! Data-driven behavior trees for authored behaviors
+//!
+//! Trees are re-evaluated from the root every decision, so nodes only report
+//! success or failure; there is no running state to carry between ticks.
+//! - `sequence` succeeds when all children succeed, stopping at the first
+//!   failure
+//! - `selector` succeeds with the first child that succeeds
+//! - `not` inverts its child
+//! - conditions read `ObservationData`
+//! - actions produce the `ActionCommand`; the last action on the successful
+//!   path wins, and actions inside a failed sequence are discarded
+//!
+//! A library maps species to trees and is loaded from JSON, see
+//! `behaviors/default.json`.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use bevy_ecs::prelude::*;
+use ecosystem_ai_shared::{PerceivedEntity, PerceptionSnapshot};
+use ecosystem_components::prelude::*;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::assignment::PolicyVariant;
+use crate::heuristic::{nearest, wander};
+use crate::inference::RuntimeRng;
+use crate::registry::PolicyRegistry;
+use crate::throttle::DecisionTicks;
+
+#[derive(Debug, Error)]
+pub enum BehaviorFileError {
+    #[error("failed to read behavior file {path}: {source}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("invalid behavior file {path}: {source}")]
+    Parse {
+        path: PathBuf,
+        #[source]
+        source: serde_json::Error,
+    },
+}
+
+/// Leaf checks against the organism's own observation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BehaviorCondition {
+    /// An organism of the given species is visible, optionally within a distance
+    Sees {
+        organism: OrganismType,
+        #[serde(default)]
+        within: Option<f32>,
+    },
+    EnergyBelow(f32),
+    EnergyAbove(f32),
+    HealthBelow(f32),
+    /// Succeeds with the given probability
+    Chance(f32),
+}
+
+impl BehaviorCondition {
+    fn check<R: Rng + ?Sized>(&self, observation: &ObservationData, rng: &mut R) -> bool {
+        let state = &observation.self_state;
+        match self {
+            BehaviorCondition::Sees { organism, within } => nearest(observation, *organism)
+                .is_some_and(|seen| within.is_none_or(|limit| seen.distance() <= limit)),
+            BehaviorCondition::EnergyBelow(ratio) => state.energy_ratio < *ratio,
+            BehaviorCondition::EnergyAbove(ratio) => state.energy_ratio > *ratio,
+            BehaviorCondition::HealthBelow(ratio) => state.health_ratio < *ratio,
+            BehaviorCondition::Chance(probability) => {
+                rng.gen_bool(probability.clamp(0.0, 1.0) as f64)
+            }
+        }
+    }
+}
+
+/// Leaf actions; target-seeking actions fail when the target is not visible
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BehaviorAction {
+    Rest,
+    /// Keep the previous heading, turning randomly with `turn_chance` per tick
+    Wander {
+        #[serde(default = "default_turn_chance")]
+        turn_chance: f32,
+    },
+    /// Move towards the nearest visible organism of a species
+    Approach {
+        target: OrganismType,
+    },
+    /// Move away from the nearest visible organism of a species
+    Flee {
+        from: OrganismType,
+    },
+    /// Move in a fixed direction
+    Move {
+        direction: Vec2,
+    },
+}
+
+fn default_turn_chance() -> f32 {
+    0.1
+}
+
+impl BehaviorAction {
+    fn perform<R: Rng + ?Sized>(
+        &self,
+        observation: &ObservationData,
+        previous: &ActionCommand,
+        rng: &mut R,
+    ) -> Option<ActionCommand> {
+        match self {
+            BehaviorAction::Rest => Some(ActionCommand::rest()),
+            BehaviorAction::Wander { turn_chance } => Some(wander(previous, *turn_chance, rng)),
+            BehaviorAction::Approach { target } => {
+                nearest(observation, *target).map(|seen| ActionCommand::move_to(seen.direction()))
+            }
+            BehaviorAction::Flee { from } => {
+                nearest(observation, *from).map(|seen| ActionCommand::move_to(-seen.direction()))
+            }
+            BehaviorAction::Move { direction } => {
+                Some(ActionCommand::move_to(direction.normalize_or_zero()))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BehaviorNode {
+    Sequence(Vec<BehaviorNode>),
+    Selector(Vec<BehaviorNode>),
+    Not(Box<BehaviorNode>),
+    Condition(BehaviorCondition),
+    Action(BehaviorAction),
+}
+
+struct TickContext<'a, R: ?Sized> {
+    observation: &'a ObservationData,
+    previous: &'a ActionCommand,
+    rng: &'a mut R,
+    command: Option<ActionCommand>,
+}
+
+impl BehaviorNode {
+    fn tick<R: Rng + ?Sized>(&self, context: &mut TickContext<'_, R>) -> bool {
+        match self {
+            BehaviorNode::Sequence(children) => {
+                let before = context.command.clone();
+                let success = children.iter().all(|child| child.tick(context));
+                if !success {
+                    context.command = before;
+                }
+                success
+            }
+            BehaviorNode::Selector(children) => children.iter().any(|child| child.tick(context)),
+            BehaviorNode::Not(child) => {
+                let before = context.command.clone();
+                let success = child.tick(context);
+                context.command = before;
+                !success
+            }
+            BehaviorNode::Condition(condition) => {
+                condition.check(context.observation, &mut *context.rng)
+            }
+            BehaviorNode::Action(action) => {
+                match action.perform(context.observation, context.previous, &mut *context.rng) {
+                    Some(command) => {
+                        context.command = Some(command);
+                        true
+                    }
+                    None => false,
+                }
+            }
+        }
+    }
+}
+
+/// A tree evaluated once per decision
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct BehaviorTree {
+    pub root: BehaviorNode,
+}
+
+impl BehaviorTree {
+    pub fn new(root: BehaviorNode) -> Self {
+        Self { root }
+    }
+
+    /// Evaluate the tree; organisms rest when no action succeeds
+    pub fn decide<R: Rng + ?Sized>(
+        &self,
+        observation: &ObservationData,
+        previous: &ActionCommand,
+        rng: &mut R,
+    ) -> ActionCommand {
+        let mut context = TickContext {
+            observation,
+            previous,
+            rng,
+            command: None,
+        };
+        self.root.tick(&mut context);
+        context.command.unwrap_or_else(ActionCommand::rest)
+    }
+}
+
+/// Behavior trees assigned per species
+///
+/// Species with a tree and no policy are driven by the tree instead of the
+/// heuristic brain.
+#[derive(Resource, Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct BehaviorLibrary {
+    trees: HashMap<OrganismType, BehaviorTree>,
+}
+
+impl BehaviorLibrary {
+    /// Load a JSON file mapping species names to trees
+    pub fn load(path: &Path) -> Result<Self, BehaviorFileError> {
+        let text = fs::read_to_string(path).map_err(|source| BehaviorFileError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        serde_json::from_str(&text).map_err(|source| BehaviorFileError::Parse {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+
+    pub fn assign(&mut self, species: OrganismType, tree: BehaviorTree) {
+        self.trees.insert(species, tree);
+    }
+
+    pub fn remove(&mut self, species: OrganismType) -> Option<BehaviorTree> {
+        self.trees.remove(&species)
+    }
+
+    pub fn get(&self, species: OrganismType) -> Option<&BehaviorTree> {
+        self.trees.get(&species)
+    }
+
+    pub fn contains(&self, species: OrganismType) -> bool {
+        self.trees.contains_key(&species)
+    }
+}
+
+type TreeQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static OrganismType,
+        &'static Position,
+        &'static Vision,
+        &'static Health,
+        &'static Energy,
+        &'static mut BrainSize,
+        &'static mut ActionCommand,
+        Option<&'static PolicyVariant>,
+    ),
+    With<Alive>,
+>;
+
+/// Drive organisms of species that have a tree, unless a loaded policy
+/// variant drives them
+///
+/// Trees cost no upkeep, so the organisms driven lose any [`BrainSize`] left
+/// from a policy that was since removed.
+pub fn run_behavior_trees(
+    library: Res<BehaviorLibrary>,
+    registry: Res<PolicyRegistry>,
+    decisions: Res<DecisionTicks>,
+    mut rng: ResMut<RuntimeRng>,
+    perceivable: Query<(Entity, &OrganismType, &Position), With<Alive>>,
+    mut agents: TreeQuery,
+) {
+    let active: Vec<(OrganismType, &BehaviorTree)> = OrganismType::all()
+        .iter()
+        .filter_map(|species| library.get(*species).map(|tree| (*species, tree)))
+        .collect();
+    if active.is_empty() {
+        return;
+    }
+
+    let snapshot =
+        PerceptionSnapshot::new(perceivable.iter().map(|(entity, organism_type, position)| {
+            PerceivedEntity {
+                entity,
+                organism_type: *organism_type,
+                position: position.to_vec2(),
+            }
+        }));
+    for (
+        entity,
+        organism_type,
+        position,
+        vision,
+        health,
+        energy,
+        mut brain,
+        mut command,
+        assigned,
+    ) in &mut agents
+    {
+        let Some((_, tree)) = active.iter().find(|(species, _)| species == organism_type) else {
+            continue;
+        };
+        if registry
+            .resolve(*organism_type, assigned.copied().unwrap_or_default())
+            .is_some()
+        {
+            continue;
+        }
+        brain.set_if_neq(BrainSize::default());
+        if !decisions.is_due(entity) {
+            continue;
+        }
+        let observation = snapshot.observe(entity, position, vision, health, energy);
+        *command = tree.decide(&observation, &command, &mut rng.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    fn observation(energy: f32, seen: &[(OrganismType, Vec2)]) -> ObservationData {
+        let mut observation = ObservationData::new(SelfState::new(1.0, energy, Vec2::ZERO));
+        for (organism_type, offset) in seen {
+            observation.add_observation(EntityObservation::new(*organism_type, *offset));
+        }
+        observation
+    }
+
+    fn default_library() -> BehaviorLibrary {
+        serde_json::from_str(include_str!("../behaviors/default.json")).unwrap()
+    }
+
+    #[test]
+    fn test_default_library_matches_heuristics() {
+        let library = default_library();
+        let blue = library.get(OrganismType::Blue).unwrap();
+        let red = library.get(OrganismType::Red).unwrap();
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let rest = ActionCommand::rest();
+
+        let threatened = observation(
+            0.1,
+            &[
+                (OrganismType::Red, Vec2::new(10.0, 0.0)),
+                (OrganismType::Plant, Vec2::new(0.0, 5.0)),
+            ],
+        );
+        assert_eq!(
+            blue.decide(&threatened, &rest, &mut rng),
+            ActionCommand::move_to(Vec2::new(-1.0, 0.0))
+        );
+        let hungry = observation(0.3, &[(OrganismType::Plant, Vec2::new(0.0, 5.0))]);
+        assert_eq!(
+            blue.decide(&hungry, &rest, &mut rng),
+            ActionCommand::move_to(Vec2::Y)
+        );
+        let prey = observation(0.9, &[(OrganismType::Blue, Vec2::new(0.0, 8.0))]);
+        assert_eq!(
+            red.decide(&prey, &rest, &mut rng),
+            ActionCommand::move_to(Vec2::Y)
+        );
+        assert_eq!(red.decide(&observation(0.1, &[]), &rest, &mut rng), rest);
+    }
+
+    #[test]
+    fn test_failed_sequence_discards_actions() {
+        let tree = BehaviorTree::new(BehaviorNode::Selector(vec![
+            BehaviorNode::Sequence(vec![
+                BehaviorNode::Action(BehaviorAction::Move { direction: Vec2::X }),
+                BehaviorNode::Condition(BehaviorCondition::EnergyBelow(0.5)),
+            ]),
+            BehaviorNode::Not(Box::new(BehaviorNode::Action(BehaviorAction::Move {
+                direction: Vec2::Y,
+            }))),
+        ]));
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        assert_eq!(
+            tree.decide(&observation(0.9, &[]), &ActionCommand::rest(), &mut rng),
+            ActionCommand::rest()
+        );
+        assert_eq!(
+            tree.decide(&observation(0.1, &[]), &ActionCommand::rest(), &mut rng),
+            ActionCommand::move_to(Vec2::X)
+        );
+    }
+
+    #[test]
+    fn test_round_trip_and_load_errors() {
+        let library = default_library();
+        let json = serde_json::to_string(&library).unwrap();
+        assert_eq!(
+            serde_json::from_str::<BehaviorLibrary>(&json).unwrap(),
+            library
+        );
+
+        let missing = BehaviorLibrary::load(Path::new("does/not/exist.json"));
+        assert!(matches!(missing, Err(BehaviorFileError::Io { .. })));
+    }
+}
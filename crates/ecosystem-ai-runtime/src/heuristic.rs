@@ -0,0 +1,277 @@
+//! Scripted fallback behaviour for species without a trained policy
+//!
+//! Rules, evaluated on `ObservationData` only so they see exactly what a
+//! policy would see:
+//! - Blue flees the nearest visible Red; when low on energy it seeks the
+//!   nearest visible Plant
+//! - Red chases the nearest visible Blue
+//! - Otherwise organisms wander, or rest once energy runs low
+//! - Plants never act
+
+use std::f32::consts::TAU;
+
+use bevy_ecs::prelude::*;
+use ecosystem_ai_shared::PerceptionSnapshot;
+use ecosystem_components::prelude::*;
+use rand::Rng;
+
+use crate::assignment::PolicyVariant;
+use crate::behavior::BehaviorLibrary;
+use crate::inference::RuntimeRng;
+use crate::registry::PolicyRegistry;
+use crate::throttle::DecisionTicks;
+
+/// Thresholds and wander behaviour of the heuristic brain
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct HeuristicConfig {
+    /// Drive species that have no policy loaded
+    pub enabled: bool,
+    /// Energy ratio below which Blue goes looking for plants
+    /// (same semantics as `Energy::is_low`)
+    pub hungry_below: f32,
+    /// Energy ratio below which an idle organism rests instead of wandering
+    pub rest_below: f32,
+    /// Chance per tick that a wandering organism picks a new direction
+    pub wander_turn_chance: f32,
+}
+
+impl Default for HeuristicConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            hungry_below: 0.6,
+            rest_below: 0.25,
+            wander_turn_chance: 0.1,
+        }
+    }
+}
+
+impl HeuristicConfig {
+    /// Decide an action for one organism
+    ///
+    /// `previous` is the organism's last command, used to keep wandering in a
+    /// consistent direction instead of jittering every tick.
+    pub fn decide<R: Rng + ?Sized>(
+        &self,
+        organism_type: OrganismType,
+        observation: &ObservationData,
+        previous: &ActionCommand,
+        rng: &mut R,
+    ) -> ActionCommand {
+        let energy = observation.self_state.energy_ratio;
+        match organism_type {
+            OrganismType::Plant => ActionCommand::rest(),
+            OrganismType::Blue => {
+                if let Some(threat) = nearest(observation, OrganismType::Red) {
+                    return ActionCommand::move_to(-threat.direction());
+                }
+                if energy < self.hungry_below
+                    && let Some(food) = nearest(observation, OrganismType::Plant)
+                {
+                    return ActionCommand::move_to(food.direction());
+                }
+                self.idle(energy, previous, rng)
+            }
+            OrganismType::Red => match nearest(observation, OrganismType::Blue) {
+                Some(prey) => ActionCommand::move_to(prey.direction()),
+                None => self.idle(energy, previous, rng),
+            },
+        }
+    }
+
+    fn idle<R: Rng + ?Sized>(
+        &self,
+        energy: f32,
+        previous: &ActionCommand,
+        rng: &mut R,
+    ) -> ActionCommand {
+        if energy < self.rest_below {
+            return ActionCommand::rest();
+        }
+        wander(previous, self.wander_turn_chance, rng)
+    }
+}
+
+/// Keep moving in the previous direction, turning randomly now and then
+pub(crate) fn wander<R: Rng + ?Sized>(
+    previous: &ActionCommand,
+    turn_chance: f32,
+    rng: &mut R,
+) -> ActionCommand {
+    match previous.action_type {
+        ActionType::Move(direction)
+            if direction != Vec2::ZERO && !rng.gen_bool(turn_chance.clamp(0.0, 1.0) as f64) =>
+        {
+            ActionCommand::move_to(direction.normalize())
+        }
+        _ => {
+            let angle = rng.gen_range(0.0..TAU);
+            ActionCommand::move_to(Vec2::new(angle.cos(), angle.sin()))
+        }
+    }
+}
+
+/// Nearest visible organism of a species
+pub(crate) fn nearest(
+    observation: &ObservationData,
+    organism_type: OrganismType,
+) -> Option<&EntityObservation> {
+    observation
+        .visible_entities
+        .iter()
+        .filter(|seen| seen.organism_type == organism_type)
+        .min_by(|a, b| a.distance().total_cmp(&b.distance()))
+}
+
+type BrainQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static OrganismType,
+        &'static Position,
+        &'static Vision,
+        &'static Health,
+        &'static Energy,
+        &'static mut BrainSize,
+        &'static mut ActionCommand,
+        Option<&'static PolicyVariant>,
+    ),
+    With<Alive>,
+>;
+
+/// Drive every organism that no loaded policy variant drives and whose
+/// species has no behavior tree with the heuristic brain
+///
+/// Scripted rules cost no upkeep, so the organisms driven lose any
+/// [`BrainSize`] left from a policy that was since removed.
+pub fn run_heuristic_brain(
+    config: Res<HeuristicConfig>,
+    registry: Res<PolicyRegistry>,
+    behaviors: Res<BehaviorLibrary>,
+    decisions: Res<DecisionTicks>,
+    mut rng: ResMut<RuntimeRng>,
+    perceivable: Query<(Entity, &OrganismType, &Position), With<Alive>>,
+    mut agents: BrainQuery,
+) {
+    if !config.enabled {
+        return;
+    }
+    let fallback: Vec<OrganismType> = OrganismType::all()
+        .iter()
+        .copied()
+        .filter(|species| *species != OrganismType::Plant && !behaviors.contains(*species))
+        .collect();
+    if fallback.is_empty() {
+        return;
+    }
+
+    let snapshot =
+        PerceptionSnapshot::new(perceivable.iter().map(|(entity, organism_type, position)| {
+            ecosystem_ai_shared::PerceivedEntity {
+                entity,
+                organism_type: *organism_type,
+                position: position.to_vec2(),
+            }
+        }));
+    for (
+        entity,
+        organism_type,
+        position,
+        vision,
+        health,
+        energy,
+        mut brain,
+        mut command,
+        assigned,
+    ) in &mut agents
+    {
+        if !fallback.contains(organism_type)
+            || registry
+                .resolve(*organism_type, assigned.copied().unwrap_or_default())
+                .is_some()
+        {
+            continue;
+        }
+        brain.set_if_neq(BrainSize::default());
+        if !decisions.is_due(entity) {
+            continue;
+        }
+        let observation = snapshot.observe(entity, position, vision, health, energy);
+        *command = config.decide(*organism_type, &observation, &command, &mut rng.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    fn observation(energy: f32, seen: &[(OrganismType, Vec2)]) -> ObservationData {
+        let mut observation = ObservationData::new(SelfState::new(1.0, energy, Vec2::ZERO));
+        for (organism_type, offset) in seen {
+            observation.add_observation(EntityObservation::new(*organism_type, *offset));
+        }
+        observation
+    }
+
+    #[test]
+    fn test_blue_flees_then_feeds() {
+        let config = HeuristicConfig::default();
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let rest = ActionCommand::rest();
+
+        let threatened = observation(
+            0.1,
+            &[
+                (OrganismType::Red, Vec2::new(10.0, 0.0)),
+                (OrganismType::Plant, Vec2::new(0.0, 5.0)),
+            ],
+        );
+        assert_eq!(
+            config.decide(OrganismType::Blue, &threatened, &rest, &mut rng),
+            ActionCommand::move_to(Vec2::new(-1.0, 0.0))
+        );
+
+        let hungry = observation(0.3, &[(OrganismType::Plant, Vec2::new(0.0, 5.0))]);
+        assert_eq!(
+            config.decide(OrganismType::Blue, &hungry, &rest, &mut rng),
+            ActionCommand::move_to(Vec2::Y)
+        );
+
+        // Well fed Blue ignores plants and wanders
+        let fed = observation(0.9, &[(OrganismType::Plant, Vec2::new(0.0, 5.0))]);
+        let wander = config.decide(OrganismType::Blue, &fed, &rest, &mut rng);
+        assert!(matches!(wander.action_type, ActionType::Move(d) if d != Vec2::Y));
+    }
+
+    #[test]
+    fn test_red_chases_or_rests() {
+        let config = HeuristicConfig::default();
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let rest = ActionCommand::rest();
+
+        let prey = observation(
+            0.9,
+            &[
+                (OrganismType::Blue, Vec2::new(0.0, -30.0)),
+                (OrganismType::Blue, Vec2::new(0.0, 8.0)),
+            ],
+        );
+        assert_eq!(
+            config.decide(OrganismType::Red, &prey, &rest, &mut rng),
+            ActionCommand::move_to(Vec2::Y)
+        );
+
+        let tired = observation(0.1, &[]);
+        assert_eq!(
+            config.decide(OrganismType::Red, &tired, &rest, &mut rng),
+            ActionCommand::rest()
+        );
+        assert_eq!(
+            config.decide(OrganismType::Plant, &prey, &rest, &mut rng),
+            ActionCommand::rest()
+        );
+    }
+}
@@ -0,0 +1,229 @@
+//! Swapping in newer model files while the simulation runs
+//!
+//! The watcher polls a directory for `*.policy` files and reloads any file
+//! that appeared or changed since the last scan. The metadata header is
+//! validated against the running encoder and action space before the weights
+//! are read; if anything fails the species keeps its current policy.
+//! Reloading runs before [`AiSet`], so a swap always happens between ticks.
+//!
+//! Every file replaces the primary policy of the species it was trained for;
+//! variants such as league challengers are not hot-reloaded and have to be
+//! assigned through [`PolicyRegistry::load_variant`].
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use bevy_app::prelude::*;
+use bevy_ecs::prelude::*;
+use bevy_utils::HashMap;
+use ecosystem_components::prelude::OrganismType;
+
+use crate::AiSet;
+use crate::inference::RuntimeSettings;
+use crate::registry::PolicyRegistry;
+
+/// A species now runs a newly loaded model
+#[derive(Event, Debug, Clone, PartialEq)]
+pub struct PolicySwapped {
+    pub species: OrganismType,
+    pub path: PathBuf,
+    pub run_id: String,
+    pub iteration: u32,
+}
+
+/// A changed model file was rejected; the previous policy stays in place
+#[derive(Event, Debug, Clone, PartialEq)]
+pub struct PolicyReloadFailed {
+    pub path: PathBuf,
+    pub error: String,
+}
+
+/// Directory being watched and what was seen there
+#[derive(Resource, Debug, Clone)]
+pub struct ModelWatcher {
+    dir: PathBuf,
+    poll_interval: Duration,
+    last_poll: Option<Instant>,
+    seen: HashMap<PathBuf, SystemTime>,
+}
+
+impl ModelWatcher {
+    pub fn new(dir: impl Into<PathBuf>, poll_interval: Duration) -> Self {
+        Self {
+            dir: dir.into(),
+            poll_interval,
+            last_poll: None,
+            seen: HashMap::default(),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Model files that appeared or changed since the previous scan
+    fn changed_files(&mut self) -> Vec<PathBuf> {
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return Vec::new();
+        };
+        let mut changed: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "policy"))
+            .filter_map(|path| {
+                let modified = fs::metadata(&path).and_then(|meta| meta.modified()).ok()?;
+                let previous = self.seen.insert(path.clone(), modified);
+                (previous != Some(modified)).then_some(path)
+            })
+            .collect();
+        changed.sort();
+        changed
+    }
+}
+
+/// Reload changed model files into the [`PolicyRegistry`]
+pub fn reload_changed_models(
+    mut watcher: ResMut<ModelWatcher>,
+    settings: Res<RuntimeSettings>,
+    mut registry: ResMut<PolicyRegistry>,
+    mut swapped: EventWriter<PolicySwapped>,
+    mut failed: EventWriter<PolicyReloadFailed>,
+) {
+    let now = Instant::now();
+    if watcher
+        .last_poll
+        .is_some_and(|last| now.duration_since(last) < watcher.poll_interval)
+    {
+        return;
+    }
+    watcher.last_poll = Some(now);
+
+    for path in watcher.changed_files() {
+        match registry.load(&path, &settings.encoder, &settings.action_space) {
+            Ok(species) => {
+                let (run_id, iteration) = registry
+                    .get(species)
+                    .map(|model| (model.metadata.run_id.clone(), model.metadata.iteration))
+                    .expect("policy was just loaded");
+                swapped.send(PolicySwapped {
+                    species,
+                    path,
+                    run_id,
+                    iteration,
+                });
+            }
+            Err(error) => failed.send(PolicyReloadFailed {
+                path,
+                error: error.to_string(),
+            }),
+        }
+    }
+}
+
+/// Watches a model directory and hot-swaps policies; add after [`crate::AiRuntimePlugin`]
+#[derive(Debug, Clone)]
+pub struct ModelHotReloadPlugin {
+    pub dir: PathBuf,
+    pub poll_interval: Duration,
+}
+
+impl ModelHotReloadPlugin {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            poll_interval: Duration::from_secs(1),
+        }
+    }
+}
+
+impl Plugin for ModelHotReloadPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(ModelWatcher::new(self.dir.clone(), self.poll_interval))
+            .add_event::<PolicySwapped>()
+            .add_event::<PolicyReloadFailed>()
+            .add_systems(Update, reload_changed_models.before(AiSet));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AiRuntimePlugin;
+    use ecosystem_ai_shared::{
+        ActionSpace, InferenceBackend, ModelMetadata, ObservationEncoder, PolicyConfig,
+        RunningNormalizer, save_policy, seeded_init,
+    };
+
+    fn write_model(path: &Path, species: OrganismType, iteration: u32) {
+        let encoder = ObservationEncoder::default();
+        let action_space = ActionSpace::default();
+        let policy = PolicyConfig::new(encoder.input_size(), action_space.size());
+        let network = seeded_init(policy.init::<InferenceBackend>(&Default::default()), 3);
+        let mut metadata = ModelMetadata::new(
+            species,
+            encoder,
+            action_space,
+            policy,
+            RunningNormalizer::new(encoder.input_size(), 5.0),
+        );
+        metadata.iteration = iteration;
+        save_policy(path, &metadata, &network).unwrap();
+    }
+
+    fn bump_mtime(path: &Path, seconds: u64) {
+        let file = fs::File::options().write(true).open(path).unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(seconds))
+            .unwrap();
+    }
+
+    fn drain<E: Event + Clone>(app: &mut App) -> Vec<E> {
+        app.world.resource_mut::<Events<E>>().drain().collect()
+    }
+
+    fn loaded_iteration(app: &App, species: OrganismType) -> Option<u32> {
+        let registry = app.world.resource::<PolicyRegistry>();
+        registry.get(species).map(|model| model.metadata.iteration)
+    }
+
+    #[test]
+    fn test_swaps_newer_models_and_keeps_old_on_failure() {
+        let dir = std::env::temp_dir().join(format!("eco-hot-reload-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let blue = dir.join("blue.policy");
+        write_model(&blue, OrganismType::Blue, 1);
+
+        let mut app = App::new();
+        app.add_plugins(AiRuntimePlugin::default())
+            .add_plugins(ModelHotReloadPlugin {
+                dir: dir.clone(),
+                poll_interval: Duration::ZERO,
+            });
+
+        app.update();
+        let swaps = drain::<PolicySwapped>(&mut app);
+        assert_eq!(swaps.len(), 1);
+        assert_eq!(swaps[0].species, OrganismType::Blue);
+        assert_eq!(loaded_iteration(&app, OrganismType::Blue), Some(1));
+
+        // Unchanged files are not reloaded
+        app.update();
+        assert!(drain::<PolicySwapped>(&mut app).is_empty());
+
+        write_model(&blue, OrganismType::Blue, 2);
+        bump_mtime(&blue, 10);
+        app.update();
+        assert_eq!(drain::<PolicySwapped>(&mut app)[0].iteration, 2);
+        assert_eq!(loaded_iteration(&app, OrganismType::Blue), Some(2));
+
+        fs::write(&blue, b"not a model").unwrap();
+        bump_mtime(&blue, 20);
+        app.update();
+        assert!(drain::<PolicySwapped>(&mut app).is_empty());
+        assert_eq!(drain::<PolicyReloadFailed>(&mut app).len(), 1);
+        assert_eq!(loaded_iteration(&app, OrganismType::Blue), Some(2));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
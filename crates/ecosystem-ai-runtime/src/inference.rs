@@ -0,0 +1,372 @@
+//! Batched policy inference
+
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::time::{Duration, Instant};
+
+use bevy_ecs::prelude::*;
+use ecosystem_ai_shared::{
+    ActionSpace, ObservationEncoder, PerceptionSnapshot, QuantizedPolicy, greedy_index,
+    sample_index,
+};
+use ecosystem_components::prelude::*;
+use rand_chacha::ChaCha8Rng;
+
+use crate::assignment::PolicyVariant;
+use crate::inspector::{DecisionInspector, Inspected, InspectedDecision};
+use crate::lifetime::LifetimeLearners;
+use crate::registry::PolicyRegistry;
+use crate::throttle::DecisionTicks;
+
+/// How action probabilities become a single action
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ActionSelection {
+    /// Draw from the distribution, as during training
+    #[default]
+    Sample,
+    /// Always take the most likely action
+    Greedy,
+}
+
+/// Numeric precision of the forward pass
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Precision {
+    /// The policy as trained
+    #[default]
+    F32,
+    /// Int8 weights (see [`QuantizedPolicy`]); policies with attention or
+    /// recurrence fall back to f32
+    Int8,
+}
+
+/// Encoder, action space, selection rule and precision shared by every policy
+#[derive(Resource, Debug, Clone, PartialEq)]
+pub struct RuntimeSettings {
+    pub encoder: ObservationEncoder,
+    pub action_space: ActionSpace,
+    pub selection: ActionSelection,
+    pub precision: Precision,
+}
+
+/// RNG used for stochastic action selection
+#[derive(Resource, Debug, Clone)]
+pub struct RuntimeRng(pub ChaCha8Rng);
+
+/// Cost of running one species' policy for one tick
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SpeciesTiming {
+    pub agents: usize,
+    /// Building and encoding observations
+    pub encode: Duration,
+    /// The batched forward pass
+    pub forward: Duration,
+    /// Choosing actions and writing `ActionCommand`s
+    pub write_back: Duration,
+}
+
+impl SpeciesTiming {
+    pub fn total(&self) -> Duration {
+        self.encode + self.forward + self.write_back
+    }
+}
+
+/// AI cost per tick, refreshed every time inference runs
+#[derive(Resource, Debug, Clone, Default)]
+pub struct InferenceTimings {
+    /// Snapshotting the world for perception (shared by all species)
+    pub perception: Duration,
+    /// Per-species breakdown for the last tick
+    pub species: Vec<(OrganismType, SpeciesTiming)>,
+    /// Ticks measured so far
+    pub ticks: u64,
+    /// Sum of every measured tick
+    pub cumulative: Duration,
+}
+
+impl InferenceTimings {
+    /// Total AI time spent in the last tick
+    pub fn last_tick(&self) -> Duration {
+        self.perception
+            + self
+                .species
+                .iter()
+                .map(|(_, timing)| timing.total())
+                .sum::<Duration>()
+    }
+
+    /// Average AI time per tick since startup
+    pub fn mean_per_tick(&self) -> Duration {
+        if self.ticks == 0 {
+            Duration::ZERO
+        } else {
+            self.cumulative / self.ticks as u32
+        }
+    }
+
+    pub fn get(&self, species: OrganismType) -> Option<&SpeciesTiming> {
+        self.species
+            .iter()
+            .find(|(kind, _)| *kind == species)
+            .map(|(_, timing)| timing)
+    }
+
+    /// One-line report, e.g. for periodic logging
+    pub fn summary(&self) -> String {
+        let mut line = format!(
+            "ai {:.2}ms (mean {:.2}ms, perception {:.2}ms)",
+            millis(self.last_tick()),
+            millis(self.mean_per_tick()),
+            millis(self.perception)
+        );
+        for (species, timing) in &self.species {
+            let _ = write!(
+                line,
+                " | {} x{} {:.2}ms (fwd {:.2}ms)",
+                species.short_name(),
+                timing.agents,
+                millis(timing.total()),
+                millis(timing.forward)
+            );
+        }
+        line
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+type AgentQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static OrganismType,
+        &'static Position,
+        &'static Vision,
+        &'static Health,
+        &'static Energy,
+        (&'static Movement, &'static Collision),
+        &'static mut BrainSize,
+        &'static mut ActionCommand,
+        Option<&'static mut RecurrentState>,
+        (Option<&'static PolicyVariant>, Has<Newborn>, Has<Inspected>),
+    ),
+    With<Alive>,
+>;
+
+/// Run one batched forward pass per loaded policy and write the chosen
+/// `ActionCommand`s back for organisms due a decision
+///
+/// Organisms run the variant named by their [`PolicyVariant`] if it is
+/// loaded, and their species' primary policy otherwise. Timings are summed
+/// over the variants of a species.
+///
+/// Recurrent policies read and update each organism's [`RecurrentState`];
+/// newborns, organisms without a state yet, and states of the wrong length
+/// (e.g. after swapping in a different architecture) start from zeros.
+///
+/// Every organism driven by a policy carries that policy's [`BrainSize`], so
+/// metabolism can charge for it. Primary-policy decisions of adaptive species
+/// are handed to [`LifetimeLearners`], if present, and decisions of [`Inspected`]
+/// organisms are explained to the [`DecisionInspector`], if present.
+///
+/// Attention policies see every visible entity: each batch is encoded with as
+/// many slots as its most crowded observation holds, rather than truncated to
+/// the encoder's `max_visible`. Adaptive species keep the fixed slot count,
+/// since their learners batch decisions of equal length.
+///
+/// With [`Precision::Int8`], quantized copies of the policies are rebuilt
+/// whenever the registry changes.
+#[allow(clippy::too_many_arguments)]
+pub fn run_policy_inference(
+    settings: Res<RuntimeSettings>,
+    registry: Res<PolicyRegistry>,
+    decisions: Res<DecisionTicks>,
+    mut rng: ResMut<RuntimeRng>,
+    mut timings: ResMut<InferenceTimings>,
+    mut learners: Option<ResMut<LifetimeLearners>>,
+    mut inspector: Option<ResMut<DecisionInspector>>,
+    perceivable: Query<(Entity, &OrganismType, &Position), With<Alive>>,
+    mut agents: AgentQuery,
+    mut quantized: Local<HashMap<(OrganismType, PolicyVariant), QuantizedPolicy>>,
+) {
+    if registry.is_changed() || settings.is_changed() {
+        quantized.clear();
+        if let Some(inspector) = inspector.as_deref_mut() {
+            inspector.forget_networks();
+        }
+        if settings.precision == Precision::Int8 {
+            for species in registry.species() {
+                for variant in registry.variants(species) {
+                    if let Some(policy) = registry
+                        .get_variant(species, variant)
+                        .and_then(|model| QuantizedPolicy::from_network(&model.network))
+                    {
+                        quantized.insert((species, variant), policy);
+                    }
+                }
+            }
+        }
+    }
+
+    let started = Instant::now();
+    let snapshot =
+        PerceptionSnapshot::new(perceivable.iter().map(|(entity, organism_type, position)| {
+            ecosystem_ai_shared::PerceivedEntity {
+                entity,
+                organism_type: *organism_type,
+                position: position.to_vec2(),
+            }
+        }));
+    let perception = started.elapsed();
+
+    let device = Default::default();
+    let mut species_timings = Vec::new();
+
+    for species in OrganismType::all() {
+        let mut timing = SpeciesTiming::default();
+        for variant in registry.variants(*species) {
+            let Some(model) = registry.get_variant(*species, variant) else {
+                continue;
+            };
+
+            let started = Instant::now();
+            let state_size = model.network.state_size();
+            let brain_size = model.network.brain_size();
+            let learning = variant == PolicyVariant::PRIMARY
+                && learners.as_deref_mut().is_some_and(|learners| {
+                    let generation = registry.generation(*species, variant).unwrap_or_default();
+                    learners.track(*species, &model, generation)
+                });
+            let mut wellbeing = Vec::new();
+            let mut entities = Vec::new();
+            let mut observations = Vec::new();
+            let mut states = Vec::new();
+            let mut inspected = Vec::new();
+            for (
+                entity,
+                organism_type,
+                position,
+                vision,
+                health,
+                energy,
+                (movement, collision),
+                _,
+                _,
+                memory,
+                (assigned, newborn, is_inspected),
+            ) in &agents
+            {
+                let assigned = assigned.copied().unwrap_or_default();
+                if organism_type != species
+                    || registry.resolve(*species, assigned) != Some(variant)
+                    || !decisions.is_due(entity)
+                {
+                    continue;
+                }
+                observations.push(
+                    snapshot
+                        .observe(entity, position, vision, health, energy)
+                        .with_traits(BodyTraits::new(vision, collision, movement, energy)),
+                );
+                inspected.push(is_inspected && inspector.is_some());
+                entities.push(entity);
+                if learning {
+                    wellbeing.push((health.ratio(), energy.ratio()));
+                }
+                if state_size > 0 {
+                    match memory.and_then(|memory| memory.get(state_size)) {
+                        Some(state) if !newborn => states.extend_from_slice(state),
+                        _ => states.extend(std::iter::repeat_n(0.0, state_size)),
+                    }
+                }
+            }
+            if entities.is_empty() {
+                continue;
+            }
+            let encoder = if model.network.has_attention() && !learning {
+                let crowd = observations
+                    .iter()
+                    .map(|observation| observation.visible_entities.len())
+                    .max()
+                    .unwrap_or(0);
+                settings.encoder.with_max_visible(crowd)
+            } else {
+                settings.encoder
+            };
+            let input_size = encoder.input_size();
+            let mut inputs = Vec::with_capacity(entities.len() * input_size);
+            for observation in &observations {
+                encoder.encode_into(observation, &mut inputs);
+            }
+            model
+                .metadata
+                .normalizer
+                .normalize_rows(&mut inputs, input_size);
+            timing.encode += started.elapsed();
+
+            let started = Instant::now();
+            let (evaluation, next_states) = match quantized.get(&(*species, variant)) {
+                Some(policy) => (policy.evaluate(&inputs), Vec::new()),
+                None => model
+                    .network
+                    .evaluate_rows(&inputs, input_size, &states, &device),
+            };
+            timing.forward += started.elapsed();
+
+            let started = Instant::now();
+            for (row, (entity, probabilities)) in
+                entities.iter().zip(&evaluation.probabilities).enumerate()
+            {
+                let index = match settings.selection {
+                    ActionSelection::Sample => sample_index(probabilities, &mut rng.0),
+                    ActionSelection::Greedy => greedy_index(probabilities),
+                };
+                if let (true, Some(learners)) = (learning, learners.as_deref_mut()) {
+                    let (health, energy) = wellbeing[row];
+                    let input = &inputs[row * input_size..(row + 1) * input_size];
+                    learners.record(*species, *entity, input, index, health, energy);
+                }
+                if let (true, Some(inspector)) = (inspected[row], inspector.as_deref_mut()) {
+                    let state = states
+                        .get(row * state_size..(row + 1) * state_size)
+                        .unwrap_or_default();
+                    let decision = InspectedDecision {
+                        tick: timings.ticks,
+                        entity: *entity,
+                        observation: observations[row].clone(),
+                        encoder,
+                        input: &inputs[row * input_size..(row + 1) * input_size],
+                        state,
+                        probabilities,
+                        value: evaluation.values[row],
+                        action_index: index,
+                        action: settings.action_space.to_command(index),
+                    };
+                    inspector.record(*species, variant, &model, decision);
+                }
+                if let Ok((.., mut brain, mut command, memory, _)) = agents.get_mut(*entity) {
+                    *command = settings.action_space.to_command(index);
+                    brain.set_if_neq(brain_size);
+                    if let (Some(mut memory), true) = (memory, state_size > 0) {
+                        memory.set(&next_states[row * state_size..(row + 1) * state_size]);
+                    }
+                }
+            }
+            timing.write_back += started.elapsed();
+
+            debug_assert_eq!(inputs.len(), entities.len() * input_size);
+            timing.agents += entities.len();
+        }
+        if timing.agents > 0 {
+            species_timings.push((*species, timing));
+        }
+    }
+
+    timings.perception = perception;
+    timings.species = species_timings;
+    timings.ticks += 1;
+    let last_tick = timings.last_tick();
+    timings.cumulative += last_tick;
+}
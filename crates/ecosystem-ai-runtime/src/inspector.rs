@@ -0,0 +1,345 @@
+//! Explaining individual decisions
+//!
+//! Tag an organism with [`Inspected`] and insert a [`DecisionInspector`] to
+//! keep, for each of its policy decisions, the full action distribution, the
+//! value estimate and how strongly each observation feature pushed towards
+//! the chosen action. Saliency is the gradient of the chosen action's
+//! log-probability with respect to the raw encoded features, taken through
+//! the f32 policy and its input normalizer (also under
+//! [`crate::Precision::Int8`]). Features clipped by the normalizer get zero.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::fs::File;
+use std::io::{self, BufWriter, Write as _};
+use std::path::Path;
+use std::sync::{Mutex, PoisonError};
+
+use bevy_ecs::prelude::*;
+use bevy_utils::HashMap;
+use ecosystem_ai_shared::{InferenceBackend, ObservationEncoder, PolicyModel, PolicyNetwork};
+use ecosystem_components::prelude::*;
+use serde::Serialize;
+
+use crate::assignment::PolicyVariant;
+use crate::lifetime::{AdaptiveBackend, trainable};
+
+/// Marks an organism whose policy decisions the [`DecisionInspector`] keeps
+#[derive(Component, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Inspected;
+
+/// One encoded input feature and its influence on a decision
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FeatureSaliency {
+    /// Feature name from `ObservationEncoder::feature_names`
+    pub name: String,
+    /// Encoded value before normalization
+    pub value: f32,
+    /// d log p(action) / d value
+    pub saliency: f32,
+}
+
+/// Everything the policy computed for one decision
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DecisionExplanation {
+    /// Inference tick of the decision (see [`crate::InferenceTimings::ticks`])
+    pub tick: u64,
+    /// `Entity::to_bits` of the organism
+    pub entity: u64,
+    pub species: OrganismType,
+    /// Policy variant that made the decision
+    pub variant: PolicyVariant,
+    /// Index of the chosen action in the action space
+    pub action_index: usize,
+    pub action: ActionCommand,
+    /// Probability of every action, in action-space order
+    pub probabilities: Vec<f32>,
+    /// Critic's value estimate
+    pub value: f32,
+    pub observation: ObservationData,
+    pub features: Vec<FeatureSaliency>,
+}
+
+impl DecisionExplanation {
+    /// The `count` features with the largest absolute saliency, strongest first
+    pub fn most_salient(&self, count: usize) -> Vec<&FeatureSaliency> {
+        let mut features: Vec<&FeatureSaliency> = self.features.iter().collect();
+        features.sort_by(|a, b| b.saliency.abs().total_cmp(&a.saliency.abs()));
+        features.truncate(count);
+        features
+    }
+}
+
+/// A policy decision of an inspected organism, as seen by inference
+pub(crate) struct InspectedDecision<'a> {
+    pub tick: u64,
+    pub entity: Entity,
+    pub observation: ObservationData,
+    /// Encoder that turned `observation` into `input`
+    pub encoder: ObservationEncoder,
+    /// Normalized network input
+    pub input: &'a [f32],
+    /// Recurrent state the decision started from (empty for feed-forward)
+    pub state: &'a [f32],
+    pub probabilities: &'a [f32],
+    pub value: f32,
+    pub action_index: usize,
+    pub action: ActionCommand,
+}
+
+/// Decision history of every [`Inspected`] organism
+///
+/// Keeps the last `capacity` decisions per organism, including organisms
+/// that have since died, for up to `max_organisms` organisms; past that, the
+/// organism that decided least recently is forgotten. Differentiable copies
+/// of the policies are built on first use and dropped whenever the
+/// [`crate::PolicyRegistry`] changes; like the registry they sit behind a
+/// mutex because autodiff modules are not `Sync`.
+#[derive(Resource)]
+pub struct DecisionInspector {
+    capacity: usize,
+    max_organisms: usize,
+    decisions: BTreeMap<Entity, VecDeque<DecisionExplanation>>,
+    networks: Mutex<HashMap<(OrganismType, PolicyVariant), PolicyNetwork<AdaptiveBackend>>>,
+}
+
+impl Default for DecisionInspector {
+    fn default() -> Self {
+        Self::new(256)
+    }
+}
+
+impl DecisionInspector {
+    /// Keep up to `capacity` decisions per organism, of up to 64 organisms
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            max_organisms: 64,
+            decisions: BTreeMap::new(),
+            networks: Mutex::default(),
+        }
+    }
+
+    /// Keep the decisions of up to `max_organisms` organisms
+    pub fn with_max_organisms(mut self, max_organisms: usize) -> Self {
+        self.max_organisms = max_organisms.max(1);
+        self
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn max_organisms(&self) -> usize {
+        self.max_organisms
+    }
+
+    /// Organisms with at least one recorded decision
+    pub fn entities(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.decisions.keys().copied()
+    }
+
+    /// Decisions of `entity`, oldest first
+    pub fn history(&self, entity: Entity) -> impl Iterator<Item = &DecisionExplanation> {
+        self.decisions.get(&entity).into_iter().flatten()
+    }
+
+    /// Most recent decision of `entity`
+    pub fn latest(&self, entity: Entity) -> Option<&DecisionExplanation> {
+        self.decisions.get(&entity).and_then(VecDeque::back)
+    }
+
+    /// Number of decisions held across all organisms
+    pub fn len(&self) -> usize {
+        self.decisions.values().map(VecDeque::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.decisions.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.decisions.clear();
+    }
+
+    /// Every held decision as a JSON array, grouped by organism and in tick
+    /// order
+    pub fn to_json(&self) -> serde_json::Value {
+        let decisions: Vec<&DecisionExplanation> = self.decisions.values().flatten().collect();
+        serde_json::to_value(decisions).expect("decisions serialize to JSON")
+    }
+
+    /// Write [`DecisionInspector::to_json`] to `path`
+    pub fn dump_json(&self, path: &Path) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut writer, &self.to_json())?;
+        writer.flush()
+    }
+
+    /// Drop the differentiable policy copies, e.g. after a policy swap
+    pub(crate) fn forget_networks(&mut self) {
+        self.networks
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clear();
+    }
+
+    pub(crate) fn record(
+        &mut self,
+        species: OrganismType,
+        variant: PolicyVariant,
+        model: &PolicyModel<InferenceBackend>,
+        decision: InspectedDecision<'_>,
+    ) {
+        let network = self
+            .networks
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry((species, variant))
+            .or_insert_with(|| trainable(model));
+        let mut saliency = network.saliency(
+            decision.input,
+            decision.state,
+            decision.action_index,
+            &Default::default(),
+        );
+        let encoder = decision.encoder;
+        let raw = encoder.encode(&decision.observation);
+        debug_assert_eq!(raw.len(), decision.input.len());
+        model.metadata.normalizer.backpropagate(&raw, &mut saliency);
+        let features = encoder
+            .feature_names()
+            .into_iter()
+            .zip(raw)
+            .zip(saliency)
+            .map(|((name, value), saliency)| FeatureSaliency {
+                name,
+                value,
+                saliency,
+            })
+            .collect();
+
+        if !self.decisions.contains_key(&decision.entity)
+            && self.decisions.len() >= self.max_organisms
+            && let Some(stalest) = self
+                .decisions
+                .iter()
+                .min_by_key(|(_, history)| history.back().map_or(0, |latest| latest.tick))
+                .map(|(entity, _)| *entity)
+        {
+            self.decisions.remove(&stalest);
+        }
+        let history = self.decisions.entry(decision.entity).or_default();
+        if history.len() == self.capacity {
+            history.pop_front();
+        }
+        history.push_back(DecisionExplanation {
+            tick: decision.tick,
+            entity: decision.entity.to_bits(),
+            species,
+            variant,
+            action_index: decision.action_index,
+            action: decision.action,
+            probabilities: decision.probabilities.to_vec(),
+            value: decision.value,
+            observation: decision.observation,
+            features,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy_app::prelude::*;
+    use ecosystem_ai_shared::{ActionSpace, ObservationEncoder, PolicyConfig};
+
+    use crate::tests::seeded_model;
+    use crate::{ActionSelection, AiRuntimePlugin, PolicyRegistry};
+
+    #[test]
+    fn test_inspected_decisions_are_explained() {
+        let encoder = ObservationEncoder::default();
+        let action_space = ActionSpace::default();
+        let policy = PolicyConfig::new(encoder.input_size(), action_space.size());
+        let model = seeded_model(OrganismType::Blue, encoder, policy, 3);
+
+        let mut app = App::new();
+        app.add_plugins(AiRuntimePlugin {
+            selection: ActionSelection::Greedy,
+            ..Default::default()
+        })
+        .insert_resource(DecisionInspector::new(2).with_max_organisms(1));
+        app.world
+            .resource_mut::<PolicyRegistry>()
+            .assign(OrganismType::Blue, model, &encoder, &action_space)
+            .unwrap();
+        let watched = OrganismFactory::spawn(&mut app.world, OrganismType::Blue, Position::zero());
+        app.world.entity_mut(watched).insert(Inspected);
+        let other =
+            OrganismFactory::spawn(&mut app.world, OrganismType::Blue, Position::new(30.0, 0.0));
+        OrganismFactory::spawn(
+            &mut app.world,
+            OrganismType::Plant,
+            Position::new(10.0, 5.0),
+        );
+
+        for _ in 0..3 {
+            app.update();
+        }
+
+        let inspector = app.world.resource::<DecisionInspector>();
+        assert_eq!(inspector.entities().collect::<Vec<_>>(), [watched]);
+        assert!(inspector.latest(other).is_none());
+        assert_eq!(inspector.history(watched).count(), 2);
+
+        let decision = inspector.latest(watched).unwrap();
+        assert_eq!(decision.tick, 2);
+        assert_eq!(decision.entity, watched.to_bits());
+        assert_eq!(decision.probabilities.len(), action_space.size());
+        assert!((decision.probabilities.iter().sum::<f32>() - 1.0).abs() < 1e-5);
+        assert_eq!(
+            decision.action,
+            *app.world.get::<ActionCommand>(watched).unwrap()
+        );
+        assert_eq!(decision.features.len(), encoder.input_size());
+        assert_eq!(decision.features[0].name, "health_ratio");
+        assert!(
+            decision
+                .features
+                .iter()
+                .any(|feature| feature.saliency != 0.0)
+        );
+        assert!(decision.observation.visible_entities.len() >= 2);
+
+        // Probabilities of the other actions fall as the chosen one rises, so
+        // nudging a feature along its saliency makes the action more likely
+        let model = app.world.resource::<PolicyRegistry>();
+        let model = model.get(OrganismType::Blue).unwrap();
+        let feature = decision.most_salient(1)[0];
+        let index = decision
+            .features
+            .iter()
+            .position(|candidate| candidate.name == feature.name)
+            .unwrap();
+        let mut input = encoder.encode(&decision.observation);
+        input[index] += 1e-2 * feature.saliency.signum();
+        let nudged = model.network.evaluate(&input, &Default::default());
+        assert!(
+            nudged.probabilities[0][decision.action_index]
+                > decision.probabilities[decision.action_index]
+        );
+
+        let json = inspector.to_json();
+        assert_eq!(json.as_array().unwrap().len(), 2);
+        assert_eq!(json[1]["features"][0]["name"], "health_ratio");
+        drop(model);
+
+        // A newly inspected organism pushes out the one watched before
+        app.world.entity_mut(watched).remove::<Inspected>();
+        app.world.entity_mut(other).insert(Inspected);
+        app.update();
+        let inspector = app.world.resource::<DecisionInspector>();
+        assert_eq!(inspector.entities().collect::<Vec<_>>(), [other]);
+        assert_eq!(inspector.len(), 1);
+    }
+}
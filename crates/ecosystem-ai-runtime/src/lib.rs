@@ -1,14 +1,738 @@
-pub fn add(left: u64, right: u64) -> u64 {
-    left + right
+//! Runtime AI for the ecosystem simulation
+//!
+//! A bevy plugin that drives organisms with trained policies:
+//! - Policies are loaded from model files and assigned per species
+//! - Every tick, observations of all organisms of a species are encoded and
+//!   run through one batched forward pass on the CPU backend
+//! - The chosen actions are written back as `ActionCommand` components
+//! - [`DecisionRates`] throttles how often each species decides; the last
+//!   `ActionCommand` persists in between
+//! - [`ModelHotReloadPlugin`] swaps in newer model files between ticks
+//! - Per-species timings are kept in [`InferenceTimings`]
+//! - [`Precision::Int8`] runs plain MLP policies with quantized weights
+//! - Inserting a [`TrajectoryRecorder`] logs every decision for behavior cloning
+//! - Inserting a [`DecisionInspector`] explains the decisions of [`Inspected`]
+//!   organisms: action probabilities, value estimate and feature saliency
+//! - Species without a policy are driven by an authored behavior tree from
+//!   the [`BehaviorLibrary`], or else by a scripted heuristic brain
+//! - Several policies of one species can compete in the same world: a
+//!   [`PolicyAssignment`] gives organisms a [`PolicyVariant`] at spawn and
+//!   [`VariantPopulations`] keeps score
+//! - Policies are frozen unless [`LifetimeLearning`] lets a species keep
+//!   learning from live experience
+
+use bevy_app::prelude::*;
+use bevy_ecs::prelude::*;
+use ecosystem_ai_shared::{ActionSpace, ObservationEncoder};
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+pub mod assignment;
+pub mod behavior;
+pub mod heuristic;
+pub mod hot_reload;
+pub mod inference;
+pub mod inspector;
+pub mod lifetime;
+pub mod recorder;
+pub mod registry;
+pub mod throttle;
+
+pub use assignment::*;
+pub use behavior::*;
+pub use heuristic::*;
+pub use hot_reload::*;
+pub use inference::*;
+pub use inspector::*;
+pub use lifetime::*;
+pub use recorder::*;
+pub use registry::*;
+pub use throttle::*;
+
+/// System set containing the decision-making systems; schedule it before physics
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AiSet;
+
+/// Adds policy inference to an app
+#[derive(Debug, Clone, Default)]
+pub struct AiRuntimePlugin {
+    pub encoder: ObservationEncoder,
+    pub action_space: ActionSpace,
+    pub selection: ActionSelection,
+    pub precision: Precision,
+    /// Seed for stochastic action selection
+    pub seed: u64,
+    /// Fallback behaviour for species without a policy
+    pub heuristics: HeuristicConfig,
+    /// How often organisms of each species decide
+    pub decision_rates: DecisionRates,
+    /// Keep training the policies of these species while running; `None`
+    /// freezes every policy
+    pub lifetime_learning: Option<LifetimeLearning>,
+    /// Split species between their loaded policy variants; `None` runs the
+    /// primary policies only
+    pub policy_assignment: Option<PolicyAssignment>,
+}
+
+impl Plugin for AiRuntimePlugin {
+    fn build(&self, app: &mut App) {
+        if let Some(config) = &self.lifetime_learning {
+            app.insert_resource(LifetimeLearners::new(config.clone()));
+        }
+        if let Some(assignment) = &self.policy_assignment {
+            app.insert_resource(assignment.clone())
+                .init_resource::<VariantPopulations>();
+        }
+        app.insert_resource(RuntimeSettings {
+            encoder: self.encoder,
+            action_space: self.action_space,
+            selection: self.selection,
+            precision: self.precision,
+        })
+        .insert_resource(RuntimeRng(ChaCha8Rng::seed_from_u64(self.seed)))
+        .insert_resource(self.heuristics)
+        .insert_resource(self.decision_rates.clone())
+        .init_resource::<DecisionTicks>()
+        .init_resource::<PolicyRegistry>()
+        .init_resource::<BehaviorLibrary>()
+        .init_resource::<InferenceTimings>()
+        .add_systems(
+            Update,
+            (
+                schedule_decisions,
+                run_heuristic_brain,
+                run_behavior_trees,
+                run_policy_inference,
+            )
+                .chain()
+                .in_set(AiSet),
+        )
+        .add_systems(
+            Update,
+            (
+                assign_policy_variants,
+                apply_deferred,
+                track_variant_populations,
+            )
+                .chain()
+                .before(schedule_decisions)
+                .in_set(AiSet)
+                .run_if(resource_exists::<PolicyAssignment>()),
+        )
+        .add_systems(
+            Update,
+            update_lifetime_learners
+                .after(run_policy_inference)
+                .in_set(AiSet)
+                .run_if(resource_exists::<LifetimeLearners>()),
+        )
+        .add_systems(
+            Update,
+            record_decisions
+                .after(run_policy_inference)
+                .in_set(AiSet)
+                .run_if(resource_exists::<TrajectoryRecorder>()),
+        );
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use ecosystem_ai_shared::{
+        AttentionConfig, InferenceBackend, ModelFileError, ModelMetadata, PolicyConfig,
+        PolicyModel, RunningNormalizer, SELF_FEATURES, SLOT_FEATURES, seeded_init,
+    };
+    use ecosystem_components::prelude::*;
+    use ecosystem_physics::{MetabolismConfig, PhysicsConfig, PhysicsPlugin, PhysicsSet};
+
+    /// Policy fixture shared by the tests of this crate
+    pub(crate) fn seeded_model(
+        species: OrganismType,
+        encoder: ObservationEncoder,
+        policy: PolicyConfig,
+        seed: u64,
+    ) -> PolicyModel<InferenceBackend> {
+        PolicyModel {
+            network: seeded_init(policy.init(&Default::default()), seed),
+            metadata: ModelMetadata::new(
+                species,
+                encoder,
+                ActionSpace::default(),
+                policy,
+                RunningNormalizer::new(encoder.input_size(), 5.0),
+            ),
+        }
+    }
+
+    fn model(species: OrganismType, encoder: ObservationEncoder) -> PolicyModel<InferenceBackend> {
+        let policy = PolicyConfig::new(encoder.input_size(), ActionSpace::default().size());
+        seeded_model(species, encoder, policy, 5)
+    }
+
+    #[test]
+    fn test_inference_writes_actions_for_assigned_species() {
+        let encoder = ObservationEncoder::default();
+        let mut app = App::new();
+        app.add_plugins(AiRuntimePlugin {
+            selection: ActionSelection::Greedy,
+            heuristics: HeuristicConfig {
+                enabled: false,
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        app.world
+            .resource_mut::<PolicyRegistry>()
+            .assign(
+                OrganismType::Blue,
+                model(OrganismType::Blue, encoder),
+                &encoder,
+                &ActionSpace::default(),
+            )
+            .unwrap();
+
+        let blues: Vec<Entity> = (0..3)
+            .map(|i| {
+                OrganismFactory::spawn(
+                    &mut app.world,
+                    OrganismType::Blue,
+                    Position::new(i as f32 * 20.0, 0.0),
+                )
+            })
+            .collect();
+        let red = OrganismFactory::spawn(&mut app.world, OrganismType::Red, Position::zero());
+        for entity in blues.iter().chain([&red]) {
+            app.world
+                .entity_mut(*entity)
+                .insert(ActionCommand::move_to(Vec2::new(0.0, -1.0)));
+        }
+
+        app.update();
+
+        let space = ActionSpace::default();
+        for blue in &blues {
+            let command = app.world.get::<ActionCommand>(*blue).unwrap();
+            assert_eq!(space.to_command(space.to_index(command)), *command);
+        }
+        // Red has no policy and keeps its command
+        assert_eq!(
+            app.world.get::<ActionCommand>(red).unwrap(),
+            &ActionCommand::move_to(Vec2::new(0.0, -1.0))
+        );
+
+        let timings = app.world.resource::<InferenceTimings>();
+        assert_eq!(timings.ticks, 1);
+        assert_eq!(timings.get(OrganismType::Blue).unwrap().agents, 3);
+        assert!(timings.get(OrganismType::Red).is_none());
+    }
+
+    #[test]
+    fn test_int8_precision_matches_f32_decisions() {
+        let encoder = ObservationEncoder::default();
+        let decisions = |precision| {
+            let mut app = App::new();
+            app.add_plugins(AiRuntimePlugin {
+                selection: ActionSelection::Greedy,
+                precision,
+                ..Default::default()
+            });
+            app.world
+                .resource_mut::<PolicyRegistry>()
+                .assign(
+                    OrganismType::Blue,
+                    model(OrganismType::Blue, encoder),
+                    &encoder,
+                    &ActionSpace::default(),
+                )
+                .unwrap();
+            let blues: Vec<Entity> = (0..6)
+                .map(|i| {
+                    OrganismFactory::spawn(
+                        &mut app.world,
+                        OrganismType::Blue,
+                        Position::new(i as f32 * 15.0, (i % 2) as f32 * 30.0),
+                    )
+                })
+                .collect();
+            OrganismFactory::spawn(&mut app.world, OrganismType::Red, Position::new(20.0, 10.0));
+            app.update();
+            blues
+                .iter()
+                .map(|blue| app.world.get::<ActionCommand>(*blue).unwrap().clone())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(decisions(Precision::Int8), decisions(Precision::F32));
+    }
+
+    #[test]
+    fn test_attention_policies_see_past_max_visible() {
+        let encoder = ObservationEncoder::new(2, 500.0, 160.0);
+        let action_space = ActionSpace::default();
+        let policy = PolicyConfig::new(encoder.input_size(), action_space.size())
+            .with_attention(Some(AttentionConfig::new().with_embed_size(8)));
+        let probabilities = |far: &[Vec2]| {
+            let mut app = App::new();
+            app.add_plugins(AiRuntimePlugin {
+                encoder,
+                selection: ActionSelection::Greedy,
+                ..Default::default()
+            })
+            .insert_resource(DecisionInspector::new(1));
+            let mut model = seeded_model(OrganismType::Blue, encoder, policy.clone(), 5);
+            model.metadata.normalizer = RunningNormalizer::new(SELF_FEATURES, 5.0)
+                .with_passthrough(encoder.input_size() - SELF_FEATURES);
+            app.world
+                .resource_mut::<PolicyRegistry>()
+                .assign(OrganismType::Blue, model, &encoder, &action_space)
+                .unwrap();
+            let blue = OrganismFactory::spawn(&mut app.world, OrganismType::Blue, Position::zero());
+            app.world.entity_mut(blue).insert(Inspected);
+            let near = [Vec2::new(10.0, 0.0), Vec2::new(0.0, 12.0)];
+            for position in near.iter().chain(far) {
+                OrganismFactory::spawn(
+                    &mut app.world,
+                    OrganismType::Red,
+                    Position::new(position.x, position.y),
+                );
+            }
+            app.update();
+            let inspector = app.world.resource::<DecisionInspector>();
+            let decision = inspector.latest(blue).unwrap();
+            assert_eq!(decision.observation.visible_entities.len(), 2 + far.len());
+            assert_eq!(
+                decision.features.len(),
+                SELF_FEATURES + (2 + far.len()) * SLOT_FEATURES
+            );
+            decision.probabilities.clone()
+        };
+
+        // Both near entities fill the encoder's two slots; the far ones only
+        // reach the policy if it is not truncated to them
+        let alone = probabilities(&[]);
+        let crowded = probabilities(&[Vec2::new(-70.0, 20.0), Vec2::new(40.0, -80.0)]);
+        assert!(
+            alone
+                .iter()
+                .zip(&crowded)
+                .any(|(a, b)| (a - b).abs() > 1e-6)
+        );
+    }
 
     #[test]
-    fn it_works() {
-        let result = add(2, 2);
-        assert_eq!(result, 4);
+    fn test_recurrent_state_persists_and_resets_on_birth() {
+        let encoder = ObservationEncoder::default();
+        let policy = PolicyConfig::new(encoder.input_size(), ActionSpace::default().size())
+            .with_hidden_size(8)
+            .with_recurrent(Some(ecosystem_ai_shared::RecurrentKind::Gru));
+        let recurrent = seeded_model(OrganismType::Blue, encoder, policy, 5);
+
+        let mut app = App::new();
+        app.add_plugins(AiRuntimePlugin::default());
+        app.world
+            .resource_mut::<PolicyRegistry>()
+            .assign(
+                OrganismType::Blue,
+                recurrent,
+                &encoder,
+                &ActionSpace::default(),
+            )
+            .unwrap();
+        let blue = OrganismFactory::spawn(&mut app.world, OrganismType::Blue, Position::zero());
+        let state = |app: &App| {
+            app.world
+                .get::<RecurrentState>(blue)
+                .unwrap()
+                .values
+                .clone()
+        };
+
+        app.update();
+        let first = state(&app);
+        assert_eq!(first.len(), 8);
+
+        app.world.entity_mut(blue).remove::<Newborn>();
+        app.update();
+        assert_ne!(state(&app), first);
+
+        app.world.entity_mut(blue).insert(Newborn);
+        app.update();
+        assert_eq!(state(&app), first);
+    }
+
+    #[test]
+    fn test_lifetime_learning_updates_the_running_policy() {
+        let encoder = ObservationEncoder::default();
+        let mut app = App::new();
+        app.add_plugins(AiRuntimePlugin {
+            lifetime_learning: Some(LifetimeLearning {
+                batch_size: 4,
+                learning_rate: 1e-2,
+                ..LifetimeLearning::new([OrganismType::Blue])
+            }),
+            ..Default::default()
+        });
+        app.world
+            .resource_mut::<PolicyRegistry>()
+            .assign(
+                OrganismType::Blue,
+                model(OrganismType::Blue, encoder),
+                &encoder,
+                &ActionSpace::default(),
+            )
+            .unwrap();
+        for i in 0..3 {
+            OrganismFactory::spawn(
+                &mut app.world,
+                OrganismType::Blue,
+                Position::new(i as f32 * 20.0, 0.0),
+            );
+        }
+        let probe = vec![0.1; encoder.input_size()];
+        let outputs = |app: &App| {
+            let registry = app.world.resource::<PolicyRegistry>();
+            let model = registry.get(OrganismType::Blue).unwrap();
+            model.network.evaluate(&probe, &Default::default())
+        };
+        let frozen = outputs(&app);
+
+        for _ in 0..3 {
+            app.update();
+        }
+
+        let stats = app
+            .world
+            .resource::<LifetimeLearners>()
+            .stats(OrganismType::Blue)
+            .unwrap();
+        assert_eq!(stats.transitions, 6);
+        assert_eq!(stats.updates, 1);
+        assert_ne!(outputs(&app), frozen);
+    }
+
+    #[test]
+    fn test_lifetime_learning_restarts_for_reassigned_policy() {
+        let encoder = ObservationEncoder::default();
+        let mut app = App::new();
+        app.add_plugins(AiRuntimePlugin {
+            lifetime_learning: Some(LifetimeLearning {
+                batch_size: 4,
+                learning_rate: 1e-2,
+                ..LifetimeLearning::new([OrganismType::Blue])
+            }),
+            ..Default::default()
+        });
+        let assign = |app: &mut App, model| {
+            app.world
+                .resource_mut::<PolicyRegistry>()
+                .assign(OrganismType::Blue, model, &encoder, &ActionSpace::default())
+                .unwrap();
+        };
+        assign(&mut app, model(OrganismType::Blue, encoder));
+        for i in 0..3 {
+            OrganismFactory::spawn(
+                &mut app.world,
+                OrganismType::Blue,
+                Position::new(i as f32 * 20.0, 0.0),
+            );
+        }
+        for _ in 0..3 {
+            app.update();
+        }
+
+        // Same run id and iteration, different weights
+        let policy = PolicyConfig::new(encoder.input_size(), ActionSpace::default().size());
+        let replacement = seeded_model(OrganismType::Blue, encoder, policy, 6);
+        let probe = vec![0.1; encoder.input_size()];
+        let expected = replacement.network.evaluate(&probe, &Default::default());
+        assign(&mut app, replacement);
+        for _ in 0..2 {
+            app.update();
+        }
+
+        let stats = app
+            .world
+            .resource::<LifetimeLearners>()
+            .stats(OrganismType::Blue)
+            .unwrap();
+        assert_eq!((stats.transitions, stats.updates), (3, 0));
+        let registry = app.world.resource::<PolicyRegistry>();
+        let model = registry.get(OrganismType::Blue).unwrap();
+        assert_eq!(
+            model.network.evaluate(&probe, &Default::default()),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_policy_variants_compete_within_a_species() {
+        let encoder = ObservationEncoder::default();
+        let policy = PolicyConfig::new(encoder.input_size(), ActionSpace::default().size())
+            .with_hidden_size(16);
+        let challenger = seeded_model(OrganismType::Blue, encoder, policy, 6);
+        let brains = [
+            model(OrganismType::Blue, encoder).network.brain_size(),
+            challenger.network.brain_size(),
+        ];
+
+        let mut app = App::new();
+        app.add_plugins(AiRuntimePlugin {
+            policy_assignment: Some(
+                PolicyAssignment::default().with_rule(
+                    OrganismType::Blue,
+                    AssignmentRule::even([PolicyVariant::PRIMARY, PolicyVariant::CHALLENGER])
+                        .inherited(),
+                ),
+            ),
+            ..Default::default()
+        });
+        let mut registry = app.world.resource_mut::<PolicyRegistry>();
+        registry
+            .assign(
+                OrganismType::Blue,
+                model(OrganismType::Blue, encoder),
+                &encoder,
+                &ActionSpace::default(),
+            )
+            .unwrap();
+        registry
+            .assign_variant(
+                OrganismType::Blue,
+                PolicyVariant::CHALLENGER,
+                challenger,
+                &encoder,
+                &ActionSpace::default(),
+            )
+            .unwrap();
+        assert_eq!(
+            registry.variants(OrganismType::Blue),
+            [PolicyVariant::PRIMARY, PolicyVariant::CHALLENGER]
+        );
+
+        let blues: Vec<Entity> = (0..4)
+            .map(|i| {
+                OrganismFactory::spawn(
+                    &mut app.world,
+                    OrganismType::Blue,
+                    Position::new(i as f32 * 20.0, 0.0),
+                )
+            })
+            .collect();
+        app.update();
+
+        for (i, blue) in blues.iter().enumerate() {
+            assert_eq!(
+                app.world.get::<PolicyVariant>(*blue),
+                Some(&PolicyVariant(i as u8 % 2))
+            );
+            // Each organism carries the brain of the policy that drove it
+            assert_eq!(app.world.get::<BrainSize>(*blue), Some(&brains[i % 2]));
+        }
+        assert_eq!(
+            app.world
+                .resource::<InferenceTimings>()
+                .get(OrganismType::Blue)
+                .unwrap()
+                .agents,
+            4
+        );
+
+        // A challenger dies and another one breeds
+        app.world.despawn(blues[1]);
+        let offspring =
+            OrganismFactory::spawn_offspring(&mut app.world, blues[3], Position::new(0.0, 30.0))
+                .unwrap();
+        app.update();
+
+        assert_eq!(
+            app.world.get::<PolicyVariant>(offspring),
+            Some(&PolicyVariant::CHALLENGER)
+        );
+        let populations = app.world.resource::<VariantPopulations>();
+        assert_eq!(populations.tick(), 2);
+        let primary = populations
+            .get(OrganismType::Blue, PolicyVariant::PRIMARY)
+            .unwrap();
+        assert_eq!((primary.assigned, primary.alive, primary.deaths), (2, 2, 0));
+        let challengers = populations
+            .get(OrganismType::Blue, PolicyVariant::CHALLENGER)
+            .unwrap();
+        assert_eq!(
+            (challengers.assigned, challengers.alive, challengers.deaths),
+            (3, 2, 1)
+        );
+        assert_eq!(challengers.mean_lifespan(), 1.0);
+        assert!(populations.summary().contains("Blue#1 2 alive, 1 dead"));
+    }
+
+    #[test]
+    fn test_heuristics_drive_species_without_policy() {
+        let mut app = App::new();
+        app.add_plugins(AiRuntimePlugin::default());
+        let red = OrganismFactory::spawn(&mut app.world, OrganismType::Red, Position::zero());
+        OrganismFactory::spawn(&mut app.world, OrganismType::Blue, Position::new(0.0, 40.0));
+
+        app.update();
+
+        assert_eq!(
+            app.world.get::<ActionCommand>(red).unwrap(),
+            &ActionCommand::move_to(Vec2::Y)
+        );
+    }
+
+    #[test]
+    fn test_brain_upkeep_stops_with_the_policy() {
+        let encoder = ObservationEncoder::default();
+        let config = PhysicsConfig {
+            metabolism: MetabolismConfig {
+                enabled: true,
+                brain_rate: 20.0,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut app = App::new();
+        app.add_plugins((
+            PhysicsPlugin {
+                config: config.clone(),
+                bounds: WorldBounds::default(),
+            },
+            AiRuntimePlugin::default(),
+        ))
+        .configure_sets(Update, AiSet.before(PhysicsSet));
+        let model = model(OrganismType::Blue, encoder);
+        let brain = model.network.brain_size();
+        app.world
+            .resource_mut::<PolicyRegistry>()
+            .assign(OrganismType::Blue, model, &encoder, &ActionSpace::default())
+            .unwrap();
+        let blue = OrganismFactory::spawn(&mut app.world, OrganismType::Blue, Position::zero());
+        let brain_drain =
+            config.metabolism.brain_rate * brain.parameters as f32 / 1000.0 * config.tick_seconds;
+        let spent = |app: &mut App| {
+            let before = app.world.get::<Energy>(blue).unwrap().current();
+            app.update();
+            before - app.world.get::<Energy>(blue).unwrap().current()
+        };
+
+        assert!(spent(&mut app) >= brain_drain);
+        assert_eq!(app.world.get::<BrainSize>(blue), Some(&brain));
+
+        // The heuristics take over and charge nothing for thinking
+        app.world
+            .resource_mut::<PolicyRegistry>()
+            .remove(OrganismType::Blue);
+        assert!(spent(&mut app) < 1.0);
+        assert_eq!(
+            app.world.get::<BrainSize>(blue),
+            Some(&BrainSize::default())
+        );
+    }
+
+    #[test]
+    fn test_fallback_brains_leave_challengers_alone() {
+        #[derive(Resource, Default)]
+        struct BrainChanges(usize);
+
+        fn count_brain_changes(
+            mut changes: ResMut<BrainChanges>,
+            brains: Query<(), Changed<BrainSize>>,
+        ) {
+            changes.0 += brains.iter().count();
+        }
+
+        let encoder = ObservationEncoder::default();
+        let challenger = model(OrganismType::Blue, encoder);
+        let brain = challenger.network.brain_size();
+        let mut app = App::new();
+        app.add_plugins(AiRuntimePlugin {
+            policy_assignment: Some(PolicyAssignment::default().with_rule(
+                OrganismType::Blue,
+                AssignmentRule::even([PolicyVariant::PRIMARY, PolicyVariant::CHALLENGER]),
+            )),
+            ..Default::default()
+        })
+        .init_resource::<BrainChanges>()
+        .add_systems(
+            Update,
+            count_brain_changes
+                .after(run_behavior_trees)
+                .before(run_policy_inference),
+        );
+        // Only the challenger is loaded; primary organisms fall back to heuristics
+        app.world
+            .resource_mut::<PolicyRegistry>()
+            .assign_variant(
+                OrganismType::Blue,
+                PolicyVariant::CHALLENGER,
+                challenger,
+                &encoder,
+                &ActionSpace::default(),
+            )
+            .unwrap();
+        let blues: Vec<Entity> = (0..2)
+            .map(|i| {
+                OrganismFactory::spawn(
+                    &mut app.world,
+                    OrganismType::Blue,
+                    Position::new(i as f32 * 20.0, 0.0),
+                )
+            })
+            .collect();
+
+        // Settle the brains inference hands out on the first tick
+        app.update();
+        app.update();
+        app.world.resource_mut::<BrainChanges>().0 = 0;
+        app.update();
+
+        assert_eq!(app.world.resource::<BrainChanges>().0, 0);
+        assert_eq!(
+            app.world.get::<BrainSize>(blues[0]),
+            Some(&BrainSize::default())
+        );
+        assert_eq!(app.world.get::<BrainSize>(blues[1]), Some(&brain));
+    }
+
+    #[test]
+    fn test_registry_rejects_mismatched_policies() {
+        let encoder = ObservationEncoder::default();
+        let action_space = ActionSpace::default();
+        let mut registry = PolicyRegistry::default();
+        let result = registry.assign(
+            OrganismType::Red,
+            model(OrganismType::Blue, encoder),
+            &encoder,
+            &action_space,
+        );
+        assert!(matches!(
+            result,
+            Err(ModelFileError::SpeciesMismatch { .. })
+        ));
+        assert!(!registry.contains(OrganismType::Red));
+
+        let narrow = ObservationEncoder::new(2, 500.0, 160.0);
+        let result = registry.assign(
+            OrganismType::Blue,
+            model(OrganismType::Blue, narrow),
+            &encoder,
+            &action_space,
+        );
+        assert!(matches!(result, Err(ModelFileError::SchemaMismatch { .. })));
+
+        // Metadata that matches but a network that does not
+        let mut reshaped = model(OrganismType::Blue, encoder);
+        reshaped.network =
+            PolicyConfig::new(narrow.input_size(), action_space.size()).init(&Default::default());
+        let result = registry.assign_variant(
+            OrganismType::Blue,
+            PolicyVariant::CHALLENGER,
+            reshaped,
+            &encoder,
+            &action_space,
+        );
+        assert!(matches!(result, Err(ModelFileError::ShapeMismatch { .. })));
+        assert!(registry.variants(OrganismType::Blue).is_empty());
     }
 }
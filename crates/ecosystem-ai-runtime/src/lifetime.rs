@@ -0,0 +1,467 @@
+//! Lifetime learning
+//!
+//! Policies are frozen at runtime unless their species is listed in
+//! [`LifetimeLearning`]. Adaptive species keep training while the simulation
+//! runs: every decision is scored by how the organism's energy and health
+//! changed by its next decision (or by a penalty if it died in between), and
+//! once `batch_size` of these transitions are collected the species policy
+//! takes one advantage actor-critic step on them. The updated weights drive
+//! the very next tick, so a whole population adapts together.
+//!
+//! Only feed-forward policies adapt; recurrent ones stay frozen. A policy
+//! newly assigned to the registry, e.g. by hot reload, restarts learning from
+//! the new weights.
+
+use std::fmt::Write as _;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use bevy_ecs::prelude::*;
+use bevy_utils::HashMap;
+use burn::backend::Autodiff;
+use burn::grad_clipping::GradientClippingConfig;
+use burn::module::AutodiffModule;
+use burn::optim::adaptor::OptimizerAdaptor;
+use burn::optim::{Adam, AdamConfig, GradientsParams, Optimizer};
+use burn::prelude::*;
+use burn::record::{FullPrecisionSettings, NamedMpkBytesRecorder, Recorder};
+use burn::tensor::activation;
+use ecosystem_ai_shared::{InferenceBackend, PolicyModel, PolicyNetwork};
+use ecosystem_components::prelude::*;
+
+use crate::registry::PolicyRegistry;
+
+/// Backend of the policies being trained in place
+pub type AdaptiveBackend = Autodiff<InferenceBackend>;
+
+type AdaptiveOptimizer = OptimizerAdaptor<Adam, PolicyNetwork<AdaptiveBackend>, AdaptiveBackend>;
+
+/// Which species keep learning at runtime, and how
+#[derive(Debug, Clone, PartialEq)]
+pub struct LifetimeLearning {
+    /// Adaptive species; everyone else keeps a frozen policy
+    pub species: Vec<OrganismType>,
+    pub learning_rate: f64,
+    pub discount: f32,
+    /// Transitions per gradient step
+    pub batch_size: usize,
+    pub value_coef: f32,
+    pub entropy_coef: f32,
+    pub max_grad_norm: f32,
+    /// Reward per unit change of the energy ratio between decisions
+    pub energy_weight: f32,
+    /// Reward per unit change of the health ratio between decisions
+    pub health_weight: f32,
+    /// Reward for a decision after which the organism died
+    pub death_penalty: f32,
+}
+
+impl Default for LifetimeLearning {
+    fn default() -> Self {
+        Self {
+            species: Vec::new(),
+            learning_rate: 1e-4,
+            discount: 0.95,
+            batch_size: 64,
+            value_coef: 0.5,
+            entropy_coef: 0.01,
+            max_grad_norm: 0.5,
+            energy_weight: 1.0,
+            health_weight: 1.0,
+            death_penalty: -1.0,
+        }
+    }
+}
+
+impl LifetimeLearning {
+    pub fn new(species: impl IntoIterator<Item = OrganismType>) -> Self {
+        Self {
+            species: species.into_iter().collect(),
+            ..Default::default()
+        }
+    }
+
+    pub fn adapts(&self, species: OrganismType) -> bool {
+        self.species.contains(&species)
+    }
+}
+
+/// Progress of one adaptive species
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LifetimeStats {
+    /// Gradient steps taken since the policy was (re)loaded
+    pub updates: u64,
+    /// Completed transitions, including ones still waiting for an update
+    pub transitions: u64,
+    /// Transitions that ended in death
+    pub deaths: u64,
+    /// Mean reward of the batch behind the last update
+    pub mean_reward: f32,
+    pub policy_loss: f32,
+    pub value_loss: f32,
+}
+
+/// A decision waiting for the organism's next one to be scored
+#[derive(Debug, Clone)]
+struct OpenDecision {
+    input: Vec<f32>,
+    action: usize,
+    wellbeing: f32,
+}
+
+#[derive(Debug, Clone)]
+struct Transition {
+    input: Vec<f32>,
+    action: usize,
+    reward: f32,
+    /// Input of the next decision; `None` once the organism died
+    next_input: Option<Vec<f32>>,
+}
+
+/// Trainable copy of one species' policy plus its pending experience
+struct SpeciesLearner {
+    /// Registry generation of the model this learner started from
+    generation: u64,
+    model: PolicyNetwork<AdaptiveBackend>,
+    optimizer: AdaptiveOptimizer,
+    open: HashMap<Entity, OpenDecision>,
+    transitions: Vec<Transition>,
+    stats: LifetimeStats,
+}
+
+/// Runtime learners of every adaptive species
+///
+/// Insert this resource (e.g. through `AiRuntimePlugin::lifetime_learning`)
+/// to let policies adapt; without it every policy stays frozen.
+///
+/// Autodiff modules are not `Sync`, so like the [`PolicyRegistry`] the
+/// learners sit behind a mutex.
+#[derive(Resource)]
+pub struct LifetimeLearners {
+    config: LifetimeLearning,
+    learners: Mutex<HashMap<OrganismType, SpeciesLearner>>,
+}
+
+impl LifetimeLearners {
+    pub fn new(config: LifetimeLearning) -> Self {
+        Self {
+            config,
+            learners: Mutex::default(),
+        }
+    }
+
+    fn learners(&self) -> MutexGuard<'_, HashMap<OrganismType, SpeciesLearner>> {
+        self.learners.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn learners_mut(&mut self) -> &mut HashMap<OrganismType, SpeciesLearner> {
+        self.learners
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn config(&self) -> &LifetimeLearning {
+        &self.config
+    }
+
+    pub fn adapts(&self, species: OrganismType) -> bool {
+        self.config.adapts(species)
+    }
+
+    pub fn stats(&self, species: OrganismType) -> Option<LifetimeStats> {
+        self.learners().get(&species).map(|learner| learner.stats)
+    }
+
+    /// One-line report, e.g. for periodic logging
+    pub fn summary(&self) -> String {
+        let mut line = String::from("adapting");
+        for species in OrganismType::all() {
+            if let Some(stats) = self.stats(*species) {
+                let _ = write!(
+                    line,
+                    " | {} {} updates, reward {:.3}",
+                    species.short_name(),
+                    stats.updates,
+                    stats.mean_reward
+                );
+            }
+        }
+        line
+    }
+
+    /// Make sure the learner of `species` trains the policy in `model`,
+    /// assigned in registry `generation` (see [`PolicyRegistry::generation`])
+    ///
+    /// Returns whether decisions of this species should be recorded.
+    pub fn track(
+        &mut self,
+        species: OrganismType,
+        model: &PolicyModel<InferenceBackend>,
+        generation: u64,
+    ) -> bool {
+        if !self.adapts(species) || model.network.state_size() > 0 {
+            self.learners_mut().remove(&species);
+            return false;
+        }
+        let max_grad_norm = self.config.max_grad_norm;
+        let learners = self.learners_mut();
+        if learners
+            .get(&species)
+            .is_none_or(|learner| learner.generation != generation)
+        {
+            learners.insert(
+                species,
+                SpeciesLearner {
+                    generation,
+                    model: trainable(model),
+                    optimizer: AdamConfig::new()
+                        .with_grad_clipping(Some(GradientClippingConfig::Norm(max_grad_norm)))
+                        .init(),
+                    open: HashMap::default(),
+                    transitions: Vec::new(),
+                    stats: LifetimeStats::default(),
+                },
+            );
+        }
+        true
+    }
+
+    /// Record a decision of a tracked species from its normalized `input`
+    ///
+    /// Closes the organism's previous decision, scored by the change in
+    /// `health_ratio` and `energy_ratio` since then.
+    pub fn record(
+        &mut self,
+        species: OrganismType,
+        entity: Entity,
+        input: &[f32],
+        action: usize,
+        health_ratio: f32,
+        energy_ratio: f32,
+    ) {
+        let wellbeing =
+            self.config.health_weight * health_ratio + self.config.energy_weight * energy_ratio;
+        let Some(learner) = self.learners_mut().get_mut(&species) else {
+            return;
+        };
+        let decision = OpenDecision {
+            input: input.to_vec(),
+            action,
+            wellbeing,
+        };
+        if let Some(previous) = learner.open.insert(entity, decision) {
+            learner.stats.transitions += 1;
+            learner.transitions.push(Transition {
+                reward: wellbeing - previous.wellbeing,
+                input: previous.input,
+                action: previous.action,
+                next_input: Some(input.to_vec()),
+            });
+        }
+    }
+
+    /// Close the decisions of organisms that are gone, as deaths
+    pub fn close_dead(&mut self, is_alive: impl Fn(Entity) -> bool) {
+        let penalty = self.config.death_penalty;
+        for learner in self.learners_mut().values_mut() {
+            let dead: Vec<Entity> = learner
+                .open
+                .keys()
+                .copied()
+                .filter(|entity| !is_alive(*entity))
+                .collect();
+            for entity in dead {
+                let decision = learner.open.remove(&entity).expect("listed as open");
+                learner.stats.transitions += 1;
+                learner.stats.deaths += 1;
+                learner.transitions.push(Transition {
+                    input: decision.input,
+                    action: decision.action,
+                    reward: penalty,
+                    next_input: None,
+                });
+            }
+        }
+    }
+
+    /// Take a gradient step for every species with a full batch and return
+    /// the species whose weights changed
+    pub fn update(&mut self) -> Vec<OrganismType> {
+        let config = self.config.clone();
+        let mut updated = Vec::new();
+        for (species, learner) in self.learners_mut().iter_mut() {
+            if learner.transitions.len() >= config.batch_size.max(1) {
+                learner.step(&config);
+                updated.push(*species);
+            }
+        }
+        updated.sort_by_key(|species| *species as usize);
+        updated
+    }
+
+    /// Current weights of an adaptive species
+    pub fn network(&self, species: OrganismType) -> Option<PolicyNetwork<InferenceBackend>> {
+        self.learners()
+            .get(&species)
+            .map(|learner| learner.model.valid())
+    }
+}
+
+/// Copy an inference policy onto the autodiff backend
+pub(crate) fn trainable(model: &PolicyModel<InferenceBackend>) -> PolicyNetwork<AdaptiveBackend> {
+    let recorder = NamedMpkBytesRecorder::<FullPrecisionSettings>::default();
+    let device = Default::default();
+    let bytes = recorder
+        .record(model.network.clone().into_record(), ())
+        .expect("policy weights serialize");
+    let record = recorder
+        .load(bytes, &device)
+        .expect("policy weights deserialize");
+    model.metadata.policy.init(&device).load_record(record)
+}
+
+impl SpeciesLearner {
+    /// One advantage actor-critic step on every pending transition
+    fn step(&mut self, config: &LifetimeLearning) {
+        let device = Default::default();
+        let transitions = std::mem::take(&mut self.transitions);
+        let rows = transitions.len();
+        let input_size = transitions[0].input.len();
+
+        let next_inputs: Vec<f32> = transitions
+            .iter()
+            .filter_map(|transition| transition.next_input.as_deref())
+            .flatten()
+            .copied()
+            .collect();
+        let mut next_values = self
+            .model
+            .valid()
+            .evaluate(&next_inputs, &device)
+            .values
+            .into_iter();
+        let targets: Vec<f32> = transitions
+            .iter()
+            .map(|transition| match transition.next_input {
+                Some(_) => {
+                    let next = next_values.next().expect("one value per next input");
+                    transition.reward + config.discount * next
+                }
+                None => transition.reward,
+            })
+            .collect();
+
+        let mut inputs = Vec::with_capacity(rows * input_size);
+        for transition in &transitions {
+            inputs.extend_from_slice(&transition.input);
+        }
+        let actions: Vec<i64> = transitions.iter().map(|t| t.action as i64).collect();
+        let inputs = Tensor::<AdaptiveBackend, 2>::from_data(
+            TensorData::new(inputs, [rows, input_size]),
+            &device,
+        );
+        let actions = Tensor::<AdaptiveBackend, 2, Int>::from_data(
+            TensorData::new(actions, [rows, 1]),
+            &device,
+        );
+        let targets =
+            Tensor::<AdaptiveBackend, 1>::from_data(TensorData::new(targets, [rows]), &device);
+
+        let output = self.model.forward(inputs);
+        let advantages = targets.clone() - output.values.clone().detach();
+        let log_probs = activation::log_softmax(output.logits, 1);
+        let chosen: Tensor<AdaptiveBackend, 1> = log_probs.clone().gather(1, actions).squeeze(1);
+        let entropy = (log_probs.clone().exp() * log_probs)
+            .sum_dim(1)
+            .mean()
+            .neg();
+        let policy_loss = (chosen * advantages).mean().neg();
+        let value_loss = (output.values - targets).powf_scalar(2.0).mean();
+        let loss = policy_loss.clone() + value_loss.clone() * config.value_coef
+            - entropy * config.entropy_coef;
+
+        let grads = GradientsParams::from_grads(loss.backward(), &self.model);
+        self.model = self
+            .optimizer
+            .step(config.learning_rate, self.model.clone(), grads);
+
+        let scalar = |tensor: Tensor<AdaptiveBackend, 1>| tensor.into_scalar().elem::<f32>();
+        self.stats.updates += 1;
+        self.stats.mean_reward = transitions.iter().map(|t| t.reward).sum::<f32>() / rows as f32;
+        self.stats.policy_loss = scalar(policy_loss.detach());
+        self.stats.value_loss = scalar(value_loss.detach());
+    }
+}
+
+/// Score decisions of organisms that died, train adaptive species with full
+/// batches, and put the new weights in the [`PolicyRegistry`]
+///
+/// Runs right after [`crate::run_policy_inference`] when
+/// [`LifetimeLearners`] exists.
+pub fn update_lifetime_learners(
+    mut learners: ResMut<LifetimeLearners>,
+    mut registry: ResMut<PolicyRegistry>,
+    alive: Query<(), With<Alive>>,
+) {
+    learners.close_dead(|entity| alive.contains(entity));
+    let updated = learners.update();
+    for &species in &updated {
+        if let (Some(mut model), Some(network)) = (registry.get(species), learners.network(species))
+        {
+            model.network = network;
+        }
+    }
+    if !updated.is_empty() {
+        // The network is swapped behind the registry's lock, so flag it for
+        // systems that cache derived copies
+        registry.set_changed();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ecosystem_ai_shared::{ActionSpace, ObservationEncoder, PolicyConfig};
+
+    use crate::tests::seeded_model;
+
+    fn model() -> PolicyModel<InferenceBackend> {
+        let encoder = ObservationEncoder::new(2, 100.0, 50.0);
+        let policy = PolicyConfig::new(encoder.input_size(), ActionSpace::default().size())
+            .with_hidden_size(8);
+        seeded_model(OrganismType::Blue, encoder, policy, 4)
+    }
+
+    #[test]
+    fn test_rewards_and_deaths_close_decisions() {
+        let model = model();
+        let input = vec![0.5; model.network.input_size()];
+        let mut learners = LifetimeLearners::new(LifetimeLearning {
+            batch_size: 3,
+            ..LifetimeLearning::new([OrganismType::Blue])
+        });
+        assert!(!learners.track(OrganismType::Red, &model, 1));
+        assert!(learners.track(OrganismType::Blue, &model, 1));
+
+        let (a, b) = (Entity::from_raw(1), Entity::from_raw(2));
+        learners.record(OrganismType::Blue, a, &input, 0, 1.0, 0.5);
+        learners.record(OrganismType::Blue, b, &input, 1, 1.0, 0.5);
+        learners.record(OrganismType::Blue, a, &input, 2, 1.0, 0.75);
+        assert!(learners.update().is_empty());
+        learners.close_dead(|entity| entity != b);
+
+        let stats = learners.stats(OrganismType::Blue).unwrap();
+        assert_eq!((stats.transitions, stats.deaths), (2, 1));
+        learners.record(OrganismType::Blue, a, &input, 0, 1.0, 0.75);
+        assert_eq!(learners.update(), vec![OrganismType::Blue]);
+
+        let stats = learners.stats(OrganismType::Blue).unwrap();
+        assert_eq!(stats.updates, 1);
+        assert!((stats.mean_reward - (0.25 - 1.0) / 3.0).abs() < 1e-6);
+        let before = model.network.evaluate(&input, &Default::default());
+        let after = learners
+            .network(OrganismType::Blue)
+            .unwrap()
+            .evaluate(&input, &Default::default());
+        assert_ne!(before, after);
+    }
+}
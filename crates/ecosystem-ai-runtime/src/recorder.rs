@@ -0,0 +1,244 @@
+//! Recording decisions for behavior cloning
+
+use std::path::Path;
+
+use bevy_ecs::prelude::*;
+use ecosystem_ai_shared::{
+    DatasetError, DatasetWriter, PerceivedEntity, PerceptionSnapshot, TrajectoryRecord,
+};
+use ecosystem_components::prelude::*;
+
+use crate::throttle::DecisionTicks;
+
+/// Records between flushes of the dataset file
+const FLUSH_EVERY: u64 = 1024;
+
+/// Writes every decision of the recorded species to a trajectory dataset
+///
+/// Insert it as a resource to start recording. Whatever produced the
+/// `ActionCommand` (a policy, a behavior tree, the heuristics, or a human
+/// writing commands directly) is recorded as the action, paired with the
+/// observation the organism had when it was due to decide. Records carry no
+/// reward and belong to [`TrajectoryRecorder::with_episode`] (0 by default).
+/// The first write error stops the recording and is kept in
+/// [`TrajectoryRecorder::error`].
+#[derive(Resource, Debug)]
+pub struct TrajectoryRecorder {
+    writer: DatasetWriter,
+    species: Vec<OrganismType>,
+    episode: u64,
+    tick: u64,
+    error: Option<DatasetError>,
+}
+
+impl TrajectoryRecorder {
+    /// Record to `path`, as JSON lines for `.jsonl` files and binary otherwise
+    pub fn create(path: &Path, species: &[OrganismType]) -> Result<Self, DatasetError> {
+        Ok(Self {
+            writer: DatasetWriter::create(path)?,
+            species: species.to_vec(),
+            episode: 0,
+            tick: 0,
+            error: None,
+        })
+    }
+
+    /// Episode id stamped into every record, e.g. to tell sessions apart
+    pub fn with_episode(mut self, episode: u64) -> Self {
+        self.episode = episode;
+        self
+    }
+
+    pub fn records(&self) -> u64 {
+        self.writer.records()
+    }
+
+    pub fn path(&self) -> &Path {
+        self.writer.path()
+    }
+
+    pub fn error(&self) -> Option<&DatasetError> {
+        self.error.as_ref()
+    }
+
+    /// Flush buffered steps to disk
+    pub fn flush(&mut self) -> Result<(), DatasetError> {
+        self.writer.flush()
+    }
+
+    fn write(&mut self, record: &TrajectoryRecord) {
+        if self.error.is_some() {
+            return;
+        }
+        let result = self.writer.write(record).and_then(|()| {
+            if self.writer.records().is_multiple_of(FLUSH_EVERY) {
+                self.writer.flush()
+            } else {
+                Ok(())
+            }
+        });
+        if let Err(error) = result {
+            self.error = Some(error);
+        }
+    }
+}
+
+impl Drop for TrajectoryRecorder {
+    fn drop(&mut self) {
+        let _ = self.writer.flush();
+    }
+}
+
+type RecordedQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static OrganismType,
+        &'static Position,
+        &'static Vision,
+        &'static Health,
+        &'static Energy,
+        (&'static Movement, &'static Collision),
+        &'static ActionCommand,
+    ),
+    With<Alive>,
+>;
+
+/// Record the observation and chosen action of every organism that decided
+/// this tick
+///
+/// Runs last in [`crate::AiSet`] when a [`TrajectoryRecorder`] exists, so
+/// with the set ordered before physics the observations are the ones the
+/// brains decided from, not the world after the actions moved anyone.
+pub fn record_decisions(
+    mut recorder: ResMut<TrajectoryRecorder>,
+    decisions: Res<DecisionTicks>,
+    perceivable: Query<(Entity, &OrganismType, &Position), With<Alive>>,
+    agents: RecordedQuery,
+) {
+    let tick = recorder.tick;
+    recorder.tick += 1;
+    let snapshot =
+        PerceptionSnapshot::new(perceivable.iter().map(|(entity, organism_type, position)| {
+            PerceivedEntity {
+                entity,
+                organism_type: *organism_type,
+                position: position.to_vec2(),
+            }
+        }));
+    let mut due: Vec<_> = agents
+        .iter()
+        .filter(|(entity, organism_type, ..)| {
+            recorder.species.contains(organism_type) && decisions.is_due(*entity)
+        })
+        .collect();
+    due.sort_by_key(|(entity, ..)| *entity);
+    for (entity, organism_type, position, vision, health, energy, (movement, collision), action) in
+        due
+    {
+        let record = TrajectoryRecord {
+            episode: recorder.episode,
+            entity: entity.to_bits(),
+            tick,
+            species: *organism_type,
+            observation: snapshot
+                .observe(entity, position, vision, health, energy)
+                .with_traits(BodyTraits::new(vision, collision, movement, energy)),
+            action: action.clone(),
+            reward: 0.0,
+            done: false,
+        };
+        recorder.write(&record);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AiRuntimePlugin;
+    use bevy_app::prelude::*;
+    use ecosystem_ai_shared::read_dataset;
+    use ecosystem_physics::{PhysicsPlugin, PhysicsSet};
+
+    use crate::AiSet;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("eco-recorder-{name}-{}.traj", std::process::id()))
+    }
+
+    #[test]
+    fn test_records_due_decisions_of_selected_species() {
+        let path = temp_path("species");
+        let mut app = App::new();
+        app.add_plugins(AiRuntimePlugin::default());
+        app.insert_resource(
+            TrajectoryRecorder::create(&path, &[OrganismType::Blue])
+                .unwrap()
+                .with_episode(7),
+        );
+        for i in 0..3 {
+            OrganismFactory::spawn(
+                &mut app.world,
+                OrganismType::Blue,
+                Position::new(i as f32 * 10.0, 0.0),
+            );
+        }
+        OrganismFactory::spawn(&mut app.world, OrganismType::Red, Position::zero());
+
+        app.update();
+        app.update();
+        app.world
+            .resource_mut::<TrajectoryRecorder>()
+            .flush()
+            .unwrap();
+
+        let records = read_dataset(&path).unwrap();
+        assert_eq!(records.len(), 6);
+        assert!(
+            records
+                .iter()
+                .all(|record| record.species == OrganismType::Blue)
+        );
+        assert!(records.iter().all(|record| record.episode == 7));
+        assert_eq!(records[0].tick, 0);
+        assert_eq!(records[5].tick, 1);
+        assert!(records[0].observation.visible_entities.len() >= 2);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_records_observations_from_before_physics() {
+        let path = temp_path("physics");
+        let mut app = App::new();
+        app.add_plugins((PhysicsPlugin::default(), AiRuntimePlugin::default()))
+            .configure_sets(Update, AiSet.before(PhysicsSet))
+            .insert_resource(TrajectoryRecorder::create(&path, &[OrganismType::Blue]).unwrap());
+        let blue = OrganismFactory::spawn(&mut app.world, OrganismType::Blue, Position::zero());
+        OrganismFactory::spawn(
+            &mut app.world,
+            OrganismType::Plant,
+            Position::new(40.0, 0.0),
+        );
+
+        app.update();
+        app.world
+            .resource_mut::<TrajectoryRecorder>()
+            .flush()
+            .unwrap();
+
+        // The blue heads for the plant this tick, but the record holds the
+        // position it decided from
+        assert_ne!(
+            app.world.get::<Position>(blue).unwrap().to_vec2(),
+            Vec2::ZERO
+        );
+        let records = read_dataset(&path).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(
+            records[0].observation.self_state.current_position,
+            Vec2::ZERO
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,195 @@
+//! Which policy drives which species
+
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use bevy_ecs::prelude::*;
+use bevy_utils::HashMap;
+use ecosystem_ai_shared::{
+    ActionSpace, InferenceBackend, ModelFileError, ObservationEncoder, PolicyModel,
+    import_onnx_policy, load_policy,
+};
+use ecosystem_components::prelude::OrganismType;
+
+use crate::assignment::PolicyVariant;
+
+/// Loaded policies, one primary policy per species plus optional variants
+///
+/// Species without a policy keep whatever `ActionCommand` other systems give
+/// them, so the registry can be filled gradually. Organisms run the variant
+/// named by their [`PolicyVariant`], or the primary policy if that variant
+/// is not loaded; the plain accessors below all refer to the primary.
+///
+/// Burn modules are `Send` but not `Sync`, so each policy sits behind its own
+/// mutex; only the inference system locks them, once per tick.
+#[derive(Resource, Default)]
+pub struct PolicyRegistry {
+    policies: HashMap<(OrganismType, PolicyVariant), LoadedPolicy>,
+    /// Bumped by every assignment
+    generation: u64,
+}
+
+struct LoadedPolicy {
+    /// Registry generation the model was assigned in
+    generation: u64,
+    model: Mutex<PolicyModel<InferenceBackend>>,
+}
+
+impl PolicyRegistry {
+    /// Assign a policy to a species after checking it was trained for it and
+    /// fits the encoder and action space inference will use
+    pub fn assign(
+        &mut self,
+        species: OrganismType,
+        model: PolicyModel<InferenceBackend>,
+        encoder: &ObservationEncoder,
+        action_space: &ActionSpace,
+    ) -> Result<(), ModelFileError> {
+        self.assign_variant(
+            species,
+            PolicyVariant::PRIMARY,
+            model,
+            encoder,
+            action_space,
+        )
+    }
+
+    /// Assign a policy to one variant of a species, e.g. a challenger to
+    /// compete with the primary policy, with the checks of
+    /// [`PolicyRegistry::assign`]
+    pub fn assign_variant(
+        &mut self,
+        species: OrganismType,
+        variant: PolicyVariant,
+        model: PolicyModel<InferenceBackend>,
+        encoder: &ObservationEncoder,
+        action_space: &ActionSpace,
+    ) -> Result<(), ModelFileError> {
+        model.metadata.expect_species(species)?;
+        model.validate(encoder, action_space)?;
+        self.generation += 1;
+        self.policies.insert(
+            (species, variant),
+            LoadedPolicy {
+                generation: self.generation,
+                model: Mutex::new(model),
+            },
+        );
+        Ok(())
+    }
+
+    /// Load a model file and assign it to the species it was trained for
+    pub fn load(
+        &mut self,
+        path: &Path,
+        encoder: &ObservationEncoder,
+        action_space: &ActionSpace,
+    ) -> Result<OrganismType, ModelFileError> {
+        self.load_variant(path, PolicyVariant::PRIMARY, encoder, action_space)
+    }
+
+    /// Load a model file as `variant` of the species it was trained for
+    pub fn load_variant(
+        &mut self,
+        path: &Path,
+        variant: PolicyVariant,
+        encoder: &ObservationEncoder,
+        action_space: &ActionSpace,
+    ) -> Result<OrganismType, ModelFileError> {
+        let model = load_policy(path, encoder, action_space, &Default::default())?;
+        let species = model.metadata.species;
+        self.assign_variant(species, variant, model, encoder, action_space)?;
+        Ok(species)
+    }
+
+    /// Load an ONNX policy (see [`ecosystem_ai_shared::onnx`]) for `species`,
+    /// checking its shapes against the encoder and action space
+    pub fn load_onnx(
+        &mut self,
+        path: &Path,
+        species: OrganismType,
+        encoder: &ObservationEncoder,
+        action_space: &ActionSpace,
+    ) -> Result<(), ModelFileError> {
+        let model = import_onnx_policy(path, species, encoder, action_space, &Default::default())?;
+        self.assign(species, model, encoder, action_space)
+    }
+
+    pub fn remove(&mut self, species: OrganismType) -> Option<PolicyModel<InferenceBackend>> {
+        self.remove_variant(species, PolicyVariant::PRIMARY)
+    }
+
+    pub fn remove_variant(
+        &mut self,
+        species: OrganismType,
+        variant: PolicyVariant,
+    ) -> Option<PolicyModel<InferenceBackend>> {
+        self.policies.remove(&(species, variant)).map(|loaded| {
+            loaded
+                .model
+                .into_inner()
+                .unwrap_or_else(PoisonError::into_inner)
+        })
+    }
+
+    pub fn get(
+        &self,
+        species: OrganismType,
+    ) -> Option<MutexGuard<'_, PolicyModel<InferenceBackend>>> {
+        self.get_variant(species, PolicyVariant::PRIMARY)
+    }
+
+    pub fn get_variant(
+        &self,
+        species: OrganismType,
+        variant: PolicyVariant,
+    ) -> Option<MutexGuard<'_, PolicyModel<InferenceBackend>>> {
+        self.policies
+            .get(&(species, variant))
+            .map(|loaded| loaded.model.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    /// Generation in which a policy was assigned; it changes whenever the
+    /// policy is replaced, even by a model with the same provenance
+    pub fn generation(&self, species: OrganismType, variant: PolicyVariant) -> Option<u64> {
+        self.policies
+            .get(&(species, variant))
+            .map(|loaded| loaded.generation)
+    }
+
+    pub fn contains(&self, species: OrganismType) -> bool {
+        self.contains_variant(species, PolicyVariant::PRIMARY)
+    }
+
+    pub fn contains_variant(&self, species: OrganismType, variant: PolicyVariant) -> bool {
+        self.policies.contains_key(&(species, variant))
+    }
+
+    /// Species with at least one loaded policy
+    pub fn species(&self) -> impl Iterator<Item = OrganismType> + '_ {
+        OrganismType::all()
+            .iter()
+            .copied()
+            .filter(|species| self.policies.keys().any(|(kind, _)| kind == species))
+    }
+
+    /// Loaded variants of `species`, in ascending order
+    pub fn variants(&self, species: OrganismType) -> Vec<PolicyVariant> {
+        let mut variants: Vec<PolicyVariant> = self
+            .policies
+            .keys()
+            .filter(|(kind, _)| *kind == species)
+            .map(|(_, variant)| *variant)
+            .collect();
+        variants.sort();
+        variants
+    }
+
+    /// The variant an organism assigned `variant` actually runs: `variant`
+    /// itself if loaded, else the primary policy if loaded
+    pub fn resolve(&self, species: OrganismType, variant: PolicyVariant) -> Option<PolicyVariant> {
+        [variant, PolicyVariant::PRIMARY]
+            .into_iter()
+            .find(|variant| self.contains_variant(species, *variant))
+    }
+}
@@ -0,0 +1,266 @@
+//! Decision-rate throttling and AI level of detail
+//!
+//! Organisms do not need a fresh decision every tick. Each species has a
+//! decision interval; entities of a species are staggered by their index so
+//! the same share of them decides every tick. Between decisions the last
+//! `ActionCommand` stays in place. Optionally, organisms with no other agent
+//! nearby decide even less often.
+
+use bevy_ecs::prelude::*;
+use bevy_utils::{HashMap, HashSet};
+use ecosystem_components::prelude::*;
+
+/// How often an organism of a species picks a new action
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecisionInterval {
+    Never,
+    /// Every `n` ticks; `Every(1)` decides every tick
+    Every(u32),
+}
+
+/// Slow down decisions of organisms far from any other agent
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DistanceLod {
+    /// Nearest other agent further than this counts as far
+    pub distance: f32,
+    /// The species interval is multiplied by this while far
+    pub multiplier: u32,
+}
+
+/// Per-species decision intervals
+#[derive(Resource, Debug, Clone, PartialEq)]
+pub struct DecisionRates {
+    pub blue: DecisionInterval,
+    pub red: DecisionInterval,
+    pub plant: DecisionInterval,
+    pub far_from_others: Option<DistanceLod>,
+}
+
+impl Default for DecisionRates {
+    fn default() -> Self {
+        Self {
+            blue: DecisionInterval::Every(1),
+            red: DecisionInterval::Every(1),
+            plant: DecisionInterval::Never,
+            far_from_others: None,
+        }
+    }
+}
+
+impl DecisionRates {
+    pub fn interval(&self, species: OrganismType) -> DecisionInterval {
+        match species {
+            OrganismType::Blue => self.blue,
+            OrganismType::Red => self.red,
+            OrganismType::Plant => self.plant,
+        }
+    }
+
+    pub fn with_interval(mut self, species: OrganismType, interval: DecisionInterval) -> Self {
+        match species {
+            OrganismType::Blue => self.blue = interval,
+            OrganismType::Red => self.red = interval,
+            OrganismType::Plant => self.plant = interval,
+        }
+        self
+    }
+
+    pub fn with_distance_lod(mut self, lod: DistanceLod) -> Self {
+        self.far_from_others = Some(lod);
+        self
+    }
+}
+
+/// Entities due for a decision this tick; brain systems skip everyone else
+#[derive(Resource, Debug, Clone, Default)]
+pub struct DecisionTicks {
+    tick: u64,
+    next_tick: u64,
+    due: HashSet<Entity>,
+}
+
+impl DecisionTicks {
+    /// Tick the current set of due entities was computed for
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    pub fn is_due(&self, entity: Entity) -> bool {
+        self.due.contains(&entity)
+    }
+
+    pub fn due_count(&self) -> usize {
+        self.due.len()
+    }
+}
+
+/// Agents bucketed into square cells as wide as the LOD distance, so only
+/// the 3x3 cells around a position can hold an agent within that distance
+struct AgentGrid {
+    cell_size: f32,
+    cells: HashMap<(i32, i32), Vec<(Entity, Vec2)>>,
+}
+
+impl AgentGrid {
+    fn new(cell_size: f32, agents: impl Iterator<Item = (Entity, Vec2)>) -> Self {
+        let mut grid = Self {
+            cell_size: cell_size.max(f32::EPSILON),
+            cells: HashMap::default(),
+        };
+        for (entity, position) in agents {
+            let cell = grid.cell(position);
+            grid.cells.entry(cell).or_default().push((entity, position));
+        }
+        grid
+    }
+
+    fn cell(&self, position: Vec2) -> (i32, i32) {
+        let cell = (position / self.cell_size).floor();
+        (cell.x as i32, cell.y as i32)
+    }
+
+    /// Whether an agent other than `entity` is within `distance` of `position`
+    fn has_neighbor(&self, entity: Entity, position: Vec2, distance: f32) -> bool {
+        let (x, y) = self.cell(position);
+        (x - 1..=x + 1)
+            .flat_map(|x| (y - 1..=y + 1).map(move |y| (x, y)))
+            .filter_map(|cell| self.cells.get(&cell))
+            .flatten()
+            .any(|(other, other_position)| {
+                *other != entity && position.distance(*other_position) <= distance
+            })
+    }
+}
+
+/// Work out which entities decide this tick; runs first in [`crate::AiSet`]
+pub fn schedule_decisions(
+    rates: Res<DecisionRates>,
+    mut decisions: ResMut<DecisionTicks>,
+    organisms: Query<(Entity, &OrganismType, &Position), With<Alive>>,
+) {
+    let tick = decisions.next_tick;
+    decisions.tick = tick;
+    decisions.next_tick += 1;
+    let grid = rates.far_from_others.map(|lod| {
+        AgentGrid::new(
+            lod.distance,
+            organisms
+                .iter()
+                .filter(|(_, organism_type, _)| {
+                    rates.interval(**organism_type) != DecisionInterval::Never
+                })
+                .map(|(entity, _, position)| (entity, position.to_vec2())),
+        )
+    });
+
+    decisions.due.clear();
+    for (entity, organism_type, position) in &organisms {
+        let DecisionInterval::Every(interval) = rates.interval(*organism_type) else {
+            continue;
+        };
+        let mut interval = interval.max(1) as u64;
+        if let (Some(lod), Some(grid)) = (rates.far_from_others, &grid)
+            && !grid.has_neighbor(entity, position.to_vec2(), lod.distance)
+        {
+            interval *= lod.multiplier.max(1) as u64;
+        }
+        if (tick + entity.index() as u64).is_multiple_of(interval) {
+            decisions.due.insert(entity);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn due_counts(world: &mut World, ticks: usize) -> Vec<Vec<Entity>> {
+        let mut schedule = Schedule::default();
+        schedule.add_systems(schedule_decisions);
+        (0..ticks)
+            .map(|_| {
+                schedule.run(world);
+                let decisions = world.resource::<DecisionTicks>();
+                let mut due: Vec<Entity> = decisions.due.iter().copied().collect();
+                due.sort();
+                due
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_intervals_are_staggered() {
+        let mut world = World::new();
+        world.insert_resource(
+            DecisionRates::default().with_interval(OrganismType::Blue, DecisionInterval::Every(2)),
+        );
+        world.init_resource::<DecisionTicks>();
+        let blues: Vec<Entity> = (0..4)
+            .map(|i| {
+                OrganismFactory::spawn(&mut world, OrganismType::Blue, Position::new(i as f32, 0.0))
+            })
+            .collect();
+        let red = OrganismFactory::spawn(&mut world, OrganismType::Red, Position::zero());
+        let plant = OrganismFactory::spawn(&mut world, OrganismType::Plant, Position::zero());
+
+        let ticks = due_counts(&mut world, 4);
+        for due in &ticks {
+            assert_eq!(
+                due.iter().filter(|entity| blues.contains(entity)).count(),
+                2
+            );
+            assert!(due.contains(&red));
+            assert!(!due.contains(&plant));
+        }
+        // Every blue decides exactly once per interval
+        for blue in &blues {
+            assert_eq!(
+                ticks[..2].iter().filter(|due| due.contains(blue)).count(),
+                1
+            );
+        }
+    }
+
+    #[test]
+    fn test_isolated_organisms_decide_less_often() {
+        let mut world = World::new();
+        world.insert_resource(DecisionRates::default().with_distance_lod(DistanceLod {
+            distance: 50.0,
+            multiplier: 4,
+        }));
+        world.init_resource::<DecisionTicks>();
+        let near_a = OrganismFactory::spawn(&mut world, OrganismType::Blue, Position::zero());
+        let near_b =
+            OrganismFactory::spawn(&mut world, OrganismType::Red, Position::new(10.0, 0.0));
+        let far = OrganismFactory::spawn(&mut world, OrganismType::Blue, Position::new(400.0, 0.0));
+
+        let ticks = due_counts(&mut world, 8);
+        let count = |entity| ticks.iter().filter(|due| due.contains(&entity)).count();
+        assert_eq!(count(near_a), 8);
+        assert_eq!(count(near_b), 8);
+        assert_eq!(count(far), 2);
+    }
+
+    #[test]
+    fn test_grid_matches_brute_force() {
+        let mut world = World::new();
+        let agents: Vec<(Entity, Vec2)> = (0..200)
+            .map(|i| {
+                let position = Vec2::new(
+                    ((i * 37) % 101) as f32 * 7.3 - 350.0,
+                    ((i * 53) % 89) as f32 * 8.1 - 360.0,
+                );
+                (world.spawn_empty().id(), position)
+            })
+            .collect();
+        for distance in [5.0, 30.0, 120.0] {
+            let grid = AgentGrid::new(distance, agents.iter().copied());
+            for (entity, position) in &agents {
+                let brute = agents
+                    .iter()
+                    .any(|(other, at)| other != entity && position.distance(*at) <= distance);
+                assert_eq!(grid.has_neighbor(*entity, *position, distance), brute);
+            }
+        }
+    }
+}
@@ -0,0 +1,145 @@
+//! Discrete action space mapping network outputs to `ActionCommand`s
+
+use std::f32::consts::TAU;
+
+use ecosystem_components::prelude::*;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// Rest plus `directions` evenly spaced unit move directions
+///
+/// Index 0 is `Rest`; index `i > 0` moves at angle `TAU * (i - 1) / directions`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "RawActionSpace")]
+pub struct ActionSpace {
+    pub directions: usize,
+}
+
+/// Deserialized form, clamped through `ActionSpace::new`
+#[derive(Deserialize)]
+#[serde(default)]
+struct RawActionSpace {
+    directions: usize,
+}
+
+impl Default for RawActionSpace {
+    fn default() -> Self {
+        Self {
+            directions: ActionSpace::default().directions,
+        }
+    }
+}
+
+impl From<RawActionSpace> for ActionSpace {
+    fn from(raw: RawActionSpace) -> Self {
+        Self::new(raw.directions)
+    }
+}
+
+impl ActionSpace {
+    pub fn new(directions: usize) -> Self {
+        Self {
+            directions: directions.max(1),
+        }
+    }
+
+    /// Number of discrete actions (network output width)
+    pub fn size(&self) -> usize {
+        self.directions + 1
+    }
+
+    /// Human-readable description stored alongside trained models
+    pub fn describe(&self) -> String {
+        format!("discrete{}:rest+move{}", self.size(), self.directions)
+    }
+
+    /// Unit direction for a move action index
+    pub fn direction(&self, index: usize) -> Option<Vec2> {
+        if index == 0 || index > self.directions {
+            return None;
+        }
+        let angle = TAU * (index - 1) as f32 / self.directions as f32;
+        Some(Vec2::new(angle.cos(), angle.sin()))
+    }
+
+    /// Convert an action index into a command (out-of-range indices rest)
+    pub fn to_command(&self, index: usize) -> ActionCommand {
+        match self.direction(index) {
+            Some(direction) => ActionCommand::move_to(direction),
+            None => ActionCommand::rest(),
+        }
+    }
+
+    /// Nearest action index for an arbitrary command
+    pub fn to_index(&self, command: &ActionCommand) -> usize {
+        match command.action_type {
+            ActionType::Rest => 0,
+            ActionType::Move(direction) if direction == Vec2::ZERO => 0,
+            ActionType::Move(direction) => {
+                let angle = direction.y.atan2(direction.x).rem_euclid(TAU);
+                let sector = (angle / TAU * self.directions as f32).round() as usize;
+                sector % self.directions + 1
+            }
+        }
+    }
+}
+
+impl Default for ActionSpace {
+    fn default() -> Self {
+        Self::new(8)
+    }
+}
+
+/// Draw an action index from a probability row
+pub fn sample_index<R: Rng + ?Sized>(probabilities: &[f32], rng: &mut R) -> usize {
+    let mut remaining = rng.r#gen::<f32>() * probabilities.iter().sum::<f32>();
+    for (index, probability) in probabilities.iter().enumerate() {
+        remaining -= probability;
+        if remaining <= 0.0 {
+            return index;
+        }
+    }
+    probabilities.len().saturating_sub(1)
+}
+
+/// Most likely action index (first one on ties)
+pub fn greedy_index(probabilities: &[f32]) -> usize {
+    probabilities
+        .iter()
+        .enumerate()
+        .fold((0, f32::NEG_INFINITY), |best, (index, probability)| {
+            if *probability > best.1 {
+                (index, *probability)
+            } else {
+                best
+            }
+        })
+        .0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let space = ActionSpace::default();
+        assert_eq!(space.size(), 9);
+        for index in 0..space.size() {
+            assert_eq!(space.to_index(&space.to_command(index)), index);
+        }
+        assert_eq!(space.to_command(42), ActionCommand::rest());
+        assert_eq!(
+            space.to_index(&ActionCommand::move_to(Vec2::new(1.0, 0.1))),
+            1
+        );
+    }
+
+    #[test]
+    fn test_deserialize_clamps_directions() {
+        let space: ActionSpace = serde_json::from_str(r#"{"directions":0}"#).unwrap();
+        assert_eq!(space, ActionSpace::new(1));
+        let space: ActionSpace = serde_json::from_str("{}").unwrap();
+        assert_eq!(space, ActionSpace::default());
+    }
+}
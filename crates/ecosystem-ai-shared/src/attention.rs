@@ -0,0 +1,242 @@
+//! Attention over the visible entities of an observation
+//!
+//! Each entity slot of the encoded observation becomes a token built from a
+//! type embedding plus a projection of its relative position. Tokens attend
+//! to each other, then a query derived from the organism's own state pools
+//! them into a fixed-size summary. Empty slots are masked out everywhere, and
+//! no weight depends on the number of slots, so a model can run with more or
+//! fewer slots than it was trained with.
+
+use burn::nn::{Linear, LinearConfig};
+use burn::prelude::*;
+use burn::tensor::activation;
+
+use crate::encoding::SLOT_FEATURES;
+
+/// Slot layout: present flag, three type one-hots, relative x/y and distance
+const TYPE_FEATURES: usize = 3;
+const POSITION_FEATURES: usize = 3;
+
+/// Added to attention scores of empty slots
+const MASKED: f32 = -1e9;
+
+/// Settings of an [`EntityAttention`] block
+#[derive(Config, Debug, PartialEq)]
+pub struct AttentionConfig {
+    /// Width of entity tokens and of the pooled summary
+    #[config(default = 32)]
+    pub embed_size: usize,
+}
+
+impl AttentionConfig {
+    /// `input_size` is the encoded observation length, which fixes the slot
+    /// count once the leading `self_features` are taken off
+    pub fn init<B: Backend>(
+        &self,
+        input_size: usize,
+        self_features: usize,
+        device: &B::Device,
+    ) -> EntityAttention<B> {
+        let size = self.embed_size;
+        let linear = |input| LinearConfig::new(input, size).init(device);
+        EntityAttention {
+            self_embedding: linear(self_features),
+            type_embedding: LinearConfig::new(TYPE_FEATURES, size)
+                .with_bias(false)
+                .init(device),
+            position_embedding: linear(POSITION_FEATURES),
+            query: linear(size),
+            key: linear(size),
+            value: linear(size),
+            pool_query: linear(size),
+            pool_key: linear(size),
+            pool_value: linear(size),
+            slots: input_size.saturating_sub(self_features) / SLOT_FEATURES,
+        }
+    }
+
+    /// Width of the features handed on to the rest of the network
+    pub fn output_size(&self) -> usize {
+        2 * self.embed_size
+    }
+}
+
+/// Self-attention over entity slots, pooled by a self-state query
+#[derive(Module, Debug)]
+pub struct EntityAttention<B: Backend> {
+    self_embedding: Linear<B>,
+    type_embedding: Linear<B>,
+    position_embedding: Linear<B>,
+    query: Linear<B>,
+    key: Linear<B>,
+    value: Linear<B>,
+    pool_query: Linear<B>,
+    pool_key: Linear<B>,
+    pool_value: Linear<B>,
+    slots: usize,
+}
+
+impl<B: Backend> EntityAttention<B> {
+    /// Input length at the slot count the block was built for; `forward`
+    /// takes any other slot count as well
+    pub fn input_size(&self) -> usize {
+        self.self_features() + self.slots * SLOT_FEATURES
+    }
+
+    /// Length of the self block embedded into the pooling query
+    pub fn self_features(&self) -> usize {
+        self.self_embedding.weight.dims()[0]
+    }
+
+    pub fn embed_size(&self) -> usize {
+        self.query.weight.dims()[0]
+    }
+
+    /// Multiply-adds of one forward pass with every slot filled
+    pub fn operations(&self) -> usize {
+        let size = self.embed_size();
+        let own = (self.self_features() + size) * size;
+        let per_slot = (TYPE_FEATURES + POSITION_FEATURES) * size
+            // Query, key and value, then the pooling key and value
+            + 5 * size * size
+            // Scores against and mixing of every other slot, then pooling
+            + 2 * (self.slots + 1) * size;
+        own + self.slots * per_slot
+    }
+
+    /// `[batch, self_features + n * SLOT_FEATURES]` encoded observations, for
+    /// any slot count `n`, to `[batch, 2 * embed_size]`: the self embedding
+    /// followed by the pooled entity summary
+    pub fn forward(&self, observations: Tensor<B, 2>) -> Tensor<B, 2> {
+        let [rows, width] = observations.dims();
+        let size = self.embed_size();
+        let scale = (size as f32).sqrt();
+        let self_features = self.self_features();
+        let slot_count = width.saturating_sub(self_features) / SLOT_FEATURES;
+
+        let own = activation::tanh(self.self_embedding.forward(observations.clone().narrow(
+            1,
+            0,
+            self_features,
+        )));
+        if slot_count == 0 {
+            return Tensor::cat(vec![own.clone(), own.zeros_like()], 1);
+        }
+
+        let slots = observations
+            .narrow(1, self_features, slot_count * SLOT_FEATURES)
+            .reshape([rows, slot_count, SLOT_FEATURES]);
+        let present = slots.clone().narrow(2, 0, 1).reshape([rows, 1, slot_count]);
+        let tokens = activation::tanh(
+            self.type_embedding
+                .forward(slots.clone().narrow(2, 1, TYPE_FEATURES))
+                + self.position_embedding.forward(slots.narrow(
+                    2,
+                    1 + TYPE_FEATURES,
+                    POSITION_FEATURES,
+                )),
+        );
+        let key_bias = (present.clone() - 1.0) * -MASKED;
+
+        // Entities attend to each other
+        let scores = self
+            .query
+            .forward(tokens.clone())
+            .matmul(self.key.forward(tokens.clone()).swap_dims(1, 2))
+            / scale
+            + key_bias.clone();
+        let attended = activation::softmax(scores, 2).matmul(self.value.forward(tokens.clone()));
+        let tokens = tokens + attended;
+
+        // The organism's own state asks what matters among them
+        let query = self
+            .pool_query
+            .forward(own.clone())
+            .reshape([rows, 1, size]);
+        let scores =
+            query.matmul(self.pool_key.forward(tokens.clone()).swap_dims(1, 2)) / scale + key_bias;
+        let pooled = activation::softmax(scores, 2)
+            .matmul(self.pool_value.forward(tokens))
+            .reshape([rows, size]);
+        // Nothing visible: all weights were masked, so drop the summary
+        let any_visible = present.reshape([rows, slot_count]).max_dim(1);
+
+        Tensor::cat(vec![own, pooled * any_visible], 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoding::SELF_FEATURES;
+    use crate::{InferenceBackend, seeded_init};
+
+    fn slot(kind: usize, x: f32, y: f32) -> [f32; SLOT_FEATURES] {
+        let mut slot = [0.0; SLOT_FEATURES];
+        slot[0] = 1.0;
+        slot[1 + kind] = 1.0;
+        slot[4] = x;
+        slot[5] = y;
+        slot[6] = (x * x + y * y).sqrt();
+        slot
+    }
+
+    fn run(attention: &EntityAttention<InferenceBackend>, row: Vec<f32>) -> Vec<f32> {
+        let width = row.len();
+        attention
+            .forward(Tensor::from_data(
+                TensorData::new(row, [1, width]),
+                &Default::default(),
+            ))
+            .into_data()
+            .to_vec::<f32>()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_slot_order_and_padding_do_not_matter() {
+        let config = AttentionConfig::new().with_embed_size(8);
+        let device = Default::default();
+        let four = seeded_init(
+            config.init::<InferenceBackend>(
+                SELF_FEATURES + 4 * SLOT_FEATURES,
+                SELF_FEATURES,
+                &device,
+            ),
+            2,
+        );
+        let two = seeded_init(
+            config.init::<InferenceBackend>(
+                SELF_FEATURES + 2 * SLOT_FEATURES,
+                SELF_FEATURES,
+                &device,
+            ),
+            2,
+        );
+        let own = [0.5, 0.8, 0.1, -0.2];
+        let a = slot(0, 0.3, 0.1);
+        let b = slot(1, -0.4, 0.6);
+        let row = |slots: &[[f32; SLOT_FEATURES]], total: usize| {
+            let mut row = own.to_vec();
+            for slot in slots {
+                row.extend_from_slice(slot);
+            }
+            row.resize(SELF_FEATURES + total * SLOT_FEATURES, 0.0);
+            row
+        };
+
+        let reference = run(&two, row(&[a, b], 2));
+        let close = |other: Vec<f32>| {
+            reference
+                .iter()
+                .zip(&other)
+                .all(|(x, y)| (x - y).abs() < 1e-5)
+        };
+        assert!(close(run(&two, row(&[b, a], 2))));
+        assert!(close(run(&four, row(&[a, b], 4))));
+
+        // Nothing visible leaves only the self embedding
+        let empty = run(&four, row(&[], 4));
+        assert!(empty[8..].iter().all(|value| *value == 0.0));
+    }
+}
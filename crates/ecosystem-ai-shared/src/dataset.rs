@@ -0,0 +1,807 @@
+//! On-disk trajectory datasets
+//!
+//! Rollouts, recordings and replays all store [`TrajectoryRecord`]s, one per
+//! agent step, in one of two formats chosen by file extension:
+//!
+//! - Binary (`.traj`, the default): a header followed by chunks of records.
+//!   Every chunk header lists the episodes inside it, so opening a file only
+//!   reads headers and one episode can be loaded without decoding the rest.
+//!   A file cut short by a crash loses at most the chunk being written.
+//! - JSON lines (`.jsonl`): one record per line, for inspection and
+//!   debugging. Random access scans the whole file.
+//!
+//! ```text
+//! "ECOTRAJ\0"  u32 version
+//! chunk*:      "CHNK"  u32 records  u32 payload bytes  u32 episodes  u64 episode*  payload
+//! ```
+//!
+//! Integers and floats are little-endian. Observations are stored unencoded,
+//! so a dataset can train policies with any encoder and action space.
+//! Version 2 added the observer's body traits; version 1 files still read,
+//! with all traits zero.
+
+use std::collections::{BTreeSet, VecDeque};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use ecosystem_components::prelude::*;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+const MAGIC: &[u8; 8] = b"ECOTRAJ\0";
+const CHUNK_MAGIC: &[u8; 4] = b"CHNK";
+const VERSION: u32 = 2;
+/// Oldest binary version that can still be read
+const OLDEST_VERSION: u32 = 1;
+/// Records per binary chunk unless configured otherwise
+pub const DEFAULT_CHUNK_RECORDS: usize = 1024;
+
+/// One step of one agent
+///
+/// In JSON, `episode`, `reward` and `done` may be omitted and default to zero
+/// and false, as in plain decision logs.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrajectoryRecord {
+    #[serde(default)]
+    pub episode: u64,
+    /// `Entity::to_bits` of the agent; only unique within an episode
+    pub entity: u64,
+    pub tick: u64,
+    pub species: OrganismType,
+    pub observation: ObservationData,
+    pub action: ActionCommand,
+    /// Reward for this step (0 when the producer has no reward signal)
+    #[serde(default)]
+    pub reward: f32,
+    /// The agent's trajectory ended with this step
+    #[serde(default)]
+    pub done: bool,
+}
+
+/// Storage format of a dataset file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DatasetFormat {
+    #[default]
+    Binary,
+    JsonLines,
+}
+
+impl DatasetFormat {
+    /// JSON lines for `.jsonl` / `.json` files, binary otherwise
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("jsonl" | "json") => Self::JsonLines,
+            _ => Self::Binary,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum DatasetError {
+    #[error("dataset I/O failed for {path}: {source}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("invalid record at {path}:{line}: {source}")]
+    Parse {
+        path: PathBuf,
+        line: usize,
+        #[source]
+        source: serde_json::Error,
+    },
+    #[error("corrupt dataset {path} at byte {offset}: {reason}")]
+    Corrupt {
+        path: PathBuf,
+        offset: u64,
+        reason: &'static str,
+    },
+}
+
+/// Appends records to a dataset file
+///
+/// Binary chunks are written once full, on [`DatasetWriter::flush`] and when
+/// the writer is dropped.
+#[derive(Debug)]
+pub struct DatasetWriter {
+    path: PathBuf,
+    format: DatasetFormat,
+    file: BufWriter<File>,
+    chunk_records: usize,
+    chunk: Vec<u8>,
+    chunk_episodes: BTreeSet<u64>,
+    pending: usize,
+    records: u64,
+}
+
+impl DatasetWriter {
+    /// Create (or truncate) a dataset in the format implied by its extension
+    pub fn create(path: &Path) -> Result<Self, DatasetError> {
+        Self::create_with(path, DatasetFormat::from_path(path), DEFAULT_CHUNK_RECORDS)
+    }
+
+    /// Create (or truncate) a dataset, creating parent directories
+    pub fn create_with(
+        path: &Path,
+        format: DatasetFormat,
+        chunk_records: usize,
+    ) -> Result<Self, DatasetError> {
+        let io_error = |source| DatasetError::Io {
+            path: path.to_path_buf(),
+            source,
+        };
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            std::fs::create_dir_all(parent).map_err(io_error)?;
+        }
+        let mut file = BufWriter::new(File::create(path).map_err(io_error)?);
+        if format == DatasetFormat::Binary {
+            file.write_all(MAGIC).map_err(io_error)?;
+            file.write_all(&VERSION.to_le_bytes()).map_err(io_error)?;
+        }
+        Ok(Self {
+            path: path.to_path_buf(),
+            format,
+            file,
+            chunk_records: chunk_records.max(1),
+            chunk: Vec::new(),
+            chunk_episodes: BTreeSet::new(),
+            pending: 0,
+            records: 0,
+        })
+    }
+
+    pub fn write(&mut self, record: &TrajectoryRecord) -> Result<(), DatasetError> {
+        self.records += 1;
+        match self.format {
+            DatasetFormat::JsonLines => {
+                let result = serde_json::to_writer(&mut self.file, record)
+                    .map_err(io::Error::from)
+                    .and_then(|()| self.file.write_all(b"\n"));
+                result.map_err(|source| self.io_error(source))
+            }
+            DatasetFormat::Binary => {
+                encode_record(record, &mut self.chunk);
+                self.chunk_episodes.insert(record.episode);
+                self.pending += 1;
+                if self.pending >= self.chunk_records {
+                    self.write_chunk()?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Write any partial chunk and flush to disk
+    pub fn flush(&mut self) -> Result<(), DatasetError> {
+        self.write_chunk()?;
+        self.file.flush().map_err(|source| self.io_error(source))
+    }
+
+    /// Records written so far
+    pub fn records(&self) -> u64 {
+        self.records
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn format(&self) -> DatasetFormat {
+        self.format
+    }
+
+    fn write_chunk(&mut self) -> Result<(), DatasetError> {
+        if self.pending == 0 {
+            return Ok(());
+        }
+        let mut header = Vec::with_capacity(16 + 8 * self.chunk_episodes.len());
+        header.extend_from_slice(CHUNK_MAGIC);
+        header.extend_from_slice(&(self.pending as u32).to_le_bytes());
+        header.extend_from_slice(&(self.chunk.len() as u32).to_le_bytes());
+        header.extend_from_slice(&(self.chunk_episodes.len() as u32).to_le_bytes());
+        for episode in &self.chunk_episodes {
+            header.extend_from_slice(&episode.to_le_bytes());
+        }
+        let result = self
+            .file
+            .write_all(&header)
+            .and_then(|()| self.file.write_all(&self.chunk));
+        self.chunk.clear();
+        self.chunk_episodes.clear();
+        self.pending = 0;
+        result.map_err(|source| self.io_error(source))
+    }
+
+    fn io_error(&self, source: io::Error) -> DatasetError {
+        DatasetError::Io {
+            path: self.path.clone(),
+            source,
+        }
+    }
+}
+
+impl Drop for DatasetWriter {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+/// Location of one binary chunk
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ChunkEntry {
+    /// Offset of the payload
+    offset: u64,
+    records: u32,
+    payload_len: u32,
+    episodes: Vec<u64>,
+    /// Format version of the file the chunk belongs to
+    version: u32,
+}
+
+/// Reads a dataset file written by [`DatasetWriter`]
+///
+/// Opening a binary dataset indexes its chunks; records are decoded lazily.
+#[derive(Debug, Clone)]
+pub struct DatasetReader {
+    path: PathBuf,
+    format: DatasetFormat,
+    chunks: Vec<ChunkEntry>,
+}
+
+impl DatasetReader {
+    pub fn open(path: &Path) -> Result<Self, DatasetError> {
+        let format = DatasetFormat::from_path(path);
+        let chunks = match format {
+            DatasetFormat::Binary => index_chunks(path)?,
+            DatasetFormat::JsonLines => {
+                File::open(path).map_err(|source| DatasetError::Io {
+                    path: path.to_path_buf(),
+                    source,
+                })?;
+                Vec::new()
+            }
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            format,
+            chunks,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn format(&self) -> DatasetFormat {
+        self.format
+    }
+
+    /// Stream every record in file order
+    pub fn records(&self) -> Result<DatasetRecords, DatasetError> {
+        let file = File::open(&self.path).map_err(|source| self.io_error(source))?;
+        let source = match self.format {
+            DatasetFormat::Binary => RecordSource::Binary {
+                file: BufReader::new(file),
+                chunks: self.chunks.clone().into(),
+                decoded: VecDeque::new(),
+            },
+            DatasetFormat::JsonLines => RecordSource::JsonLines {
+                lines: BufReader::new(file).lines(),
+                line: 0,
+            },
+        };
+        Ok(DatasetRecords {
+            path: self.path.clone(),
+            source,
+            failed: false,
+        })
+    }
+
+    /// Total number of records
+    pub fn len(&self) -> Result<usize, DatasetError> {
+        match self.format {
+            DatasetFormat::Binary => Ok(self.chunks.iter().map(|c| c.records as usize).sum()),
+            DatasetFormat::JsonLines => self.records()?.try_fold(0, |count, record| {
+                record?;
+                Ok(count + 1)
+            }),
+        }
+    }
+
+    pub fn is_empty(&self) -> Result<bool, DatasetError> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Every episode id in the dataset, ascending
+    pub fn episodes(&self) -> Result<Vec<u64>, DatasetError> {
+        let episodes: BTreeSet<u64> = match self.format {
+            DatasetFormat::Binary => self
+                .chunks
+                .iter()
+                .flat_map(|chunk| chunk.episodes.iter().copied())
+                .collect(),
+            DatasetFormat::JsonLines => self
+                .records()?
+                .map(|record| record.map(|record| record.episode))
+                .collect::<Result<_, _>>()?,
+        };
+        Ok(episodes.into_iter().collect())
+    }
+
+    /// All records of one episode, in file order
+    ///
+    /// Binary datasets only decode the chunks holding the episode.
+    pub fn episode(&self, episode: u64) -> Result<Vec<TrajectoryRecord>, DatasetError> {
+        let mut records = Vec::new();
+        if self.format == DatasetFormat::JsonLines {
+            for record in self.records()? {
+                let record = record?;
+                if record.episode == episode {
+                    records.push(record);
+                }
+            }
+            return Ok(records);
+        }
+        let mut file = File::open(&self.path).map_err(|source| self.io_error(source))?;
+        for chunk in self
+            .chunks
+            .iter()
+            .filter(|chunk| chunk.episodes.binary_search(&episode).is_ok())
+        {
+            let payload = read_payload(&mut file, chunk).map_err(|source| self.io_error(source))?;
+            let mut decoded = VecDeque::new();
+            decode_chunk(&self.path, chunk, &payload, &mut decoded)?;
+            records.extend(
+                decoded
+                    .into_iter()
+                    .filter(|record| record.episode == episode),
+            );
+        }
+        Ok(records)
+    }
+
+    fn io_error(&self, source: io::Error) -> DatasetError {
+        DatasetError::Io {
+            path: self.path.clone(),
+            source,
+        }
+    }
+}
+
+/// Read every record of a dataset into memory
+pub fn read_dataset(path: &Path) -> Result<Vec<TrajectoryRecord>, DatasetError> {
+    DatasetReader::open(path)?.records()?.collect()
+}
+
+enum RecordSource {
+    Binary {
+        file: BufReader<File>,
+        chunks: VecDeque<ChunkEntry>,
+        decoded: VecDeque<TrajectoryRecord>,
+    },
+    JsonLines {
+        lines: io::Lines<BufReader<File>>,
+        line: usize,
+    },
+}
+
+/// Streaming iterator over the records of a dataset; stops after an error
+pub struct DatasetRecords {
+    path: PathBuf,
+    source: RecordSource,
+    failed: bool,
+}
+
+impl Iterator for DatasetRecords {
+    type Item = Result<TrajectoryRecord, DatasetError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let path = &self.path;
+        let result = match &mut self.source {
+            RecordSource::Binary {
+                file,
+                chunks,
+                decoded,
+            } => loop {
+                if let Some(record) = decoded.pop_front() {
+                    break Some(Ok(record));
+                }
+                let chunk = chunks.pop_front()?;
+                let decode = read_payload(file.get_mut(), &chunk)
+                    .map_err(|source| DatasetError::Io {
+                        path: path.clone(),
+                        source,
+                    })
+                    .and_then(|payload| decode_chunk(path, &chunk, &payload, decoded));
+                if let Err(error) = decode {
+                    break Some(Err(error));
+                }
+            },
+            RecordSource::JsonLines { lines, line } => loop {
+                *line += 1;
+                match lines.next()? {
+                    Err(source) => {
+                        break Some(Err(DatasetError::Io {
+                            path: path.clone(),
+                            source,
+                        }));
+                    }
+                    Ok(text) if text.trim().is_empty() => continue,
+                    Ok(text) => {
+                        break Some(serde_json::from_str(&text).map_err(|source| {
+                            DatasetError::Parse {
+                                path: path.clone(),
+                                line: *line,
+                                source,
+                            }
+                        }));
+                    }
+                }
+            },
+        };
+        self.failed = matches!(result, Some(Err(_)));
+        result
+    }
+}
+
+/// Yields items of `source` in random order, holding at most `capacity`
+///
+/// Shuffles streams too large for memory: the buffer is filled from the
+/// source and a random element is emitted each step. Larger buffers mix
+/// records from further apart in the stream.
+pub struct ShuffleBuffer<I: Iterator, R> {
+    source: I,
+    buffer: Vec<I::Item>,
+    capacity: usize,
+    rng: R,
+}
+
+impl<I: Iterator, R: Rng> ShuffleBuffer<I, R> {
+    pub fn new(source: I, capacity: usize, rng: R) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            source,
+            buffer: Vec::with_capacity(capacity),
+            capacity,
+            rng,
+        }
+    }
+}
+
+impl<I: Iterator, R: Rng> Iterator for ShuffleBuffer<I, R> {
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.buffer.len() < self.capacity {
+            match self.source.next() {
+                Some(item) => self.buffer.push(item),
+                None => break,
+            }
+        }
+        if self.buffer.is_empty() {
+            return None;
+        }
+        let index = self.rng.gen_range(0..self.buffer.len());
+        Some(self.buffer.swap_remove(index))
+    }
+}
+
+/// Read the header of every complete chunk, skipping payloads
+fn index_chunks(path: &Path) -> Result<Vec<ChunkEntry>, DatasetError> {
+    let io_error = |source| DatasetError::Io {
+        path: path.to_path_buf(),
+        source,
+    };
+    let corrupt = |offset, reason| DatasetError::Corrupt {
+        path: path.to_path_buf(),
+        offset,
+        reason,
+    };
+    let mut file = BufReader::new(File::open(path).map_err(io_error)?);
+    let length = file.get_ref().metadata().map_err(io_error)?.len();
+    let mut header = [0u8; 12];
+    file.read_exact(&mut header)
+        .map_err(|_| corrupt(0, "missing header"))?;
+    if &header[..8] != MAGIC {
+        return Err(corrupt(0, "not a trajectory dataset"));
+    }
+    let version = u32::from_le_bytes(header[8..].try_into().expect("4 bytes"));
+    if !(OLDEST_VERSION..=VERSION).contains(&version) {
+        return Err(corrupt(8, "unsupported dataset version"));
+    }
+
+    let mut chunks = Vec::new();
+    let mut offset = header.len() as u64;
+    loop {
+        let mut fixed = [0u8; 16];
+        match read_full(&mut file, &mut fixed).map_err(io_error)? {
+            0 => break,
+            16 => {}
+            // A chunk header cut short by a crash ends the dataset
+            _ => break,
+        }
+        if &fixed[..4] != CHUNK_MAGIC {
+            return Err(corrupt(offset, "missing chunk marker"));
+        }
+        let word = |at: usize| u32::from_le_bytes(fixed[at..at + 4].try_into().expect("4 bytes"));
+        let (records, payload_len, episode_count) = (word(4), word(8), word(12));
+        let mut episode_bytes = vec![0u8; episode_count as usize * 8];
+        if read_full(&mut file, &mut episode_bytes).map_err(io_error)? < episode_bytes.len() {
+            break;
+        }
+        let payload_offset = offset + 16 + episode_bytes.len() as u64;
+        if payload_offset + payload_len as u64 > length {
+            break;
+        }
+        chunks.push(ChunkEntry {
+            offset: payload_offset,
+            records,
+            payload_len,
+            episodes: episode_bytes
+                .chunks_exact(8)
+                .map(|bytes| u64::from_le_bytes(bytes.try_into().expect("8 bytes")))
+                .collect(),
+            version,
+        });
+        offset = payload_offset + payload_len as u64;
+        file.seek(SeekFrom::Start(offset)).map_err(io_error)?;
+    }
+    Ok(chunks)
+}
+
+/// Like `read_exact`, but returns how much was read before end of file
+fn read_full(reader: &mut impl Read, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(read) => filled += read,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+            Err(error) => return Err(error),
+        }
+    }
+    Ok(filled)
+}
+
+fn read_payload(file: &mut File, chunk: &ChunkEntry) -> io::Result<Vec<u8>> {
+    let mut payload = vec![0u8; chunk.payload_len as usize];
+    file.seek(SeekFrom::Start(chunk.offset))?;
+    file.read_exact(&mut payload)?;
+    Ok(payload)
+}
+
+fn decode_chunk(
+    path: &Path,
+    chunk: &ChunkEntry,
+    payload: &[u8],
+    records: &mut VecDeque<TrajectoryRecord>,
+) -> Result<(), DatasetError> {
+    let mut bytes = Bytes { data: payload };
+    for _ in 0..chunk.records {
+        let record =
+            decode_record(&mut bytes, chunk.version).ok_or_else(|| DatasetError::Corrupt {
+                path: path.to_path_buf(),
+                offset: chunk.offset + (payload.len() - bytes.data.len()) as u64,
+                reason: "invalid record",
+            })?;
+        records.push_back(record);
+    }
+    Ok(())
+}
+
+fn encode_record(record: &TrajectoryRecord, out: &mut Vec<u8>) {
+    let species = |organism: OrganismType| {
+        OrganismType::all()
+            .iter()
+            .position(|kind| *kind == organism)
+            .expect("every organism type is listed") as u8
+    };
+    let state = &record.observation.self_state;
+    out.extend_from_slice(&record.episode.to_le_bytes());
+    out.extend_from_slice(&record.entity.to_le_bytes());
+    out.extend_from_slice(&record.tick.to_le_bytes());
+    out.push(species(record.species));
+    for value in [
+        state.health_ratio,
+        state.energy_ratio,
+        state.current_position.x,
+        state.current_position.y,
+    ]
+    .into_iter()
+    .chain(state.traits.to_array())
+    {
+        out.extend_from_slice(&value.to_le_bytes());
+    }
+    out.extend_from_slice(&(record.observation.visible_entities.len() as u32).to_le_bytes());
+    for seen in &record.observation.visible_entities {
+        out.push(species(seen.organism_type));
+        out.extend_from_slice(&seen.relative_position.x.to_le_bytes());
+        out.extend_from_slice(&seen.relative_position.y.to_le_bytes());
+    }
+    match record.action.action_type {
+        ActionType::Rest => out.push(0),
+        ActionType::Move(direction) => {
+            out.push(1);
+            out.extend_from_slice(&direction.x.to_le_bytes());
+            out.extend_from_slice(&direction.y.to_le_bytes());
+        }
+    }
+    out.extend_from_slice(&record.reward.to_le_bytes());
+    out.push(record.done as u8);
+}
+
+fn decode_record(bytes: &mut Bytes, version: u32) -> Option<TrajectoryRecord> {
+    let episode = bytes.u64()?;
+    let entity = bytes.u64()?;
+    let tick = bytes.u64()?;
+    let species = bytes.species()?;
+    let (health, energy) = (bytes.f32()?, bytes.f32()?);
+    let position = bytes.vec2()?;
+    let mut observation = ObservationData::new(SelfState::new(health, energy, position));
+    if version >= 2 {
+        let mut traits = [0.0; BodyTraits::LEN];
+        for value in &mut traits {
+            *value = bytes.f32()?;
+        }
+        observation = observation.with_traits(BodyTraits::from_array(traits));
+    }
+    for _ in 0..bytes.u32()? {
+        let organism_type = bytes.species()?;
+        observation.add_observation(EntityObservation::new(organism_type, bytes.vec2()?));
+    }
+    let action = match bytes.u8()? {
+        0 => ActionCommand::rest(),
+        1 => ActionCommand::move_to(bytes.vec2()?),
+        _ => return None,
+    };
+    Some(TrajectoryRecord {
+        episode,
+        entity,
+        tick,
+        species,
+        observation,
+        action,
+        reward: bytes.f32()?,
+        done: bytes.u8()? != 0,
+    })
+}
+
+/// Little-endian cursor over a chunk payload
+struct Bytes<'a> {
+    data: &'a [u8],
+}
+
+impl Bytes<'_> {
+    fn take<const N: usize>(&mut self) -> Option<[u8; N]> {
+        let (head, rest) = self.data.split_first_chunk::<N>()?;
+        self.data = rest;
+        Some(*head)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.take::<1>().map(|[byte]| byte)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.take().map(u32::from_le_bytes)
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        self.take().map(u64::from_le_bytes)
+    }
+
+    fn f32(&mut self) -> Option<f32> {
+        self.take().map(f32::from_le_bytes)
+    }
+
+    fn vec2(&mut self) -> Option<Vec2> {
+        Some(Vec2::new(self.f32()?, self.f32()?))
+    }
+
+    fn species(&mut self) -> Option<OrganismType> {
+        OrganismType::all().get(self.u8()? as usize).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    fn record(episode: u64, tick: u64) -> TrajectoryRecord {
+        let mut observation = ObservationData::new(SelfState::new(0.5, 0.25, Vec2::new(1.0, 2.0)))
+            .with_traits(BodyTraits::from_array([90.0, 4.0, 2.0, 100.0, 0.5, 1.5]));
+        observation.add_observation(EntityObservation::new(OrganismType::Red, Vec2::X));
+        TrajectoryRecord {
+            episode,
+            entity: 42 + tick % 2,
+            tick,
+            species: OrganismType::Blue,
+            observation,
+            action: if tick.is_multiple_of(3) {
+                ActionCommand::rest()
+            } else {
+                ActionCommand::move_to(Vec2::new(-0.6, 0.8))
+            },
+            reward: tick as f32 * 0.5,
+            done: tick == 9,
+        }
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("eco-dataset-{}-{name}", std::process::id()))
+    }
+
+    #[test]
+    fn test_formats_round_trip_with_random_access() {
+        let records: Vec<_> = (0..3)
+            .flat_map(|episode| (0..10).map(move |tick| record(episode, tick)))
+            .collect();
+        for name in ["a.traj", "a.jsonl"] {
+            let path = temp_path(name);
+            let mut writer =
+                DatasetWriter::create_with(&path, DatasetFormat::from_path(&path), 7).unwrap();
+            for record in &records {
+                writer.write(record).unwrap();
+            }
+            drop(writer);
+
+            let reader = DatasetReader::open(&path).unwrap();
+            assert_eq!(read_dataset(&path).unwrap(), records);
+            assert_eq!(reader.len().unwrap(), 30);
+            assert_eq!(reader.episodes().unwrap(), vec![0, 1, 2]);
+            assert_eq!(reader.episode(1).unwrap(), records[10..20]);
+            assert!(reader.episode(7).unwrap().is_empty());
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_truncated_binary_keeps_complete_chunks() {
+        let path = temp_path("truncated.traj");
+        let mut writer = DatasetWriter::create_with(&path, DatasetFormat::Binary, 4).unwrap();
+        for tick in 0..10 {
+            writer.write(&record(0, tick)).unwrap();
+        }
+        drop(writer);
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &bytes[..bytes.len() - 5]).unwrap();
+
+        let records = read_dataset(&path).unwrap();
+        assert_eq!(records.len(), 8);
+
+        std::fs::write(&path, b"not a dataset").unwrap();
+        assert!(matches!(
+            DatasetReader::open(&path),
+            Err(DatasetError::Corrupt { .. })
+        ));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_shuffle_buffer_permutes() {
+        let shuffled: Vec<u32> =
+            ShuffleBuffer::new(0..100, 16, ChaCha8Rng::seed_from_u64(1)).collect();
+        assert_ne!(shuffled, (0..100).collect::<Vec<_>>());
+        let mut sorted = shuffled.clone();
+        sorted.sort();
+        assert_eq!(sorted, (0..100).collect::<Vec<_>>());
+        // An item can't be emitted before it entered the buffer
+        assert!(
+            shuffled
+                .iter()
+                .enumerate()
+                .all(|(i, &v)| v as usize <= i + 15)
+        );
+    }
+}
@@ -0,0 +1,216 @@
+//! Flattening `ObservationData` into fixed-size network inputs
+
+use ecosystem_components::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Bumped whenever the feature layout below changes
+pub const ENCODER_VERSION: u32 = 1;
+
+/// Self features: health ratio, energy ratio, normalized x, normalized y
+pub const SELF_FEATURES: usize = 4;
+
+/// Body trait features appended to the self features when enabled: vision
+/// range, collision radius, max speed, max energy, movement cost, regen rate
+pub const TRAIT_FEATURES: usize = BodyTraits::LEN;
+
+/// Per-slot features: present flag, blue/red/plant one-hot, relative x, relative y, distance
+pub const SLOT_FEATURES: usize = 7;
+
+/// Encodes observations into a fixed-length vector
+///
+/// Visible entities are written nearest-first into `max_visible` slots; extra
+/// entities are dropped and missing ones are zero-padded.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ObservationEncoder {
+    /// Number of visible-entity slots
+    pub max_visible: usize,
+    /// World half-extent used to normalize absolute positions
+    pub position_scale: f32,
+    /// Distance used to normalize relative positions
+    pub vision_scale: f32,
+    /// Follow the self features with the observer's raw body traits, so one
+    /// policy can condition on the body it drives
+    pub traits: bool,
+}
+
+impl ObservationEncoder {
+    pub fn new(max_visible: usize, position_scale: f32, vision_scale: f32) -> Self {
+        Self {
+            max_visible,
+            position_scale: position_scale.max(f32::EPSILON),
+            vision_scale: vision_scale.max(f32::EPSILON),
+            traits: false,
+        }
+    }
+
+    pub fn with_traits(mut self, traits: bool) -> Self {
+        self.traits = traits;
+        self
+    }
+
+    /// Same layout with `max_visible` slots, e.g. enough for every entity an
+    /// attention policy should see
+    pub fn with_max_visible(mut self, max_visible: usize) -> Self {
+        self.max_visible = max_visible;
+        self
+    }
+
+    /// Length of the self block ahead of the entity slots
+    pub fn self_size(&self) -> usize {
+        if self.traits {
+            SELF_FEATURES + TRAIT_FEATURES
+        } else {
+            SELF_FEATURES
+        }
+    }
+
+    /// Length of the encoded vector
+    pub fn input_size(&self) -> usize {
+        self.self_size() + self.max_visible * SLOT_FEATURES
+    }
+
+    /// Identifier of the feature layout; models only load against a matching schema
+    pub fn schema_id(&self) -> String {
+        let schema = format!(
+            "obs-v{}-k{}-p{}-v{}",
+            ENCODER_VERSION, self.max_visible, self.position_scale, self.vision_scale
+        );
+        if self.traits {
+            schema + "-traits"
+        } else {
+            schema
+        }
+    }
+
+    /// Name of every encoded feature, in input order (e.g. `slot0_dx`)
+    pub fn feature_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = ["health_ratio", "energy_ratio", "position_x", "position_y"]
+            .into_iter()
+            .map(str::to_string)
+            .collect();
+        if self.traits {
+            names.extend(BodyTraits::NAMES.iter().map(|name| name.to_string()));
+        }
+        for slot in 0..self.max_visible {
+            for feature in ["present", "blue", "red", "plant", "dx", "dy", "distance"] {
+                names.push(format!("slot{slot}_{feature}"));
+            }
+        }
+        names
+    }
+
+    /// Encode into a freshly allocated vector
+    pub fn encode(&self, observation: &ObservationData) -> Vec<f32> {
+        let mut features = Vec::with_capacity(self.input_size());
+        self.encode_into(observation, &mut features);
+        features
+    }
+
+    /// Append the encoding to `out` (used to build batches without reallocating)
+    pub fn encode_into(&self, observation: &ObservationData, out: &mut Vec<f32>) {
+        let state = &observation.self_state;
+        out.push(state.health_ratio);
+        out.push(state.energy_ratio);
+        out.push(state.current_position.x / self.position_scale);
+        out.push(state.current_position.y / self.position_scale);
+        if self.traits {
+            out.extend_from_slice(&state.traits.to_array());
+        }
+
+        let mut visible: Vec<&EntityObservation> = observation.visible_entities.iter().collect();
+        visible.sort_by(|a, b| a.distance().total_cmp(&b.distance()));
+
+        for slot in 0..self.max_visible {
+            match visible.get(slot) {
+                Some(seen) => {
+                    let relative = seen.relative_position / self.vision_scale;
+                    out.extend_from_slice(&[
+                        1.0,
+                        (seen.organism_type == OrganismType::Blue) as u8 as f32,
+                        (seen.organism_type == OrganismType::Red) as u8 as f32,
+                        (seen.organism_type == OrganismType::Plant) as u8 as f32,
+                        relative.x,
+                        relative.y,
+                        seen.distance() / self.vision_scale,
+                    ]);
+                }
+                None => out.extend_from_slice(&[0.0; SLOT_FEATURES]),
+            }
+        }
+    }
+}
+
+impl Default for ObservationEncoder {
+    fn default() -> Self {
+        Self::new(8, 500.0, 160.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_pads_and_orders() {
+        let encoder = ObservationEncoder::new(2, 100.0, 10.0);
+        let mut observation = ObservationData::new(SelfState::new(0.5, 1.0, Vec2::new(50.0, 0.0)));
+        observation.add_observation(EntityObservation::new(
+            OrganismType::Red,
+            Vec2::new(5.0, 0.0),
+        ));
+        observation.add_observation(EntityObservation::new(
+            OrganismType::Plant,
+            Vec2::new(0.0, 1.0),
+        ));
+
+        let encoded = encoder.encode(&observation);
+
+        assert_eq!(encoded.len(), encoder.input_size());
+        assert_eq!(&encoded[..4], &[0.5, 1.0, 0.5, 0.0]);
+        // Nearest (plant) first
+        assert_eq!(&encoded[4..11], &[1.0, 0.0, 0.0, 1.0, 0.0, 0.1, 0.1]);
+        assert_eq!(&encoded[11..15], &[1.0, 0.0, 1.0, 0.0]);
+
+        observation.clear_observations();
+        let empty = encoder.encode(&observation);
+        assert!(empty[4..].iter().all(|value| *value == 0.0));
+    }
+
+    #[test]
+    fn test_schema_id_tracks_layout() {
+        let base = ObservationEncoder::default();
+        assert_eq!(base.schema_id(), ObservationEncoder::default().schema_id());
+        assert_ne!(
+            base.schema_id(),
+            ObservationEncoder::new(4, 500.0, 160.0).schema_id()
+        );
+        assert_ne!(base.schema_id(), base.with_traits(true).schema_id());
+    }
+
+    #[test]
+    fn test_traits_follow_self_features() {
+        let encoder = ObservationEncoder::new(1, 100.0, 10.0).with_traits(true);
+        let traits = BodyTraits::from_array([120.0, 6.0, 2.5, 80.0, 0.2, 1.0]);
+        let mut observation =
+            ObservationData::new(SelfState::new(0.5, 1.0, Vec2::ZERO)).with_traits(traits);
+        observation.add_observation(EntityObservation::new(
+            OrganismType::Plant,
+            Vec2::new(0.0, 1.0),
+        ));
+
+        let encoded = encoder.encode(&observation);
+
+        assert_eq!(encoder.self_size(), SELF_FEATURES + TRAIT_FEATURES);
+        assert_eq!(encoded.len(), encoder.input_size());
+        assert_eq!(
+            &encoded[SELF_FEATURES..encoder.self_size()],
+            &traits.to_array()
+        );
+        assert_eq!(encoded[encoder.self_size()], 1.0);
+        let names = encoder.feature_names();
+        assert_eq!(names.len(), encoder.input_size());
+        assert_eq!(names[SELF_FEATURES], "vision_range");
+        assert_eq!(names[encoder.self_size() + 4], "slot0_dx");
+    }
+}
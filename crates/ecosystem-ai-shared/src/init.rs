@@ -0,0 +1,76 @@
+//! Deterministic parameter initialization
+//!
+//! Burn's initializers draw from a process-global backend RNG, which makes
+//! weights depend on whatever else touched that RNG first. Re-initializing
+//! from an explicit seed keeps training runs reproducible.
+
+use burn::module::{ModuleMapper, ParamId};
+use burn::prelude::*;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+/// Re-initialize every float parameter of `module` from `seed`
+///
+/// Matrices get Xavier-uniform values, vectors (biases) are zeroed.
+pub fn seeded_init<B: Backend, M: Module<B>>(module: M, seed: u64) -> M {
+    let mut mapper = SeededInit {
+        rng: ChaCha8Rng::seed_from_u64(seed),
+    };
+    module.map(&mut mapper)
+}
+
+struct SeededInit {
+    rng: ChaCha8Rng,
+}
+
+impl<B: Backend> ModuleMapper<B> for SeededInit {
+    fn map_float<const D: usize>(&mut self, _id: ParamId, tensor: Tensor<B, D>) -> Tensor<B, D> {
+        let shape = tensor.shape();
+        let device = tensor.device();
+        let require_grad = tensor.is_require_grad();
+
+        let count = shape.num_elements();
+        let values: Vec<f32> = if D < 2 {
+            vec![0.0; count]
+        } else {
+            let fan_in = shape.dims[0];
+            let fan_out = count / fan_in.max(1);
+            let bound = (6.0 / (fan_in + fan_out).max(1) as f32).sqrt();
+            (0..count)
+                .map(|_| self.rng.gen_range(-bound..=bound))
+                .collect()
+        };
+
+        let initialized = Tensor::from_data(TensorData::new(values, shape), &device);
+        if require_grad {
+            initialized.require_grad()
+        } else {
+            initialized
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{InferenceBackend, PolicyConfig};
+
+    #[test]
+    fn test_same_seed_same_weights() {
+        let device = Default::default();
+        let config = PolicyConfig::new(5, 3);
+        let a = seeded_init(config.init::<InferenceBackend>(&device), 7);
+        let b = seeded_init(config.init::<InferenceBackend>(&device), 7);
+        let c = seeded_init(config.init::<InferenceBackend>(&device), 8);
+
+        let input = [0.3, -0.2, 0.9, 0.1, 0.5];
+        assert_eq!(
+            a.action_probabilities(&input, &device),
+            b.action_probabilities(&input, &device)
+        );
+        assert_ne!(
+            a.action_probabilities(&input, &device),
+            c.action_probabilities(&input, &device)
+        );
+    }
+}
@@ -1,14 +1,41 @@
-pub fn add(left: u64, right: u64) -> u64 {
-    left + right
-}
+//! AI building blocks shared by the trainer and the runtime
+//!
+//! Both sides must agree exactly on how the world is perceived and encoded
+//! and on what the network outputs mean, so all of that lives here:
+//! - Perception (building `ObservationData` from the world)
+//! - Observation encoding and normalization
+//! - The discrete action space
+//! - Trajectory datasets shared by recordings, rollouts and replays
+//! - Policy network definitions (optionally with entity attention and
+//!   recurrence) and the model file format
+//! - An int8 inference path for plain MLP policies
+//! - Importing plain MLP policies from ONNX files
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+pub mod action_space;
+pub mod attention;
+pub mod dataset;
+pub mod encoding;
+pub mod init;
+pub mod model_file;
+pub mod normalizer;
+pub mod onnx;
+pub mod perception;
+pub mod policy;
+pub mod quantized;
+pub mod recurrent;
 
-    #[test]
-    fn it_works() {
-        let result = add(2, 2);
-        assert_eq!(result, 4);
-    }
-}
+pub use action_space::*;
+pub use attention::*;
+pub use dataset::*;
+pub use encoding::*;
+pub use init::*;
+pub use model_file::*;
+pub use normalizer::*;
+pub use onnx::*;
+pub use perception::*;
+pub use policy::*;
+pub use quantized::*;
+pub use recurrent::*;
+
+/// CPU backend used for inference
+pub type InferenceBackend = burn::backend::NdArray<f32>;
@@ -0,0 +1,499 @@
+//! Versioned policy model files
+//!
+//! A model file is everything the runtime needs to drive a species:
+//!
+//! ```text
+//! "ECOPOLCY"                 8-byte magic
+//! format version             u32 little-endian
+//! metadata length            u32 little-endian
+//! metadata                   JSON (ModelMetadata)
+//! weights                    burn record, full-precision MessagePack
+//! ```
+//!
+//! Loading checks the metadata against the running simulation's encoder and
+//! action space before touching the weights, so a model trained against a
+//! different observation layout is rejected with a clear error instead of
+//! silently producing garbage actions.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use burn::module::{ModuleVisitor, ParamId};
+use burn::prelude::*;
+use burn::record::{FullPrecisionSettings, NamedMpkBytesRecorder, Recorder};
+use ecosystem_components::prelude::OrganismType;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::encoding::SLOT_FEATURES;
+use crate::{ActionSpace, ObservationEncoder, PolicyConfig, PolicyNetwork, RunningNormalizer};
+
+/// Bumped whenever the container layout changes
+pub const MODEL_FORMAT_VERSION: u32 = 1;
+
+const MAGIC: &[u8; 8] = b"ECOPOLCY";
+const HEADER_LEN: usize = MAGIC.len() + 8;
+
+/// Everything known about a trained policy besides its weights
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelMetadata {
+    /// `ecosystem_components::VERSION` of the trainer that wrote the file
+    pub crate_version: String,
+    /// Schema of the observation encoder the policy was trained on
+    pub encoder_schema: String,
+    pub encoder: ObservationEncoder,
+    /// Human-readable action space (see [`ActionSpace::describe`])
+    pub action_space_description: String,
+    pub action_space: ActionSpace,
+    pub species: OrganismType,
+    pub policy: PolicyConfig,
+    /// Input statistics the policy expects observations to be normalized with
+    pub normalizer: RunningNormalizer,
+    pub run_id: String,
+    pub iteration: u32,
+    /// Training configuration, kept opaque so the runtime need not know it
+    pub hyperparameters: serde_json::Value,
+}
+
+impl ModelMetadata {
+    /// Metadata for a policy trained on `encoder` / `action_space`
+    ///
+    /// Provenance fields (`run_id`, `iteration`, `hyperparameters`) start
+    /// empty; the trainer fills them in.
+    pub fn new(
+        species: OrganismType,
+        encoder: ObservationEncoder,
+        action_space: ActionSpace,
+        policy: PolicyConfig,
+        normalizer: RunningNormalizer,
+    ) -> Self {
+        Self {
+            crate_version: ecosystem_components::VERSION.to_string(),
+            encoder_schema: encoder.schema_id(),
+            encoder,
+            action_space_description: action_space.describe(),
+            action_space,
+            species,
+            policy,
+            normalizer,
+            run_id: String::new(),
+            iteration: 0,
+            hyperparameters: serde_json::Value::Null,
+        }
+    }
+
+    /// Check that this model can run against the given encoder and action space
+    ///
+    /// Attention policies do not depend on the number of entity slots, so for
+    /// them `max_visible` may differ from training.
+    pub fn validate(
+        &self,
+        encoder: &ObservationEncoder,
+        action_space: &ActionSpace,
+    ) -> Result<(), ModelFileError> {
+        let slot_agnostic = self.policy.attention.is_some();
+        let model_schema = if slot_agnostic {
+            ObservationEncoder {
+                max_visible: encoder.max_visible,
+                ..self.encoder
+            }
+            .schema_id()
+        } else {
+            self.encoder_schema.clone()
+        };
+        if model_schema != encoder.schema_id() {
+            return Err(ModelFileError::SchemaMismatch {
+                model: self.encoder_schema.clone(),
+                expected: encoder.schema_id(),
+            });
+        }
+        if self.action_space != *action_space {
+            return Err(ModelFileError::ActionSpaceMismatch {
+                model: self.action_space_description.clone(),
+                expected: action_space.describe(),
+            });
+        }
+        let own_inputs = if slot_agnostic {
+            // Loaded attention models are resized to the runtime slot count
+            let slots = self.policy.input_size.checked_sub(self.encoder.self_size());
+            slots.is_some_and(|slots| slots.is_multiple_of(SLOT_FEATURES))
+        } else {
+            self.policy.input_size == self.encoder.input_size()
+        };
+        if !own_inputs || self.policy.action_count != self.action_space.size() {
+            return Err(ModelFileError::Corrupt(format!(
+                "network shape {}x{} does not match its own schema ({}x{})",
+                self.policy.input_size,
+                self.policy.action_count,
+                self.encoder.input_size(),
+                self.action_space.size()
+            )));
+        }
+        if (!slot_agnostic && self.policy.input_size != encoder.input_size())
+            || self.policy.action_count != action_space.size()
+        {
+            return Err(ModelFileError::ShapeMismatch {
+                model: (self.policy.input_size, self.policy.action_count),
+                expected: (encoder.input_size(), action_space.size()),
+            });
+        }
+        Ok(())
+    }
+
+    /// Check that this model was trained for `species`
+    pub fn expect_species(&self, species: OrganismType) -> Result<(), ModelFileError> {
+        if self.species == species {
+            Ok(())
+        } else {
+            Err(ModelFileError::SpeciesMismatch {
+                model: self.species,
+                expected: species,
+            })
+        }
+    }
+}
+
+/// Errors reading or writing model files
+#[derive(Debug, Error)]
+pub enum ModelFileError {
+    #[error("model file {path}: {source}")]
+    Io { path: PathBuf, source: io::Error },
+
+    #[error("not a policy model file (bad magic bytes)")]
+    NotAModel,
+
+    #[error("model file format version {found} is not supported (expected {expected})")]
+    UnsupportedVersion { found: u32, expected: u32 },
+
+    #[error(
+        "model was trained on observation schema `{model}` but the simulation uses `{expected}`"
+    )]
+    SchemaMismatch { model: String, expected: String },
+
+    #[error("model was trained with action space `{model}` but the simulation uses `{expected}`")]
+    ActionSpaceMismatch { model: String, expected: String },
+
+    #[error("model controls {model:?} but was assigned to {expected:?}")]
+    SpeciesMismatch {
+        model: OrganismType,
+        expected: OrganismType,
+    },
+
+    #[error(
+        "model network maps {} inputs to {} actions but the simulation needs {} inputs and {} actions",
+        model.0, model.1, expected.0, expected.1
+    )]
+    ShapeMismatch {
+        model: (usize, usize),
+        expected: (usize, usize),
+    },
+
+    #[error("invalid model metadata: {0}")]
+    Metadata(#[from] serde_json::Error),
+
+    #[error("corrupt model file: {0}")]
+    Corrupt(String),
+
+    #[error(transparent)]
+    Onnx(#[from] crate::onnx::OnnxError),
+}
+
+/// A validated policy ready for inference
+#[derive(Debug, Clone)]
+pub struct PolicyModel<B: Backend> {
+    pub metadata: ModelMetadata,
+    pub network: PolicyNetwork<B>,
+}
+
+impl<B: Backend> PolicyModel<B> {
+    /// Check the metadata and the network's actual shape against the encoder
+    /// and action space the caller will feed it
+    pub fn validate(
+        &self,
+        encoder: &ObservationEncoder,
+        action_space: &ActionSpace,
+    ) -> Result<(), ModelFileError> {
+        self.metadata.validate(encoder, action_space)?;
+        let shape = (self.network.input_size(), self.network.action_count());
+        let expected = (encoder.input_size(), action_space.size());
+        if shape != expected {
+            return Err(ModelFileError::ShapeMismatch {
+                model: shape,
+                expected,
+            });
+        }
+        Ok(())
+    }
+}
+
+type WeightsRecorder = NamedMpkBytesRecorder<FullPrecisionSettings>;
+
+/// Write `network` and its metadata to `path` (atomically via a temp file)
+pub fn save_policy<B: Backend>(
+    path: &Path,
+    metadata: &ModelMetadata,
+    network: &PolicyNetwork<B>,
+) -> Result<(), ModelFileError> {
+    let metadata_bytes = serde_json::to_vec(metadata)?;
+    let weights = Recorder::<B>::record(
+        &WeightsRecorder::default(),
+        network.clone().into_record(),
+        (),
+    )
+    .map_err(|err| ModelFileError::Corrupt(format!("serializing weights: {err:?}")))?;
+
+    let mut bytes = Vec::with_capacity(HEADER_LEN + metadata_bytes.len() + weights.len());
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&MODEL_FORMAT_VERSION.to_le_bytes());
+    bytes.extend_from_slice(&(metadata_bytes.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&metadata_bytes);
+    bytes.extend_from_slice(&weights);
+
+    let io_error = |source| ModelFileError::Io {
+        path: path.to_path_buf(),
+        source,
+    };
+    let staging = path.with_extension("partial");
+    fs::write(&staging, bytes).map_err(io_error)?;
+    fs::rename(&staging, path).map_err(io_error)
+}
+
+/// Read only the metadata of a model file
+pub fn read_metadata(path: &Path) -> Result<ModelMetadata, ModelFileError> {
+    let bytes = read_file(path)?;
+    Ok(split(&bytes)?.0)
+}
+
+/// Load a model, rejecting it unless it matches `encoder` and `action_space`
+pub fn load_policy<B: Backend>(
+    path: &Path,
+    encoder: &ObservationEncoder,
+    action_space: &ActionSpace,
+    device: &B::Device,
+) -> Result<PolicyModel<B>, ModelFileError> {
+    let bytes = read_file(path)?;
+    let (mut metadata, weights) = split(&bytes)?;
+    metadata.validate(encoder, action_space)?;
+    if metadata.policy.attention.is_some() {
+        // Run with the caller's slot count; no weight depends on it
+        let slots = encoder.input_size() - encoder.self_size();
+        metadata.policy.input_size = encoder.input_size();
+        metadata.normalizer = metadata.normalizer.with_passthrough(slots);
+    }
+
+    let record = WeightsRecorder::default()
+        .load(weights.to_vec(), device)
+        .map_err(|err| ModelFileError::Corrupt(format!("loading weights: {err:?}")))?;
+    // burn loads records without checking shapes, so compare every parameter
+    // against the network the metadata describes
+    let fresh = metadata.policy.init::<B>(device);
+    let expected = param_shapes(&fresh);
+    let network = fresh.load_record(record);
+    if param_shapes(&network) != expected {
+        return Err(ModelFileError::Corrupt(
+            "weights do not match the network described by the metadata".into(),
+        ));
+    }
+    let model = PolicyModel { metadata, network };
+    model.validate(encoder, action_space)?;
+    Ok(model)
+}
+
+/// Collects the dims of every float parameter in visiting order
+struct ParamShapes(Vec<Vec<usize>>);
+
+impl<B: Backend> ModuleVisitor<B> for ParamShapes {
+    fn visit_float<const D: usize>(&mut self, _id: ParamId, tensor: &Tensor<B, D>) {
+        self.0.push(tensor.dims().to_vec());
+    }
+}
+
+fn param_shapes<B: Backend>(network: &PolicyNetwork<B>) -> Vec<Vec<usize>> {
+    let mut shapes = ParamShapes(Vec::new());
+    network.visit(&mut shapes);
+    shapes.0
+}
+
+fn read_file(path: &Path) -> Result<Vec<u8>, ModelFileError> {
+    fs::read(path).map_err(|source| ModelFileError::Io {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+fn split(bytes: &[u8]) -> Result<(ModelMetadata, &[u8]), ModelFileError> {
+    if bytes.len() < HEADER_LEN || &bytes[..MAGIC.len()] != MAGIC {
+        return Err(ModelFileError::NotAModel);
+    }
+    let word = |offset: usize| {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().expect("4-byte slice"))
+    };
+    let version = word(MAGIC.len());
+    if version != MODEL_FORMAT_VERSION {
+        return Err(ModelFileError::UnsupportedVersion {
+            found: version,
+            expected: MODEL_FORMAT_VERSION,
+        });
+    }
+    let metadata_len = word(MAGIC.len() + 4) as usize;
+    let metadata_end = HEADER_LEN + metadata_len;
+    if bytes.len() < metadata_end {
+        return Err(ModelFileError::Corrupt("truncated metadata".to_string()));
+    }
+    let metadata = serde_json::from_slice(&bytes[HEADER_LEN..metadata_end])?;
+    Ok((metadata, &bytes[metadata_end..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{InferenceBackend, SELF_FEATURES, seeded_init};
+
+    fn metadata(encoder: ObservationEncoder, action_space: ActionSpace) -> ModelMetadata {
+        ModelMetadata {
+            run_id: "test".to_string(),
+            iteration: 3,
+            hyperparameters: serde_json::json!({ "lr": 0.001 }),
+            ..ModelMetadata::new(
+                OrganismType::Blue,
+                encoder,
+                action_space,
+                PolicyConfig::new(encoder.input_size(), action_space.size()).with_hidden_size(8),
+                RunningNormalizer::new(encoder.input_size(), 5.0),
+            )
+        }
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "ecosystem-model-{name}-{}.policy",
+            std::process::id()
+        ))
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let device = Default::default();
+        let encoder = ObservationEncoder::new(2, 100.0, 50.0);
+        let action_space = ActionSpace::default();
+        let meta = metadata(encoder, action_space);
+        let network = seeded_init(meta.policy.init::<InferenceBackend>(&device), 1);
+        let path = temp_path("round-trip");
+
+        save_policy(&path, &meta, &network).unwrap();
+        let loaded =
+            load_policy::<InferenceBackend>(&path, &encoder, &action_space, &device).unwrap();
+
+        assert_eq!(loaded.metadata, meta);
+        let input = vec![0.25; encoder.input_size()];
+        assert_eq!(
+            loaded.network.action_probabilities(&input, &device),
+            network.action_probabilities(&input, &device)
+        );
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_rejects_weights_that_disagree_with_metadata() {
+        let device = Default::default();
+        let encoder = ObservationEncoder::new(2, 100.0, 50.0);
+        let action_space = ActionSpace::default();
+        let meta = metadata(encoder, action_space);
+        let mut wider = meta.policy.clone();
+        wider.hidden_size *= 2;
+        let network = wider.init::<InferenceBackend>(&device);
+        let path = temp_path("wrong-weights");
+        save_policy(&path, &meta, &network).unwrap();
+
+        let err =
+            load_policy::<InferenceBackend>(&path, &encoder, &action_space, &device).unwrap_err();
+        assert!(matches!(err, ModelFileError::Corrupt(_)));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_rejects_mismatched_schema() {
+        let device = Default::default();
+        let encoder = ObservationEncoder::new(2, 100.0, 50.0);
+        let action_space = ActionSpace::default();
+        let meta = metadata(encoder, action_space);
+        let network = meta.policy.init::<InferenceBackend>(&device);
+        let path = temp_path("mismatch");
+        save_policy(&path, &meta, &network).unwrap();
+
+        let other = ObservationEncoder::new(4, 100.0, 50.0);
+        let err =
+            load_policy::<InferenceBackend>(&path, &other, &action_space, &device).unwrap_err();
+        assert!(matches!(err, ModelFileError::SchemaMismatch { .. }));
+        assert!(err.to_string().contains(&other.schema_id()));
+
+        let err = load_policy::<InferenceBackend>(&path, &encoder, &ActionSpace::new(4), &device)
+            .unwrap_err();
+        assert!(matches!(err, ModelFileError::ActionSpaceMismatch { .. }));
+        assert!(
+            read_metadata(&path)
+                .unwrap()
+                .expect_species(OrganismType::Red)
+                .is_err()
+        );
+
+        // A file whose network disagrees with its own encoder is corrupt, and
+        // the message names the file's sizes rather than the caller's
+        let mut inconsistent = meta.clone();
+        inconsistent.policy.input_size += 1;
+        let err = inconsistent.validate(&other, &action_space).unwrap_err();
+        assert!(matches!(err, ModelFileError::SchemaMismatch { .. }));
+        let err = inconsistent.validate(&encoder, &action_space).unwrap_err();
+        assert!(matches!(err, ModelFileError::Corrupt(_)));
+        let message = err.to_string();
+        assert!(message.contains(&format!(
+            "{}x{} does not match its own schema ({}x{})",
+            encoder.input_size() + 1,
+            action_space.size(),
+            encoder.input_size(),
+            action_space.size()
+        )));
+
+        fs::write(&path, b"definitely not a model").unwrap();
+        assert!(matches!(
+            read_metadata(&path),
+            Err(ModelFileError::NotAModel)
+        ));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_attention_models_accept_other_slot_counts() {
+        let device = Default::default();
+        let encoder = ObservationEncoder::new(2, 100.0, 50.0);
+        let action_space = ActionSpace::default();
+        let mut meta = metadata(encoder, action_space);
+        meta.policy = meta
+            .policy
+            .with_attention(Some(crate::AttentionConfig::new().with_embed_size(8)));
+        meta.normalizer = RunningNormalizer::new(SELF_FEATURES, 5.0)
+            .with_passthrough(encoder.input_size() - SELF_FEATURES);
+        let network = seeded_init(meta.policy.init::<InferenceBackend>(&device), 1);
+        let path = temp_path("attention");
+        save_policy(&path, &meta, &network).unwrap();
+
+        let wider = ObservationEncoder::new(6, 100.0, 50.0);
+        let loaded =
+            load_policy::<InferenceBackend>(&path, &wider, &action_space, &device).unwrap();
+        assert_eq!(loaded.network.input_size(), wider.input_size());
+        assert_eq!(loaded.metadata.normalizer.row_len(), wider.input_size());
+        let probabilities = loaded
+            .network
+            .action_probabilities(&vec![0.0; wider.input_size()], &device);
+        assert_eq!(probabilities[0].len(), action_space.size());
+
+        // Other layout changes are still rejected
+        let rescaled = ObservationEncoder::new(2, 200.0, 50.0);
+        assert!(matches!(
+            load_policy::<InferenceBackend>(&path, &rescaled, &action_space, &device),
+            Err(ModelFileError::SchemaMismatch { .. })
+        ));
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,196 @@
+//! Running mean / variance normalization of encoded observations
+
+use serde::{Deserialize, Serialize};
+
+/// Per-feature running statistics (Welford / Chan parallel update)
+///
+/// Statistics are accumulated in `f64` during training and frozen into the
+/// model file so the runtime normalizes inputs exactly like the trainer did.
+///
+/// Rows may carry `passthrough` extra features after the normalized ones;
+/// those are left untouched (e.g. entity slots read by an attention policy,
+/// whose presence flags must stay 0/1).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunningNormalizer {
+    count: f64,
+    mean: Vec<f64>,
+    m2: Vec<f64>,
+    clip: f32,
+    #[serde(default)]
+    passthrough: usize,
+}
+
+impl RunningNormalizer {
+    pub fn new(size: usize, clip: f32) -> Self {
+        Self {
+            count: 0.0,
+            mean: vec![0.0; size],
+            m2: vec![0.0; size],
+            clip: clip.abs(),
+            passthrough: 0,
+        }
+    }
+
+    /// Leave `passthrough` trailing features of every row unnormalized
+    pub fn with_passthrough(mut self, passthrough: usize) -> Self {
+        self.passthrough = passthrough;
+        self
+    }
+
+    /// Number of normalized features per row
+    pub fn size(&self) -> usize {
+        self.mean.len()
+    }
+
+    pub fn passthrough(&self) -> usize {
+        self.passthrough
+    }
+
+    /// Full row length: normalized plus passthrough features
+    pub fn row_len(&self) -> usize {
+        self.size() + self.passthrough
+    }
+
+    pub fn count(&self) -> f64 {
+        self.count
+    }
+
+    pub fn mean(&self) -> &[f64] {
+        &self.mean
+    }
+
+    /// Per-feature variance (zero until two samples were seen)
+    pub fn variance(&self) -> Vec<f64> {
+        if self.count < 2.0 {
+            return vec![0.0; self.size()];
+        }
+        self.m2.iter().map(|m2| m2 / self.count).collect()
+    }
+
+    /// Fold a row-major batch of `rows * row_len()` values into the statistics
+    pub fn update(&mut self, batch: &[f32]) {
+        let size = self.size();
+        let row_len = self.row_len();
+        if size == 0 || batch.is_empty() {
+            return;
+        }
+        let rows = batch.len() / row_len;
+        let batch_count = rows as f64;
+
+        let mut batch_mean = vec![0.0f64; size];
+        for row in batch.chunks_exact(row_len) {
+            for (mean, value) in batch_mean.iter_mut().zip(row) {
+                *mean += *value as f64;
+            }
+        }
+        batch_mean.iter_mut().for_each(|mean| *mean /= batch_count);
+
+        let mut batch_m2 = vec![0.0f64; size];
+        for row in batch.chunks_exact(row_len) {
+            for ((m2, mean), value) in batch_m2.iter_mut().zip(&batch_mean).zip(row) {
+                let delta = *value as f64 - mean;
+                *m2 += delta * delta;
+            }
+        }
+
+        let total = self.count + batch_count;
+        for i in 0..size {
+            let delta = batch_mean[i] - self.mean[i];
+            self.mean[i] += delta * batch_count / total;
+            self.m2[i] += batch_m2[i] + delta * delta * self.count * batch_count / total;
+        }
+        self.count = total;
+    }
+
+    /// Normalize a row-major batch in place
+    pub fn normalize(&self, batch: &mut [f32]) {
+        self.normalize_rows(batch, self.row_len());
+    }
+
+    /// Normalize a row-major batch of `row_len`-feature rows in place, e.g.
+    /// rows with more or fewer passthrough features than [`Self::row_len`]
+    pub fn normalize_rows(&self, batch: &mut [f32], row_len: usize) {
+        let size = self.size();
+        if size == 0 || self.count < 2.0 {
+            return;
+        }
+        let std: Vec<f64> = self
+            .variance()
+            .into_iter()
+            .map(|variance| (variance + 1e-8).sqrt())
+            .collect();
+        debug_assert!(row_len >= size);
+        for row in batch.chunks_exact_mut(row_len) {
+            for ((value, mean), std) in row.iter_mut().zip(&self.mean).zip(&std) {
+                let normalized = ((*value as f64 - mean) / std) as f32;
+                *value = normalized.clamp(-self.clip, self.clip);
+            }
+        }
+    }
+
+    /// Map a gradient with respect to one normalized row back onto the raw
+    /// `row`, in place (clipped features get zero)
+    pub fn backpropagate(&self, row: &[f32], gradient: &mut [f32]) {
+        let size = self.size();
+        if size == 0 || self.count < 2.0 {
+            return;
+        }
+        for (((gradient, value), mean), variance) in gradient
+            .iter_mut()
+            .zip(row)
+            .zip(&self.mean)
+            .zip(self.variance())
+        {
+            let std = (variance + 1e-8).sqrt();
+            let normalized = (*value as f64 - mean) / std;
+            *gradient = if normalized.abs() > self.clip as f64 {
+                0.0
+            } else {
+                (*gradient as f64 / std) as f32
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_batched_update_matches_single_pass() {
+        let data = [1.0, 10.0, 2.0, 20.0, 3.0, 30.0, 4.0, 40.0];
+        let mut whole = RunningNormalizer::new(2, 5.0);
+        whole.update(&data);
+        let mut split = RunningNormalizer::new(2, 5.0);
+        split.update(&data[..2]);
+        split.update(&data[2..]);
+
+        assert_eq!(whole.count(), 4.0);
+        assert!((whole.mean()[1] - 25.0).abs() < 1e-12);
+        for (a, b) in whole.variance().iter().zip(split.variance()) {
+            assert!((a - b).abs() < 1e-9);
+        }
+
+        let mut row = [2.5, 25.0];
+        whole.normalize(&mut row);
+        assert!(row.iter().all(|value| value.abs() < 1e-6));
+    }
+
+    #[test]
+    fn test_passthrough_features_are_untouched() {
+        let mut normalizer = RunningNormalizer::new(1, 5.0).with_passthrough(2);
+        normalizer.update(&[1.0, 0.0, 7.0, 3.0, 1.0, 9.0]);
+        assert_eq!(normalizer.mean(), &[2.0]);
+
+        let mut rows = [2.0, 1.0, 0.5];
+        normalizer.normalize(&mut rows);
+        assert_eq!(rows, [0.0, 1.0, 0.5]);
+
+        let mut gradient = [1.0, 1.0, 1.0];
+        normalizer.backpropagate(&[2.0, 1.0, 0.5], &mut gradient);
+        assert!((gradient[0] - 1.0 / normalizer.variance()[0].sqrt() as f32).abs() < 1e-4);
+        assert_eq!(&gradient[1..], &[1.0, 1.0]);
+        normalizer.backpropagate(&[100.0, 1.0, 0.5], &mut gradient);
+        assert_eq!(gradient[0], 0.0);
+    }
+}
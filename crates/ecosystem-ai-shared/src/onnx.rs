@@ -0,0 +1,1036 @@
+//! Importing policies trained elsewhere from ONNX files
+//!
+//! Only the subset of ONNX a policy MLP exports to is understood, decoded
+//! straight from the protobuf wire format:
+//! - One float observation input `[batch, input_size]`
+//! - A torso of dense layers (`Gemm`, or `MatMul` followed by `Add`), each
+//!   followed by `Tanh`
+//! - An action head (a dense layer, optionally followed by `Softmax` or
+//!   `LogSoftmax`) and optionally a value head branching off the torso
+//!
+//! The graph is converted into a [`PolicyNetwork`], so imported policies run
+//! through the same inference path as trained ones. The graph sees raw
+//! encoded observations; fold any input normalization into its first layer.
+//! Shapes are checked against the observation encoder and the action space
+//! when loading.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use burn::module::Param;
+use burn::nn::Linear;
+use burn::prelude::*;
+use ecosystem_components::prelude::OrganismType;
+
+use crate::action_space::ActionSpace;
+use crate::encoding::ObservationEncoder;
+use crate::model_file::{ModelFileError, ModelMetadata, PolicyModel};
+use crate::normalizer::RunningNormalizer;
+use crate::policy::{PolicyConfig, PolicyNetwork};
+
+/// Why an ONNX file cannot drive a species
+#[derive(Debug, thiserror::Error)]
+pub enum OnnxError {
+    #[error("malformed ONNX file: {0}")]
+    Decode(String),
+
+    #[error(
+        "unsupported ONNX operator `{op}` (node `{node}`); policies must be dense layers \
+         (Gemm, or MatMul + Add) with Tanh activations"
+    )]
+    UnsupportedOp { op: String, node: String },
+
+    #[error("ONNX tensor `{name}` has unsupported element type {data_type} (expected float32)")]
+    UnsupportedType { name: String, data_type: i32 },
+
+    #[error("ONNX graph has {0} observation inputs, expected exactly one")]
+    InputCount(usize),
+
+    #[error(
+        "ONNX input `{name}` has shape {found}, but observation schema `{schema}` encodes \
+         [batch, {expected}]"
+    )]
+    InputShape {
+        name: String,
+        found: String,
+        schema: String,
+        expected: usize,
+    },
+
+    #[error(
+        "ONNX action output has {found} values per observation, but the action space \
+         `{description}` has {expected} actions"
+    )]
+    ActionCount {
+        found: usize,
+        expected: usize,
+        description: String,
+    },
+
+    #[error("ONNX value output has {0} values per observation, expected 1")]
+    ValueShape(usize),
+
+    #[error("unsupported ONNX graph layout: {0}")]
+    Layout(String),
+}
+
+/// Load an ONNX policy for `species`, validated against the simulation's
+/// encoder and action space
+///
+/// The model metadata records the file as its run id; the normalizer is the
+/// identity.
+pub fn import_onnx_policy<B: Backend>(
+    path: &Path,
+    species: OrganismType,
+    encoder: &ObservationEncoder,
+    action_space: &ActionSpace,
+    device: &B::Device,
+) -> Result<PolicyModel<B>, ModelFileError> {
+    let bytes = std::fs::read(path).map_err(|source| ModelFileError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let layers = OnnxLayers::decode(&bytes, encoder, action_space)?;
+
+    let hidden_size = layers.torso.first().map_or(64, DenseLayer::outputs);
+    let policy = PolicyConfig::new(encoder.input_size(), action_space.size())
+        .with_hidden_size(hidden_size)
+        .with_hidden_layers(layers.torso.len());
+    let critic = layers
+        .critic
+        .unwrap_or_else(|| DenseLayer::zeros(layers.actor.inputs, 1));
+    let network = PolicyNetwork::from_linear_layers(
+        layers
+            .torso
+            .iter()
+            .map(|layer| layer.to_linear(device))
+            .collect(),
+        layers.actor.to_linear(device),
+        critic.to_linear(device),
+    );
+    let metadata = ModelMetadata {
+        run_id: path.display().to_string(),
+        hyperparameters: serde_json::json!({ "source": "onnx" }),
+        ..ModelMetadata::new(
+            species,
+            *encoder,
+            *action_space,
+            policy,
+            RunningNormalizer::new(encoder.input_size(), 5.0),
+        )
+    };
+    Ok(PolicyModel { metadata, network })
+}
+
+/// A dense layer `y = x W + b` with `W` stored `[inputs, outputs]`
+#[derive(Debug, Clone, PartialEq)]
+struct DenseLayer {
+    inputs: usize,
+    weights: Vec<f32>,
+    bias: Vec<f32>,
+    tanh: bool,
+}
+
+impl DenseLayer {
+    fn zeros(inputs: usize, outputs: usize) -> Self {
+        Self {
+            inputs,
+            weights: vec![0.0; inputs * outputs],
+            bias: vec![0.0; outputs],
+            tanh: false,
+        }
+    }
+
+    fn outputs(&self) -> usize {
+        self.bias.len()
+    }
+
+    fn to_linear<B: Backend>(&self, device: &B::Device) -> Linear<B> {
+        Linear {
+            weight: Param::from_tensor(Tensor::from_data(
+                TensorData::new(self.weights.clone(), [self.inputs, self.outputs()]),
+                device,
+            )),
+            bias: Some(Param::from_tensor(Tensor::from_data(
+                TensorData::new(self.bias.clone(), [self.outputs()]),
+                device,
+            ))),
+        }
+    }
+}
+
+/// Dense layers recovered from a policy graph
+#[derive(Debug)]
+struct OnnxLayers {
+    torso: Vec<DenseLayer>,
+    actor: DenseLayer,
+    critic: Option<DenseLayer>,
+}
+
+impl OnnxLayers {
+    fn decode(
+        bytes: &[u8],
+        encoder: &ObservationEncoder,
+        action_space: &ActionSpace,
+    ) -> Result<Self, OnnxError> {
+        let graph = Graph::decode(bytes)?;
+        let inputs: Vec<&ValueInfo> = graph
+            .inputs
+            .iter()
+            .filter(|input| !graph.constants.contains_key(&input.name))
+            .collect();
+        let [input] = inputs.as_slice() else {
+            return Err(OnnxError::InputCount(inputs.len()));
+        };
+        let input_size = encoder.input_size();
+        let shape_error = |found: String| OnnxError::InputShape {
+            name: input.name.clone(),
+            found,
+            schema: encoder.schema_id(),
+            expected: input_size,
+        };
+        if let Some(data_type) = input.elem_type.filter(|&data_type| data_type != FLOAT) {
+            return Err(OnnxError::UnsupportedType {
+                name: input.name.clone(),
+                data_type,
+            });
+        }
+        if let Some(shape) = &input.shape {
+            let matches = match shape.as_slice() {
+                [_, Dim::Fixed(features)] => *features == input_size as i64,
+                [_, Dim::Symbolic] => true,
+                _ => false,
+            };
+            if !matches {
+                return Err(shape_error(format_shape(shape)));
+            }
+        }
+
+        let consumers = graph.consumers(&input.name);
+        let [first] = consumers.as_slice() else {
+            return Err(OnnxError::Layout(format!(
+                "the input `{}` must feed exactly one layer",
+                input.name
+            )));
+        };
+        let (mut layers, end) = graph.walk(first)?;
+        let branches = graph.consumers(&end);
+        let (actor, critic) = match branches.as_slice() {
+            [] => {
+                let actor = layers
+                    .pop()
+                    .ok_or_else(|| OnnxError::Layout("the graph has no dense layer".into()))?;
+                (actor, None)
+            }
+            [first, second] => {
+                let mut heads = Vec::with_capacity(2);
+                for node in [first, second] {
+                    let (mut head, output) = graph.walk(node)?;
+                    if head.len() != 1 || !graph.is_output(&output) {
+                        return Err(OnnxError::Layout(
+                            "each head after the torso must be a single dense layer ending \
+                             in a graph output"
+                                .into(),
+                        ));
+                    }
+                    heads.push(head.remove(0));
+                }
+                let critic = heads.remove(1);
+                let actor = heads.remove(0);
+                match (actor.outputs(), critic.outputs()) {
+                    (_, 1) if actor.outputs() == action_space.size() => (actor, Some(critic)),
+                    (1, _) if critic.outputs() == action_space.size() => (critic, Some(actor)),
+                    _ if actor.outputs() == action_space.size() => {
+                        return Err(OnnxError::ValueShape(critic.outputs()));
+                    }
+                    _ if critic.outputs() == action_space.size() => {
+                        return Err(OnnxError::ValueShape(actor.outputs()));
+                    }
+                    _ => {
+                        return Err(OnnxError::ActionCount {
+                            found: actor.outputs(),
+                            expected: action_space.size(),
+                            description: action_space.describe(),
+                        });
+                    }
+                }
+            }
+            _ => {
+                return Err(OnnxError::Layout(format!(
+                    "`{end}` feeds {} nodes; expected an action head and at most one value head",
+                    branches.len()
+                )));
+            }
+        };
+
+        if layers.iter().any(|layer| !layer.tanh) {
+            return Err(OnnxError::Layout(
+                "every hidden layer must be followed by Tanh".into(),
+            ));
+        }
+        if actor.tanh || critic.as_ref().is_some_and(|critic| critic.tanh) {
+            return Err(OnnxError::Layout(
+                "the action and value heads must not have an activation".into(),
+            ));
+        }
+        if actor.outputs() != action_space.size() {
+            return Err(OnnxError::ActionCount {
+                found: actor.outputs(),
+                expected: action_space.size(),
+                description: action_space.describe(),
+            });
+        }
+        let mut width = input_size;
+        for layer in layers.iter().chain([&actor]).chain(critic.as_ref()) {
+            if layer.inputs != width {
+                return Err(if width == input_size {
+                    shape_error(format!("[batch, {}]", layer.inputs))
+                } else {
+                    OnnxError::Layout(format!(
+                        "a layer reads {} features from a layer producing {width}",
+                        layer.inputs
+                    ))
+                });
+            }
+            if layer.tanh {
+                width = layer.outputs();
+            }
+        }
+        if let Some(hidden) = layers.first().map(DenseLayer::outputs)
+            && layers.iter().any(|layer| layer.outputs() != hidden)
+        {
+            return Err(OnnxError::Layout(
+                "all hidden layers must have the same width".into(),
+            ));
+        }
+        Ok(Self {
+            torso: layers,
+            actor,
+            critic,
+        })
+    }
+}
+
+fn format_shape(shape: &[Dim]) -> String {
+    let dims: Vec<String> = shape
+        .iter()
+        .map(|dim| match dim {
+            Dim::Fixed(size) => size.to_string(),
+            Dim::Symbolic => "?".to_string(),
+        })
+        .collect();
+    format!("[{}]", dims.join(", "))
+}
+
+/// `TensorProto.DataType.FLOAT`
+const FLOAT: i32 = 1;
+
+#[derive(Debug, Default)]
+struct Graph {
+    nodes: Vec<Node>,
+    /// Initializers and `Constant` node outputs
+    constants: HashMap<String, Constant>,
+    inputs: Vec<ValueInfo>,
+    outputs: Vec<ValueInfo>,
+}
+
+#[derive(Debug, Default)]
+struct Node {
+    name: String,
+    op_type: String,
+    inputs: Vec<String>,
+    outputs: Vec<String>,
+    attributes: Vec<Attribute>,
+}
+
+#[derive(Debug, Default)]
+struct Attribute {
+    name: String,
+    float: Option<f32>,
+    int: Option<i64>,
+    tensor: Option<Constant>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Constant {
+    dims: Vec<usize>,
+    values: Vec<f32>,
+}
+
+#[derive(Debug, Default)]
+struct ValueInfo {
+    name: String,
+    elem_type: Option<i32>,
+    shape: Option<Vec<Dim>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Dim {
+    Fixed(i64),
+    Symbolic,
+}
+
+impl Node {
+    fn attribute(&self, name: &str) -> Option<&Attribute> {
+        self.attributes
+            .iter()
+            .find(|attribute| attribute.name == name)
+    }
+
+    fn int(&self, name: &str, default: i64) -> i64 {
+        self.attribute(name)
+            .and_then(|attribute| attribute.int)
+            .unwrap_or(default)
+    }
+
+    fn float(&self, name: &str, default: f32) -> f32 {
+        self.attribute(name)
+            .and_then(|attribute| attribute.float)
+            .unwrap_or(default)
+    }
+
+    fn unsupported(&self) -> OnnxError {
+        OnnxError::UnsupportedOp {
+            op: self.op_type.clone(),
+            node: self.name.clone(),
+        }
+    }
+
+    fn output(&self) -> Result<&str, OnnxError> {
+        match self.outputs.as_slice() {
+            [output] => Ok(output),
+            _ => Err(OnnxError::Layout(format!(
+                "node `{}` must have exactly one output",
+                self.name
+            ))),
+        }
+    }
+}
+
+impl Graph {
+    /// Decode a serialized `ModelProto`
+    fn decode(bytes: &[u8]) -> Result<Self, OnnxError> {
+        let mut graph = None;
+        for field in fields(bytes)? {
+            if let (7, Value::Bytes(bytes)) = field {
+                graph = Some(Self::decode_graph(bytes)?);
+            }
+        }
+        graph.ok_or_else(|| OnnxError::Decode("no graph in the model".into()))
+    }
+
+    fn decode_graph(bytes: &[u8]) -> Result<Self, OnnxError> {
+        let mut graph = Self::default();
+        for field in fields(bytes)? {
+            match field {
+                (1, Value::Bytes(bytes)) => graph.nodes.push(decode_node(bytes)?),
+                (5, Value::Bytes(bytes)) => {
+                    let (name, constant) = decode_tensor(bytes)?;
+                    graph.constants.insert(name, constant);
+                }
+                (11, Value::Bytes(bytes)) => graph.inputs.push(decode_value_info(bytes)?),
+                (12, Value::Bytes(bytes)) => graph.outputs.push(decode_value_info(bytes)?),
+                _ => {}
+            }
+        }
+        // Fold `Constant` nodes into the constants
+        let mut nodes = Vec::with_capacity(graph.nodes.len());
+        for mut node in graph.nodes {
+            if node.op_type != "Constant" {
+                nodes.push(node);
+                continue;
+            }
+            let output = node.output()?.to_string();
+            let value = node
+                .attributes
+                .iter_mut()
+                .find(|attribute| attribute.name == "value")
+                .and_then(|attribute| attribute.tensor.take())
+                .ok_or_else(|| node.unsupported())?;
+            graph.constants.insert(output, value);
+        }
+        graph.nodes = nodes;
+        Ok(graph)
+    }
+
+    fn consumers(&self, name: &str) -> Vec<&Node> {
+        self.nodes
+            .iter()
+            .filter(|node| node.inputs.iter().any(|input| input == name))
+            .collect()
+    }
+
+    fn is_output(&self, name: &str) -> bool {
+        self.outputs.iter().any(|output| output.name == name)
+    }
+
+    fn constant(&self, node: &Node, index: usize) -> Result<&Constant, OnnxError> {
+        node.inputs
+            .get(index)
+            .and_then(|name| self.constants.get(name))
+            .ok_or_else(|| {
+                OnnxError::Layout(format!(
+                    "node `{}` ({}) needs constant weights",
+                    node.name, node.op_type
+                ))
+            })
+    }
+
+    /// Follow a chain of nodes from `node` while each output feeds exactly
+    /// one node, returning the dense layers and the last output
+    fn walk<'a>(&'a self, mut node: &'a Node) -> Result<(Vec<DenseLayer>, String), OnnxError> {
+        let mut layers: Vec<DenseLayer> = Vec::new();
+        // A `MatMul` waiting for the `Add` that carries its bias
+        let mut needs_bias = false;
+        loop {
+            match node.op_type.as_str() {
+                "Gemm" => {
+                    if node.int("transA", 0) != 0 {
+                        return Err(node.unsupported());
+                    }
+                    let weights = self.constant(node, 1)?;
+                    let mut layer = dense(weights, node.int("transB", 0) != 0, node)?;
+                    let alpha = node.float("alpha", 1.0);
+                    layer.weights.iter_mut().for_each(|weight| *weight *= alpha);
+                    if node.inputs.len() > 2 {
+                        let beta = node.float("beta", 1.0);
+                        let bias = broadcast_bias(self.constant(node, 2)?, layer.outputs(), node)?;
+                        layer.bias = bias.into_iter().map(|value| value * beta).collect();
+                    }
+                    layers.push(layer);
+                }
+                "MatMul" => {
+                    layers.push(dense(self.constant(node, 1)?, false, node)?);
+                    needs_bias = true;
+                }
+                "Add" if needs_bias => {
+                    let layer = layers.last_mut().expect("MatMul pushed a layer");
+                    let constant = node
+                        .inputs
+                        .iter()
+                        .find_map(|name| self.constants.get(name))
+                        .ok_or_else(|| node.unsupported())?;
+                    layer.bias = broadcast_bias(constant, layer.outputs(), node)?;
+                    needs_bias = false;
+                }
+                "Tanh" => match layers.last_mut() {
+                    Some(layer) if !layer.tanh => layer.tanh = true,
+                    _ => return Err(node.unsupported()),
+                },
+                // Softmax leaves the action distribution unchanged
+                "Softmax" | "LogSoftmax" | "Identity" => {}
+                _ => return Err(node.unsupported()),
+            }
+            if node.op_type != "MatMul" {
+                needs_bias = false;
+            }
+            let output = node.output()?;
+            let next = self.consumers(output);
+            match next.as_slice() {
+                [single] if !self.is_output(output) => node = single,
+                _ => return Ok((layers, output.to_string())),
+            }
+        }
+    }
+}
+
+/// Dense layer from a weight constant, `[inputs, outputs]` or transposed
+fn dense(weights: &Constant, transposed: bool, node: &Node) -> Result<DenseLayer, OnnxError> {
+    let &[rows, columns] = weights.dims.as_slice() else {
+        return Err(OnnxError::Layout(format!(
+            "node `{}` has {}-dimensional weights, expected 2",
+            node.name,
+            weights.dims.len()
+        )));
+    };
+    let (inputs, outputs) = if transposed {
+        (columns, rows)
+    } else {
+        (rows, columns)
+    };
+    let values = if transposed {
+        (0..inputs)
+            .flat_map(|input| (0..outputs).map(move |output| (input, output)))
+            .map(|(input, output)| weights.values[output * inputs + input])
+            .collect()
+    } else {
+        weights.values.clone()
+    };
+    Ok(DenseLayer {
+        inputs,
+        weights: values,
+        bias: vec![0.0; outputs],
+        tanh: false,
+    })
+}
+
+fn broadcast_bias(bias: &Constant, outputs: usize, node: &Node) -> Result<Vec<f32>, OnnxError> {
+    match bias.values.len() {
+        1 => Ok(vec![bias.values[0]; outputs]),
+        len if len == outputs => Ok(bias.values.clone()),
+        len => Err(OnnxError::Layout(format!(
+            "node `{}` has {len} bias values for {outputs} outputs",
+            node.name
+        ))),
+    }
+}
+
+fn decode_node(bytes: &[u8]) -> Result<Node, OnnxError> {
+    let mut node = Node::default();
+    for field in fields(bytes)? {
+        match field {
+            (1, Value::Bytes(bytes)) => node.inputs.push(string(bytes)?),
+            (2, Value::Bytes(bytes)) => node.outputs.push(string(bytes)?),
+            (3, Value::Bytes(bytes)) => node.name = string(bytes)?,
+            (4, Value::Bytes(bytes)) => node.op_type = string(bytes)?,
+            (5, Value::Bytes(bytes)) => node.attributes.push(decode_attribute(bytes)?),
+            _ => {}
+        }
+    }
+    Ok(node)
+}
+
+fn decode_attribute(bytes: &[u8]) -> Result<Attribute, OnnxError> {
+    let mut attribute = Attribute::default();
+    for field in fields(bytes)? {
+        match field {
+            (1, Value::Bytes(bytes)) => attribute.name = string(bytes)?,
+            (2, Value::Fixed32(bits)) => attribute.float = Some(f32::from_bits(bits)),
+            (3, Value::Varint(value)) => attribute.int = Some(value as i64),
+            (5, Value::Bytes(bytes)) => attribute.tensor = Some(decode_tensor(bytes)?.1),
+            _ => {}
+        }
+    }
+    Ok(attribute)
+}
+
+fn decode_tensor(bytes: &[u8]) -> Result<(String, Constant), OnnxError> {
+    let mut name = String::new();
+    let mut dims = Vec::new();
+    let mut data_type = FLOAT;
+    let mut values = Vec::new();
+    let mut raw = None;
+    for field in fields(bytes)? {
+        match field {
+            (1, Value::Varint(dim)) => dims.push(dim as usize),
+            (1, Value::Bytes(packed)) => {
+                let mut reader = Reader { bytes: packed };
+                while !reader.bytes.is_empty() {
+                    dims.push(reader.varint()? as usize);
+                }
+            }
+            (2, Value::Varint(kind)) => data_type = kind as i32,
+            (4, Value::Fixed32(bits)) => values.push(f32::from_bits(bits)),
+            (4, Value::Bytes(packed)) => values.extend(floats(packed)?),
+            (8, Value::Bytes(bytes)) => name = string(bytes)?,
+            (9, Value::Bytes(bytes)) => raw = Some(floats(bytes)?),
+            (13, _) => {
+                return Err(OnnxError::Decode(format!(
+                    "tensor `{name}` uses external data; export with weights embedded"
+                )));
+            }
+            _ => {}
+        }
+    }
+    if data_type != FLOAT {
+        return Err(OnnxError::UnsupportedType { name, data_type });
+    }
+    let values = raw.unwrap_or(values);
+    if values.len() != dims.iter().product::<usize>() {
+        return Err(OnnxError::Decode(format!(
+            "tensor `{name}` holds {} values for shape {dims:?}",
+            values.len()
+        )));
+    }
+    Ok((name, Constant { dims, values }))
+}
+
+fn decode_value_info(bytes: &[u8]) -> Result<ValueInfo, OnnxError> {
+    let mut info = ValueInfo::default();
+    for field in fields(bytes)? {
+        match field {
+            (1, Value::Bytes(bytes)) => info.name = string(bytes)?,
+            // TypeProto.tensor_type
+            (2, Value::Bytes(bytes)) => {
+                for field in fields(bytes)? {
+                    if let (1, Value::Bytes(tensor)) = field {
+                        for field in fields(tensor)? {
+                            match field {
+                                (1, Value::Varint(kind)) => info.elem_type = Some(kind as i32),
+                                (2, Value::Bytes(shape)) => {
+                                    info.shape = Some(decode_shape(shape)?);
+                                }
+                                _ => {}
+                            }
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(info)
+}
+
+fn decode_shape(bytes: &[u8]) -> Result<Vec<Dim>, OnnxError> {
+    let mut shape = Vec::new();
+    for field in fields(bytes)? {
+        if let (1, Value::Bytes(dimension)) = field {
+            let mut dim = Dim::Symbolic;
+            for field in fields(dimension)? {
+                if let (1, Value::Varint(size)) = field {
+                    dim = Dim::Fixed(size as i64);
+                }
+            }
+            shape.push(dim);
+        }
+    }
+    Ok(shape)
+}
+
+fn string(bytes: &[u8]) -> Result<String, OnnxError> {
+    String::from_utf8(bytes.to_vec()).map_err(|_| OnnxError::Decode("invalid UTF-8".into()))
+}
+
+fn floats(bytes: &[u8]) -> Result<Vec<f32>, OnnxError> {
+    if !bytes.len().is_multiple_of(4) {
+        return Err(OnnxError::Decode(
+            "float data is not a multiple of 4 bytes".into(),
+        ));
+    }
+    Ok(bytes
+        .chunks_exact(4)
+        .map(|chunk| f32::from_le_bytes(chunk.try_into().expect("4-byte chunk")))
+        .collect())
+}
+
+/// One protobuf field value
+#[derive(Debug, Clone, Copy)]
+enum Value<'a> {
+    Varint(u64),
+    /// Skipped; no field this module reads is 64-bit fixed
+    Fixed64,
+    Bytes(&'a [u8]),
+    Fixed32(u32),
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], OnnxError> {
+        if self.bytes.len() < len {
+            return Err(OnnxError::Decode("truncated message".into()));
+        }
+        let (head, tail) = self.bytes.split_at(len);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    fn varint(&mut self) -> Result<u64, OnnxError> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.take(1)?[0];
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(OnnxError::Decode("varint too long".into()))
+    }
+
+    fn field(&mut self) -> Result<(u32, Value<'a>), OnnxError> {
+        let key = self.varint()?;
+        let number = (key >> 3) as u32;
+        let value = match key & 7 {
+            0 => Value::Varint(self.varint()?),
+            1 => {
+                self.take(8)?;
+                Value::Fixed64
+            }
+            2 => {
+                let len = self.varint()? as usize;
+                Value::Bytes(self.take(len)?)
+            }
+            5 => Value::Fixed32(u32::from_le_bytes(
+                self.take(4)?.try_into().expect("4-byte slice"),
+            )),
+            wire_type => {
+                return Err(OnnxError::Decode(format!(
+                    "unsupported wire type {wire_type}"
+                )));
+            }
+        };
+        Ok((number, value))
+    }
+}
+
+/// Every field of one message, in order
+fn fields(bytes: &[u8]) -> Result<Vec<(u32, Value<'_>)>, OnnxError> {
+    let mut reader = Reader { bytes };
+    let mut fields = Vec::new();
+    while !reader.bytes.is_empty() {
+        fields.push(reader.field()?);
+    }
+    Ok(fields)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::InferenceBackend;
+
+    /// Minimal protobuf writer for building test graphs
+    #[derive(Default)]
+    struct Message(Vec<u8>);
+
+    impl Message {
+        fn varint(&mut self, mut value: u64) {
+            loop {
+                let byte = (value & 0x7f) as u8;
+                value >>= 7;
+                if value == 0 {
+                    self.0.push(byte);
+                    return;
+                }
+                self.0.push(byte | 0x80);
+            }
+        }
+
+        fn int(mut self, field: u32, value: i64) -> Self {
+            self.varint(u64::from(field) << 3);
+            self.varint(value as u64);
+            self
+        }
+
+        fn float(mut self, field: u32, value: f32) -> Self {
+            self.varint((u64::from(field) << 3) | 5);
+            self.0.extend(value.to_le_bytes());
+            self
+        }
+
+        fn bytes(mut self, field: u32, bytes: &[u8]) -> Self {
+            self.varint((u64::from(field) << 3) | 2);
+            self.varint(bytes.len() as u64);
+            self.0.extend_from_slice(bytes);
+            self
+        }
+
+        fn text(self, field: u32, text: &str) -> Self {
+            self.bytes(field, text.as_bytes())
+        }
+
+        fn message(self, field: u32, message: Message) -> Self {
+            self.bytes(field, &message.0)
+        }
+    }
+
+    fn tensor(name: &str, dims: &[usize], values: &[f32]) -> Message {
+        let mut message = Message::default();
+        for &dim in dims {
+            message = message.int(1, dim as i64);
+        }
+        let raw: Vec<u8> = values
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect();
+        message.int(2, 1).text(8, name).bytes(9, &raw)
+    }
+
+    fn node(op: &str, inputs: &[&str], output: &str, attributes: Vec<Message>) -> Message {
+        let mut message = Message::default();
+        for input in inputs {
+            message = message.text(1, input);
+        }
+        message = message.text(2, output).text(3, output).text(4, op);
+        for attribute in attributes {
+            message = message.message(5, attribute);
+        }
+        message
+    }
+
+    fn value_info(name: &str, features: usize) -> Message {
+        let shape = Message::default()
+            .message(1, Message::default().text(2, "batch"))
+            .message(1, Message::default().int(1, features as i64));
+        let tensor_type = Message::default().int(1, 1).message(2, shape);
+        Message::default()
+            .text(1, name)
+            .message(2, Message::default().message(1, tensor_type))
+    }
+
+    fn values(count: usize, seed: f32) -> Vec<f32> {
+        (0..count).map(|i| (i as f32 * seed).sin() * 0.3).collect()
+    }
+
+    /// A PyTorch-style export: Gemm (transB) + Tanh torso, softmax action
+    /// head and a MatMul + Add value head
+    fn policy_graph(inputs: usize, hidden: usize, actions: usize) -> Vec<u8> {
+        let trans_b = || Message::default().text(1, "transB").int(3, 1);
+        let graph = Message::default()
+            .message(1, node("Gemm", &["obs", "w1", "b1"], "h1", vec![trans_b()]))
+            .message(1, node("Tanh", &["h1"], "a1", vec![]))
+            .message(1, node("Gemm", &["a1", "w2", "b2"], "h2", vec![trans_b()]))
+            .message(1, node("Tanh", &["h2"], "a2", vec![]))
+            .message(
+                1,
+                node(
+                    "Gemm",
+                    &["a2", "wa", "ba"],
+                    "logits",
+                    vec![trans_b(), Message::default().text(1, "alpha").float(2, 1.0)],
+                ),
+            )
+            .message(
+                1,
+                node(
+                    "Softmax",
+                    &["logits"],
+                    "probs",
+                    vec![Message::default().text(1, "axis").int(3, 1)],
+                ),
+            )
+            .message(1, node("MatMul", &["a2", "wv"], "v0", vec![]))
+            .message(1, node("Add", &["v0", "bv"], "value", vec![]))
+            .text(2, "policy")
+            .message(
+                5,
+                tensor("w1", &[hidden, inputs], &values(hidden * inputs, 0.7)),
+            )
+            .message(5, tensor("b1", &[hidden], &values(hidden, 1.3)))
+            .message(
+                5,
+                tensor("w2", &[hidden, hidden], &values(hidden * hidden, 0.9)),
+            )
+            .message(5, tensor("b2", &[hidden], &values(hidden, 0.4)))
+            .message(
+                5,
+                tensor("wa", &[actions, hidden], &values(actions * hidden, 1.1)),
+            )
+            .message(5, tensor("ba", &[actions], &values(actions, 0.2)))
+            .message(5, tensor("wv", &[hidden, 1], &values(hidden, 0.5)))
+            .message(5, tensor("bv", &[1], &[0.25]))
+            .message(11, value_info("obs", inputs))
+            .message(12, value_info("probs", actions))
+            .message(12, value_info("value", 1));
+        Message::default().int(1, 8).message(7, graph).0
+    }
+
+    /// Reference forward pass straight from the test weights
+    fn reference(inputs: usize, hidden: usize, actions: usize, x: &[f32]) -> (Vec<f32>, f32) {
+        let layer = |x: &[f32], w: &[f32], b: &[f32], outputs: usize| -> Vec<f32> {
+            (0..outputs)
+                .map(|o| b[o] + (0..x.len()).map(|i| x[i] * w[o * x.len() + i]).sum::<f32>())
+                .collect()
+        };
+        let h1: Vec<f32> = layer(
+            x,
+            &values(hidden * inputs, 0.7),
+            &values(hidden, 1.3),
+            hidden,
+        )
+        .into_iter()
+        .map(f32::tanh)
+        .collect();
+        let h2: Vec<f32> = layer(
+            &h1,
+            &values(hidden * hidden, 0.9),
+            &values(hidden, 0.4),
+            hidden,
+        )
+        .into_iter()
+        .map(f32::tanh)
+        .collect();
+        let logits = layer(
+            &h2,
+            &values(actions * hidden, 1.1),
+            &values(actions, 0.2),
+            actions,
+        );
+        let max = logits.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+        let exp: Vec<f32> = logits.iter().map(|logit| (logit - max).exp()).collect();
+        let total: f32 = exp.iter().sum();
+        let weights = values(hidden, 0.5);
+        let value = 0.25 + h2.iter().zip(&weights).map(|(h, w)| h * w).sum::<f32>();
+        (exp.iter().map(|e| e / total).collect(), value)
+    }
+
+    #[test]
+    fn test_import_matches_reference_forward() {
+        let encoder = ObservationEncoder::default();
+        let action_space = ActionSpace::default();
+        let (inputs, actions) = (encoder.input_size(), action_space.size());
+        let path = std::env::temp_dir().join(format!("ecosystem-onnx-{}.onnx", std::process::id()));
+        std::fs::write(&path, policy_graph(inputs, 16, actions)).unwrap();
+
+        let model = import_onnx_policy::<InferenceBackend>(
+            &path,
+            OrganismType::Blue,
+            &encoder,
+            &action_space,
+            &Default::default(),
+        )
+        .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(model.metadata.policy.hidden_size, 16);
+        assert_eq!(model.metadata.policy.hidden_layers, 2);
+        model
+            .metadata
+            .validate(&encoder, &action_space)
+            .expect("metadata matches the simulation");
+
+        let x = values(inputs, 0.37);
+        let evaluation = model.network.evaluate(&x, &Default::default());
+        let (probabilities, value) = reference(inputs, 16, actions, &x);
+        for (p, q) in evaluation.probabilities[0].iter().zip(&probabilities) {
+            assert!((p - q).abs() < 1e-5);
+        }
+        assert!((evaluation.values[0] - value).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_shape_mismatches_are_reported() {
+        let encoder = ObservationEncoder::default();
+        let action_space = ActionSpace::default();
+        let (inputs, actions) = (encoder.input_size(), action_space.size());
+
+        let error = OnnxLayers::decode(
+            &policy_graph(inputs + 3, 8, actions),
+            &encoder,
+            &action_space,
+        )
+        .unwrap_err();
+        assert!(matches!(error, OnnxError::InputShape { expected, .. } if expected == inputs));
+        assert!(error.to_string().contains(&encoder.schema_id()));
+
+        let error = OnnxLayers::decode(
+            &policy_graph(inputs, 8, actions + 1),
+            &encoder,
+            &action_space,
+        )
+        .unwrap_err();
+        assert!(
+            matches!(error, OnnxError::ActionCount { found, expected, .. }
+            if found == actions + 1 && expected == actions)
+        );
+
+        let relu = Message::default()
+            .message(1, node("Gemm", &["obs", "w", "b"], "h", vec![]))
+            .message(1, node("Relu", &["h"], "out", vec![]))
+            .message(
+                5,
+                tensor("w", &[inputs, actions], &values(inputs * actions, 0.3)),
+            )
+            .message(5, tensor("b", &[actions], &values(actions, 0.3)))
+            .message(11, value_info("obs", inputs))
+            .message(12, value_info("out", actions));
+        let error = OnnxLayers::decode(
+            &Message::default().message(7, relu).0,
+            &encoder,
+            &action_space,
+        )
+        .unwrap_err();
+        assert!(matches!(error, OnnxError::UnsupportedOp { ref op, .. } if op == "Relu"));
+    }
+}
@@ -0,0 +1,119 @@
+//! Building `ObservationData` from the world state
+
+use bevy_ecs::prelude::*;
+use bevy_math::Vec2;
+use ecosystem_components::prelude::*;
+
+/// One organism as seen by the perception pass
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PerceivedEntity {
+    pub entity: Entity,
+    pub organism_type: OrganismType,
+    pub position: Vec2,
+}
+
+/// Positions of every living organism at the start of a tick
+///
+/// Observations are built against this snapshot so every organism sees the
+/// same world regardless of system ordering.
+#[derive(Debug, Clone, Default)]
+pub struct PerceptionSnapshot {
+    entities: Vec<PerceivedEntity>,
+}
+
+impl PerceptionSnapshot {
+    pub fn new(entities: impl IntoIterator<Item = PerceivedEntity>) -> Self {
+        Self {
+            entities: entities.into_iter().collect(),
+        }
+    }
+
+    /// Snapshot every living organism in a world
+    pub fn from_world(world: &mut World) -> Self {
+        let mut query = world.query_filtered::<(Entity, &OrganismType, &Position), With<Alive>>();
+        Self::new(
+            query
+                .iter(world)
+                .map(|(entity, organism_type, position)| PerceivedEntity {
+                    entity,
+                    organism_type: *organism_type,
+                    position: position.to_vec2(),
+                }),
+        )
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    pub fn entities(&self) -> &[PerceivedEntity] {
+        &self.entities
+    }
+
+    /// Build the observation for `observer`, nearest entities first
+    pub fn observe(
+        &self,
+        observer: Entity,
+        position: &Position,
+        vision: &Vision,
+        health: &Health,
+        energy: &Energy,
+    ) -> ObservationData {
+        let origin = position.to_vec2();
+        let mut observation =
+            ObservationData::new(SelfState::new(health.ratio(), energy.ratio(), origin));
+
+        let mut visible: Vec<EntityObservation> = self
+            .entities
+            .iter()
+            .filter(|other| other.entity != observer)
+            .map(|other| EntityObservation::new(other.organism_type, other.position - origin))
+            .filter(|seen| seen.distance() <= vision.range)
+            .collect();
+        visible.sort_by(|a, b| a.distance().total_cmp(&b.distance()));
+
+        for seen in visible {
+            observation.add_observation(seen);
+        }
+        observation
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_observe_sorted_and_in_range() {
+        let mut world = World::new();
+        let me = OrganismFactory::spawn(&mut world, OrganismType::Blue, Position::zero());
+        OrganismFactory::spawn(&mut world, OrganismType::Red, Position::new(50.0, 0.0));
+        OrganismFactory::spawn(&mut world, OrganismType::Plant, Position::new(0.0, 10.0));
+        OrganismFactory::spawn(&mut world, OrganismType::Red, Position::new(1000.0, 0.0));
+
+        let snapshot = PerceptionSnapshot::from_world(&mut world);
+        let observation = snapshot.observe(
+            me,
+            world.get::<Position>(me).unwrap(),
+            world.get::<Vision>(me).unwrap(),
+            world.get::<Health>(me).unwrap(),
+            world.get::<Energy>(me).unwrap(),
+        );
+
+        assert_eq!(snapshot.len(), 4);
+        assert_eq!(observation.visible_entities.len(), 2);
+        assert_eq!(
+            observation.visible_entities[0].organism_type,
+            OrganismType::Plant
+        );
+        assert_eq!(
+            observation.visible_entities[1].relative_position,
+            Vec2::new(50.0, 0.0)
+        );
+        assert_eq!(observation.self_state.health_ratio, 1.0);
+    }
+}
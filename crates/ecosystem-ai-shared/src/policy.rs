@@ -0,0 +1,395 @@
+//! Actor-critic policy network
+//!
+//! An optional attention block over visible entities, an MLP torso, and an
+//! optional recurrent cell whose state is carried per entity between ticks,
+//! feeding an action head and a value head.
+
+use burn::nn::{Linear, LinearConfig};
+use burn::prelude::*;
+use burn::tensor::activation;
+use burn::tensor::backend::AutodiffBackend;
+use ecosystem_components::prelude::BrainSize;
+
+use crate::attention::{AttentionConfig, EntityAttention};
+use crate::encoding::SELF_FEATURES;
+use crate::recurrent::{RecurrentCell, RecurrentKind};
+
+/// Architecture of a [`PolicyNetwork`]
+#[derive(Config, Debug, PartialEq)]
+pub struct PolicyConfig {
+    /// Encoded observation length
+    pub input_size: usize,
+    /// Number of discrete actions
+    pub action_count: usize,
+    #[config(default = 64)]
+    pub hidden_size: usize,
+    #[config(default = 2)]
+    pub hidden_layers: usize,
+    /// Recurrent cell of `hidden_size` after the torso, if any
+    #[config(default = "None")]
+    pub recurrent: Option<RecurrentKind>,
+    /// Read entity slots through attention instead of a flat MLP input
+    #[config(default = "None")]
+    pub attention: Option<AttentionConfig>,
+    /// Leading self features of the input, which attention embeds apart from
+    /// the entity slots; `None` for the plain [`SELF_FEATURES`]
+    #[config(default = "None")]
+    pub self_features: Option<usize>,
+}
+
+impl PolicyConfig {
+    pub fn init<B: Backend>(&self, device: &B::Device) -> PolicyNetwork<B> {
+        let mut torso = Vec::with_capacity(self.hidden_layers);
+        let mut width = self.input_size;
+        let entities = self.attention.as_ref().map(|attention| {
+            width = attention.output_size();
+            let self_features = self.self_features.unwrap_or(SELF_FEATURES);
+            attention.init(self.input_size, self_features, device)
+        });
+        for _ in 0..self.hidden_layers {
+            torso.push(LinearConfig::new(width, self.hidden_size).init(device));
+            width = self.hidden_size;
+        }
+        let memory = self.recurrent.map(|kind| {
+            let cell = kind.init(width, self.hidden_size, device);
+            width = self.hidden_size;
+            cell
+        });
+        PolicyNetwork {
+            entities,
+            torso,
+            memory,
+            actor: LinearConfig::new(width, self.action_count).init(device),
+            critic: LinearConfig::new(width, 1).init(device),
+        }
+    }
+}
+
+/// MLP with a shared tanh torso, an optional recurrent cell, an action-logit
+/// head and a value head
+#[derive(Module, Debug)]
+pub struct PolicyNetwork<B: Backend> {
+    entities: Option<EntityAttention<B>>,
+    torso: Vec<Linear<B>>,
+    memory: Option<RecurrentCell<B>>,
+    actor: Linear<B>,
+    critic: Linear<B>,
+}
+
+/// Torso, action head and value head of a plain MLP policy
+pub(crate) type MlpLayers<'a, B> = (&'a [Linear<B>], &'a Linear<B>, &'a Linear<B>);
+
+/// Output of one forward pass over a batch
+#[derive(Debug, Clone)]
+pub struct PolicyOutput<B: Backend> {
+    /// Unnormalized action scores `[batch, actions]`
+    pub logits: Tensor<B, 2>,
+    /// State value estimates `[batch]`
+    pub values: Tensor<B, 1>,
+}
+
+/// Plain-vector result of [`PolicyNetwork::evaluate`]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PolicyEvaluation {
+    /// One probability row per observation
+    pub probabilities: Vec<Vec<f32>>,
+    /// One value estimate per observation
+    pub values: Vec<f32>,
+}
+
+impl<B: Backend> PolicyNetwork<B> {
+    /// Plain MLP policy from existing layers, e.g. imported from ONNX
+    pub(crate) fn from_linear_layers(
+        torso: Vec<Linear<B>>,
+        actor: Linear<B>,
+        critic: Linear<B>,
+    ) -> Self {
+        Self {
+            entities: None,
+            torso,
+            memory: None,
+            actor,
+            critic,
+        }
+    }
+
+    /// Forward a batch of encoded observations `[batch, input_size]`
+    ///
+    /// Recurrent policies start from a zero state; use
+    /// [`PolicyNetwork::forward_step`] to carry state between ticks.
+    pub fn forward(&self, observations: Tensor<B, 2>) -> PolicyOutput<B> {
+        self.forward_step(observations, None).0
+    }
+
+    /// Forward one tick, returning the next recurrent state (`None` for
+    /// feed-forward policies)
+    ///
+    /// A missing `state` on a recurrent policy means a fresh zero state.
+    pub fn forward_step(
+        &self,
+        observations: Tensor<B, 2>,
+        state: Option<Tensor<B, 2>>,
+    ) -> (PolicyOutput<B>, Option<Tensor<B, 2>>) {
+        let mut hidden = match &self.entities {
+            Some(attention) => attention.forward(observations),
+            None => observations,
+        };
+        for layer in &self.torso {
+            hidden = activation::tanh(layer.forward(hidden));
+        }
+        let mut next_state = None;
+        if let Some(cell) = &self.memory {
+            let [rows, _] = hidden.dims();
+            let state =
+                state.unwrap_or_else(|| Tensor::zeros([rows, cell.state_size()], &hidden.device()));
+            let (output, state) = cell.step(hidden, state);
+            hidden = output;
+            next_state = Some(state);
+        }
+        let output = PolicyOutput {
+            logits: self.actor.forward(hidden.clone()),
+            values: self.critic.forward(hidden).squeeze(1),
+        };
+        (output, next_state)
+    }
+
+    /// Run a row-major batch of encoded observations and return plain vectors
+    pub fn evaluate(&self, observations: &[f32], device: &B::Device) -> PolicyEvaluation {
+        self.evaluate_step(observations, &[], device).0
+    }
+
+    /// Like [`PolicyNetwork::evaluate`], but carrying recurrent state
+    ///
+    /// `states` holds one row of [`PolicyNetwork::state_size`] values per
+    /// observation (or is empty for a fresh state); the next states are
+    /// returned in the same layout. Feed-forward policies return no states.
+    pub fn evaluate_step(
+        &self,
+        observations: &[f32],
+        states: &[f32],
+        device: &B::Device,
+    ) -> (PolicyEvaluation, Vec<f32>) {
+        self.evaluate_rows(observations, self.input_size(), states, device)
+    }
+
+    /// Like [`PolicyNetwork::evaluate_step`], for rows of `input_size`
+    /// features
+    ///
+    /// Only attention policies take rows other than
+    /// [`PolicyNetwork::input_size`]: any self block followed by whole entity
+    /// slots (see [`PolicyNetwork::has_attention`]).
+    pub fn evaluate_rows(
+        &self,
+        observations: &[f32],
+        input_size: usize,
+        states: &[f32],
+        device: &B::Device,
+    ) -> (PolicyEvaluation, Vec<f32>) {
+        debug_assert!(self.has_attention() || input_size == self.input_size());
+        let rows = observations.len() / input_size.max(1);
+        if rows == 0 {
+            return (PolicyEvaluation::default(), Vec::new());
+        }
+        let input = Tensor::<B, 2>::from_data(
+            TensorData::new(
+                observations[..rows * input_size].to_vec(),
+                [rows, input_size],
+            ),
+            device,
+        );
+        let state_size = self.state_size();
+        let state = (state_size > 0 && states.len() == rows * state_size).then(|| {
+            Tensor::<B, 2>::from_data(TensorData::new(states.to_vec(), [rows, state_size]), device)
+        });
+        let (output, next_state) = self.forward_step(input, state);
+        let probabilities = activation::softmax(output.logits, 1)
+            .into_data()
+            .to_vec::<f32>()
+            .expect("policy output is f32");
+        let values = output
+            .values
+            .into_data()
+            .to_vec::<f32>()
+            .expect("policy output is f32");
+        let next_states = next_state
+            .map(|state| {
+                state
+                    .into_data()
+                    .to_vec::<f32>()
+                    .expect("policy state is f32")
+            })
+            .unwrap_or_default();
+        let evaluation = PolicyEvaluation {
+            probabilities: probabilities
+                .chunks_exact(self.action_count())
+                .map(<[f32]>::to_vec)
+                .collect(),
+            values,
+        };
+        (evaluation, next_states)
+    }
+
+    /// Action probabilities for a row-major batch, as plain vectors
+    pub fn action_probabilities(&self, observations: &[f32], device: &B::Device) -> Vec<Vec<f32>> {
+        self.evaluate(observations, device).probabilities
+    }
+
+    pub fn input_size(&self) -> usize {
+        if let Some(attention) = &self.entities {
+            return attention.input_size();
+        }
+        match (self.torso.first(), &self.memory) {
+            (Some(layer), _) => layer.weight.dims()[0],
+            (None, Some(cell)) => cell.input_size(),
+            (None, None) => self.actor.weight.dims()[0],
+        }
+    }
+
+    /// Whether entity slots are read through attention, which takes any
+    /// number of them
+    pub fn has_attention(&self) -> bool {
+        self.entities.is_some()
+    }
+
+    /// Per-entity recurrent state length; 0 for feed-forward policies
+    pub fn state_size(&self) -> usize {
+        self.memory.as_ref().map_or(0, RecurrentCell::state_size)
+    }
+
+    pub fn is_recurrent(&self) -> bool {
+        self.memory.is_some()
+    }
+
+    pub fn action_count(&self) -> usize {
+        self.actor.weight.dims()[1]
+    }
+
+    /// Torso, action head and value head of a plain MLP policy; `None` with
+    /// attention or recurrence
+    pub(crate) fn linear_layers(&self) -> Option<MlpLayers<'_, B>> {
+        match (&self.entities, &self.memory) {
+            (None, None) => Some((&self.torso, &self.actor, &self.critic)),
+            _ => None,
+        }
+    }
+
+    /// Parameter count and per-decision multiply-adds, for metabolic costs
+    pub fn brain_size(&self) -> BrainSize {
+        let linear = |layer: &Linear<B>| layer.weight.dims().iter().product::<usize>();
+        let operations = self
+            .entities
+            .as_ref()
+            .map_or(0, EntityAttention::operations)
+            + self.torso.iter().map(linear).sum::<usize>()
+            + self.memory.as_ref().map_or(0, Module::num_params)
+            + linear(&self.actor)
+            + linear(&self.critic);
+        let clamp = |count: usize| u32::try_from(count).unwrap_or(u32::MAX);
+        BrainSize::new(clamp(self.num_params()), clamp(operations))
+    }
+}
+
+impl<B: AutodiffBackend> PolicyNetwork<B> {
+    /// Gradient of `log p(action)` with respect to each input feature of a
+    /// single encoded observation
+    ///
+    /// `state` is the recurrent state the decision was made from (empty for
+    /// a fresh state); it is held constant. Attention policies take the whole
+    /// `observation`, whatever its slot count.
+    pub fn saliency(
+        &self,
+        observation: &[f32],
+        state: &[f32],
+        action: usize,
+        device: &B::Device,
+    ) -> Vec<f32> {
+        let input_size = if self.has_attention() {
+            observation.len()
+        } else {
+            self.input_size()
+        };
+        let input = Tensor::<B, 2>::from_data(
+            TensorData::new(observation[..input_size].to_vec(), [1, input_size]),
+            device,
+        )
+        .require_grad();
+        let state_size = self.state_size();
+        let state = (state_size > 0 && state.len() == state_size).then(|| {
+            Tensor::<B, 2>::from_data(TensorData::new(state.to_vec(), [1, state_size]), device)
+        });
+        let (output, _) = self.forward_step(input.clone(), state);
+        let action = action.min(self.action_count() - 1);
+        let log_probability = activation::log_softmax(output.logits, 1)
+            .slice([0..1, action..action + 1])
+            .sum();
+        let gradients = log_probability.backward();
+        input
+            .grad(&gradients)
+            .map(|gradient| {
+                gradient
+                    .into_data()
+                    .to_vec::<f32>()
+                    .expect("policy input is f32")
+            })
+            .unwrap_or_else(|| vec![0.0; input_size])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::InferenceBackend;
+
+    #[test]
+    fn test_forward_shapes() {
+        let device = Default::default();
+        let policy = PolicyConfig::new(6, 4).init::<InferenceBackend>(&device);
+        let output = policy.forward(Tensor::zeros([3, 6], &device));
+
+        assert_eq!(output.logits.dims(), [3, 4]);
+        assert_eq!(output.values.dims(), [3]);
+        assert_eq!(policy.input_size(), 6);
+        // 6x64 + 64x64 torso, then the 4-action and value heads, plus biases
+        let weights = 6 * 64 + 64 * 64 + 64 * 4 + 64;
+        assert_eq!(
+            policy.brain_size(),
+            BrainSize::new((weights + 64 + 64 + 4 + 1) as u32, weights as u32)
+        );
+
+        let probabilities = policy.action_probabilities(&[0.0; 12], &device);
+        assert_eq!(probabilities.len(), 2);
+        assert!((probabilities[0].iter().sum::<f32>() - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_recurrent_state_carries_over() {
+        let device = Default::default();
+        for kind in [RecurrentKind::Gru, RecurrentKind::Lstm] {
+            let policy = crate::seeded_init(
+                PolicyConfig::new(3, 4)
+                    .with_hidden_size(8)
+                    .with_recurrent(Some(kind))
+                    .init::<InferenceBackend>(&device),
+                1,
+            );
+            assert_eq!(policy.state_size(), kind.state_size(8));
+            assert!(
+                policy.brain_size().parameters
+                    > PolicyConfig::new(3, 4)
+                        .with_hidden_size(8)
+                        .init::<InferenceBackend>(&device)
+                        .brain_size()
+                        .parameters
+            );
+            assert_eq!(policy.input_size(), 3);
+
+            let observations = [0.5, -0.5, 1.0, 0.1, 0.2, 0.3];
+            let (first, state) = policy.evaluate_step(&observations, &[], &device);
+            assert_eq!(state.len(), 2 * policy.state_size());
+            let (second, _) = policy.evaluate_step(&observations, &state, &device);
+            // Same input, different memory -> different output
+            assert_ne!(first.probabilities, second.probabilities);
+            assert_eq!(first, policy.evaluate(&observations, &device));
+        }
+    }
+}
@@ -0,0 +1,309 @@
+//! Int8 inference for plain MLP policies
+//!
+//! Weights are quantized symmetrically per output unit; each input row is
+//! quantized on the fly, so a layer is an integer dot product accumulated in
+//! i32 and rescaled once per output. Biases, activations and the softmax stay
+//! in f32. Attention and recurrent policies are not supported and keep
+//! running in f32.
+//!
+//! Weights are stored as i8; each weight row is widened to i16 once per
+//! batch and multiplied with i16 activations, which compiles to packed
+//! multiply-adds even on baseline x86-64.
+
+use burn::nn::Linear;
+use burn::prelude::*;
+
+use crate::policy::{PolicyEvaluation, PolicyNetwork};
+
+/// A dense layer with int8 weights
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuantizedLinear {
+    inputs: usize,
+    outputs: usize,
+    /// Row-major `[outputs, inputs]`, so each output reads a contiguous row
+    weights: Vec<i8>,
+    /// Dequantization scale of each output row
+    scales: Vec<f32>,
+    bias: Vec<f32>,
+}
+
+impl QuantizedLinear {
+    pub fn from_linear<B: Backend>(layer: &Linear<B>) -> Self {
+        let [inputs, outputs] = layer.weight.dims();
+        let weights = layer
+            .weight
+            .val()
+            .into_data()
+            .to_vec::<f32>()
+            .expect("policy weights are f32");
+        let bias = layer
+            .bias
+            .as_ref()
+            .map(|bias| {
+                bias.val()
+                    .into_data()
+                    .to_vec::<f32>()
+                    .expect("policy weights are f32")
+            })
+            .unwrap_or_else(|| vec![0.0; outputs]);
+
+        let mut quantized = vec![0; inputs * outputs];
+        let mut scales = Vec::with_capacity(outputs);
+        for output in 0..outputs {
+            let column = (0..inputs).map(|input| weights[input * outputs + output]);
+            let scale = scale_of(column.clone());
+            for (input, weight) in column.enumerate() {
+                quantized[output * inputs + input] = quantize(weight, scale);
+            }
+            scales.push(scale);
+        }
+        Self {
+            inputs,
+            outputs,
+            weights: quantized,
+            scales,
+            bias,
+        }
+    }
+
+    /// Forward a row-major batch `[rows, inputs]` into `[rows, outputs]`
+    pub fn forward(&self, input: &[f32]) -> Vec<f32> {
+        self.forward_quantized(&QuantizedRows::new(input, self.inputs))
+    }
+
+    fn forward_quantized(&self, input: &QuantizedRows) -> Vec<f32> {
+        debug_assert_eq!(input.width, self.inputs);
+        let rows = input.scales.len();
+        let mut output = vec![0.0; rows * self.outputs];
+        let mut widened = vec![0i16; self.inputs];
+        for (out, weights) in self.weights.chunks_exact(self.inputs).enumerate() {
+            for (wide, &weight) in widened.iter_mut().zip(weights) {
+                *wide = i16::from(weight);
+            }
+            let scale = self.scales[out];
+            let bias = self.bias[out];
+            for (row, (values, row_scale)) in input
+                .values
+                .chunks_exact(self.inputs)
+                .zip(&input.scales)
+                .enumerate()
+            {
+                output[row * self.outputs + out] =
+                    dot(&widened, values) as f32 * row_scale * scale + bias;
+            }
+        }
+        output
+    }
+
+    pub fn input_size(&self) -> usize {
+        self.inputs
+    }
+
+    pub fn output_size(&self) -> usize {
+        self.outputs
+    }
+
+    /// Bytes taken by weights, scales and biases
+    pub fn size_bytes(&self) -> usize {
+        self.weights.len() + 4 * (self.scales.len() + self.bias.len())
+    }
+}
+
+/// Activations quantized per row, shared by every layer reading them
+struct QuantizedRows {
+    width: usize,
+    /// Int8 range, held as i16 for the multiply-adds
+    values: Vec<i16>,
+    scales: Vec<f32>,
+}
+
+impl QuantizedRows {
+    fn new(input: &[f32], width: usize) -> Self {
+        let rows = input.len() / width.max(1);
+        let mut values = Vec::with_capacity(rows * width);
+        let mut scales = Vec::with_capacity(rows);
+        for row in input.chunks_exact(width) {
+            let scale = scale_of(row.iter().copied());
+            values.extend(row.iter().map(|&value| i16::from(quantize(value, scale))));
+            scales.push(scale);
+        }
+        Self {
+            width,
+            values,
+            scales,
+        }
+    }
+}
+
+/// Dot product with eight independent accumulators, so it vectorizes
+fn dot(weights: &[i16], inputs: &[i16]) -> i32 {
+    let weight_chunks = weights.chunks_exact(8);
+    let input_chunks = inputs.chunks_exact(8);
+    let tail: i32 = weight_chunks
+        .remainder()
+        .iter()
+        .zip(input_chunks.remainder())
+        .map(|(&w, &x)| i32::from(w) * i32::from(x))
+        .sum();
+    let mut lanes = [0i32; 8];
+    for (weights, inputs) in weight_chunks.zip(input_chunks) {
+        for lane in 0..8 {
+            lanes[lane] += i32::from(weights[lane]) * i32::from(inputs[lane]);
+        }
+    }
+    lanes.iter().sum::<i32>() + tail
+}
+
+fn scale_of(values: impl Iterator<Item = f32>) -> f32 {
+    values.fold(0.0f32, |max, value| max.max(value.abs())) / 127.0
+}
+
+/// Round to nearest without a libm call; the `as` cast saturates
+fn quantize(value: f32, scale: f32) -> i8 {
+    if scale > 0.0 {
+        let scaled = value / scale;
+        (scaled + 0.5f32.copysign(scaled)) as i8
+    } else {
+        0
+    }
+}
+
+/// Int8 copy of a feed-forward [`PolicyNetwork`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuantizedPolicy {
+    torso: Vec<QuantizedLinear>,
+    actor: QuantizedLinear,
+    critic: QuantizedLinear,
+}
+
+impl QuantizedPolicy {
+    /// Quantize a policy, or `None` if it uses attention or recurrence
+    pub fn from_network<B: Backend>(network: &PolicyNetwork<B>) -> Option<Self> {
+        let (torso, actor, critic) = network.linear_layers()?;
+        Some(Self {
+            torso: torso.iter().map(QuantizedLinear::from_linear).collect(),
+            actor: QuantizedLinear::from_linear(actor),
+            critic: QuantizedLinear::from_linear(critic),
+        })
+    }
+
+    /// Int8 counterpart of [`PolicyNetwork::evaluate`]
+    pub fn evaluate(&self, observations: &[f32]) -> PolicyEvaluation {
+        let rows = observations.len() / self.input_size().max(1);
+        let mut hidden = observations[..rows * self.input_size()].to_vec();
+        for layer in &self.torso {
+            hidden = layer.forward(&hidden);
+            hidden
+                .iter_mut()
+                .for_each(|value| *value = fast_tanh(*value));
+        }
+        let width = self.actor.input_size();
+        let hidden = QuantizedRows::new(&hidden, width);
+        let logits = self.actor.forward_quantized(&hidden);
+        PolicyEvaluation {
+            probabilities: logits
+                .chunks_exact(self.action_count())
+                .map(softmax)
+                .collect(),
+            values: self.critic.forward_quantized(&hidden),
+        }
+    }
+
+    /// Action probabilities for a row-major batch
+    pub fn action_probabilities(&self, observations: &[f32]) -> Vec<Vec<f32>> {
+        self.evaluate(observations).probabilities
+    }
+
+    pub fn input_size(&self) -> usize {
+        self.torso.first().unwrap_or(&self.actor).input_size()
+    }
+
+    pub fn action_count(&self) -> usize {
+        self.actor.output_size()
+    }
+
+    /// Bytes taken by all layers; roughly a quarter of the f32 policy
+    pub fn size_bytes(&self) -> usize {
+        self.torso
+            .iter()
+            .chain([&self.actor, &self.critic])
+            .map(QuantizedLinear::size_bytes)
+            .sum()
+    }
+}
+
+/// Rational approximation of tanh (as in Eigen), within a few ulp of
+/// `f32::tanh` but without the libm call, so the loop vectorizes
+fn fast_tanh(x: f32) -> f32 {
+    const CLAMP: f32 = 7.905_311;
+    const ALPHA: [f32; 7] = [
+        4.893_524_6e-3,
+        6.372_619e-4,
+        1.485_722_4e-5,
+        5.122_297e-8,
+        -8.604_672e-11,
+        2.000_188e-13,
+        -2.760_768_5e-16,
+    ];
+    const BETA: [f32; 4] = [4.893_525e-3, 2.268_434_6e-3, 1.185_347_1e-4, 1.198_258_4e-6];
+    let x = x.clamp(-CLAMP, CLAMP);
+    let x2 = x * x;
+    let p = ALPHA.iter().rev().fold(0.0, |acc, &alpha| acc * x2 + alpha) * x;
+    let q = BETA.iter().rev().fold(0.0, |acc, &beta| acc * x2 + beta);
+    p / q
+}
+
+fn softmax(logits: &[f32]) -> Vec<f32> {
+    let max = logits.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    let exp: Vec<f32> = logits.iter().map(|logit| (logit - max).exp()).collect();
+    let total: f32 = exp.iter().sum();
+    exp.into_iter().map(|value| value / total).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{InferenceBackend, PolicyConfig, RecurrentKind, seeded_init};
+
+    #[test]
+    fn test_fast_tanh() {
+        for i in -2000..=2000 {
+            let x = i as f32 * 0.01;
+            assert!((fast_tanh(x) - x.tanh()).abs() < 1e-6, "{x}");
+        }
+    }
+
+    #[test]
+    fn test_quantized_policy_tracks_f32() {
+        let device = Default::default();
+        let network = seeded_init(
+            PolicyConfig::new(12, 5)
+                .with_hidden_size(32)
+                .init::<InferenceBackend>(&device),
+            7,
+        );
+        let quantized = QuantizedPolicy::from_network(&network).unwrap();
+        assert_eq!(quantized.input_size(), 12);
+        assert_eq!(quantized.action_count(), 5);
+        let f32_bytes = 4 * network.num_params();
+        assert!(quantized.size_bytes() < f32_bytes / 2);
+
+        let observations: Vec<f32> = (0..12 * 16).map(|i| (i as f32 * 0.37).sin()).collect();
+        let exact = network.evaluate(&observations, &device);
+        let approximate = quantized.evaluate(&observations);
+        assert_eq!(approximate.probabilities.len(), 16);
+        for (exact, approximate) in exact.probabilities.iter().zip(&approximate.probabilities) {
+            for (p, q) in exact.iter().zip(approximate) {
+                assert!((p - q).abs() < 0.02, "{exact:?} vs {approximate:?}");
+            }
+        }
+        for (v, w) in exact.values.iter().zip(&approximate.values) {
+            assert!((v - w).abs() < 0.05);
+        }
+
+        let recurrent = PolicyConfig::new(12, 5)
+            .with_recurrent(Some(RecurrentKind::Gru))
+            .init::<InferenceBackend>(&device);
+        assert!(QuantizedPolicy::from_network(&recurrent).is_none());
+    }
+}
@@ -0,0 +1,108 @@
+//! Recurrent cells giving policies memory across ticks
+//!
+//! A cell's state is a flat row per entity so it can live in a plain
+//! component between ticks: `[h]` for a GRU, `[h | c]` for an LSTM.
+
+use burn::nn::{Linear, LinearConfig};
+use burn::prelude::*;
+use burn::tensor::activation;
+use serde::{Deserialize, Serialize};
+
+/// Which recurrent cell sits between the torso and the heads
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecurrentKind {
+    Gru,
+    Lstm,
+}
+
+impl RecurrentKind {
+    fn gates(self) -> usize {
+        match self {
+            RecurrentKind::Gru => 3,
+            RecurrentKind::Lstm => 4,
+        }
+    }
+
+    /// Length of the per-entity state row for a cell of `hidden_size`
+    pub fn state_size(self, hidden_size: usize) -> usize {
+        match self {
+            RecurrentKind::Gru => hidden_size,
+            RecurrentKind::Lstm => 2 * hidden_size,
+        }
+    }
+
+    pub fn init<B: Backend>(
+        self,
+        input_size: usize,
+        hidden_size: usize,
+        device: &B::Device,
+    ) -> RecurrentCell<B> {
+        RecurrentCell {
+            input: LinearConfig::new(input_size, self.gates() * hidden_size).init(device),
+            hidden: LinearConfig::new(hidden_size, self.gates() * hidden_size)
+                .with_bias(false)
+                .init(device),
+        }
+    }
+}
+
+/// A GRU or LSTM cell; the kind follows from the gate count of its weights
+#[derive(Module, Debug)]
+pub struct RecurrentCell<B: Backend> {
+    input: Linear<B>,
+    hidden: Linear<B>,
+}
+
+impl<B: Backend> RecurrentCell<B> {
+    pub fn input_size(&self) -> usize {
+        self.input.weight.dims()[0]
+    }
+
+    pub fn hidden_size(&self) -> usize {
+        self.hidden.weight.dims()[0]
+    }
+
+    pub fn kind(&self) -> RecurrentKind {
+        if self.hidden.weight.dims()[1] == 4 * self.hidden_size() {
+            RecurrentKind::Lstm
+        } else {
+            RecurrentKind::Gru
+        }
+    }
+
+    pub fn state_size(&self) -> usize {
+        self.kind().state_size(self.hidden_size())
+    }
+
+    /// Advance one tick: returns the cell output `[batch, hidden]` and the
+    /// next state `[batch, state_size]`
+    pub fn step(&self, input: Tensor<B, 2>, state: Tensor<B, 2>) -> (Tensor<B, 2>, Tensor<B, 2>) {
+        let size = self.hidden_size();
+        let gate =
+            |tensor: &Tensor<B, 2>, index: usize| tensor.clone().narrow(1, index * size, size);
+        match self.kind() {
+            RecurrentKind::Gru => {
+                let x = self.input.forward(input);
+                let h = self.hidden.forward(state.clone());
+                let update = activation::sigmoid(gate(&x, 0) + gate(&h, 0));
+                let reset = activation::sigmoid(gate(&x, 1) + gate(&h, 1));
+                let candidate = activation::tanh(gate(&x, 2) + reset * gate(&h, 2));
+                let next = candidate.clone() + update * (state - candidate);
+                (next.clone(), next)
+            }
+            RecurrentKind::Lstm => {
+                let h = state.clone().narrow(1, 0, size);
+                let c = state.narrow(1, size, size);
+                let gates = self.input.forward(input) + self.hidden.forward(h);
+                let input_gate = activation::sigmoid(gate(&gates, 0));
+                let forget_gate = activation::sigmoid(gate(&gates, 1));
+                let candidate = activation::tanh(gate(&gates, 2));
+                let output_gate = activation::sigmoid(gate(&gates, 3));
+                let c = forget_gate * c + input_gate * candidate;
+                let h = output_gate * activation::tanh(c.clone());
+                (h.clone(), Tensor::cat(vec![h, c], 1))
+            }
+        }
+    }
+}
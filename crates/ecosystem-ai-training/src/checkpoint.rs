@@ -0,0 +1,278 @@
+//! Training checkpoints for exact resume
+//!
+//! A checkpoint is a directory holding everything the trainer needs to
+//! continue as if it had never stopped: weights and optimizer state per
+//! species (and of its centralized critic, if any), normalizer statistics,
+//! the sampling RNG, counters and the league.
+//!
+//! ```text
+//! <run>/checkpoints/
+//! ├── latest                      # name of the newest complete checkpoint
+//! └── iter_000010/
+//!     ├── state.json              # counters, RNG, config, normalizers
+//!     ├── blue_policy.mpk
+//!     ├── blue_optimizer.mpk
+//!     ├── blue_critic.mpk             # with a centralized critic
+//!     ├── blue_critic_optimizer.mpk
+//!     └── league/blue_iter_000005.mpk
+//! ```
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use burn::record::{FullPrecisionSettings, NamedMpkBytesRecorder, Record, Recorder};
+use burn::tensor::backend::Backend;
+use ecosystem_ai_shared::RunningNormalizer;
+use ecosystem_components::prelude::OrganismType;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::config::TrainingConfig;
+use crate::curriculum::CurriculumState;
+use crate::league::{LeaguePool, LeagueSnapshot};
+
+/// Bumped when the checkpoint layout changes incompatibly
+pub const CHECKPOINT_FORMAT_VERSION: u32 = 1;
+
+const STATE_FILE: &str = "state.json";
+const LATEST_FILE: &str = "latest";
+const LEAGUE_DIR: &str = "league";
+
+/// Full-precision recorder so reloaded weights are bit-identical
+type BytesRecorder = NamedMpkBytesRecorder<FullPrecisionSettings>;
+
+/// Serialize a burn record (module or optimizer state) to bytes
+pub fn record_to_bytes<B: Backend, R: Record<B>>(record: R) -> Result<Vec<u8>> {
+    Recorder::<B>::record(&BytesRecorder::default(), record, ())
+        .map_err(|err| anyhow::anyhow!("serializing record: {err:?}"))
+}
+
+/// Deserialize a burn record previously written by [`record_to_bytes`]
+pub fn record_from_bytes<B: Backend, R: Record<B>>(
+    bytes: Vec<u8>,
+    device: &B::Device,
+) -> Result<R> {
+    BytesRecorder::default()
+        .load(bytes, device)
+        .map_err(|err| anyhow::anyhow!("deserializing record: {err:?}"))
+}
+
+/// Learned state of one species
+#[derive(Debug, Clone)]
+pub struct SpeciesCheckpoint {
+    pub species: OrganismType,
+    pub normalizer: RunningNormalizer,
+    pub policy: Vec<u8>,
+    pub optimizer: Vec<u8>,
+    /// Centralized critic weights and optimizer state
+    pub critic: Option<(Vec<u8>, Vec<u8>)>,
+}
+
+/// In-memory view of a checkpoint directory
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+    pub iteration: u32,
+    pub global_step: u64,
+    pub rng: ChaCha8Rng,
+    pub config: TrainingConfig,
+    pub species: Vec<SpeciesCheckpoint>,
+    pub league: LeaguePool,
+    pub curriculum: CurriculumState,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StateFile {
+    format_version: u32,
+    iteration: u32,
+    global_step: u64,
+    rng: ChaCha8Rng,
+    config: TrainingConfig,
+    normalizers: Vec<(OrganismType, RunningNormalizer)>,
+    league: Vec<(OrganismType, u32)>,
+    #[serde(default)]
+    curriculum: CurriculumState,
+}
+
+fn checkpoint_name(iteration: u32) -> String {
+    format!("iter_{iteration:06}")
+}
+
+fn species_stem(species: OrganismType) -> String {
+    species.short_name().to_lowercase()
+}
+
+fn league_file(snapshot_species: OrganismType, iteration: u32) -> String {
+    format!(
+        "{}_{}.mpk",
+        species_stem(snapshot_species),
+        checkpoint_name(iteration)
+    )
+}
+
+impl Checkpoint {
+    /// Write under `root`, point `latest` at it and prune to `keep` checkpoints
+    pub fn save(&self, root: &Path, keep: usize) -> Result<PathBuf> {
+        let name = checkpoint_name(self.iteration);
+        let staging = root.join(format!(".{name}.partial"));
+        let target = root.join(&name);
+        if staging.exists() {
+            fs::remove_dir_all(&staging)?;
+        }
+        fs::create_dir_all(staging.join(LEAGUE_DIR))
+            .with_context(|| format!("creating {}", staging.display()))?;
+
+        for species in &self.species {
+            let stem = species_stem(species.species);
+            fs::write(staging.join(format!("{stem}_policy.mpk")), &species.policy)?;
+            fs::write(
+                staging.join(format!("{stem}_optimizer.mpk")),
+                &species.optimizer,
+            )?;
+            if let Some((critic, optimizer)) = &species.critic {
+                fs::write(staging.join(format!("{stem}_critic.mpk")), critic)?;
+                fs::write(
+                    staging.join(format!("{stem}_critic_optimizer.mpk")),
+                    optimizer,
+                )?;
+            }
+        }
+        for snapshot in self.league.snapshots() {
+            fs::write(
+                staging
+                    .join(LEAGUE_DIR)
+                    .join(league_file(snapshot.species, snapshot.iteration)),
+                &snapshot.weights,
+            )?;
+        }
+
+        let state = StateFile {
+            format_version: CHECKPOINT_FORMAT_VERSION,
+            iteration: self.iteration,
+            global_step: self.global_step,
+            rng: self.rng.clone(),
+            config: self.config.clone(),
+            normalizers: self
+                .species
+                .iter()
+                .map(|s| (s.species, s.normalizer.clone()))
+                .collect(),
+            league: self
+                .league
+                .snapshots()
+                .iter()
+                .map(|s| (s.species, s.iteration))
+                .collect(),
+            curriculum: self.curriculum.clone(),
+        };
+        fs::write(staging.join(STATE_FILE), serde_json::to_vec_pretty(&state)?)?;
+
+        // Only expose the checkpoint once it is complete
+        if target.exists() {
+            fs::remove_dir_all(&target)?;
+        }
+        fs::rename(&staging, &target)?;
+        let latest_tmp = root.join(format!("{LATEST_FILE}.tmp"));
+        fs::write(&latest_tmp, &name)?;
+        fs::rename(&latest_tmp, root.join(LATEST_FILE))?;
+
+        prune(root, keep)?;
+        Ok(target)
+    }
+
+    /// Read a checkpoint directory written by [`Checkpoint::save`]
+    pub fn load(dir: &Path) -> Result<Self> {
+        let state_path = dir.join(STATE_FILE);
+        let state: StateFile = serde_json::from_slice(
+            &fs::read(&state_path).with_context(|| format!("reading {}", state_path.display()))?,
+        )
+        .with_context(|| format!("parsing {}", state_path.display()))?;
+        if state.format_version != CHECKPOINT_FORMAT_VERSION {
+            bail!(
+                "checkpoint {} has format version {}, expected {}",
+                dir.display(),
+                state.format_version,
+                CHECKPOINT_FORMAT_VERSION
+            );
+        }
+
+        let mut species = Vec::with_capacity(state.normalizers.len());
+        for (organism_type, normalizer) in state.normalizers {
+            let stem = species_stem(organism_type);
+            let read = |suffix: &str| {
+                let path = dir.join(format!("{stem}_{suffix}.mpk"));
+                fs::read(&path).with_context(|| format!("reading {}", path.display()))
+            };
+            species.push(SpeciesCheckpoint {
+                species: organism_type,
+                normalizer,
+                policy: read("policy")?,
+                optimizer: read("optimizer")?,
+                critic: if state.config.critic.centralized {
+                    Some((read("critic")?, read("critic_optimizer")?))
+                } else {
+                    None
+                },
+            });
+        }
+
+        let mut league = LeaguePool::new(state.config.league.max_snapshots);
+        for (organism_type, iteration) in state.league {
+            let path = dir
+                .join(LEAGUE_DIR)
+                .join(league_file(organism_type, iteration));
+            league.add(LeagueSnapshot {
+                species: organism_type,
+                iteration,
+                weights: fs::read(&path).with_context(|| format!("reading {}", path.display()))?,
+            });
+        }
+
+        Ok(Self {
+            iteration: state.iteration,
+            global_step: state.global_step,
+            rng: state.rng,
+            config: state.config,
+            species,
+            league,
+            curriculum: state.curriculum,
+        })
+    }
+}
+
+/// Directory of the newest complete checkpoint under `root`, if any
+pub fn latest_checkpoint(root: &Path) -> Result<Option<PathBuf>> {
+    let latest = root.join(LATEST_FILE);
+    if !latest.exists() {
+        return Ok(None);
+    }
+    let name = fs::read_to_string(&latest)?;
+    let dir = root.join(name.trim());
+    if !dir.is_dir() {
+        bail!(
+            "{} points at missing checkpoint {}",
+            latest.display(),
+            dir.display()
+        );
+    }
+    Ok(Some(dir))
+}
+
+fn prune(root: &Path, keep: usize) -> Result<()> {
+    let mut checkpoints: Vec<PathBuf> = fs::read_dir(root)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.is_dir()
+                && path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| name.starts_with("iter_"))
+        })
+        .collect();
+    checkpoints.sort();
+    let excess = checkpoints.len().saturating_sub(keep.max(1));
+    for old in &checkpoints[..excess] {
+        fs::remove_dir_all(old)?;
+    }
+    Ok(())
+}
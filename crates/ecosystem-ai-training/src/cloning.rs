@@ -0,0 +1,402 @@
+//! Behavior cloning from recorded decisions
+//!
+//! Fits a species' policy to the actions in one or more trajectory datasets
+//! (see [`ecosystem_ai_shared::DatasetWriter`]) with a cross-entropy loss on the
+//! action head. Whole organisms are held out for validation, so the reported
+//! accuracy measures generalization to unseen trajectories rather than to
+//! neighbouring ticks of the same one.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use burn::module::AutodiffModule;
+use burn::optim::{GradientsParams, Optimizer};
+use burn::prelude::*;
+use burn::tensor::activation;
+use ecosystem_ai_shared::{
+    ModelMetadata, PolicyNetwork, RunningNormalizer, TrajectoryRecord, greedy_index, save_policy,
+    seeded_init,
+};
+use ecosystem_components::prelude::*;
+use rand::SeedableRng;
+use rand::seq::SliceRandom;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::config::TrainingConfig;
+use crate::env::derive_seed;
+use crate::ppo::init_optimizer;
+use crate::trainer::{
+    TrainBackend, initial_normalizer, model_file_name, models_dir, policy_config,
+};
+
+/// Supervised training hyperparameters
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CloneConfig {
+    pub epochs: usize,
+    pub minibatch_size: usize,
+    pub learning_rate: f64,
+    /// Share of recorded organisms held out for validation
+    pub validation_fraction: f32,
+}
+
+impl Default for CloneConfig {
+    fn default() -> Self {
+        Self {
+            epochs: 20,
+            minibatch_size: 128,
+            learning_rate: 1e-3,
+            validation_fraction: 0.2,
+        }
+    }
+}
+
+/// Training loss and accuracy of one epoch
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CloneEpoch {
+    pub epoch: usize,
+    pub loss: f32,
+    pub train_accuracy: f32,
+}
+
+/// Outcome of cloning one species
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CloneReport {
+    pub species: OrganismType,
+    pub train_steps: usize,
+    pub validation_steps: usize,
+    pub epochs: Vec<CloneEpoch>,
+    /// Share of held-out steps where the greedy action matches the recording
+    pub validation_accuracy: f32,
+    /// Accuracy of always predicting the most common training action
+    pub baseline_accuracy: f32,
+}
+
+/// A cloned policy ready for export or for warm-starting PPO
+pub struct ClonedPolicy {
+    pub model: PolicyNetwork<TrainBackend>,
+    pub normalizer: RunningNormalizer,
+    pub report: CloneReport,
+}
+
+/// Encoded steps of one species, grouped by split
+#[derive(Default)]
+struct Split {
+    inputs: Vec<f32>,
+    actions: Vec<usize>,
+}
+
+/// Fit a policy for `species` to every matching step of `recordings`
+///
+/// Organisms are identified per recording and episode, so reused entity ids
+/// stay distinct. Rewards in the recordings are ignored.
+pub fn clone_species(
+    config: &TrainingConfig,
+    species: OrganismType,
+    recordings: &[Vec<TrajectoryRecord>],
+) -> Result<ClonedPolicy> {
+    if config.network.recurrent.is_some() {
+        bail!("behavior cloning trains on single steps and does not support recurrent policies");
+    }
+    let clone = &config.cloning;
+    let input_size = config.encoder.input_size();
+
+    let organisms: BTreeSet<(usize, u64, u64)> = recordings
+        .iter()
+        .enumerate()
+        .flat_map(|(source, steps)| {
+            steps
+                .iter()
+                .filter(|step| step.species == species)
+                .map(move |step| (source, step.episode, step.entity))
+        })
+        .collect();
+    if organisms.len() < 2 {
+        bail!(
+            "need recorded steps of at least two {} organisms to hold one out",
+            species.display_name()
+        );
+    }
+    let mut rng = ChaCha8Rng::seed_from_u64(derive_seed(config.seed, &[0xC10E, species as u64]));
+    let mut organisms: Vec<_> = organisms.into_iter().collect();
+    organisms.shuffle(&mut rng);
+    let held_out = ((organisms.len() as f32 * clone.validation_fraction).round() as usize)
+        .clamp(1, organisms.len() - 1);
+    let validation_organisms: BTreeSet<_> = organisms[..held_out].iter().copied().collect();
+
+    let mut train = Split::default();
+    let mut validation = Split::default();
+    for (source, steps) in recordings.iter().enumerate() {
+        for step in steps.iter().filter(|step| step.species == species) {
+            let split = if validation_organisms.contains(&(source, step.episode, step.entity)) {
+                &mut validation
+            } else {
+                &mut train
+            };
+            config
+                .encoder
+                .encode_into(&step.observation, &mut split.inputs);
+            split
+                .actions
+                .push(config.action_space.to_index(&step.action));
+        }
+    }
+
+    let mut normalizer = initial_normalizer(config);
+    normalizer.update(&train.inputs);
+    normalizer.normalize(&mut train.inputs);
+    normalizer.normalize(&mut validation.inputs);
+
+    let device = Default::default();
+    let mut model = seeded_init(
+        policy_config(config).init::<TrainBackend>(&device),
+        derive_seed(config.seed, &[0x5EED, species as u64]),
+    );
+    let mut optimizer = init_optimizer::<TrainBackend>(&config.ppo);
+    let mut order: Vec<usize> = (0..train.actions.len()).collect();
+    let mut epochs = Vec::with_capacity(clone.epochs);
+    for epoch in 1..=clone.epochs {
+        order.shuffle(&mut rng);
+        let mut loss_sum = 0.0;
+        let mut correct = 0;
+        for chunk in order.chunks(clone.minibatch_size.max(1)) {
+            let mut inputs = Vec::with_capacity(chunk.len() * input_size);
+            for &i in chunk {
+                inputs.extend_from_slice(&train.inputs[i * input_size..(i + 1) * input_size]);
+            }
+            let inputs = Tensor::<TrainBackend, 2>::from_data(
+                TensorData::new(inputs, [chunk.len(), input_size]),
+                &device,
+            );
+            let labels: Vec<i64> = chunk.iter().map(|&i| train.actions[i] as i64).collect();
+            let labels = Tensor::<TrainBackend, 2, Int>::from_data(
+                TensorData::new(labels.clone(), [chunk.len(), 1]),
+                &device,
+            );
+
+            let logits = model.forward(inputs).logits;
+            let predicted = logits.clone().argmax(1).equal(labels.clone());
+            correct += predicted.int().sum().into_scalar().elem::<i64>() as usize;
+            let loss = activation::log_softmax(logits, 1)
+                .gather(1, labels)
+                .mean()
+                .neg();
+            loss_sum += loss.clone().into_scalar().elem::<f32>() * chunk.len() as f32;
+
+            let grads = GradientsParams::from_grads(loss.backward(), &model);
+            model = optimizer.step(clone.learning_rate, model, grads);
+        }
+        let steps = order.len().max(1) as f32;
+        epochs.push(CloneEpoch {
+            epoch,
+            loss: loss_sum / steps,
+            train_accuracy: correct as f32 / steps,
+        });
+    }
+
+    let predictions = model
+        .valid()
+        .action_probabilities(&validation.inputs, &device);
+    let validation_accuracy = accuracy(
+        predictions
+            .iter()
+            .map(|probabilities| greedy_index(probabilities)),
+        &validation.actions,
+    );
+    let mut counts = vec![0usize; config.action_space.size()];
+    for &action in &train.actions {
+        counts[action] += 1;
+    }
+    let majority = (0..counts.len()).max_by_key(|&i| counts[i]).unwrap_or(0);
+    let baseline_accuracy = accuracy(
+        std::iter::repeat_n(majority, validation.actions.len()),
+        &validation.actions,
+    );
+
+    Ok(ClonedPolicy {
+        model,
+        normalizer,
+        report: CloneReport {
+            species,
+            train_steps: train.actions.len(),
+            validation_steps: validation.actions.len(),
+            epochs,
+            validation_accuracy,
+            baseline_accuracy,
+        },
+    })
+}
+
+fn accuracy(predictions: impl Iterator<Item = usize>, labels: &[usize]) -> f32 {
+    let correct = predictions
+        .zip(labels)
+        .filter(|(predicted, label)| predicted == *label)
+        .count();
+    correct as f32 / labels.len().max(1) as f32
+}
+
+/// Write `<out>/models/<species>.policy` for every cloned policy, plus
+/// `<out>/clone_report.json`
+///
+/// The models carry iteration 0, so `warm_start` can pick them up.
+pub fn export_cloned(
+    out_dir: &Path,
+    config: &TrainingConfig,
+    cloned: &[ClonedPolicy],
+) -> Result<Vec<PathBuf>> {
+    let dir = models_dir(out_dir);
+    fs::create_dir_all(&dir).with_context(|| format!("creating {}", dir.display()))?;
+    config.save(&out_dir.join("config.json"))?;
+    let run_id = out_dir
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "clone".to_string());
+    let hyperparameters = serde_json::to_value(config)?;
+
+    let mut paths = Vec::with_capacity(cloned.len());
+    for policy in cloned {
+        let species = policy.report.species;
+        let metadata = ModelMetadata {
+            run_id: run_id.clone(),
+            hyperparameters: hyperparameters.clone(),
+            ..ModelMetadata::new(
+                species,
+                config.encoder,
+                config.action_space,
+                policy_config(config),
+                policy.normalizer.clone(),
+            )
+        };
+        let path = dir.join(model_file_name(species));
+        save_policy(&path, &metadata, &policy.model.valid())?;
+        paths.push(path);
+    }
+    let reports: Vec<&CloneReport> = cloned.iter().map(|policy| &policy.report).collect();
+    let report_path = out_dir.join("clone_report.json");
+    fs::write(&report_path, serde_json::to_string_pretty(&reports)?)
+        .with_context(|| format!("writing {}", report_path.display()))?;
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Trainer;
+    use crate::env::{EnvConfig, RewardConfig, SimEnv};
+    use ecosystem_ai_runtime::HeuristicConfig;
+    use ecosystem_ai_shared::InferenceBackend;
+
+    /// Decisions of the heuristic brain in a small world
+    fn heuristic_recording(seed: u64, ticks: u32) -> Vec<TrajectoryRecord> {
+        let mut env = SimEnv::new(
+            EnvConfig {
+                half_extent: 80.0,
+                blue_count: 8,
+                red_count: 2,
+                plant_count: 10,
+                ..Default::default()
+            },
+            RewardConfig::default(),
+            seed,
+        );
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        let mut steps = Vec::new();
+        for _ in 0..ticks {
+            let mut actions = Vec::new();
+            for agent in env.observe() {
+                let previous = env
+                    .world()
+                    .get::<ActionCommand>(agent.entity)
+                    .cloned()
+                    .unwrap_or_default();
+                let action = HeuristicConfig::default().decide(
+                    agent.organism_type,
+                    &agent.observation,
+                    &previous,
+                    &mut rng,
+                );
+                steps.push(TrajectoryRecord {
+                    episode: seed,
+                    tick: env.tick() as u64,
+                    entity: agent.entity.to_bits(),
+                    species: agent.organism_type,
+                    observation: agent.observation,
+                    action: action.clone(),
+                    reward: 0.0,
+                    done: false,
+                });
+                actions.push((agent.entity, action));
+            }
+            env.step(&actions);
+        }
+        steps
+    }
+
+    #[test]
+    fn test_clone_heuristics_and_warm_start() {
+        let mut config = TrainingConfig {
+            seed: 3,
+            iterations: 1,
+            episodes_per_iteration: 1,
+            episode_ticks: 10,
+            trained_species: vec![OrganismType::Blue],
+            ..Default::default()
+        };
+        config.network.hidden_size = 32;
+        config.cloning.epochs = 15;
+        let recordings = vec![heuristic_recording(1, 60), heuristic_recording(2, 60)];
+
+        let cloned = clone_species(&config, OrganismType::Blue, &recordings).unwrap();
+        let report = &cloned.report;
+        assert!(report.validation_steps > 0 && report.train_steps > report.validation_steps);
+        assert!(report.epochs.last().unwrap().loss < report.epochs[0].loss);
+        assert!(
+            report.validation_accuracy > report.baseline_accuracy,
+            "{report:?}"
+        );
+
+        let dir = std::env::temp_dir().join(format!("ecosystem-clone-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let paths = export_cloned(&dir.join("clone"), &config, &[cloned]).unwrap();
+        assert_eq!(paths.len(), 1);
+        assert!(dir.join("clone/clone_report.json").exists());
+
+        let cloned = ecosystem_ai_shared::load_policy::<InferenceBackend>(
+            &paths[0],
+            &config.encoder,
+            &config.action_space,
+            &Default::default(),
+        )
+        .unwrap();
+        config.warm_start = Some(models_dir(&dir.join("clone")));
+        let mut trainer = Trainer::new(config.clone(), dir.join("ppo")).unwrap();
+        let probe: Vec<f32> = (0..config.encoder.input_size())
+            .map(|i| (i as f32).cos())
+            .collect();
+        let learner = &trainer.learners()[0];
+        assert_eq!(learner.normalizer, cloned.metadata.normalizer);
+        assert_eq!(
+            learner
+                .model
+                .valid()
+                .action_probabilities(&probe, &Default::default()),
+            cloned
+                .network
+                .action_probabilities(&probe, &Default::default())
+        );
+        trainer.run().unwrap();
+        assert_ne!(
+            trainer.learners()[0]
+                .model
+                .valid()
+                .action_probabilities(&probe, &Default::default()),
+            cloned
+                .network
+                .action_probabilities(&probe, &Default::default())
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
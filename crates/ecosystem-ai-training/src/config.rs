@@ -0,0 +1,130 @@
+//! Training run configuration
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use ecosystem_ai_shared::{ActionSpace, AttentionConfig, ObservationEncoder, RecurrentKind};
+use ecosystem_components::prelude::OrganismType;
+use serde::{Deserialize, Serialize};
+
+use crate::cloning::CloneConfig;
+use crate::critic::CriticConfig;
+use crate::curiosity::CuriosityConfig;
+use crate::curriculum::CurriculumConfig;
+use crate::env::{EnvConfig, RewardConfig};
+use crate::league::LeagueConfig;
+use crate::ppo::PpoConfig;
+
+/// Hidden layer layout shared by every trained species
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NetworkConfig {
+    pub hidden_size: usize,
+    pub hidden_layers: usize,
+    /// Add a GRU or LSTM cell after the hidden layers
+    pub recurrent: Option<RecurrentKind>,
+    /// Read visible entities through self-attention instead of flat slots
+    pub attention: Option<AttentionConfig>,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        Self {
+            hidden_size: 64,
+            hidden_layers: 2,
+            recurrent: None,
+            attention: None,
+        }
+    }
+}
+
+/// Everything needed to reproduce a training run
+///
+/// Loaded from JSON; every field has a default so config files only need to
+/// list what they change.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TrainingConfig {
+    /// Root seed for weights, environments and action sampling
+    pub seed: u64,
+    /// Total PPO iterations
+    pub iterations: u32,
+    /// Independent episodes collected per iteration
+    pub episodes_per_iteration: u32,
+    /// Ticks per episode
+    pub episode_ticks: u32,
+    /// Threads collecting episodes in parallel; results do not depend on it
+    pub workers: usize,
+    /// Iterations between checkpoints (0 disables periodic checkpoints)
+    pub checkpoint_interval: u32,
+    /// Number of checkpoints kept on disk
+    pub keep_checkpoints: usize,
+    /// Species controlled by learned policies; others rest
+    pub trained_species: Vec<OrganismType>,
+    /// Clip applied to normalized observations
+    pub normalizer_clip: f32,
+    pub env: EnvConfig,
+    pub rewards: RewardConfig,
+    /// Count-based exploration bonus (off by default)
+    pub curiosity: CuriosityConfig,
+    pub encoder: ObservationEncoder,
+    pub action_space: ActionSpace,
+    pub network: NetworkConfig,
+    /// Optional centralized value network, used during training only
+    pub critic: CriticConfig,
+    pub ppo: PpoConfig,
+    pub league: LeagueConfig,
+    /// Scenario stages applied on top of `env`
+    pub curriculum: CurriculumConfig,
+    /// Supervised settings for `ecosystem-train clone`
+    pub cloning: CloneConfig,
+    /// Models directory (e.g. a cloned run's `models/`) whose `<species>.policy`
+    /// files initialize the matching learners instead of random weights
+    pub warm_start: Option<PathBuf>,
+    /// Write every agent step to `<run>/rollouts/iter_NNNNNN.traj`
+    pub save_rollouts: bool,
+}
+
+impl Default for TrainingConfig {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            iterations: 200,
+            episodes_per_iteration: 4,
+            episode_ticks: 500,
+            workers: 1,
+            checkpoint_interval: 10,
+            keep_checkpoints: 3,
+            trained_species: vec![OrganismType::Blue, OrganismType::Red],
+            normalizer_clip: 5.0,
+            env: EnvConfig::default(),
+            rewards: RewardConfig::default(),
+            curiosity: CuriosityConfig::default(),
+            encoder: ObservationEncoder::default(),
+            action_space: ActionSpace::default(),
+            network: NetworkConfig::default(),
+            critic: CriticConfig::default(),
+            ppo: PpoConfig::default(),
+            league: LeagueConfig::default(),
+            curriculum: CurriculumConfig::default(),
+            cloning: CloneConfig::default(),
+            warm_start: None,
+            save_rollouts: false,
+        }
+    }
+}
+
+impl TrainingConfig {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("reading training config {}", path.display()))?;
+        serde_json::from_str(&text)
+            .with_context(|| format!("parsing training config {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let text = serde_json::to_string_pretty(self)?;
+        fs::write(path, text).with_context(|| format!("writing {}", path.display()))
+    }
+}
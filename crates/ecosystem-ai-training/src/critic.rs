@@ -0,0 +1,240 @@
+//! Centralized critic
+//!
+//! With `critic.centralized`, every trained species gets a separate value
+//! network that sees the agent's own observation plus a summary of the whole
+//! world: the population of every organism type and a coarse density map per
+//! type. It only estimates advantages during training; actors, and the
+//! exported models, still act on their own `ObservationData` alone.
+
+use bevy_ecs::prelude::*;
+use burn::grad_clipping::GradientClippingConfig;
+use burn::nn::{Linear, LinearConfig};
+use burn::optim::adaptor::OptimizerAdaptor;
+use burn::optim::{Adam, AdamConfig, GradientsParams, Optimizer};
+use burn::prelude::*;
+use burn::tensor::activation;
+use burn::tensor::backend::AutodiffBackend;
+use ecosystem_components::prelude::*;
+use rand::Rng;
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+
+use crate::env::EnvConfig;
+use crate::ppo::PpoConfig;
+use crate::rollout::Batch;
+
+/// Value function settings
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CriticConfig {
+    /// Learn values with a global-state critic instead of the policy's own
+    /// value head
+    pub centralized: bool,
+    /// Cells per side of each density map
+    pub grid_size: usize,
+    pub hidden_size: usize,
+    pub hidden_layers: usize,
+}
+
+impl Default for CriticConfig {
+    fn default() -> Self {
+        Self {
+            centralized: false,
+            grid_size: 4,
+            hidden_size: 64,
+            hidden_layers: 2,
+        }
+    }
+}
+
+impl CriticConfig {
+    /// Length of the global state vector
+    pub fn global_size(&self) -> usize {
+        OrganismType::all().len() * (1 + self.grid_size * self.grid_size)
+    }
+
+    /// Critic for actors with `input_size` encoded features
+    pub fn init<B: Backend>(&self, input_size: usize, device: &B::Device) -> CentralCritic<B> {
+        let mut layers = Vec::with_capacity(self.hidden_layers);
+        let mut width = input_size + self.global_size();
+        for _ in 0..self.hidden_layers {
+            layers.push(LinearConfig::new(width, self.hidden_size).init(device));
+            width = self.hidden_size;
+        }
+        CentralCritic {
+            layers,
+            value: LinearConfig::new(width, 1).init(device),
+        }
+    }
+}
+
+/// Population of every organism type followed by one density map per type,
+/// each relative to the type's starting count in `env`
+///
+/// Density maps split the world into `grid_size` x `grid_size` cells, row by
+/// row from the minimum corner.
+pub fn global_state(world: &mut World, env: &EnvConfig, grid_size: usize) -> Vec<f32> {
+    let types = OrganismType::all();
+    let cells = grid_size * grid_size;
+    let bounds = *world.resource::<WorldBounds>();
+    let size = bounds.size().max(Vec2::splat(f32::EPSILON));
+    let mut state = vec![0.0; types.len() * (1 + cells)];
+
+    let mut query = world.query_filtered::<(&OrganismType, &Position), With<Alive>>();
+    for (organism_type, position) in query.iter(world) {
+        let kind = *organism_type as usize;
+        let scale = 1.0
+            / match organism_type {
+                OrganismType::Plant => env.plant_count,
+                OrganismType::Blue => env.blue_count,
+                OrganismType::Red => env.red_count,
+            }
+            .max(1) as f32;
+        let cell = |offset: f32, extent: f32| {
+            ((offset / extent * grid_size as f32) as usize).min(grid_size.saturating_sub(1))
+        };
+        let column = cell(position.x - bounds.min.x, size.x);
+        let row = cell(position.y - bounds.min.y, size.y);
+        state[kind] += scale;
+        if cells > 0 {
+            state[types.len() + kind * cells + row * grid_size + column] += scale;
+        }
+    }
+    state
+}
+
+/// Value network over an agent's observation and the global state
+#[derive(Module, Debug)]
+pub struct CentralCritic<B: Backend> {
+    layers: Vec<Linear<B>>,
+    value: Linear<B>,
+}
+
+impl<B: Backend> CentralCritic<B> {
+    /// Values of `[batch, input_size + global_size]` rows
+    pub fn forward(&self, inputs: Tensor<B, 2>) -> Tensor<B, 1> {
+        let mut hidden = inputs;
+        for layer in &self.layers {
+            hidden = activation::tanh(layer.forward(hidden));
+        }
+        self.value.forward(hidden).squeeze(1)
+    }
+
+    /// Values of row-major `observations` that all share one `global` state
+    pub fn values(&self, observations: &[f32], global: &[f32], device: &B::Device) -> Vec<f32> {
+        let input_size = self.input_size() - global.len();
+        let rows = observations.len() / input_size.max(1);
+        if rows == 0 {
+            return Vec::new();
+        }
+        let mut inputs = Vec::with_capacity(rows * self.input_size());
+        for observation in observations.chunks_exact(input_size) {
+            inputs.extend_from_slice(observation);
+            inputs.extend_from_slice(global);
+        }
+        let inputs = Tensor::from_data(TensorData::new(inputs, [rows, self.input_size()]), device);
+        self.forward(inputs)
+            .into_data()
+            .convert::<f32>()
+            .into_vec()
+            .expect("float values")
+    }
+
+    /// Observation plus global state length
+    pub fn input_size(&self) -> usize {
+        let first = self.layers.first().unwrap_or(&self.value);
+        first.weight.dims()[0]
+    }
+}
+
+/// Adam with gradient-norm clipping, like the policy optimizer
+pub type CriticOptimizer<B> = OptimizerAdaptor<Adam, CentralCritic<B>, B>;
+
+pub fn init_critic_optimizer<B: AutodiffBackend>(config: &PpoConfig) -> CriticOptimizer<B> {
+    AdamConfig::new()
+        .with_grad_clipping(Some(GradientClippingConfig::Norm(config.max_grad_norm)))
+        .init()
+}
+
+/// Critic and optimizer of one trained species
+pub struct CriticLearner<B: AutodiffBackend> {
+    pub model: CentralCritic<B>,
+    pub optimizer: CriticOptimizer<B>,
+}
+
+/// Regress the critic onto the batch returns for `config.epochs` passes of
+/// shuffled minibatches, returning the mean squared error
+pub fn critic_update<B: AutodiffBackend, R: Rng>(
+    mut critic: CentralCritic<B>,
+    optimizer: &mut CriticOptimizer<B>,
+    batch: &Batch,
+    config: &PpoConfig,
+    rng: &mut R,
+    device: &B::Device,
+) -> (CentralCritic<B>, f32) {
+    let len = batch.len();
+    if len == 0 || batch.global_size == 0 {
+        return (critic, 0.0);
+    }
+    let (input_size, global_size) = (batch.input_size, batch.global_size);
+    let width = input_size + global_size;
+    let mut order: Vec<usize> = (0..len).collect();
+    let mut total = 0.0;
+    let mut minibatches = 0;
+
+    for _ in 0..config.epochs {
+        order.shuffle(rng);
+        for chunk in order.chunks(config.minibatch_size.max(1)) {
+            let mut inputs = Vec::with_capacity(chunk.len() * width);
+            for &i in chunk {
+                inputs.extend_from_slice(&batch.observations[i * input_size..(i + 1) * input_size]);
+                inputs.extend_from_slice(
+                    &batch.global_states[i * global_size..(i + 1) * global_size],
+                );
+            }
+            let inputs =
+                Tensor::<B, 2>::from_data(TensorData::new(inputs, [chunk.len(), width]), device);
+            let returns: Vec<f32> = chunk.iter().map(|&i| batch.returns[i]).collect();
+            let returns =
+                Tensor::<B, 1>::from_data(TensorData::new(returns, [chunk.len()]), device);
+
+            let loss = (critic.forward(inputs) - returns).powf_scalar(2.0).mean();
+            let grads = GradientsParams::from_grads(loss.backward(), &critic);
+            critic = optimizer.step(config.learning_rate, critic, grads);
+            total += loss.detach().into_scalar().elem::<f32>();
+            minibatches += 1;
+        }
+    }
+    (critic, total / minibatches.max(1) as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::env::{RewardConfig, SimEnv};
+
+    #[test]
+    fn test_global_state_counts_and_maps() {
+        let env_config = EnvConfig {
+            blue_count: 4,
+            red_count: 2,
+            plant_count: 5,
+            ..Default::default()
+        };
+        let mut env = SimEnv::new(env_config.clone(), RewardConfig::default(), 3);
+        let state = global_state(env.world_mut(), &env_config, 3);
+        let critic = CriticConfig {
+            grid_size: 3,
+            ..Default::default()
+        };
+        assert_eq!(state.len(), critic.global_size());
+
+        let types = OrganismType::all().len();
+        for kind in 0..types {
+            // Everyone is alive at the start, and every map sums to its count
+            assert!((state[kind] - 1.0).abs() < 1e-6);
+            let map = &state[types + kind * 9..types + (kind + 1) * 9];
+            assert!((map.iter().sum::<f32>() - 1.0).abs() < 1e-5);
+        }
+    }
+}
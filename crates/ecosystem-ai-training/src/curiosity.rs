@@ -0,0 +1,89 @@
+//! Count-based curiosity
+//!
+//! Agents of the curious species earn `coefficient / sqrt(n)` for every step
+//! that ends in a grid cell they have now visited `n` times this episode, so
+//! ground they have not covered yet pays more than pacing the same spot. The
+//! bonus is added to the reward PPO learns from but logged separately, so
+//! reported rewards stay comparable with and without curiosity.
+
+use std::collections::BTreeMap;
+
+use bevy_ecs::entity::Entity;
+use ecosystem_components::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Intrinsic reward settings
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CuriosityConfig {
+    /// Bonus for a first visit; 0 turns curiosity off
+    pub coefficient: f32,
+    /// Side length of a grid cell in world units
+    pub cell_size: f32,
+    /// Species that earn the bonus
+    pub species: Vec<OrganismType>,
+}
+
+impl Default for CuriosityConfig {
+    fn default() -> Self {
+        Self {
+            coefficient: 0.0,
+            cell_size: 25.0,
+            species: vec![OrganismType::Red],
+        }
+    }
+}
+
+impl CuriosityConfig {
+    pub fn applies_to(&self, species: OrganismType) -> bool {
+        self.coefficient != 0.0 && self.species.contains(&species)
+    }
+}
+
+/// Per-agent visit counts over one episode
+#[derive(Debug, Clone, Default)]
+pub struct NoveltyCounter {
+    cell_size: f32,
+    visits: BTreeMap<(Entity, i32, i32), u32>,
+}
+
+impl NoveltyCounter {
+    pub fn new(cell_size: f32) -> Self {
+        Self {
+            cell_size: cell_size.max(f32::EPSILON),
+            visits: BTreeMap::new(),
+        }
+    }
+
+    /// Count a visit of `entity` to the cell holding `position` and return
+    /// `1 / sqrt(visits)`
+    pub fn visit(&mut self, entity: Entity, position: &Position) -> f32 {
+        let cell = (
+            entity,
+            (position.x / self.cell_size).floor() as i32,
+            (position.y / self.cell_size).floor() as i32,
+        );
+        let visits = self.visits.entry(cell).or_insert(0);
+        *visits += 1;
+        1.0 / (*visits as f32).sqrt()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bonus_decays_with_revisits() {
+        let mut counter = NoveltyCounter::new(10.0);
+        let entity = Entity::from_raw(1);
+        let here = Position { x: 3.0, y: -4.0 };
+        assert_eq!(counter.visit(entity, &here), 1.0);
+        assert_eq!(
+            counter.visit(entity, &Position { x: 9.0, y: -1.0 }),
+            0.5f32.sqrt()
+        );
+        assert_eq!(counter.visit(entity, &Position { x: 13.0, y: -4.0 }), 1.0);
+        assert_eq!(counter.visit(Entity::from_raw(2), &here), 1.0);
+    }
+}
@@ -0,0 +1,229 @@
+//! Curriculum of training scenarios
+//!
+//! A curriculum is a list of stages, each adjusting the episode setup on top
+//! of `TrainingConfig::env`. Training starts in the first stage and moves on
+//! once the stage's promotion criterion holds on average over a window of
+//! iterations; the last stage is kept until the end of the run. For example,
+//! prey first learn to forage without predators, then face slow predators,
+//! then the full scenario:
+//!
+//! ```json
+//! "curriculum": { "stages": [
+//!   { "name": "no_predators", "red_count": 0,
+//!     "promotion": { "species": "Blue", "metric": "mean_reward", "threshold": 2.0 } },
+//!   { "name": "slow_predators", "red_speed_scale": 0.5,
+//!     "promotion": { "species": "Blue", "metric": "mean_survival", "threshold": 300 } },
+//!   { "name": "full" }
+//! ] }
+//! ```
+
+use std::collections::VecDeque;
+
+use ecosystem_components::prelude::OrganismType;
+use serde::{Deserialize, Serialize};
+
+use crate::env::EnvConfig;
+use crate::trainer::SpeciesStats;
+
+/// Ordered scenario stages; empty means no curriculum
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CurriculumConfig {
+    pub stages: Vec<CurriculumStage>,
+}
+
+/// Episode setup while a stage is active
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CurriculumStage {
+    pub name: String,
+    /// Predators per episode (`None` keeps `env.red_count`)
+    pub red_count: Option<usize>,
+    /// Multiplier on `env.red_speed_scale`
+    pub red_speed_scale: f32,
+    /// When to move to the next stage (`None` stays here)
+    pub promotion: Option<Promotion>,
+}
+
+impl Default for CurriculumStage {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            red_count: None,
+            red_speed_scale: 1.0,
+            promotion: None,
+        }
+    }
+}
+
+impl CurriculumStage {
+    /// `env` with this stage's changes applied
+    pub fn apply(&self, env: &EnvConfig) -> EnvConfig {
+        EnvConfig {
+            red_count: self.red_count.unwrap_or(env.red_count),
+            red_speed_scale: env.red_speed_scale * self.red_speed_scale,
+            ..env.clone()
+        }
+    }
+}
+
+/// Per-iteration statistic a promotion is judged on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PromotionMetric {
+    /// Mean undiscounted return per agent
+    MeanReward,
+    /// Mean ticks an agent survived
+    MeanSurvival,
+}
+
+/// Promote once `species`' `metric`, averaged over the last `window`
+/// iterations of the stage, reaches `threshold`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Promotion {
+    pub species: OrganismType,
+    pub metric: PromotionMetric,
+    pub threshold: f32,
+    #[serde(default = "default_window")]
+    pub window: usize,
+}
+
+fn default_window() -> usize {
+    5
+}
+
+/// A stage change, as logged to the run's metrics
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StagePromotion {
+    /// Iteration whose results triggered the promotion
+    pub iteration: u32,
+    pub from: String,
+    pub to: String,
+    pub metric: PromotionMetric,
+    /// Windowed mean that reached the threshold
+    pub value: f32,
+}
+
+/// Progress through a curriculum; checkpointed with the run
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CurriculumState {
+    pub stage: usize,
+    /// Promotion metric of the latest iterations in the current stage
+    recent: VecDeque<f32>,
+}
+
+impl CurriculumState {
+    /// The active stage, if the curriculum has any
+    pub fn current<'a>(&self, config: &'a CurriculumConfig) -> Option<&'a CurriculumStage> {
+        config
+            .stages
+            .get(self.stage.min(config.stages.len().saturating_sub(1)))
+    }
+
+    /// Episode setup for the active stage
+    pub fn env(&self, config: &CurriculumConfig, env: &EnvConfig) -> EnvConfig {
+        match self.current(config) {
+            Some(stage) => stage.apply(env),
+            None => env.clone(),
+        }
+    }
+
+    /// Account for one finished iteration; returns the promotion if the
+    /// active stage's criterion is now met
+    pub fn observe(
+        &mut self,
+        config: &CurriculumConfig,
+        iteration: u32,
+        stats: &[SpeciesStats],
+    ) -> Option<StagePromotion> {
+        let stage = self.current(config)?;
+        let next = config.stages.get(self.stage + 1)?;
+        let promotion = stage.promotion.as_ref()?;
+        let species = stats.iter().find(|s| s.species == promotion.species)?;
+        self.recent.push_back(match promotion.metric {
+            PromotionMetric::MeanReward => species.mean_reward,
+            PromotionMetric::MeanSurvival => species.mean_episode_length,
+        });
+        let window = promotion.window.max(1);
+        while self.recent.len() > window {
+            self.recent.pop_front();
+        }
+        if self.recent.len() < window {
+            return None;
+        }
+        let value = self.recent.iter().sum::<f32>() / window as f32;
+        if value < promotion.threshold {
+            return None;
+        }
+        let event = StagePromotion {
+            iteration,
+            from: stage.name.clone(),
+            to: next.name.clone(),
+            metric: promotion.metric,
+            value,
+        };
+        self.stage += 1;
+        self.recent.clear();
+        Some(event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ppo::PpoStats;
+
+    fn stats(reward: f32) -> Vec<SpeciesStats> {
+        vec![SpeciesStats {
+            species: OrganismType::Blue,
+            steps: 10,
+            mean_reward: reward,
+            mean_intrinsic_reward: 0.0,
+            mean_episode_length: 10.0,
+            ppo: PpoStats::default(),
+        }]
+    }
+
+    #[test]
+    fn test_promotes_on_windowed_mean() {
+        let config = CurriculumConfig {
+            stages: vec![
+                CurriculumStage {
+                    name: "no_predators".into(),
+                    red_count: Some(0),
+                    promotion: Some(Promotion {
+                        species: OrganismType::Blue,
+                        metric: PromotionMetric::MeanReward,
+                        threshold: 1.0,
+                        window: 2,
+                    }),
+                    ..Default::default()
+                },
+                CurriculumStage {
+                    name: "slow_predators".into(),
+                    red_speed_scale: 0.5,
+                    ..Default::default()
+                },
+            ],
+        };
+        let env = EnvConfig::default();
+        let mut state = CurriculumState::default();
+        assert_eq!(state.env(&config, &env).red_count, 0);
+
+        // A single good iteration is not enough to fill the window
+        assert_eq!(state.observe(&config, 1, &stats(2.0)), None);
+        state = CurriculumState::default();
+        assert_eq!(state.observe(&config, 1, &stats(0.5)), None);
+        assert_eq!(state.observe(&config, 2, &stats(1.0)), None);
+        let promotion = state.observe(&config, 3, &stats(1.5)).unwrap();
+        assert_eq!(promotion.to, "slow_predators");
+        assert_eq!(promotion.value, 1.25);
+
+        let slow = state.env(&config, &env);
+        assert_eq!(slow.red_count, env.red_count);
+        assert_eq!(slow.red_speed_scale, 0.5);
+        // The last stage is never left
+        assert_eq!(state.observe(&config, 4, &stats(10.0)), None);
+        assert_eq!(state.stage, 1);
+    }
+}
@@ -0,0 +1,538 @@
+//! Policy distillation into smaller students
+//!
+//! Plays episodes with the teacher driving its species, records the encoded
+//! observations together with the teacher's action distribution, and fits a
+//! smaller MLP to those distributions with a cross-entropy loss on soft
+//! targets. Whole episodes are held out for validation. The report compares
+//! teacher and student, in f32 and int8, on agreement with the teacher and on
+//! the time of one batched forward pass.
+
+use std::fs;
+use std::hint::black_box;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use anyhow::{Context, Result, bail};
+use burn::module::AutodiffModule;
+use burn::optim::{GradientsParams, Optimizer};
+use burn::prelude::*;
+use burn::tensor::activation;
+use ecosystem_ai_shared::{
+    InferenceBackend, ModelMetadata, PolicyConfig, PolicyModel, PolicyNetwork, QuantizedPolicy,
+    greedy_index, sample_index, save_policy, seeded_init,
+};
+use ecosystem_components::prelude::*;
+use rand::SeedableRng;
+use rand::seq::SliceRandom;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::env::{EnvConfig, derive_seed};
+use crate::evaluate::{Controller, EVALUATED_SPECIES, drive_episode};
+use crate::ppo::{PpoConfig, init_optimizer};
+use crate::trainer::{TrainBackend, model_file_name, models_dir};
+
+/// Student architecture, data collection and training settings
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DistillConfig {
+    pub hidden_size: usize,
+    pub hidden_layers: usize,
+    /// Episodes played by the teacher to collect observations
+    pub episodes: usize,
+    /// Ticks per episode
+    pub ticks: u32,
+    pub epochs: usize,
+    pub minibatch_size: usize,
+    pub learning_rate: f64,
+    /// Share of episodes held out for validation
+    pub validation_fraction: f32,
+    /// Rows of the batch timed for the speed report
+    pub timing_batch: usize,
+    /// Forward passes averaged per timing
+    pub timing_repeats: usize,
+    pub seed: u64,
+    pub env: EnvConfig,
+}
+
+impl Default for DistillConfig {
+    fn default() -> Self {
+        Self {
+            hidden_size: 32,
+            hidden_layers: 1,
+            episodes: 8,
+            ticks: 400,
+            epochs: 20,
+            minibatch_size: 128,
+            learning_rate: 1e-3,
+            validation_fraction: 0.25,
+            timing_batch: 1000,
+            timing_repeats: 20,
+            seed: 0,
+            env: EnvConfig::default(),
+        }
+    }
+}
+
+/// Training loss of one epoch
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct DistillEpoch {
+    pub epoch: usize,
+    /// Cross-entropy against the teacher's distribution
+    pub loss: f32,
+}
+
+/// Accuracy and cost of one policy variant on the held-out steps
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VariantReport {
+    /// E.g. `teacher f32` or `student int8`
+    pub name: String,
+    pub parameters: usize,
+    /// Size of the weights in memory
+    pub bytes: usize,
+    /// Share of held-out steps where the greedy action matches the teacher's
+    pub agreement: f32,
+    /// Mean KL divergence from the teacher's distribution
+    pub kl: f32,
+    /// Mean time of one forward pass over `timing_batch` observations
+    pub batch_micros: f64,
+}
+
+/// Outcome of distilling one species' policy
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DistillReport {
+    pub species: OrganismType,
+    pub train_steps: usize,
+    pub validation_steps: usize,
+    pub epochs: Vec<DistillEpoch>,
+    /// Teacher and student, each in f32 and (when supported) int8
+    pub variants: Vec<VariantReport>,
+}
+
+impl DistillReport {
+    pub fn variant(&self, name: &str) -> Option<&VariantReport> {
+        self.variants.iter().find(|variant| variant.name == name)
+    }
+}
+
+/// A distilled student ready for export
+pub struct DistilledPolicy {
+    pub model: PolicyNetwork<TrainBackend>,
+    /// Teacher metadata, copied over so the student reads the same inputs
+    pub teacher: ModelMetadata,
+    pub student: PolicyConfig,
+    pub report: DistillReport,
+}
+
+/// Normalized teacher inputs and probabilities of one split
+#[derive(Default)]
+struct Split {
+    inputs: Vec<f32>,
+    targets: Vec<f32>,
+}
+
+impl Split {
+    fn len(&self, input_size: usize) -> usize {
+        self.inputs.len() / input_size.max(1)
+    }
+}
+
+/// Distill the policy driving `species` in `controllers` (one per
+/// [`EVALUATED_SPECIES`]) into a smaller feed-forward student
+///
+/// The other species play their own controller while observations are
+/// collected; the teacher samples its actions, as it would at runtime.
+pub fn distill_species(
+    config: &DistillConfig,
+    species: OrganismType,
+    controllers: &[Controller],
+) -> Result<DistilledPolicy> {
+    let Some(position) = EVALUATED_SPECIES.iter().position(|kind| *kind == species) else {
+        bail!("{} has no policy to distill", species.display_name());
+    };
+    let Some(Controller::Policy { model: teacher, .. }) = controllers.get(position) else {
+        bail!("no {} model to distill", species.display_name());
+    };
+    if teacher.network.is_recurrent() {
+        bail!("distillation trains on single steps and does not support recurrent teachers");
+    }
+    if config.episodes < 2 {
+        bail!("distillation needs at least 2 episodes, one of them held out");
+    }
+    let encoder = teacher.metadata.encoder;
+    let action_space = teacher.metadata.action_space;
+    let input_size = encoder.input_size();
+    let action_count = action_space.size();
+
+    let held_out = ((config.episodes as f32 * config.validation_fraction).round() as usize)
+        .clamp(1, config.episodes - 1);
+    let mut train = Split::default();
+    let mut validation = Split::default();
+    for episode in 0..config.episodes {
+        let split = if episode < held_out {
+            &mut validation
+        } else {
+            &mut train
+        };
+        collect_episode(config, species, teacher, controllers, episode, split);
+    }
+    if train.len(input_size) == 0 || validation.len(input_size) == 0 {
+        bail!(
+            "{} took no decisions in the collected episodes",
+            species.display_name()
+        );
+    }
+
+    let student = PolicyConfig::new(input_size, action_count)
+        .with_hidden_size(config.hidden_size)
+        .with_hidden_layers(config.hidden_layers);
+    let device = Default::default();
+    let mut model = seeded_init(
+        student.init::<TrainBackend>(&device),
+        derive_seed(config.seed, &[0x5EED, species as u64]),
+    );
+    let mut optimizer = init_optimizer::<TrainBackend>(&PpoConfig::default());
+    let mut rng = ChaCha8Rng::seed_from_u64(derive_seed(config.seed, &[0xD157, species as u64]));
+    let mut order: Vec<usize> = (0..train.len(input_size)).collect();
+    let mut epochs = Vec::with_capacity(config.epochs);
+    for epoch in 1..=config.epochs {
+        order.shuffle(&mut rng);
+        let mut loss_sum = 0.0;
+        for chunk in order.chunks(config.minibatch_size.max(1)) {
+            let mut inputs = Vec::with_capacity(chunk.len() * input_size);
+            let mut targets = Vec::with_capacity(chunk.len() * action_count);
+            for &i in chunk {
+                inputs.extend_from_slice(&train.inputs[i * input_size..(i + 1) * input_size]);
+                targets.extend_from_slice(&train.targets[i * action_count..(i + 1) * action_count]);
+            }
+            let inputs = Tensor::<TrainBackend, 2>::from_data(
+                TensorData::new(inputs, [chunk.len(), input_size]),
+                &device,
+            );
+            let targets = Tensor::<TrainBackend, 2>::from_data(
+                TensorData::new(targets, [chunk.len(), action_count]),
+                &device,
+            );
+
+            let logits = model.forward(inputs).logits;
+            let loss = (activation::log_softmax(logits, 1) * targets)
+                .sum_dim(1)
+                .mean()
+                .neg();
+            loss_sum += loss.clone().into_scalar().elem::<f32>() * chunk.len() as f32;
+
+            let grads = GradientsParams::from_grads(loss.backward(), &model);
+            model = optimizer.step(config.learning_rate, model, grads);
+        }
+        epochs.push(DistillEpoch {
+            epoch,
+            loss: loss_sum / order.len().max(1) as f32,
+        });
+    }
+
+    let student_network = model.valid();
+    let mut variants = vec![measure(
+        "teacher f32",
+        teacher.network.num_params(),
+        |inputs| teacher.network.action_probabilities(inputs, &device),
+        &validation,
+        config,
+        input_size,
+    )];
+    if let Some(quantized) = QuantizedPolicy::from_network(&teacher.network) {
+        variants.push(VariantReport {
+            bytes: quantized.size_bytes(),
+            ..measure(
+                "teacher int8",
+                teacher.network.num_params(),
+                |inputs| quantized.action_probabilities(inputs),
+                &validation,
+                config,
+                input_size,
+            )
+        });
+    }
+    variants.push(measure(
+        "student f32",
+        student_network.num_params(),
+        |inputs| student_network.action_probabilities(inputs, &device),
+        &validation,
+        config,
+        input_size,
+    ));
+    let quantized = QuantizedPolicy::from_network(&student_network)
+        .expect("students are plain MLPs and always quantize");
+    variants.push(VariantReport {
+        bytes: quantized.size_bytes(),
+        ..measure(
+            "student int8",
+            student_network.num_params(),
+            |inputs| quantized.action_probabilities(inputs),
+            &validation,
+            config,
+            input_size,
+        )
+    });
+
+    Ok(DistilledPolicy {
+        model,
+        teacher: teacher.metadata.clone(),
+        student,
+        report: DistillReport {
+            species,
+            train_steps: train.len(input_size),
+            validation_steps: validation.len(input_size),
+            epochs,
+            variants,
+        },
+    })
+}
+
+/// Play one episode and append the teacher's decisions to `split`
+fn collect_episode(
+    config: &DistillConfig,
+    species: OrganismType,
+    teacher: &PolicyModel<InferenceBackend>,
+    controllers: &[Controller],
+    episode: usize,
+    split: &mut Split,
+) {
+    let seed = derive_seed(config.seed, &[0xD157, episode as u64]);
+    let encoder = teacher.metadata.encoder;
+    drive_episode(
+        &config.env,
+        seed,
+        controllers,
+        config.ticks,
+        false,
+        &mut |kind, members, rng| {
+            if kind != species {
+                return None;
+            }
+            let start = split.inputs.len();
+            for agent in members {
+                encoder.encode_into(&agent.observation, &mut split.inputs);
+            }
+            teacher
+                .metadata
+                .normalizer
+                .normalize(&mut split.inputs[start..]);
+            let probabilities = teacher
+                .network
+                .action_probabilities(&split.inputs[start..], &Default::default());
+            let mut actions = Vec::with_capacity(members.len());
+            for (agent, probabilities) in members.iter().zip(&probabilities) {
+                let action = sample_index(probabilities, rng);
+                actions.push((
+                    agent.entity,
+                    teacher.metadata.action_space.to_command(action),
+                ));
+                split.targets.extend_from_slice(probabilities);
+            }
+            Some(actions)
+        },
+    );
+}
+
+/// Agreement, KL and batch time of one variant on the held-out steps
+fn measure(
+    name: &str,
+    parameters: usize,
+    probabilities: impl Fn(&[f32]) -> Vec<Vec<f32>>,
+    validation: &Split,
+    config: &DistillConfig,
+    input_size: usize,
+) -> VariantReport {
+    let predicted = probabilities(&validation.inputs);
+    let action_count = validation.targets.len() / predicted.len().max(1);
+    let mut agreed = 0;
+    let mut kl = 0.0;
+    for (predicted, target) in predicted
+        .iter()
+        .zip(validation.targets.chunks_exact(action_count.max(1)))
+    {
+        if greedy_index(predicted) == greedy_index(target) {
+            agreed += 1;
+        }
+        kl += target
+            .iter()
+            .zip(predicted)
+            .filter(|(p, _)| **p > 0.0)
+            .map(|(p, q)| p * (p / q.max(1e-8)).ln())
+            .sum::<f32>();
+    }
+    let steps = predicted.len().max(1) as f32;
+
+    let rows = validation.len(input_size);
+    let batch: Vec<f32> = (0..config.timing_batch.max(1))
+        .flat_map(|row| {
+            let row = row % rows.max(1);
+            validation.inputs[row * input_size..(row + 1) * input_size]
+                .iter()
+                .copied()
+        })
+        .collect();
+    black_box(probabilities(&batch));
+    let repeats = config.timing_repeats.max(1);
+    let started = Instant::now();
+    for _ in 0..repeats {
+        black_box(probabilities(black_box(&batch)));
+    }
+    let batch_micros = started.elapsed().as_secs_f64() * 1e6 / repeats as f64;
+
+    VariantReport {
+        name: name.to_string(),
+        parameters,
+        bytes: 4 * parameters,
+        agreement: agreed as f32 / steps,
+        kl: kl / steps,
+        batch_micros,
+    }
+}
+
+/// Human-readable table of a [`DistillReport`]
+pub fn render_report(report: &DistillReport) -> String {
+    let mut out = format!(
+        "{}: {} training steps, {} held out\n",
+        report.species.display_name(),
+        report.train_steps,
+        report.validation_steps
+    );
+    out.push_str(&format!(
+        "{:<14} {:>8} {:>9} {:>9} {:>8} {:>11} {:>8}\n",
+        "variant", "params", "bytes", "agreement", "kl", "batch us", "speedup"
+    ));
+    let baseline = report.variants.first().map_or(0.0, |v| v.batch_micros);
+    for variant in &report.variants {
+        out.push_str(&format!(
+            "{:<14} {:>8} {:>9} {:>9.3} {:>8.4} {:>11.1} {:>7.2}x\n",
+            variant.name,
+            variant.parameters,
+            variant.bytes,
+            variant.agreement,
+            variant.kl,
+            variant.batch_micros,
+            baseline / variant.batch_micros.max(f64::EPSILON)
+        ));
+    }
+    out
+}
+
+/// Write `<out>/models/<species>.policy` for every student, plus
+/// `<out>/distill_report.json`
+pub fn export_distilled(
+    out_dir: &Path,
+    config: &DistillConfig,
+    distilled: &[DistilledPolicy],
+) -> Result<Vec<PathBuf>> {
+    let dir = models_dir(out_dir);
+    fs::create_dir_all(&dir).with_context(|| format!("creating {}", dir.display()))?;
+    let run_id = out_dir
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "distill".to_string());
+    let hyperparameters = serde_json::to_value(config)?;
+
+    let mut paths = Vec::with_capacity(distilled.len());
+    for policy in distilled {
+        let species = policy.report.species;
+        let metadata = ModelMetadata {
+            run_id: run_id.clone(),
+            hyperparameters: hyperparameters.clone(),
+            ..ModelMetadata::new(
+                species,
+                policy.teacher.encoder,
+                policy.teacher.action_space,
+                policy.student.clone(),
+                policy.teacher.normalizer.clone(),
+            )
+        };
+        let path = dir.join(model_file_name(species));
+        save_policy(&path, &metadata, &policy.model.valid())?;
+        paths.push(path);
+    }
+    let reports: Vec<&DistillReport> = distilled.iter().map(|policy| &policy.report).collect();
+    let report_path = out_dir.join("distill_report.json");
+    fs::write(&report_path, serde_json::to_string_pretty(&reports)?)
+        .with_context(|| format!("writing {}", report_path.display()))?;
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ecosystem_ai_runtime::HeuristicConfig;
+    use ecosystem_ai_shared::{ActionSpace, ObservationEncoder, RunningNormalizer, load_policy};
+
+    #[test]
+    fn test_distill_teacher_into_smaller_student() {
+        let encoder = ObservationEncoder::default();
+        let action_space = ActionSpace::default();
+        let policy = PolicyConfig::new(encoder.input_size(), action_space.size())
+            .with_hidden_size(128)
+            .with_hidden_layers(2);
+        let teacher = PolicyModel {
+            network: seeded_init(policy.init(&Default::default()), 11),
+            metadata: ModelMetadata::new(
+                OrganismType::Blue,
+                encoder,
+                action_space,
+                policy,
+                RunningNormalizer::new(encoder.input_size(), 5.0),
+            ),
+        };
+        let controllers = [
+            Controller::Policy {
+                path: PathBuf::from("teacher.policy"),
+                model: Box::new(teacher),
+            },
+            Controller::Heuristic(HeuristicConfig::default()),
+        ];
+        let config = DistillConfig {
+            hidden_size: 16,
+            episodes: 3,
+            ticks: 40,
+            epochs: 15,
+            timing_batch: 64,
+            timing_repeats: 2,
+            env: EnvConfig {
+                half_extent: 80.0,
+                blue_count: 8,
+                red_count: 2,
+                plant_count: 10,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let distilled = distill_species(&config, OrganismType::Blue, &controllers).unwrap();
+        let report = &distilled.report;
+        assert!(report.validation_steps > 0 && report.train_steps > report.validation_steps);
+        assert!(report.epochs.last().unwrap().loss < report.epochs[0].loss);
+        let names: Vec<&str> = report.variants.iter().map(|v| v.name.as_str()).collect();
+        assert_eq!(
+            names,
+            ["teacher f32", "teacher int8", "student f32", "student int8"]
+        );
+        let teacher = report.variant("teacher f32").unwrap();
+        let student = report.variant("student f32").unwrap();
+        assert_eq!(teacher.agreement, 1.0);
+        assert!(student.parameters * 10 < teacher.parameters);
+        assert!(student.kl < 0.1, "{report:?}");
+        assert!(render_report(report).contains("student int8"));
+
+        let dir = std::env::temp_dir().join(format!("ecosystem-distill-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let paths = export_distilled(&dir, &config, &[distilled]).unwrap();
+        assert!(dir.join("distill_report.json").exists());
+        let loaded = load_policy::<InferenceBackend>(
+            &paths[0],
+            &encoder,
+            &action_space,
+            &Default::default(),
+        )
+        .unwrap();
+        assert_eq!(loaded.metadata.policy.hidden_size, 16);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
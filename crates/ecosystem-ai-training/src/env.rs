@@ -0,0 +1,321 @@
+//! Headless simulation environment for training
+//!
+//! Wraps a bevy `World` running the physics schedule and exposes the usual
+//! reset / observe / step interface, with rewards derived from physics events.
+
+use bevy_ecs::prelude::*;
+use ecosystem_ai_shared::PerceptionSnapshot;
+use ecosystem_components::prelude::*;
+use ecosystem_physics::{Consumed, Died, PhysicsConfig, init_physics_world, physics_schedule};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+
+/// World setup for one episode
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EnvConfig {
+    /// Half the side length of the square world
+    pub half_extent: f32,
+    pub blue_count: usize,
+    pub red_count: usize,
+    pub plant_count: usize,
+    /// Multiplier on predator top speed (e.g. slower predators early in a curriculum)
+    pub red_speed_scale: f32,
+    /// Chance per tick that a plant regrows while below `plant_count`
+    pub plant_regrowth: f32,
+    pub physics: PhysicsConfig,
+}
+
+impl Default for EnvConfig {
+    fn default() -> Self {
+        Self {
+            half_extent: 300.0,
+            blue_count: 20,
+            red_count: 5,
+            plant_count: 40,
+            red_speed_scale: 1.0,
+            plant_regrowth: 0.3,
+            physics: PhysicsConfig::default(),
+        }
+    }
+}
+
+/// Reward shaping per event
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RewardConfig {
+    /// Given to every living agent each tick
+    pub survival: f32,
+    /// Given to prey for eating a plant
+    pub eat_plant: f32,
+    /// Given to predators for catching prey
+    pub catch_prey: f32,
+    /// Given to an agent on the tick it dies
+    pub death: f32,
+}
+
+impl Default for RewardConfig {
+    fn default() -> Self {
+        Self {
+            survival: 0.01,
+            eat_plant: 1.0,
+            catch_prey: 1.0,
+            death: -1.0,
+        }
+    }
+}
+
+/// What one agent sees before acting
+#[derive(Debug, Clone)]
+pub struct AgentObservation {
+    pub entity: Entity,
+    pub organism_type: OrganismType,
+    pub observation: ObservationData,
+}
+
+/// Per-agent reward for the tick just simulated
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AgentReward {
+    pub entity: Entity,
+    pub reward: f32,
+    /// The agent died this tick and will not act again
+    pub done: bool,
+}
+
+/// One episode of the headless simulation
+pub struct SimEnv {
+    world: World,
+    schedule: Schedule,
+    rng: ChaCha8Rng,
+    config: EnvConfig,
+    rewards: RewardConfig,
+    tick: u32,
+    prey_caught: usize,
+}
+
+impl SimEnv {
+    /// Build a fresh world populated according to `config`
+    pub fn new(config: EnvConfig, rewards: RewardConfig, seed: u64) -> Self {
+        let mut world = World::new();
+        let bounds = WorldBounds::centered(config.half_extent);
+        init_physics_world(&mut world, config.physics.clone(), bounds);
+
+        let mut env = Self {
+            world,
+            schedule: physics_schedule(),
+            rng: ChaCha8Rng::seed_from_u64(seed),
+            config,
+            rewards,
+            tick: 0,
+            prey_caught: 0,
+        };
+        let counts = [
+            (OrganismType::Plant, env.config.plant_count),
+            (OrganismType::Blue, env.config.blue_count),
+            (OrganismType::Red, env.config.red_count),
+        ];
+        for (organism_type, count) in counts {
+            for _ in 0..count {
+                env.spawn(organism_type);
+            }
+        }
+        env
+    }
+
+    pub fn tick(&self) -> u32 {
+        self.tick
+    }
+
+    pub fn world(&self) -> &World {
+        &self.world
+    }
+
+    pub fn world_mut(&mut self) -> &mut World {
+        &mut self.world
+    }
+
+    /// Prey consumed by predators since the episode started
+    pub fn prey_caught(&self) -> usize {
+        self.prey_caught
+    }
+
+    /// Give every living organism of a species the brain that drives it, so
+    /// an enabled metabolism charges for it
+    pub fn set_brain_size(&mut self, organism_type: OrganismType, brain: BrainSize) {
+        let mut query = self
+            .world
+            .query_filtered::<(&OrganismType, &mut BrainSize), With<Alive>>();
+        for (kind, mut size) in query.iter_mut(&mut self.world) {
+            if *kind == organism_type {
+                *size = brain;
+            }
+        }
+    }
+
+    /// Number of living organisms of a type
+    pub fn population(&mut self, organism_type: OrganismType) -> usize {
+        let mut query = self.world.query_filtered::<&OrganismType, With<Alive>>();
+        query
+            .iter(&self.world)
+            .filter(|kind| **kind == organism_type)
+            .count()
+    }
+
+    /// Observations for every living mobile organism, in stable world order
+    pub fn observe(&mut self) -> Vec<AgentObservation> {
+        let snapshot = PerceptionSnapshot::from_world(&mut self.world);
+        let mut query = self.world.query_filtered::<(
+            Entity,
+            &OrganismType,
+            &Position,
+            &Vision,
+            &Health,
+            &Energy,
+            &Movement,
+            &Collision,
+        ), With<Alive>>();
+        query
+            .iter(&self.world)
+            .filter(|(_, _, _, _, _, _, movement, _)| movement.is_mobile())
+            .map(
+                |(entity, organism_type, position, vision, health, energy, movement, collision)| {
+                    AgentObservation {
+                        entity,
+                        organism_type: *organism_type,
+                        observation: snapshot
+                            .observe(entity, position, vision, health, energy)
+                            .with_traits(BodyTraits::new(vision, collision, movement, energy)),
+                    }
+                },
+            )
+            .collect()
+    }
+
+    /// Apply actions, advance one tick and return rewards for every agent
+    /// that acted (in the order of `actions`)
+    pub fn step(&mut self, actions: &[(Entity, ActionCommand)]) -> Vec<AgentReward> {
+        for (entity, command) in actions {
+            if let Some(mut action) = self.world.get_mut::<ActionCommand>(*entity) {
+                *action = command.clone();
+            }
+        }
+
+        self.schedule.run(&mut self.world);
+        self.tick += 1;
+
+        let consumed: Vec<Consumed> = self
+            .world
+            .resource_mut::<Events<Consumed>>()
+            .drain()
+            .collect();
+        let died: Vec<Died> = self.world.resource_mut::<Events<Died>>().drain().collect();
+        self.prey_caught += consumed
+            .iter()
+            .filter(|meal| meal.food_type != OrganismType::Plant)
+            .count();
+
+        let rewards = actions
+            .iter()
+            .map(|(entity, _)| {
+                let mut reward = AgentReward {
+                    entity: *entity,
+                    reward: self.rewards.survival,
+                    done: false,
+                };
+                for meal in consumed.iter().filter(|meal| meal.eater == *entity) {
+                    reward.reward += match meal.food_type {
+                        OrganismType::Plant => self.rewards.eat_plant,
+                        _ => self.rewards.catch_prey,
+                    };
+                }
+                if died.iter().any(|death| death.entity == *entity) {
+                    reward.reward = self.rewards.death;
+                    reward.done = true;
+                }
+                reward
+            })
+            .collect();
+
+        self.regrow_plants();
+        rewards
+    }
+
+    fn regrow_plants(&mut self) {
+        if self.population(OrganismType::Plant) < self.config.plant_count
+            && self
+                .rng
+                .gen_bool(self.config.plant_regrowth.clamp(0.0, 1.0) as f64)
+        {
+            self.spawn(OrganismType::Plant);
+        }
+    }
+
+    fn spawn(&mut self, organism_type: OrganismType) -> Entity {
+        let position = self
+            .world
+            .resource::<WorldBounds>()
+            .random_position(&mut self.rng);
+        let entity = OrganismFactory::spawn(&mut self.world, organism_type, position);
+        if organism_type == OrganismType::Red
+            && let Some(mut movement) = self.world.get_mut::<Movement>(entity)
+        {
+            movement.max_speed *= self.config.red_speed_scale.max(0.0);
+        }
+        entity
+    }
+}
+
+/// Derive an independent, reproducible seed (splitmix64 over the inputs)
+pub fn derive_seed(base: u64, parts: &[u64]) -> u64 {
+    let mut state = base;
+    for part in parts {
+        state ^= part.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        state = state.wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        state ^= state >> 31;
+        state = state.wrapping_mul(0x94D0_49BB_1331_11EB);
+        state ^= state >> 29;
+    }
+    state
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_seed_same_episode() {
+        let run = |seed| {
+            let mut env = SimEnv::new(EnvConfig::default(), RewardConfig::default(), seed);
+            let mut total = 0.0;
+            for _ in 0..20 {
+                let actions: Vec<_> = env
+                    .observe()
+                    .into_iter()
+                    .map(|agent| (agent.entity, ActionCommand::move_to(Vec2::X)))
+                    .collect();
+                total += env.step(&actions).iter().map(|r| r.reward).sum::<f32>();
+            }
+            (total, env.population(OrganismType::Plant))
+        };
+        assert_eq!(run(3), run(3));
+    }
+
+    #[test]
+    fn test_plants_do_not_act() {
+        let mut env = SimEnv::new(EnvConfig::default(), RewardConfig::default(), 0);
+        let agents = env.observe();
+        assert_eq!(agents.len(), 25);
+        assert!(
+            agents
+                .iter()
+                .all(|agent| agent.organism_type != OrganismType::Plant)
+        );
+    }
+
+    #[test]
+    fn test_derive_seed_spreads() {
+        assert_ne!(derive_seed(1, &[0, 1]), derive_seed(1, &[1, 0]));
+        assert_eq!(derive_seed(1, &[2, 3]), derive_seed(1, &[2, 3]));
+    }
+}
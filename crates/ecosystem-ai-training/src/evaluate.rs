@@ -0,0 +1,579 @@
+//! Policy evaluation over many seeds
+//!
+//! Plays Blue and Red, each driven by a model file or the heuristic baseline,
+//! for a fixed number of ticks on several seeds, and reports every metric as
+//! the mean over seeds with a 95% confidence interval. Each seed runs in its
+//! own world with its own RNG, so reports do not depend on the worker count.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use bevy_ecs::entity::Entity;
+use ecosystem_ai_runtime::HeuristicConfig;
+use ecosystem_ai_shared::{
+    InferenceBackend, PolicyModel, greedy_index, load_policy, read_metadata, sample_index,
+};
+use ecosystem_components::prelude::*;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use serde::Serialize;
+
+use crate::env::{AgentObservation, EnvConfig, RewardConfig, SimEnv, derive_seed};
+use crate::trainer::model_file_name;
+use crate::workers::{gather_states, parallel_ordered_map};
+
+/// Species whose survival is evaluated; Blue is the prey, Red the predator
+pub const EVALUATED_SPECIES: [OrganismType; 2] = [OrganismType::Blue, OrganismType::Red];
+
+/// How many episodes to play and how
+#[derive(Debug, Clone, PartialEq)]
+pub struct EvaluationConfig {
+    /// Number of episodes, one per derived seed
+    pub seeds: usize,
+    /// Ticks per episode (episodes end early once every agent died)
+    pub ticks: u32,
+    /// Base seed the episode seeds are derived from
+    pub seed: u64,
+    /// Take the most likely action instead of sampling
+    pub greedy: bool,
+    pub env: EnvConfig,
+    /// Threads playing episodes in parallel
+    pub workers: usize,
+}
+
+impl Default for EvaluationConfig {
+    fn default() -> Self {
+        Self {
+            seeds: 20,
+            ticks: 1000,
+            seed: 0,
+            greedy: false,
+            env: EnvConfig::default(),
+            workers: 1,
+        }
+    }
+}
+
+/// What drives a species during evaluation
+#[derive(Debug, Clone)]
+pub enum Controller {
+    Policy {
+        path: PathBuf,
+        model: Box<PolicyModel<InferenceBackend>>,
+    },
+    Heuristic(HeuristicConfig),
+}
+
+impl Controller {
+    /// `<dir>/<species>.policy` if it exists, the heuristic baseline otherwise
+    pub fn load(models_dir: &Path, species: OrganismType) -> Result<Self> {
+        let path = models_dir.join(model_file_name(species));
+        if !path.exists() {
+            return Ok(Self::Heuristic(HeuristicConfig::default()));
+        }
+        let metadata =
+            read_metadata(&path).with_context(|| format!("reading {}", path.display()))?;
+        if metadata.species != species {
+            bail!(
+                "{} holds a {} policy, expected {}",
+                path.display(),
+                metadata.species.display_name(),
+                species.display_name()
+            );
+        }
+        let model = load_policy(
+            &path,
+            &metadata.encoder,
+            &metadata.action_space,
+            &Default::default(),
+        )
+        .with_context(|| format!("loading {}", path.display()))?;
+        Ok(Self::Policy {
+            path,
+            model: Box::new(model),
+        })
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Self::Policy { path, model } => format!(
+                "policy {} (run {}, iteration {})",
+                path.display(),
+                model.metadata.run_id,
+                model.metadata.iteration
+            ),
+            Self::Heuristic(_) => "heuristic".to_string(),
+        }
+    }
+}
+
+/// Mean over seeds with a 95% confidence interval
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Estimate {
+    pub mean: f64,
+    pub ci_low: f64,
+    pub ci_high: f64,
+}
+
+impl Estimate {
+    /// Student-t interval of the mean; a single sample has no spread
+    pub fn from_samples(samples: &[f64]) -> Self {
+        let n = samples.len();
+        if n == 0 {
+            return Self {
+                mean: 0.0,
+                ci_low: 0.0,
+                ci_high: 0.0,
+            };
+        }
+        let mean = samples.iter().sum::<f64>() / n as f64;
+        let half_width = if n > 1 {
+            let variance = samples.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1) as f64;
+            t_critical_95(n - 1) * (variance / n as f64).sqrt()
+        } else {
+            0.0
+        };
+        Self {
+            mean,
+            ci_low: mean - half_width,
+            ci_high: mean + half_width,
+        }
+    }
+
+    /// Wilson score interval of a rate, which stays inside [0, 1] even at
+    /// zero or full counts
+    pub fn proportion(hits: usize, trials: usize) -> Self {
+        if trials == 0 {
+            return Self::from_samples(&[]);
+        }
+        let (n, z) = (trials as f64, 1.96_f64);
+        let rate = hits as f64 / n;
+        let denominator = 1.0 + z * z / n;
+        let center = (rate + z * z / (2.0 * n)) / denominator;
+        let half_width = z * (rate * (1.0 - rate) / n + z * z / (4.0 * n * n)).sqrt() / denominator;
+        Self {
+            mean: rate,
+            ci_low: (center - half_width).max(0.0),
+            ci_high: (center + half_width).min(1.0),
+        }
+    }
+}
+
+/// Two-sided 95% quantile of Student's t distribution
+fn t_critical_95(degrees_of_freedom: usize) -> f64 {
+    const TABLE: [f64; 30] = [
+        12.706, 4.303, 3.182, 2.776, 2.571, 2.447, 2.365, 2.306, 2.262, 2.228, 2.201, 2.179, 2.160,
+        2.145, 2.131, 2.120, 2.110, 2.101, 2.093, 2.086, 2.080, 2.074, 2.069, 2.064, 2.060, 2.056,
+        2.052, 2.048, 2.045, 2.042,
+    ];
+    match degrees_of_freedom {
+        0 => f64::INFINITY,
+        df if df <= TABLE.len() => TABLE[df - 1],
+        df if df <= 60 => 2.000,
+        df if df <= 120 => 1.980,
+        _ => 1.960,
+    }
+}
+
+/// Outcome of one species in one episode
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SpeciesOutcome {
+    pub species: OrganismType,
+    /// Mean ticks lived by the initial population; survivors count the
+    /// whole episode
+    pub survival_ticks: f64,
+    pub end_population: usize,
+    pub extinct: bool,
+}
+
+/// Raw metrics of one episode
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EpisodeOutcome {
+    pub seed: u64,
+    pub ticks: u32,
+    pub species: Vec<SpeciesOutcome>,
+    pub plant_population: usize,
+    pub prey_caught: usize,
+    /// Share of the initial prey population caught by predators
+    pub predation_success: f64,
+}
+
+/// Aggregate metrics of one species
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SpeciesSummary {
+    pub species: OrganismType,
+    pub controller: String,
+    pub survival_ticks: Estimate,
+    pub end_population: Estimate,
+    /// Share of episodes in which the species died out
+    pub extinction_rate: Estimate,
+}
+
+/// Everything `evaluate` reports, serialized as-is for `--json`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EvaluationReport {
+    pub seeds: usize,
+    pub ticks: u32,
+    pub greedy: bool,
+    pub species: Vec<SpeciesSummary>,
+    pub plant_population: Estimate,
+    pub prey_caught: Estimate,
+    pub predation_success: Estimate,
+    pub episodes: Vec<EpisodeOutcome>,
+}
+
+/// Play `config.seeds` episodes with one controller per evaluated species
+pub fn evaluate(config: &EvaluationConfig, controllers: &[Controller]) -> EvaluationReport {
+    assert_eq!(
+        controllers.len(),
+        EVALUATED_SPECIES.len(),
+        "one controller per evaluated species"
+    );
+    let episodes = play_episodes(config, controllers);
+
+    let species = EVALUATED_SPECIES
+        .iter()
+        .zip(controllers)
+        .enumerate()
+        .map(|(index, (species, controller))| {
+            let outcomes: Vec<&SpeciesOutcome> = episodes
+                .iter()
+                .map(|episode| &episode.species[index])
+                .collect();
+            let values = |metric: fn(&SpeciesOutcome) -> f64| -> Vec<f64> {
+                outcomes.iter().map(|outcome| metric(outcome)).collect()
+            };
+            SpeciesSummary {
+                species: *species,
+                controller: controller.describe(),
+                survival_ticks: Estimate::from_samples(&values(|o| o.survival_ticks)),
+                end_population: Estimate::from_samples(&values(|o| o.end_population as f64)),
+                extinction_rate: Estimate::proportion(
+                    outcomes.iter().filter(|outcome| outcome.extinct).count(),
+                    outcomes.len(),
+                ),
+            }
+        })
+        .collect();
+    let values =
+        |metric: fn(&EpisodeOutcome) -> f64| -> Vec<f64> { episodes.iter().map(metric).collect() };
+    EvaluationReport {
+        seeds: config.seeds,
+        ticks: config.ticks,
+        greedy: config.greedy,
+        species,
+        plant_population: Estimate::from_samples(&values(|e| e.plant_population as f64)),
+        prey_caught: Estimate::from_samples(&values(|e| e.prey_caught as f64)),
+        predation_success: Estimate::from_samples(&values(|e| e.predation_success)),
+        episodes,
+    }
+}
+
+fn play_episodes(config: &EvaluationConfig, controllers: &[Controller]) -> Vec<EpisodeOutcome> {
+    parallel_ordered_map(
+        (0..config.seeds).collect(),
+        config.workers,
+        // Networks are not `Sync`: every worker gets its own copy
+        || controllers.to_vec(),
+        |controllers, index| run_episode(config, controllers, index),
+    )
+}
+
+/// Actions of one species' `members` under `controller`
+///
+/// `memory` carries the recurrent state of each member between ticks.
+pub(crate) fn controller_actions(
+    controller: &Controller,
+    members: &[&AgentObservation],
+    env: &SimEnv,
+    memory: &mut BTreeMap<Entity, Vec<f32>>,
+    greedy: bool,
+    rng: &mut ChaCha8Rng,
+) -> Vec<(Entity, ActionCommand)> {
+    let mut actions = Vec::with_capacity(members.len());
+    match controller {
+        Controller::Policy { model, .. } => {
+            let encoder = model.metadata.encoder;
+            let mut inputs = Vec::with_capacity(members.len() * encoder.input_size());
+            for agent in members {
+                encoder.encode_into(&agent.observation, &mut inputs);
+            }
+            model.metadata.normalizer.normalize(&mut inputs);
+            let state_size = model.network.state_size();
+            let states = gather_states(memory, members, state_size);
+            let (evaluation, next_states) =
+                model
+                    .network
+                    .evaluate_step(&inputs, &states, &Default::default());
+            for (agent, probabilities) in members.iter().zip(&evaluation.probabilities) {
+                let action = if greedy {
+                    greedy_index(probabilities)
+                } else {
+                    sample_index(probabilities, rng)
+                };
+                actions.push((agent.entity, model.metadata.action_space.to_command(action)));
+            }
+            for (agent, state) in members
+                .iter()
+                .zip(next_states.chunks_exact(state_size.max(1)))
+            {
+                memory.insert(agent.entity, state.to_vec());
+            }
+        }
+        Controller::Heuristic(heuristic) => {
+            for agent in members {
+                let previous = env
+                    .world()
+                    .get::<ActionCommand>(agent.entity)
+                    .cloned()
+                    .unwrap_or_default();
+                let command =
+                    heuristic.decide(agent.organism_type, &agent.observation, &previous, rng);
+                actions.push((agent.entity, command));
+            }
+        }
+    }
+    actions
+}
+
+/// Actions a species' members take this tick, or `None` to leave them to
+/// their [`Controller`]
+pub(crate) type SpeciesHook<'a> = dyn FnMut(
+        OrganismType,
+        &[&AgentObservation],
+        &mut ChaCha8Rng,
+    ) -> Option<Vec<(Entity, ActionCommand)>>
+    + 'a;
+
+/// World and deaths of an episode played by [`drive_episode`]
+pub(crate) struct DrivenEpisode {
+    pub env: SimEnv,
+    /// Every agent alive at the start, with its species
+    pub initial: Vec<(Entity, OrganismType)>,
+    /// Tick each agent that died did so
+    pub died_at: BTreeMap<Entity, u32>,
+}
+
+/// Play up to `ticks` ticks of the episode seeded by `seed`, with each of
+/// [`EVALUATED_SPECIES`] under its controller unless `hook` takes it over
+pub(crate) fn drive_episode(
+    env_config: &EnvConfig,
+    seed: u64,
+    controllers: &[Controller],
+    ticks: u32,
+    greedy: bool,
+    hook: &mut SpeciesHook<'_>,
+) -> DrivenEpisode {
+    let mut rng = ChaCha8Rng::seed_from_u64(derive_seed(seed, &[0xE7A1]));
+    let mut env = SimEnv::new(env_config.clone(), RewardConfig::default(), seed);
+    for (species, controller) in EVALUATED_SPECIES.iter().zip(controllers) {
+        if let Controller::Policy { model, .. } = controller {
+            env.set_brain_size(*species, model.network.brain_size());
+        }
+    }
+    let initial = env
+        .observe()
+        .into_iter()
+        .map(|agent| (agent.entity, agent.organism_type))
+        .collect();
+    let mut memories: Vec<BTreeMap<Entity, Vec<f32>>> =
+        controllers.iter().map(|_| BTreeMap::new()).collect();
+    let mut died_at = BTreeMap::new();
+
+    for _ in 0..ticks {
+        let agents = env.observe();
+        if agents.is_empty() {
+            break;
+        }
+
+        let mut actions = Vec::with_capacity(agents.len());
+        for (index, (species, controller)) in EVALUATED_SPECIES.iter().zip(controllers).enumerate()
+        {
+            let members: Vec<_> = agents
+                .iter()
+                .filter(|agent| agent.organism_type == *species)
+                .collect();
+            if members.is_empty() {
+                continue;
+            }
+            match hook(*species, &members, &mut rng) {
+                Some(taken) => actions.extend(taken),
+                None => actions.extend(controller_actions(
+                    controller,
+                    &members,
+                    &env,
+                    &mut memories[index],
+                    greedy,
+                    &mut rng,
+                )),
+            }
+        }
+
+        for reward in env.step(&actions) {
+            if reward.done {
+                died_at.insert(reward.entity, env.tick());
+            }
+        }
+    }
+    DrivenEpisode {
+        env,
+        initial,
+        died_at,
+    }
+}
+
+fn run_episode(
+    config: &EvaluationConfig,
+    controllers: &[Controller],
+    index: usize,
+) -> EpisodeOutcome {
+    let seed = derive_seed(config.seed, &[index as u64]);
+    let DrivenEpisode {
+        mut env,
+        initial,
+        died_at,
+    } = drive_episode(
+        &config.env,
+        seed,
+        controllers,
+        config.ticks,
+        config.greedy,
+        &mut |_, _, _| None,
+    );
+
+    let ticks = env.tick();
+    let species = EVALUATED_SPECIES
+        .iter()
+        .map(|species| {
+            let lifetimes: Vec<u32> = initial
+                .iter()
+                .filter(|(_, organism_type)| organism_type == species)
+                .map(|(entity, _)| died_at.get(entity).copied().unwrap_or(ticks))
+                .collect();
+            let end_population = env.population(*species);
+            SpeciesOutcome {
+                species: *species,
+                survival_ticks: if lifetimes.is_empty() {
+                    0.0
+                } else {
+                    lifetimes.iter().map(|&t| t as f64).sum::<f64>() / lifetimes.len() as f64
+                },
+                end_population,
+                extinct: !lifetimes.is_empty() && end_population == 0,
+            }
+        })
+        .collect();
+    let initial_prey = initial
+        .iter()
+        .filter(|(_, organism_type)| *organism_type == OrganismType::Blue)
+        .count();
+    EpisodeOutcome {
+        seed,
+        ticks,
+        species,
+        plant_population: env.population(OrganismType::Plant),
+        prey_caught: env.prey_caught(),
+        predation_success: if initial_prey == 0 {
+            0.0
+        } else {
+            env.prey_caught() as f64 / initial_prey as f64
+        },
+    }
+}
+
+/// Human-readable table of a report
+pub fn render_report(report: &EvaluationReport) -> String {
+    let mut out = String::new();
+    let mode = if report.greedy { "greedy" } else { "sampled" };
+    let _ = writeln!(
+        out,
+        "Evaluated {} seeds x {} ticks ({mode} actions)",
+        report.seeds, report.ticks
+    );
+    for summary in &report.species {
+        let _ = writeln!(
+            out,
+            "  {:<6} {}",
+            format!("{}:", summary.species.short_name()),
+            summary.controller
+        );
+    }
+    let _ = writeln!(out);
+    let _ = writeln!(out, "{:<26} {:>10}  95% CI", "metric", "mean");
+
+    let mut line = |label: String, estimate: &Estimate| {
+        let _ = writeln!(
+            out,
+            "{label:<26} {:>10.3}  [{:.3}, {:.3}]",
+            estimate.mean, estimate.ci_low, estimate.ci_high
+        );
+    };
+    for summary in &report.species {
+        let name = summary.species.short_name();
+        line(format!("{name} survival (ticks)"), &summary.survival_ticks);
+        line(format!("{name} end population"), &summary.end_population);
+        line(format!("{name} extinction rate"), &summary.extinction_rate);
+    }
+    line("Plant end population".to_string(), &report.plant_population);
+    line("Prey caught".to_string(), &report.prey_caught);
+    line("Predation success".to_string(), &report.predation_success);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn small_config() -> EvaluationConfig {
+        EvaluationConfig {
+            seeds: 3,
+            ticks: 60,
+            env: EnvConfig {
+                half_extent: 60.0,
+                blue_count: 6,
+                red_count: 3,
+                plant_count: 8,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_estimates() {
+        let estimate = Estimate::from_samples(&[1.0, 2.0, 3.0]);
+        assert!((estimate.mean - 2.0).abs() < 1e-12);
+        assert!((estimate.ci_high - 2.0 - 4.303 / 3f64.sqrt()).abs() < 1e-9);
+
+        let never = Estimate::proportion(0, 10);
+        assert_eq!((never.mean, never.ci_low), (0.0, 0.0));
+        assert!((never.ci_high - 0.2775).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_report_independent_of_workers() {
+        let controllers = vec![Controller::Heuristic(HeuristicConfig::default()); 2];
+        let config = small_config();
+        let report = evaluate(&config, &controllers);
+        let parallel = evaluate(
+            &EvaluationConfig {
+                workers: 3,
+                ..config.clone()
+            },
+            &controllers,
+        );
+        assert_eq!(report, parallel);
+        assert_eq!(report.episodes.len(), 3);
+
+        for summary in &report.species {
+            let survival = summary.survival_ticks;
+            assert!(survival.ci_low <= survival.mean && survival.mean <= survival.ci_high);
+            assert!(survival.mean <= config.ticks as f64);
+        }
+        let text = render_report(&report);
+        assert!(text.contains("Predation success"));
+        assert!(serde_json::to_string(&report).is_ok());
+    }
+}
@@ -0,0 +1,130 @@
+//! League of frozen past policies used as opponents
+
+use ecosystem_components::prelude::OrganismType;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// How snapshots are taken and how often they play
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LeagueConfig {
+    /// Iterations between snapshots (0 disables the league)
+    pub snapshot_interval: u32,
+    /// Snapshots kept per species; the oldest is evicted first
+    pub max_snapshots: usize,
+    /// Chance that an episode pits learners against a past opponent
+    pub opponent_probability: f32,
+}
+
+impl Default for LeagueConfig {
+    fn default() -> Self {
+        Self {
+            snapshot_interval: 10,
+            max_snapshots: 8,
+            opponent_probability: 0.2,
+        }
+    }
+}
+
+/// Serialized weights of a species' policy at some iteration
+#[derive(Debug, Clone, PartialEq)]
+pub struct LeagueSnapshot {
+    pub species: OrganismType,
+    pub iteration: u32,
+    pub weights: Vec<u8>,
+}
+
+/// Bounded pool of past policies per species
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LeaguePool {
+    snapshots: Vec<LeagueSnapshot>,
+    max_per_species: usize,
+}
+
+impl LeaguePool {
+    pub fn new(max_per_species: usize) -> Self {
+        Self {
+            snapshots: Vec::new(),
+            max_per_species,
+        }
+    }
+
+    pub fn snapshots(&self) -> &[LeagueSnapshot] {
+        &self.snapshots
+    }
+
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+
+    pub fn has_species(&self, species: OrganismType) -> bool {
+        self.snapshots.iter().any(|s| s.species == species)
+    }
+
+    /// Add a snapshot, evicting the oldest one of the same species if full
+    pub fn add(&mut self, snapshot: LeagueSnapshot) {
+        let species = snapshot.species;
+        self.snapshots.push(snapshot);
+        while self
+            .snapshots
+            .iter()
+            .filter(|s| s.species == species)
+            .count()
+            > self.max_per_species
+        {
+            let oldest = self
+                .snapshots
+                .iter()
+                .position(|s| s.species == species)
+                .expect("species has snapshots");
+            self.snapshots.remove(oldest);
+        }
+    }
+
+    /// Uniformly pick one snapshot of `species`
+    pub fn sample<R: Rng + ?Sized>(
+        &self,
+        species: OrganismType,
+        rng: &mut R,
+    ) -> Option<&LeagueSnapshot> {
+        let candidates: Vec<&LeagueSnapshot> = self
+            .snapshots
+            .iter()
+            .filter(|s| s.species == species)
+            .collect();
+        if candidates.is_empty() {
+            None
+        } else {
+            Some(candidates[rng.gen_range(0..candidates.len())])
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eviction_is_per_species() {
+        let mut pool = LeaguePool::new(2);
+        for iteration in 0..3 {
+            for species in [OrganismType::Blue, OrganismType::Red] {
+                pool.add(LeagueSnapshot {
+                    species,
+                    iteration,
+                    weights: vec![iteration as u8],
+                });
+            }
+        }
+        assert_eq!(pool.len(), 4);
+        assert!(pool.snapshots().iter().all(|s| s.iteration > 0));
+        assert!(
+            pool.sample(OrganismType::Plant, &mut rand::thread_rng())
+                .is_none()
+        );
+    }
+}
@@ -0,0 +1,29 @@
+//! Headless multi-agent training for ecosystem organisms
+//!
+//! Runs the physics simulation without rendering, collects experience for
+//! every trained species on parallel rollout workers and updates one PPO
+//! policy per species. Runs can be checkpointed and resumed exactly. Policies
+//! can also be cloned from recorded decisions and used to warm-start PPO, and
+//! evaluated over many seeds with confidence intervals. Hyperparameter sweeps
+//! train and evaluate many configs in parallel and rank them. Large policies
+//! can be distilled into smaller students for cheaper inference.
+
+pub mod checkpoint;
+pub mod cloning;
+pub mod config;
+pub mod critic;
+pub mod curiosity;
+pub mod curriculum;
+pub mod distill;
+pub mod env;
+pub mod evaluate;
+pub mod league;
+pub mod metrics;
+pub mod ppo;
+pub mod rollout;
+pub mod sweep;
+pub mod trainer;
+pub mod workers;
+
+pub use config::TrainingConfig;
+pub use trainer::Trainer;
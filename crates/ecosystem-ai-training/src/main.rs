@@ -1,3 +1,395 @@
-fn main() {
-    println!("Hello, world!");
+use std::path::PathBuf;
+
+use anyhow::{Context, Result, bail};
+use clap::{Args, Parser, Subcommand};
+use ecosystem_ai_runtime::HeuristicConfig;
+use ecosystem_ai_shared::read_dataset;
+use ecosystem_ai_training::cloning::{clone_species, export_cloned};
+use ecosystem_ai_training::distill::{self, DistillConfig, distill_species, export_distilled};
+use ecosystem_ai_training::evaluate::{
+    self, Controller, EVALUATED_SPECIES, EvaluationConfig, render_report,
+};
+use ecosystem_ai_training::sweep::{LEADERBOARD_FILE, SweepConfig, run_dir, run_sweep};
+use ecosystem_ai_training::{Trainer, TrainingConfig};
+
+/// Train ecosystem organism policies
+#[derive(Parser, Debug)]
+#[command(version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Run (or resume) PPO training
+    Train(TrainArgs),
+
+    /// Export runtime model files from a run's latest checkpoint
+    Export(ExportArgs),
+
+    /// Fit policies to recorded decisions (behavior cloning)
+    Clone(CloneArgs),
+
+    /// Train smaller student policies from a teacher's action distribution
+    Distill(DistillArgs),
+
+    /// Measure Blue and Red over many seeds, with 95% confidence intervals
+    Evaluate(EvaluateArgs),
+
+    /// Train and evaluate a grid or random sample of configs, ranking them
+    Sweep(SweepArgs),
+}
+
+#[derive(Args, Debug)]
+struct TrainArgs {
+    /// Run directory for config, checkpoints and outputs
+    #[arg(long, default_value = "runs/default")]
+    out: PathBuf,
+
+    /// Training config (JSON); defaults are used when omitted
+    #[arg(long, conflicts_with = "resume")]
+    config: Option<PathBuf>,
+
+    /// Continue from the latest checkpoint in the run directory
+    #[arg(long)]
+    resume: bool,
+
+    /// Rollout worker threads (overrides the config; results do not change)
+    #[arg(long)]
+    workers: Option<usize>,
+}
+
+#[derive(Args, Debug)]
+struct ExportArgs {
+    /// Run directory holding checkpoints
+    #[arg(long, default_value = "runs/default")]
+    out: PathBuf,
+}
+
+#[derive(Args, Debug)]
+struct CloneArgs {
+    /// Trajectory datasets, e.g. written by `ecosystem --record`
+    #[arg(long, required = true, num_args = 1..)]
+    recordings: Vec<PathBuf>,
+
+    /// Training config (JSON) with the network, encoder and `cloning` settings
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// Output directory; models land in `<out>/models`, usable as `warm_start`
+    #[arg(long, default_value = "runs/clone")]
+    out: PathBuf,
+}
+
+#[derive(Args, Debug)]
+struct DistillArgs {
+    /// Directory with the teachers' `<species>.policy` files; species without
+    /// one play the heuristic baseline and are not distilled
+    #[arg(long)]
+    models: PathBuf,
+
+    /// Distill the Blue policy
+    #[arg(long)]
+    blue: bool,
+
+    /// Distill the Red policy
+    #[arg(long)]
+    red: bool,
+
+    /// Student hidden layer width
+    #[arg(long, default_value_t = 32)]
+    hidden_size: usize,
+
+    /// Student hidden layers
+    #[arg(long, default_value_t = 1)]
+    hidden_layers: usize,
+
+    /// Episodes played by the teacher to collect observations
+    #[arg(long, default_value_t = 8)]
+    episodes: usize,
+
+    /// Ticks per episode
+    #[arg(long, default_value_t = 400)]
+    ticks: u32,
+
+    #[arg(long, default_value_t = 20)]
+    epochs: usize,
+
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+
+    /// Training config (JSON) whose `env` sets up the world
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// Output directory; students land in `<out>/models`
+    #[arg(long, default_value = "runs/distill")]
+    out: PathBuf,
+}
+
+#[derive(Args, Debug)]
+struct EvaluateArgs {
+    /// Directory with `<species>.policy` files; species without one play the
+    /// heuristic baseline
+    #[arg(long)]
+    models: Option<PathBuf>,
+
+    /// Number of episodes, each on its own seed
+    #[arg(long, default_value_t = 20)]
+    seeds: usize,
+
+    /// Ticks per episode
+    #[arg(long, default_value_t = 1000)]
+    ticks: u32,
+
+    /// Base seed the episode seeds are derived from
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+
+    /// Training config (JSON) whose `env` sets up the world
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// Take the most likely action instead of sampling
+    #[arg(long)]
+    greedy: bool,
+
+    /// Episode worker threads (results do not change)
+    #[arg(long, default_value_t = 1)]
+    workers: usize,
+
+    /// Print the report, including every episode, as JSON
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Args, Debug)]
+struct SweepArgs {
+    /// Sweep config (JSON) with the base config, parameters and metric
+    #[arg(long)]
+    config: PathBuf,
+
+    /// Sweep directory; every run trains in its own subdirectory
+    #[arg(long, default_value = "runs/sweep")]
+    out: PathBuf,
+
+    /// Training runs at once (overrides the config)
+    #[arg(long)]
+    parallel: Option<usize>,
+}
+
+fn main() -> Result<()> {
+    // Keep escape codes out of redirected output
+    if !console::Term::stdout().is_term() {
+        colored::control::set_override(false);
+    }
+    match Cli::parse().command {
+        Command::Train(args) => train(args),
+        Command::Export(args) => export(args),
+        Command::Clone(args) => clone(args),
+        Command::Distill(args) => distill(args),
+        Command::Evaluate(args) => evaluate(args),
+        Command::Sweep(args) => sweep(args),
+    }
+}
+
+fn sweep(args: SweepArgs) -> Result<()> {
+    let mut config = SweepConfig::load(&args.config)?;
+    if let Some(parallel) = args.parallel {
+        config.parallel = parallel;
+    }
+    let metric = config.metric.name();
+    let results = run_sweep(&config, &args.out, |result| {
+        let settings: Vec<String> = result
+            .trial
+            .assignments
+            .iter()
+            .map(|(path, value)| format!("{path}={value}"))
+            .collect();
+        match &result.outcome {
+            Ok(estimate) => println!(
+                "{} {metric} {:.3} [{:.3}, {:.3}] ({})",
+                result.trial.name,
+                estimate.mean,
+                estimate.ci_low,
+                estimate.ci_high,
+                settings.join(", ")
+            ),
+            Err(error) => println!("{} failed: {error}", result.trial.name),
+        }
+    })?;
+    if let Some(best) = results.first()
+        && best.outcome.is_ok()
+    {
+        println!("Best run: {}", run_dir(&args.out, &best.trial).display());
+    }
+    println!("Wrote {}", args.out.join(LEADERBOARD_FILE).display());
+    Ok(())
+}
+
+fn evaluate(args: EvaluateArgs) -> Result<()> {
+    let env = match &args.config {
+        Some(path) => TrainingConfig::load(path)?.env,
+        None => TrainingConfig::default().env,
+    };
+    let controllers = EVALUATED_SPECIES
+        .iter()
+        .map(|species| match &args.models {
+            Some(dir) => Controller::load(dir, *species),
+            None => Ok(Controller::Heuristic(HeuristicConfig::default())),
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let config = EvaluationConfig {
+        seeds: args.seeds,
+        ticks: args.ticks,
+        seed: args.seed,
+        greedy: args.greedy,
+        env,
+        workers: args.workers,
+    };
+    let report = evaluate::evaluate(&config, &controllers);
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print!("{}", render_report(&report));
+    }
+    Ok(())
+}
+
+fn distill(args: DistillArgs) -> Result<()> {
+    let env = match &args.config {
+        Some(path) => TrainingConfig::load(path)?.env,
+        None => TrainingConfig::default().env,
+    };
+    let config = DistillConfig {
+        hidden_size: args.hidden_size,
+        hidden_layers: args.hidden_layers,
+        episodes: args.episodes,
+        ticks: args.ticks,
+        epochs: args.epochs,
+        seed: args.seed,
+        env,
+        ..Default::default()
+    };
+    let controllers = EVALUATED_SPECIES
+        .iter()
+        .map(|species| Controller::load(&args.models, *species))
+        .collect::<Result<Vec<_>>>()?;
+    let species: Vec<_> = [
+        (EVALUATED_SPECIES[0], args.blue),
+        (EVALUATED_SPECIES[1], args.red),
+    ]
+    .into_iter()
+    .filter_map(|(species, selected)| selected.then_some(species))
+    .collect();
+    if species.is_empty() {
+        bail!("pick at least one species to distill with --blue or --red");
+    }
+
+    let mut distilled = Vec::new();
+    for species in species {
+        let policy = distill_species(&config, species, &controllers)?;
+        for epoch in &policy.report.epochs {
+            println!(
+                "{} epoch {:>3} | loss {:.4}",
+                species.short_name(),
+                epoch.epoch,
+                epoch.loss
+            );
+        }
+        print!("{}", distill::render_report(&policy.report));
+        distilled.push(policy);
+    }
+    for path in export_distilled(&args.out, &config, &distilled)? {
+        println!("Wrote {}", path.display());
+    }
+    Ok(())
+}
+
+fn clone(args: CloneArgs) -> Result<()> {
+    let config = match &args.config {
+        Some(path) => TrainingConfig::load(path)?,
+        None => TrainingConfig::default(),
+    };
+    let recordings = args
+        .recordings
+        .iter()
+        .map(|path| read_dataset(path).with_context(|| format!("reading {}", path.display())))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut cloned = Vec::new();
+    for species in &config.trained_species {
+        if !recordings
+            .iter()
+            .flatten()
+            .any(|step| step.species == *species)
+        {
+            println!("{}: no recorded steps, skipped", species.display_name());
+            continue;
+        }
+        let policy = clone_species(&config, *species, &recordings)?;
+        let report = &policy.report;
+        for epoch in &report.epochs {
+            println!(
+                "{} epoch {:>3} | loss {:.4} train acc {:.3}",
+                species.short_name(),
+                epoch.epoch,
+                epoch.loss,
+                epoch.train_accuracy
+            );
+        }
+        println!(
+            "{}: held-out accuracy {:.3} (majority baseline {:.3}) on {} steps, trained on {}",
+            species.display_name(),
+            report.validation_accuracy,
+            report.baseline_accuracy,
+            report.validation_steps,
+            report.train_steps
+        );
+        cloned.push(policy);
+    }
+    if cloned.is_empty() {
+        bail!("the recordings hold no steps of any trained species");
+    }
+    for path in export_cloned(&args.out, &config, &cloned)? {
+        println!("Wrote {}", path.display());
+    }
+    Ok(())
+}
+
+fn export(args: ExportArgs) -> Result<()> {
+    let trainer = Trainer::resume(&args.out)?;
+    for path in trainer.export_policies()? {
+        println!("Wrote {}", path.display());
+    }
+    Ok(())
+}
+
+fn train(args: TrainArgs) -> Result<()> {
+    let mut trainer = if args.resume {
+        let trainer = Trainer::resume(&args.out)?;
+        println!(
+            "Resuming {} at iteration {}",
+            args.out.display(),
+            trainer.iteration()
+        );
+        trainer
+    } else {
+        if args.out.join("checkpoints").exists() {
+            bail!(
+                "{} already holds checkpoints; pass --resume or choose another --out",
+                args.out.display()
+            );
+        }
+        let config = match &args.config {
+            Some(path) => TrainingConfig::load(path)?,
+            None => TrainingConfig::default(),
+        };
+        Trainer::new(config, &args.out)?
+    };
+    if let Some(workers) = args.workers {
+        trainer.set_workers(workers);
+    }
+    trainer.run()
 }
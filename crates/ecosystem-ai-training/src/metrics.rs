@@ -0,0 +1,288 @@
+//! Training metrics: a per-iteration CSV, a progress bar and a summary table
+//!
+//! `<run>/metrics.csv` gets one row per iteration with, per trained species,
+//! mean reward, episode length and PPO diagnostics, plus the mean population
+//! of every organism type at episode end. Resuming a run keeps the rows up
+//! to the checkpoint and drops any written after it.
+
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use colored::Colorize;
+use ecosystem_components::prelude::OrganismType;
+use indicatif::{ProgressBar, ProgressStyle};
+
+use crate::trainer::IterationStats;
+
+/// File name of the metrics CSV inside a run directory
+pub const METRICS_FILE: &str = "metrics.csv";
+
+/// Appends one CSV row per iteration
+pub struct MetricsLog {
+    path: PathBuf,
+    writer: csv::Writer<File>,
+}
+
+impl MetricsLog {
+    /// Open `<run>/metrics.csv` for a run currently at `iteration`
+    pub fn open(out_dir: &Path, species: &[OrganismType], iteration: u32) -> Result<Self> {
+        let path = out_dir.join(METRICS_FILE);
+        let kept: Vec<csv::StringRecord> = if path.exists() {
+            let mut reader = csv::Reader::from_path(&path)
+                .with_context(|| format!("reading {}", path.display()))?;
+            reader
+                .records()
+                .filter_map(|record| record.ok())
+                .filter(|record| {
+                    record
+                        .get(0)
+                        .and_then(|value| value.parse::<u32>().ok())
+                        .is_some_and(|row| row <= iteration)
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        let mut writer =
+            csv::Writer::from_path(&path).with_context(|| format!("writing {}", path.display()))?;
+        writer.write_record(header(species))?;
+        for record in &kept {
+            writer.write_record(record)?;
+        }
+        writer.flush()?;
+        Ok(Self { path, writer })
+    }
+
+    pub fn append(&mut self, stats: &IterationStats) -> Result<()> {
+        self.writer
+            .write_record(row(stats))
+            .and_then(|()| self.writer.flush().map_err(csv::Error::from))
+            .with_context(|| format!("writing {}", self.path.display()))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// Column names for a run training `species`
+pub fn header(species: &[OrganismType]) -> Vec<String> {
+    let mut columns: Vec<String> = [
+        "iteration",
+        "global_step",
+        "stage",
+        "promoted_to",
+        "league_episodes",
+        "agent_steps_per_second",
+    ]
+    .map(String::from)
+    .to_vec();
+    for species in species {
+        let prefix = species.short_name().to_lowercase();
+        for metric in [
+            "reward",
+            "intrinsic_reward",
+            "episode_length",
+            "steps",
+            "policy_loss",
+            "value_loss",
+            "entropy",
+            "approx_kl",
+            "clip_fraction",
+        ] {
+            columns.push(format!("{prefix}_{metric}"));
+        }
+    }
+    for organism_type in OrganismType::all() {
+        columns.push(format!(
+            "{}_population",
+            organism_type.short_name().to_lowercase()
+        ));
+    }
+    columns
+}
+
+fn row(stats: &IterationStats) -> Vec<String> {
+    let mut values = vec![
+        stats.iteration.to_string(),
+        stats.global_step.to_string(),
+        stats.stage.clone().unwrap_or_default(),
+        stats
+            .promotion
+            .as_ref()
+            .map(|promotion| promotion.to.clone())
+            .unwrap_or_default(),
+        stats.league_episodes.to_string(),
+        format!("{:.1}", stats.agent_steps_per_second),
+    ];
+    for species in &stats.species {
+        values.extend(
+            [
+                species.mean_reward,
+                species.mean_intrinsic_reward,
+                species.mean_episode_length,
+                species.steps as f32,
+                species.ppo.policy_loss,
+                species.ppo.value_loss,
+                species.ppo.entropy,
+                species.ppo.approx_kl,
+                species.ppo.clip_fraction,
+            ]
+            .map(|value| value.to_string()),
+        );
+    }
+    for organism_type in OrganismType::all() {
+        let population = stats
+            .populations
+            .iter()
+            .find(|(kind, _)| kind == organism_type)
+            .map_or(0.0, |(_, population)| *population);
+        values.push(population.to_string());
+    }
+    values
+}
+
+/// Progress over `total` iterations, starting at `position`; hidden when
+/// stderr is not a terminal
+pub fn progress_bar(total: u32, position: u32) -> ProgressBar {
+    let bar = ProgressBar::new(total as u64).with_position(position as u64);
+    bar.set_style(
+        ProgressStyle::with_template(
+            "{bar:30.cyan/blue} {pos}/{len} iters [{elapsed_precise} eta {eta_precise}] {msg}",
+        )
+        .expect("valid progress template")
+        .progress_chars("=> "),
+    );
+    bar
+}
+
+/// One-line report of an iteration
+pub fn iteration_line(stats: &IterationStats) -> String {
+    let species: Vec<String> = stats
+        .species
+        .iter()
+        .map(|s| {
+            let bonus = if s.mean_intrinsic_reward != 0.0 {
+                format!(" (+{:.3} curiosity)", s.mean_intrinsic_reward)
+            } else {
+                String::new()
+            };
+            format!(
+                "{} reward {:.3}{bonus} len {:.1} entropy {:.3} kl {:.4}",
+                s.species.short_name(),
+                s.mean_reward,
+                s.mean_episode_length,
+                s.ppo.entropy,
+                s.ppo.approx_kl
+            )
+        })
+        .collect();
+    let mut line = format!(
+        "iter {:>5} | {} | {:.0} steps/s",
+        stats.iteration,
+        species.join(" | "),
+        stats.agent_steps_per_second
+    );
+    if let Some(promotion) = &stats.promotion {
+        line.push_str(&format!(
+            "\ncurriculum: {} -> {} ({:?} {:.3})",
+            promotion.from, promotion.to, promotion.metric, promotion.value
+        ));
+    }
+    line
+}
+
+/// Table comparing the first and last iteration of a training session
+pub fn summary_table(first: &IterationStats, last: &IterationStats, elapsed: Duration) -> String {
+    let mut table = format!(
+        "{}\n{:<8} {:>9} {:>9} {:>9} {:>9} {:>9} {:>9}\n",
+        format!(
+            "Trained iterations {}-{} in {:.1}s ({} agent steps)",
+            first.iteration,
+            last.iteration,
+            elapsed.as_secs_f32(),
+            last.global_step
+        )
+        .bold(),
+        "species",
+        "reward",
+        "change",
+        "length",
+        "change",
+        "entropy",
+        "kl"
+    );
+    for species in &last.species {
+        let before = first
+            .species
+            .iter()
+            .find(|s| s.species == species.species)
+            .unwrap_or(species);
+        let change = |now: f32, then: f32| {
+            let text = format!("{:>+9.3}", now - then);
+            if now > then {
+                text.green()
+            } else if now < then {
+                text.red()
+            } else {
+                text.normal()
+            }
+        };
+        table.push_str(&format!(
+            "{:<8} {:>9.3} {} {:>9.1} {} {:>9.3} {:>9.4}\n",
+            species.species.short_name().bold(),
+            species.mean_reward,
+            change(species.mean_reward, before.mean_reward),
+            species.mean_episode_length,
+            change(species.mean_episode_length, before.mean_episode_length),
+            species.ppo.entropy,
+            species.ppo.approx_kl
+        ));
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn stats(iteration: u32) -> IterationStats {
+        IterationStats {
+            iteration,
+            global_step: iteration as u64 * 10,
+            species: Vec::new(),
+            league_episodes: 0,
+            agent_steps_per_second: 1.0,
+            stage: Some("full".into()),
+            promotion: None,
+            populations: vec![(OrganismType::Blue, 3.5)],
+        }
+    }
+
+    #[test]
+    fn test_reopening_drops_rows_after_checkpoint() {
+        let dir = std::env::temp_dir().join(format!("ecosystem-metrics-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut log = MetricsLog::open(&dir, &[], 0).unwrap();
+        for iteration in 1..=3 {
+            log.append(&stats(iteration)).unwrap();
+        }
+        drop(log);
+
+        let mut log = MetricsLog::open(&dir, &[], 1).unwrap();
+        log.append(&stats(2)).unwrap();
+        let text = fs::read_to_string(log.path()).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("iteration,global_step,stage"));
+        assert!(lines[0].ends_with("blue_population,red_population,plant_population"));
+        assert!(lines[1].starts_with("1,10,full"));
+        assert!(lines[2].starts_with("2,20,full"));
+        assert!(lines[2].ends_with("3.5,0,0"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
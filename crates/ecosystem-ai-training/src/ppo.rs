@@ -0,0 +1,237 @@
+//! Proximal Policy Optimization update
+
+use burn::grad_clipping::GradientClippingConfig;
+use burn::optim::adaptor::OptimizerAdaptor;
+use burn::optim::{Adam, AdamConfig, GradientsParams, Optimizer};
+use burn::prelude::*;
+use burn::tensor::activation;
+use burn::tensor::backend::AutodiffBackend;
+use ecosystem_ai_shared::PolicyNetwork;
+use rand::Rng;
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+
+use crate::rollout::Batch;
+
+/// PPO hyperparameters
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PpoConfig {
+    pub learning_rate: f64,
+    pub gamma: f32,
+    pub gae_lambda: f32,
+    pub clip_epsilon: f32,
+    pub value_coef: f32,
+    pub entropy_coef: f32,
+    pub epochs: usize,
+    /// Minibatch size in steps
+    pub minibatch_size: usize,
+    pub max_grad_norm: f32,
+    /// Truncated BPTT sequence length for recurrent policies
+    pub bptt_length: usize,
+}
+
+impl Default for PpoConfig {
+    fn default() -> Self {
+        Self {
+            learning_rate: 3e-4,
+            gamma: 0.99,
+            gae_lambda: 0.95,
+            clip_epsilon: 0.2,
+            value_coef: 0.5,
+            entropy_coef: 0.01,
+            epochs: 4,
+            minibatch_size: 256,
+            max_grad_norm: 0.5,
+            bptt_length: 16,
+        }
+    }
+}
+
+/// Mean diagnostics over every minibatch of an update
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct PpoStats {
+    pub policy_loss: f32,
+    pub value_loss: f32,
+    pub entropy: f32,
+    pub approx_kl: f32,
+    pub clip_fraction: f32,
+}
+
+/// Adam with gradient-norm clipping, as used for every policy
+pub type PolicyOptimizer<B> = OptimizerAdaptor<Adam, PolicyNetwork<B>, B>;
+
+pub fn init_optimizer<B: AutodiffBackend>(config: &PpoConfig) -> PolicyOptimizer<B> {
+    AdamConfig::new()
+        .with_grad_clipping(Some(GradientClippingConfig::Norm(config.max_grad_norm)))
+        .init()
+}
+
+/// Run `config.epochs` passes of clipped-surrogate updates over `batch`
+///
+/// Feed-forward policies train on shuffled single steps. Recurrent policies
+/// train on shuffled sequences of up to `config.bptt_length` steps, each
+/// replayed from the state recorded at its first step, with gradients
+/// flowing through time inside the sequence only.
+pub fn ppo_update<B: AutodiffBackend, R: Rng>(
+    mut model: PolicyNetwork<B>,
+    optimizer: &mut PolicyOptimizer<B>,
+    batch: &Batch,
+    config: &PpoConfig,
+    rng: &mut R,
+    device: &B::Device,
+) -> (PolicyNetwork<B>, PpoStats) {
+    let len = batch.len();
+    if len == 0 {
+        return (model, PpoStats::default());
+    }
+
+    let mean = batch.advantages.iter().sum::<f32>() / len as f32;
+    let variance = batch
+        .advantages
+        .iter()
+        .map(|advantage| (advantage - mean).powi(2))
+        .sum::<f32>()
+        / len as f32;
+    let std = variance.sqrt() + 1e-8;
+    let advantages: Vec<f32> = batch
+        .advantages
+        .iter()
+        .map(|advantage| (advantage - mean) / std)
+        .collect();
+
+    let input_size = batch.input_size;
+    let state_size = batch.state_size;
+    let sequence_length = if state_size > 0 {
+        config.bptt_length.max(1)
+    } else {
+        1
+    };
+    let mut sequences = batch.sequences(sequence_length);
+    let sequences_per_minibatch = (config.minibatch_size / sequence_length).max(1);
+    let mut totals = PpoStats::default();
+    let mut minibatches = 0;
+
+    for _ in 0..config.epochs {
+        sequences.shuffle(rng);
+        for chunk in sequences.chunks(sequences_per_minibatch) {
+            let width = chunk.len();
+            let steps = chunk.iter().map(|(_, len)| *len).max().unwrap_or(0);
+            // Time-major rows; `None` pads sequences shorter than `steps`
+            let rows: Vec<Option<usize>> = (0..steps)
+                .flat_map(|t| {
+                    chunk
+                        .iter()
+                        .map(move |&(start, len)| (t < len).then_some(start + t))
+                })
+                .collect();
+            let present = rows.iter().filter(|row| row.is_some()).count() as f32;
+            let gather = |values: &[f32]| -> Tensor<B, 1> {
+                let picked: Vec<f32> = rows
+                    .iter()
+                    .map(|row| row.map_or(0.0, |i| values[i]))
+                    .collect();
+                Tensor::from_data(TensorData::new(picked, [rows.len()]), device)
+            };
+            let mask: Tensor<B, 1> = Tensor::from_data(
+                TensorData::new(
+                    rows.iter()
+                        .map(|row| if row.is_some() { 1.0 } else { 0.0 })
+                        .collect::<Vec<f32>>(),
+                    [rows.len()],
+                ),
+                device,
+            );
+            let masked_mean = |values: Tensor<B, 1>| (values * mask.clone()).sum() / present;
+            let actions: Vec<i64> = rows
+                .iter()
+                .map(|row| row.map_or(0, |i| batch.actions[i] as i64))
+                .collect();
+            let actions =
+                Tensor::<B, 2, Int>::from_data(TensorData::new(actions, [rows.len(), 1]), device);
+            let old_log_probs = gather(&batch.log_probs);
+            let minibatch_advantages = gather(&advantages);
+            let returns = gather(&batch.returns);
+
+            let mut state = (state_size > 0).then(|| {
+                let mut states = Vec::with_capacity(width * state_size);
+                for &(start, _) in chunk {
+                    states.extend_from_slice(
+                        &batch.states[start * state_size..(start + 1) * state_size],
+                    );
+                }
+                Tensor::<B, 2>::from_data(TensorData::new(states, [width, state_size]), device)
+            });
+            let mut logits = Vec::with_capacity(steps);
+            let mut values = Vec::with_capacity(steps);
+            for t in 0..steps {
+                let mut observations = Vec::with_capacity(width * input_size);
+                for row in &rows[t * width..(t + 1) * width] {
+                    match row {
+                        Some(i) => observations.extend_from_slice(
+                            &batch.observations[i * input_size..(i + 1) * input_size],
+                        ),
+                        None => observations.extend(std::iter::repeat_n(0.0, input_size)),
+                    }
+                }
+                let observations = Tensor::<B, 2>::from_data(
+                    TensorData::new(observations, [width, input_size]),
+                    device,
+                );
+                let (output, next_state) = model.forward_step(observations, state);
+                state = next_state;
+                logits.push(output.logits);
+                values.push(output.values);
+            }
+            let logits = Tensor::cat(logits, 0);
+            let values = Tensor::cat(values, 0);
+
+            let log_probs = activation::log_softmax(logits, 1);
+            let new_log_probs: Tensor<B, 1> = log_probs.clone().gather(1, actions).squeeze(1);
+
+            let log_ratio = new_log_probs - old_log_probs;
+            let ratio = log_ratio.clone().exp();
+            let unclipped = ratio.clone() * minibatch_advantages.clone();
+            let clipped = ratio
+                .clone()
+                .clamp(1.0 - config.clip_epsilon, 1.0 + config.clip_epsilon)
+                * minibatch_advantages;
+            let policy_loss = masked_mean(unclipped.min_pair(clipped)).neg();
+            let value_loss = masked_mean((values - returns).powf_scalar(2.0));
+            let entropy =
+                masked_mean((log_probs.clone().exp() * log_probs).sum_dim(1).squeeze(1)).neg();
+
+            let loss = policy_loss.clone() + value_loss.clone() * config.value_coef
+                - entropy.clone() * config.entropy_coef;
+            let grads = GradientsParams::from_grads(loss.backward(), &model);
+            model = optimizer.step(config.learning_rate, model, grads);
+
+            totals.policy_loss += scalar(policy_loss);
+            totals.value_loss += scalar(value_loss);
+            totals.entropy += scalar(entropy);
+            totals.approx_kl += scalar(masked_mean(log_ratio.clone().neg()));
+            let clip_fraction = masked_mean(
+                (ratio - 1.0)
+                    .abs()
+                    .greater_elem(config.clip_epsilon)
+                    .float(),
+            );
+            totals.clip_fraction += scalar(clip_fraction);
+            minibatches += 1;
+        }
+    }
+
+    let count = minibatches.max(1) as f32;
+    let stats = PpoStats {
+        policy_loss: totals.policy_loss / count,
+        value_loss: totals.value_loss / count,
+        entropy: totals.entropy / count,
+        approx_kl: totals.approx_kl / count,
+        clip_fraction: totals.clip_fraction / count,
+    };
+    (model, stats)
+}
+
+fn scalar<B: Backend>(tensor: Tensor<B, 1>) -> f32 {
+    tensor.detach().into_scalar().elem()
+}
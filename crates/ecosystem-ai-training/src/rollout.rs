@@ -0,0 +1,325 @@
+//! Experience collection and advantage estimation
+
+use std::collections::BTreeMap;
+
+use bevy_ecs::entity::Entity;
+
+/// Steps taken by one agent during one episode
+#[derive(Debug, Clone, Default)]
+struct Trajectory {
+    observations: Vec<f32>,
+    /// Recurrent state before each step (empty for feed-forward policies)
+    states: Vec<f32>,
+    /// Global state before each step (empty without a centralized critic)
+    global_states: Vec<f32>,
+    actions: Vec<usize>,
+    log_probs: Vec<f32>,
+    values: Vec<f32>,
+    rewards: Vec<f32>,
+    /// Exploration bonus per step, learned from on top of `rewards`
+    intrinsic: Vec<f32>,
+    terminated: bool,
+    bootstrap_value: f32,
+}
+
+/// Per-agent trajectories of one species within one episode
+///
+/// Entities are only unique within a world, so a buffer must not outlive
+/// the episode that produced it; call [`RolloutBuffer::into_batch`] at the end.
+#[derive(Debug, Clone)]
+pub struct RolloutBuffer {
+    input_size: usize,
+    state_size: usize,
+    global_size: usize,
+    index: BTreeMap<Entity, usize>,
+    trajectories: Vec<Trajectory>,
+}
+
+impl RolloutBuffer {
+    pub fn new(input_size: usize) -> Self {
+        Self::with_state_size(input_size, 0)
+    }
+
+    /// Buffer for a recurrent policy with `state_size` values of state per step
+    pub fn with_state_size(input_size: usize, state_size: usize) -> Self {
+        Self {
+            input_size,
+            state_size,
+            global_size: 0,
+            index: BTreeMap::new(),
+            trajectories: Vec::new(),
+        }
+    }
+
+    /// Also keep `global_size` values of global state per step, for a
+    /// centralized critic
+    pub fn with_global_size(mut self, global_size: usize) -> Self {
+        self.global_size = global_size;
+        self
+    }
+
+    fn trajectory(&mut self, entity: Entity) -> &mut Trajectory {
+        let next = self.trajectories.len();
+        let slot = *self.index.entry(entity).or_insert(next);
+        if slot == next {
+            self.trajectories.push(Trajectory::default());
+        }
+        &mut self.trajectories[slot]
+    }
+
+    /// Record an action taken from an (already normalized) observation
+    ///
+    /// `state` is the recurrent state the policy started the step from; pass
+    /// an empty slice for feed-forward policies.
+    pub fn record_step(
+        &mut self,
+        entity: Entity,
+        observation: &[f32],
+        state: &[f32],
+        action: usize,
+        log_prob: f32,
+        value: f32,
+    ) {
+        debug_assert_eq!(observation.len(), self.input_size);
+        debug_assert_eq!(state.len(), self.state_size);
+        let trajectory = self.trajectory(entity);
+        trajectory.observations.extend_from_slice(observation);
+        trajectory.states.extend_from_slice(state);
+        trajectory.actions.push(action);
+        trajectory.log_probs.push(log_prob);
+        trajectory.values.push(value);
+    }
+
+    /// Record the global state the most recent step of `entity` was taken in
+    pub fn record_global_state(&mut self, entity: Entity, global: &[f32]) {
+        debug_assert_eq!(global.len(), self.global_size);
+        self.trajectory(entity)
+            .global_states
+            .extend_from_slice(global);
+    }
+
+    /// Record the reward for the most recent step of `entity`
+    pub fn record_reward(&mut self, entity: Entity, reward: f32, done: bool) {
+        if let Some(&slot) = self.index.get(&entity) {
+            let trajectory = &mut self.trajectories[slot];
+            trajectory.rewards.push(reward);
+            trajectory.intrinsic.push(0.0);
+            trajectory.terminated |= done;
+        }
+    }
+
+    /// Add an intrinsic bonus to the most recently rewarded step of `entity`
+    pub fn add_intrinsic_reward(&mut self, entity: Entity, bonus: f32) {
+        if let Some(&slot) = self.index.get(&entity)
+            && let Some(last) = self.trajectories[slot].intrinsic.last_mut()
+        {
+            *last += bonus;
+        }
+    }
+
+    /// Value estimate of the observation after the last step (episode cut off)
+    pub fn set_bootstrap(&mut self, entity: Entity, value: f32) {
+        if let Some(&slot) = self.index.get(&entity) {
+            self.trajectories[slot].bootstrap_value = value;
+        }
+    }
+
+    pub fn steps(&self) -> usize {
+        self.trajectories.iter().map(|t| t.actions.len()).sum()
+    }
+
+    /// Compute GAE advantages and flatten into a training batch
+    pub fn into_batch(self, gamma: f32, lambda: f32) -> Batch {
+        let mut batch = Batch::with_state_size(self.input_size, self.state_size)
+            .with_global_size(self.global_size);
+        for trajectory in self.trajectories {
+            let steps = trajectory.rewards.len().min(trajectory.actions.len());
+            let mut advantages = vec![0.0; steps];
+            let mut next_value = if trajectory.terminated {
+                0.0
+            } else {
+                trajectory.bootstrap_value
+            };
+            let mut running = 0.0;
+            for t in (0..steps).rev() {
+                let reward = trajectory.rewards[t] + trajectory.intrinsic[t];
+                let delta = reward + gamma * next_value - trajectory.values[t];
+                running = delta + gamma * lambda * running;
+                advantages[t] = running;
+                next_value = trajectory.values[t];
+            }
+
+            batch
+                .observations
+                .extend_from_slice(&trajectory.observations[..steps * self.input_size]);
+            batch
+                .states
+                .extend_from_slice(&trajectory.states[..steps * self.state_size]);
+            batch
+                .global_states
+                .extend_from_slice(&trajectory.global_states[..steps * self.global_size]);
+            batch.actions.extend(&trajectory.actions[..steps]);
+            batch.log_probs.extend(&trajectory.log_probs[..steps]);
+            batch.returns.extend(
+                advantages
+                    .iter()
+                    .zip(&trajectory.values)
+                    .map(|(advantage, value)| advantage + value),
+            );
+            batch.advantages.extend(advantages);
+            batch.rewards.extend(&trajectory.rewards[..steps]);
+            batch
+                .intrinsic_rewards
+                .extend(&trajectory.intrinsic[..steps]);
+            batch.trajectory_lengths.push(steps);
+            batch.episodes += 1;
+        }
+        batch
+    }
+}
+
+/// Flattened on-policy experience for one species
+///
+/// Steps of one agent trajectory are stored contiguously, in order, so
+/// recurrent updates can replay them as sequences.
+#[derive(Debug, Clone, Default)]
+pub struct Batch {
+    pub input_size: usize,
+    /// Recurrent state length per step; 0 for feed-forward policies
+    pub state_size: usize,
+    /// Global state length per step; 0 without a centralized critic
+    pub global_size: usize,
+    pub observations: Vec<f32>,
+    /// Recurrent state each step started from
+    pub states: Vec<f32>,
+    /// Global state each step was taken in
+    pub global_states: Vec<f32>,
+    pub actions: Vec<usize>,
+    pub log_probs: Vec<f32>,
+    pub advantages: Vec<f32>,
+    pub returns: Vec<f32>,
+    /// Extrinsic (environment) reward per step
+    pub rewards: Vec<f32>,
+    /// Curiosity bonus per step
+    pub intrinsic_rewards: Vec<f32>,
+    /// Steps in each trajectory, in storage order
+    pub trajectory_lengths: Vec<usize>,
+    /// Number of agent trajectories folded into the batch
+    pub episodes: usize,
+}
+
+impl Batch {
+    pub fn new(input_size: usize) -> Self {
+        Self::with_state_size(input_size, 0)
+    }
+
+    pub fn with_state_size(input_size: usize, state_size: usize) -> Self {
+        Self {
+            input_size,
+            state_size,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_global_size(mut self, global_size: usize) -> Self {
+        self.global_size = global_size;
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.actions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.actions.is_empty()
+    }
+
+    pub fn extend(&mut self, other: Batch) {
+        self.observations.extend(other.observations);
+        self.states.extend(other.states);
+        self.global_states.extend(other.global_states);
+        self.actions.extend(other.actions);
+        self.log_probs.extend(other.log_probs);
+        self.advantages.extend(other.advantages);
+        self.returns.extend(other.returns);
+        self.rewards.extend(other.rewards);
+        self.intrinsic_rewards.extend(other.intrinsic_rewards);
+        self.trajectory_lengths.extend(other.trajectory_lengths);
+        self.episodes += other.episodes;
+    }
+
+    /// Split trajectories into `(start, len)` sequences of at most
+    /// `max_len` steps, for truncated backpropagation through time
+    pub fn sequences(&self, max_len: usize) -> Vec<(usize, usize)> {
+        let max_len = max_len.max(1);
+        let mut sequences = Vec::new();
+        let mut start = 0;
+        for &length in &self.trajectory_lengths {
+            let mut offset = 0;
+            while offset < length {
+                let len = (length - offset).min(max_len);
+                sequences.push((start + offset, len));
+                offset += len;
+            }
+            start += length;
+        }
+        sequences
+    }
+
+    /// Mean undiscounted return per agent trajectory
+    pub fn mean_episode_reward(&self) -> f32 {
+        if self.episodes == 0 {
+            0.0
+        } else {
+            self.rewards.iter().sum::<f32>() / self.episodes as f32
+        }
+    }
+
+    /// Mean curiosity bonus per agent trajectory
+    pub fn mean_episode_intrinsic_reward(&self) -> f32 {
+        if self.episodes == 0 {
+            0.0
+        } else {
+            self.intrinsic_rewards.iter().sum::<f32>() / self.episodes as f32
+        }
+    }
+
+    /// Mean number of steps an agent survived
+    pub fn mean_episode_length(&self) -> f32 {
+        if self.episodes == 0 {
+            0.0
+        } else {
+            self.len() as f32 / self.episodes as f32
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gae_terminal_and_bootstrap() {
+        let a = Entity::from_raw(1);
+        let b = Entity::from_raw(2);
+        let mut buffer = RolloutBuffer::new(1);
+        for _ in 0..2 {
+            buffer.record_step(a, &[0.0], &[], 1, -0.5, 0.0);
+            buffer.record_step(b, &[1.0], &[], 0, -0.5, 0.0);
+            buffer.record_reward(a, 1.0, false);
+            buffer.record_reward(b, 1.0, false);
+        }
+        buffer.set_bootstrap(b, 10.0);
+
+        let batch = buffer.into_batch(0.5, 1.0);
+
+        assert_eq!(batch.len(), 4);
+        assert_eq!(batch.episodes, 2);
+        // a: rewards [1, 1] and no bootstrap -> [1.5, 1]
+        assert_eq!(&batch.advantages[..2], &[1.5, 1.0]);
+        // b: bootstrap 10 -> [1 + 0.5 * (1 + 5), 1 + 5]
+        assert_eq!(&batch.advantages[2..], &[4.0, 6.0]);
+        assert_eq!(batch.observations, vec![0.0, 0.0, 1.0, 1.0]);
+        assert_eq!(batch.trajectory_lengths, vec![2, 2]);
+    }
+}
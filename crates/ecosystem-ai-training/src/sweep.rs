@@ -0,0 +1,515 @@
+//! Hyperparameter sweeps
+//!
+//! A sweep config lists training config fields (dotted paths such as
+//! `ppo.learning_rate` or `rewards.catch_prey`) with either explicit values or
+//! a numeric range. The grid strategy trains every combination of values, the
+//! random strategy draws `trials` combinations. Runs train in parallel, each
+//! in `<out>/<run>`, and are then evaluated with the exported models; the
+//! results are ranked in `<out>/leaderboard.csv`.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, anyhow, bail};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::config::TrainingConfig;
+use crate::evaluate::{
+    Controller, EVALUATED_SPECIES, Estimate, EvaluationConfig, EvaluationReport, evaluate,
+};
+use crate::trainer::{Trainer, models_dir};
+use crate::workers::parallel_ordered_map;
+
+/// File name of the ranked results inside a sweep directory
+pub const LEADERBOARD_FILE: &str = "leaderboard.csv";
+
+/// Everything a sweep needs; stored as `<out>/sweep.json`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SweepConfig {
+    /// Training config every run starts from
+    pub base: TrainingConfig,
+    pub parameters: Vec<SweepParameter>,
+    pub strategy: SweepStrategy,
+    /// Runs drawn by the random strategy
+    pub trials: usize,
+    /// Seed of the random strategy
+    pub seed: u64,
+    /// Training runs at once
+    pub parallel: usize,
+    /// Evaluation result the leaderboard is ranked by
+    pub metric: SweepMetric,
+    pub goal: SweepGoal,
+    /// Episodes every trained run is evaluated on
+    pub evaluation_seeds: usize,
+    pub evaluation_ticks: u32,
+}
+
+impl Default for SweepConfig {
+    fn default() -> Self {
+        Self {
+            base: TrainingConfig::default(),
+            parameters: Vec::new(),
+            strategy: SweepStrategy::Grid,
+            trials: 8,
+            seed: 0,
+            parallel: 2,
+            metric: SweepMetric::BlueSurvival,
+            goal: SweepGoal::Maximize,
+            evaluation_seeds: 10,
+            evaluation_ticks: 1000,
+        }
+    }
+}
+
+impl SweepConfig {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("reading sweep config {}", path.display()))?;
+        serde_json::from_str(&text)
+            .with_context(|| format!("parsing sweep config {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let text = serde_json::to_string_pretty(self)?;
+        fs::write(path, text).with_context(|| format!("writing {}", path.display()))
+    }
+}
+
+/// One swept field of [`TrainingConfig`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SweepParameter {
+    /// Dotted path into the training config, e.g. `ppo.learning_rate`
+    pub path: String,
+    #[serde(flatten)]
+    pub space: ParameterSpace,
+}
+
+/// Values a parameter can take
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ParameterSpace {
+    /// Explicit choices, used as-is
+    Values { values: Vec<Value> },
+    /// Numbers in `[min, max]`; random sweeps only
+    Range {
+        min: f64,
+        max: f64,
+        /// Sample uniformly in log space (learning rates and the like)
+        #[serde(default)]
+        log: bool,
+        /// Round to a whole number (epochs, batch sizes)
+        #[serde(default)]
+        integer: bool,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SweepStrategy {
+    /// Every combination of the listed values
+    Grid,
+    /// `trials` combinations drawn at random
+    Random,
+}
+
+/// Evaluation result a sweep is ranked by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SweepMetric {
+    BlueSurvival,
+    RedSurvival,
+    BlueEndPopulation,
+    RedEndPopulation,
+    BlueExtinctionRate,
+    RedExtinctionRate,
+    PredationSuccess,
+}
+
+impl SweepMetric {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::BlueSurvival => "blue_survival",
+            Self::RedSurvival => "red_survival",
+            Self::BlueEndPopulation => "blue_end_population",
+            Self::RedEndPopulation => "red_end_population",
+            Self::BlueExtinctionRate => "blue_extinction_rate",
+            Self::RedExtinctionRate => "red_extinction_rate",
+            Self::PredationSuccess => "predation_success",
+        }
+    }
+
+    pub fn estimate(self, report: &EvaluationReport) -> Estimate {
+        let species = |index: usize| &report.species[index];
+        match self {
+            Self::BlueSurvival => species(0).survival_ticks,
+            Self::RedSurvival => species(1).survival_ticks,
+            Self::BlueEndPopulation => species(0).end_population,
+            Self::RedEndPopulation => species(1).end_population,
+            Self::BlueExtinctionRate => species(0).extinction_rate,
+            Self::RedExtinctionRate => species(1).extinction_rate,
+            Self::PredationSuccess => report.predation_success,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SweepGoal {
+    Maximize,
+    Minimize,
+}
+
+/// One planned training run
+#[derive(Debug, Clone, PartialEq)]
+pub struct SweepTrial {
+    pub name: String,
+    /// Swept value of every parameter, in config order
+    pub assignments: Vec<(String, Value)>,
+    pub config: TrainingConfig,
+}
+
+/// How one run ended
+#[derive(Debug, Clone, PartialEq)]
+pub struct SweepResult {
+    pub trial: SweepTrial,
+    /// The ranking metric, or why the run failed
+    pub outcome: Result<Estimate, String>,
+}
+
+/// Expand the config into concrete runs
+pub fn plan_trials(config: &SweepConfig) -> Result<Vec<SweepTrial>> {
+    if config.parameters.is_empty() {
+        bail!("the sweep lists no parameters");
+    }
+    let combinations: Vec<Vec<Value>> = match config.strategy {
+        SweepStrategy::Grid => {
+            let mut combinations = vec![Vec::new()];
+            for parameter in &config.parameters {
+                let ParameterSpace::Values { values } = &parameter.space else {
+                    bail!(
+                        "parameter `{}` is a range; grid sweeps need `values`",
+                        parameter.path
+                    );
+                };
+                if values.is_empty() {
+                    bail!("parameter `{}` lists no values", parameter.path);
+                }
+                combinations = combinations
+                    .into_iter()
+                    .flat_map(|prefix| {
+                        values.iter().map(move |value| {
+                            let mut combination = prefix.clone();
+                            combination.push(value.clone());
+                            combination
+                        })
+                    })
+                    .collect();
+            }
+            combinations
+        }
+        SweepStrategy::Random => {
+            let mut rng = ChaCha8Rng::seed_from_u64(config.seed);
+            (0..config.trials)
+                .map(|_| {
+                    config
+                        .parameters
+                        .iter()
+                        .map(|parameter| sample(parameter, &mut rng))
+                        .collect::<Result<Vec<_>>>()
+                })
+                .collect::<Result<_>>()?
+        }
+    };
+
+    let base = serde_json::to_value(&config.base)?;
+    combinations
+        .into_iter()
+        .enumerate()
+        .map(|(index, values)| {
+            let mut tree = base.clone();
+            let mut assignments = Vec::with_capacity(values.len());
+            for (parameter, value) in config.parameters.iter().zip(values) {
+                let pointer = format!("/{}", parameter.path.replace('.', "/"));
+                let slot = tree
+                    .pointer_mut(&pointer)
+                    .ok_or_else(|| anyhow!("unknown training parameter `{}`", parameter.path))?;
+                *slot = value.clone();
+                assignments.push((parameter.path.clone(), value));
+            }
+            let name = format!("run_{index:03}");
+            let config: TrainingConfig = serde_json::from_value(tree)
+                .with_context(|| format!("invalid parameter values for {name}"))?;
+            Ok(SweepTrial {
+                name,
+                assignments,
+                config,
+            })
+        })
+        .collect()
+}
+
+fn sample(parameter: &SweepParameter, rng: &mut ChaCha8Rng) -> Result<Value> {
+    Ok(match &parameter.space {
+        ParameterSpace::Values { values } => {
+            if values.is_empty() {
+                bail!("parameter `{}` lists no values", parameter.path);
+            }
+            values[rng.gen_range(0..values.len())].clone()
+        }
+        ParameterSpace::Range {
+            min,
+            max,
+            log,
+            integer,
+        } => {
+            if min.is_nan() || max.is_nan() || min > max || (*log && *min <= 0.0) {
+                bail!(
+                    "parameter `{}` has an invalid range [{min}, {max}]",
+                    parameter.path
+                );
+            }
+            let unit = rng.r#gen::<f64>();
+            let value = if *log {
+                (min.ln() + unit * (max.ln() - min.ln())).exp()
+            } else {
+                min + unit * (max - min)
+            };
+            if *integer {
+                Value::from(value.round() as i64)
+            } else {
+                Value::from(value)
+            }
+        }
+    })
+}
+
+/// Train and evaluate every trial of `config` under `out_dir`, then write
+/// the leaderboard; results come back ranked
+///
+/// `on_finished` is called from the worker threads as runs complete.
+pub fn run_sweep(
+    config: &SweepConfig,
+    out_dir: &Path,
+    on_finished: impl Fn(&SweepResult) + Sync,
+) -> Result<Vec<SweepResult>> {
+    let trials = plan_trials(config)?;
+    if out_dir.join("sweep.json").exists() {
+        bail!("{} already holds a sweep", out_dir.display());
+    }
+    fs::create_dir_all(out_dir)
+        .with_context(|| format!("creating output directory {}", out_dir.display()))?;
+    config.save(&out_dir.join("sweep.json"))?;
+
+    let mut results = parallel_ordered_map(
+        trials,
+        config.parallel,
+        || (),
+        |_, trial| {
+            let outcome = run_trial(config, &trial, &run_dir(out_dir, &trial))
+                .map_err(|err| format!("{err:#}"));
+            let result = SweepResult { trial, outcome };
+            on_finished(&result);
+            result
+        },
+    );
+    rank(&mut results, config.goal);
+    write_leaderboard(&out_dir.join(LEADERBOARD_FILE), config, &results)?;
+    Ok(results)
+}
+
+fn run_trial(config: &SweepConfig, trial: &SweepTrial, run_dir: &Path) -> Result<Estimate> {
+    let mut trainer = Trainer::new(trial.config.clone(), run_dir)?;
+    trainer.set_quiet(true);
+    trainer.run()?;
+
+    let models = models_dir(run_dir);
+    let controllers = EVALUATED_SPECIES
+        .iter()
+        .map(|species| Controller::load(&models, *species))
+        .collect::<Result<Vec<_>>>()?;
+    let evaluation = EvaluationConfig {
+        seeds: config.evaluation_seeds,
+        ticks: config.evaluation_ticks,
+        seed: trial.config.seed,
+        env: trial.config.env.clone(),
+        ..Default::default()
+    };
+    let report = evaluate(&evaluation, &controllers);
+    fs::write(
+        run_dir.join("evaluation.json"),
+        serde_json::to_string_pretty(&report)?,
+    )
+    .with_context(|| format!("writing {}", run_dir.join("evaluation.json").display()))?;
+    Ok(config.metric.estimate(&report))
+}
+
+/// Best first, failed runs last, ties in run order
+fn rank(results: &mut [SweepResult], goal: SweepGoal) {
+    results.sort_by(|a, b| match (&a.outcome, &b.outcome) {
+        (Ok(x), Ok(y)) => {
+            let order = x.mean.total_cmp(&y.mean);
+            let order = match goal {
+                SweepGoal::Maximize => order.reverse(),
+                SweepGoal::Minimize => order,
+            };
+            order.then_with(|| a.trial.name.cmp(&b.trial.name))
+        }
+        (Ok(_), Err(_)) => std::cmp::Ordering::Less,
+        (Err(_), Ok(_)) => std::cmp::Ordering::Greater,
+        (Err(_), Err(_)) => a.trial.name.cmp(&b.trial.name),
+    });
+}
+
+fn write_leaderboard(path: &Path, config: &SweepConfig, results: &[SweepResult]) -> Result<()> {
+    let mut writer =
+        csv::Writer::from_path(path).with_context(|| format!("writing {}", path.display()))?;
+    let metric = config.metric.name();
+    let mut header = vec![
+        "rank".to_string(),
+        "run".to_string(),
+        metric.to_string(),
+        format!("{metric}_ci_low"),
+        format!("{metric}_ci_high"),
+    ];
+    header.extend(config.parameters.iter().map(|p| p.path.clone()));
+    header.push("error".to_string());
+    writer.write_record(&header)?;
+
+    for (rank, result) in results.iter().enumerate() {
+        let mut row = vec![(rank + 1).to_string(), result.trial.name.clone()];
+        match &result.outcome {
+            Ok(estimate) => row
+                .extend([estimate.mean, estimate.ci_low, estimate.ci_high].map(|x| x.to_string())),
+            Err(_) => row.extend([String::new(), String::new(), String::new()]),
+        }
+        row.extend(
+            result
+                .trial
+                .assignments
+                .iter()
+                .map(|(_, value)| match value {
+                    Value::String(text) => text.clone(),
+                    other => other.to_string(),
+                }),
+        );
+        row.push(result.outcome.as_ref().err().cloned().unwrap_or_default());
+        writer.write_record(&row)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Directory of one run inside a sweep
+pub fn run_dir(out_dir: &Path, trial: &SweepTrial) -> PathBuf {
+    out_dir.join(&trial.name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::env::EnvConfig;
+    use crate::ppo::PpoConfig;
+
+    fn tiny_sweep() -> SweepConfig {
+        SweepConfig {
+            base: TrainingConfig {
+                iterations: 1,
+                episodes_per_iteration: 1,
+                episode_ticks: 10,
+                env: EnvConfig {
+                    half_extent: 60.0,
+                    blue_count: 3,
+                    red_count: 2,
+                    plant_count: 4,
+                    ..Default::default()
+                },
+                ppo: PpoConfig {
+                    epochs: 1,
+                    minibatch_size: 16,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            parameters: vec![
+                SweepParameter {
+                    path: "ppo.learning_rate".to_string(),
+                    space: ParameterSpace::Values {
+                        values: vec![Value::from(1e-4), Value::from(1e-3)],
+                    },
+                },
+                SweepParameter {
+                    path: "rewards.catch_prey".to_string(),
+                    space: ParameterSpace::Values {
+                        values: vec![Value::from(0.5), Value::from(2.0)],
+                    },
+                },
+            ],
+            evaluation_seeds: 2,
+            evaluation_ticks: 20,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_plans_grid_and_random_trials() {
+        let mut config = tiny_sweep();
+        let grid = plan_trials(&config).unwrap();
+        assert_eq!(grid.len(), 4);
+        assert_eq!(grid[1].config.rewards.catch_prey, 2.0);
+        assert_eq!(grid[2].config.ppo.learning_rate, 1e-3);
+
+        config.strategy = SweepStrategy::Random;
+        config.trials = 5;
+        config.parameters.push(SweepParameter {
+            path: "ppo.epochs".to_string(),
+            space: ParameterSpace::Range {
+                min: 1.0,
+                max: 4.0,
+                log: false,
+                integer: true,
+            },
+        });
+        let random = plan_trials(&config).unwrap();
+        assert_eq!(random, plan_trials(&config).unwrap());
+        assert!(
+            random
+                .iter()
+                .all(|t| (1..=4).contains(&t.config.ppo.epochs))
+        );
+
+        config.strategy = SweepStrategy::Grid;
+        assert!(plan_trials(&config).is_err());
+        config.parameters.pop();
+        config.parameters[0].path = "ppo.learning_rat".to_string();
+        let err = plan_trials(&config).unwrap_err().to_string();
+        assert!(err.contains("ppo.learning_rat"), "{err}");
+    }
+
+    #[test]
+    fn test_sweep_writes_ranked_leaderboard() {
+        let out = std::env::temp_dir().join(format!("ecosystem-sweep-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&out);
+        let results = run_sweep(&tiny_sweep(), &out, |_| {}).unwrap();
+
+        assert_eq!(results.len(), 4);
+        let means: Vec<f64> = results
+            .iter()
+            .map(|r| r.outcome.as_ref().unwrap().mean)
+            .collect();
+        assert!(means.windows(2).all(|pair| pair[0] >= pair[1]));
+        for result in &results {
+            let dir = run_dir(&out, &result.trial);
+            assert!(models_dir(&dir).join("blue.policy").exists());
+            assert!(dir.join("evaluation.json").exists());
+        }
+        let leaderboard = fs::read_to_string(out.join(LEADERBOARD_FILE)).unwrap();
+        assert_eq!(leaderboard.lines().count(), 5);
+        assert!(leaderboard.starts_with("rank,run,blue_survival,"));
+        assert!(run_sweep(&tiny_sweep(), &out, |_| {}).is_err());
+        fs::remove_dir_all(&out).unwrap();
+    }
+}
@@ -0,0 +1,1053 @@
+//! Multi-species PPO training loop
+
+use std::fs;
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use anyhow::{Context, Result, bail};
+use burn::backend::{Autodiff, NdArray};
+use burn::module::AutodiffModule;
+use burn::optim::Optimizer;
+use burn::prelude::*;
+use ecosystem_ai_shared::{
+    DatasetWriter, InferenceBackend, ModelMetadata, PolicyConfig, PolicyNetwork, RunningNormalizer,
+    load_policy, save_policy, seeded_init,
+};
+use ecosystem_components::prelude::*;
+use indicatif::ProgressBar;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::checkpoint::{
+    Checkpoint, SpeciesCheckpoint, latest_checkpoint, record_from_bytes, record_to_bytes,
+};
+use crate::config::TrainingConfig;
+use crate::critic::{CriticLearner, critic_update, init_critic_optimizer};
+use crate::curriculum::{CurriculumState, StagePromotion};
+use crate::env::derive_seed;
+use crate::league::{LeaguePool, LeagueSnapshot};
+use crate::metrics::{MetricsLog, iteration_line, progress_bar, summary_table};
+use crate::ppo::{PolicyOptimizer, PpoConfig, PpoStats, init_optimizer, ppo_update};
+use crate::rollout::Batch;
+use crate::workers::{EpisodePlan, LearnerNetworks, RolloutContext, collect_episodes};
+
+/// Backend used for gradient updates
+pub type TrainBackend = Autodiff<NdArray<f32>>;
+
+/// Stage changes of a run, one JSON [`StagePromotion`] per line
+const CURRICULUM_LOG: &str = "curriculum.jsonl";
+
+/// Policy, optimizer and input statistics of one trained species
+pub struct SpeciesLearner {
+    pub species: OrganismType,
+    pub model: PolicyNetwork<TrainBackend>,
+    pub optimizer: PolicyOptimizer<TrainBackend>,
+    pub normalizer: RunningNormalizer,
+    /// Centralized critic, when `config.critic.centralized` is set
+    pub critic: Option<CriticLearner<TrainBackend>>,
+}
+
+/// Per-species results of one iteration
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpeciesStats {
+    pub species: OrganismType,
+    pub steps: usize,
+    /// Mean extrinsic return per agent
+    pub mean_reward: f32,
+    /// Mean curiosity bonus per agent, on top of `mean_reward`
+    pub mean_intrinsic_reward: f32,
+    pub mean_episode_length: f32,
+    pub ppo: PpoStats,
+}
+
+/// Results of one iteration
+#[derive(Debug, Clone, PartialEq)]
+pub struct IterationStats {
+    pub iteration: u32,
+    /// Learning-agent steps collected since the start of the run
+    pub global_step: u64,
+    pub species: Vec<SpeciesStats>,
+    /// Episodes in which one species was played by a league snapshot
+    pub league_episodes: u32,
+    /// Learning-agent steps collected per second of wall time
+    pub agent_steps_per_second: f32,
+    /// Curriculum stage the iteration was played in
+    pub stage: Option<String>,
+    /// Stage change earned by this iteration's results
+    pub promotion: Option<StagePromotion>,
+    /// Mean number of living organisms of each type at episode end
+    pub populations: Vec<(OrganismType, f32)>,
+}
+
+/// Owns all training state; everything in here is checkpointed
+pub struct Trainer {
+    config: TrainingConfig,
+    out_dir: PathBuf,
+    device: <TrainBackend as Backend>::Device,
+    learners: Vec<SpeciesLearner>,
+    league: LeaguePool,
+    curriculum: CurriculumState,
+    rng: ChaCha8Rng,
+    iteration: u32,
+    global_step: u64,
+    /// Skip the progress bar, iteration lines and summary table
+    quiet: bool,
+}
+
+impl Trainer {
+    /// Start a fresh run writing into `out_dir`
+    pub fn new(config: TrainingConfig, out_dir: impl Into<PathBuf>) -> Result<Self> {
+        let out_dir = out_dir.into();
+        if config.trained_species.contains(&OrganismType::Plant) {
+            bail!("plants cannot be trained: they never act");
+        }
+        for stage in &config.curriculum.stages {
+            if let Some(promotion) = &stage.promotion
+                && !config.trained_species.contains(&promotion.species)
+            {
+                bail!(
+                    "curriculum stage `{}` promotes on {:?}, which is not trained",
+                    stage.name,
+                    promotion.species
+                );
+            }
+        }
+        fs::create_dir_all(&out_dir)
+            .with_context(|| format!("creating output directory {}", out_dir.display()))?;
+        config.save(&out_dir.join("config.json"))?;
+
+        let device = Default::default();
+        let mut learners = Vec::with_capacity(config.trained_species.len());
+        for (index, species) in config.trained_species.iter().enumerate() {
+            let mut learner = SpeciesLearner {
+                species: *species,
+                model: seeded_init(
+                    policy_config(&config).init(&device),
+                    derive_seed(config.seed, &[0x5EED, index as u64]),
+                ),
+                optimizer: init_optimizer(&config.ppo),
+                normalizer: initial_normalizer(&config),
+                critic: config.critic.centralized.then(|| CriticLearner {
+                    model: seeded_init(
+                        config.critic.init(config.encoder.input_size(), &device),
+                        derive_seed(config.seed, &[0xC217, index as u64]),
+                    ),
+                    optimizer: init_critic_optimizer(&config.ppo),
+                }),
+            };
+            if let Some(dir) = &config.warm_start {
+                warm_start(&mut learner, &config, dir, &device)?;
+            }
+            learners.push(learner);
+        }
+
+        Ok(Self {
+            league: LeaguePool::new(config.league.max_snapshots),
+            curriculum: CurriculumState::default(),
+            rng: ChaCha8Rng::seed_from_u64(config.seed),
+            config,
+            out_dir,
+            device,
+            learners,
+            iteration: 0,
+            global_step: 0,
+            quiet: false,
+        })
+    }
+
+    /// Continue the run in `out_dir` from its latest checkpoint
+    pub fn resume(out_dir: impl Into<PathBuf>) -> Result<Self> {
+        let out_dir = out_dir.into();
+        let dir = latest_checkpoint(&checkpoint_root(&out_dir))?
+            .with_context(|| format!("no checkpoint found in {}", out_dir.display()))?;
+        let checkpoint = Checkpoint::load(&dir)?;
+        // Promotions after the checkpoint will be logged again
+        truncate_curriculum_log(&out_dir, checkpoint.iteration)?;
+
+        let device = Default::default();
+        let mut learners = Vec::with_capacity(checkpoint.species.len());
+        for saved in checkpoint.species {
+            let model = policy_config(&checkpoint.config)
+                .init(&device)
+                .load_record(record_from_bytes::<TrainBackend, _>(saved.policy, &device)?);
+            let optimizer =
+                init_optimizer(&checkpoint.config.ppo).load_record(record_from_bytes::<
+                    TrainBackend,
+                    _,
+                >(
+                    saved.optimizer, &device
+                )?);
+            let critic = match saved.critic {
+                Some((weights, optimizer)) => Some(CriticLearner {
+                    model: checkpoint
+                        .config
+                        .critic
+                        .init(checkpoint.config.encoder.input_size(), &device)
+                        .load_record(record_from_bytes::<TrainBackend, _>(weights, &device)?),
+                    optimizer: init_critic_optimizer(&checkpoint.config.ppo)
+                        .load_record(record_from_bytes::<TrainBackend, _>(optimizer, &device)?),
+                }),
+                None => None,
+            };
+            learners.push(SpeciesLearner {
+                species: saved.species,
+                model,
+                optimizer,
+                normalizer: saved.normalizer,
+                critic,
+            });
+        }
+
+        Ok(Self {
+            config: checkpoint.config,
+            out_dir,
+            device,
+            learners,
+            league: checkpoint.league,
+            curriculum: checkpoint.curriculum,
+            rng: checkpoint.rng,
+            iteration: checkpoint.iteration,
+            global_step: checkpoint.global_step,
+            quiet: false,
+        })
+    }
+
+    pub fn config(&self) -> &TrainingConfig {
+        &self.config
+    }
+
+    pub fn iteration(&self) -> u32 {
+        self.iteration
+    }
+
+    pub fn global_step(&self) -> u64 {
+        self.global_step
+    }
+
+    /// Change the number of rollout workers; results stay the same
+    pub fn set_workers(&mut self, workers: usize) {
+        self.config.workers = workers.max(1);
+    }
+
+    /// Train without console output, e.g. next to other runs
+    pub fn set_quiet(&mut self, quiet: bool) {
+        self.quiet = quiet;
+    }
+
+    pub fn learners(&self) -> &[SpeciesLearner] {
+        &self.learners
+    }
+
+    pub fn league(&self) -> &LeaguePool {
+        &self.league
+    }
+
+    pub fn curriculum(&self) -> &CurriculumState {
+        &self.curriculum
+    }
+
+    pub fn out_dir(&self) -> &Path {
+        &self.out_dir
+    }
+
+    /// Train until `config.iterations`, checkpointing along the way
+    pub fn run(&mut self) -> Result<()> {
+        self.run_until(self.config.iterations)
+    }
+
+    /// Train until `iterations` have completed; the last one is always
+    /// checkpointed and exported
+    ///
+    /// Every iteration is appended to `<run>/metrics.csv`; progress is shown
+    /// on a progress bar and a summary table is printed at the end.
+    pub fn run_until(&mut self, iterations: u32) -> Result<()> {
+        let mut metrics =
+            MetricsLog::open(&self.out_dir, &self.config.trained_species, self.iteration)?;
+        let bar = if self.quiet {
+            ProgressBar::hidden()
+        } else {
+            progress_bar(iterations, self.iteration)
+        };
+        let started = Instant::now();
+        let mut first = None;
+        let mut last = None;
+        let mut unsaved = false;
+        while self.iteration < iterations {
+            let stats = self.train_iteration()?;
+            metrics.append(&stats)?;
+            if !self.quiet {
+                let line = iteration_line(&stats);
+                if bar.is_hidden() {
+                    println!("{line}");
+                } else {
+                    bar.println(line);
+                }
+            }
+            bar.inc(1);
+            first.get_or_insert_with(|| stats.clone());
+            last = Some(stats);
+            unsaved = true;
+
+            let interval = self.config.checkpoint_interval;
+            if interval > 0 && self.iteration.is_multiple_of(interval) {
+                self.save_checkpoint()?;
+                self.export_policies()?;
+                unsaved = false;
+            }
+        }
+        if unsaved {
+            self.save_checkpoint()?;
+            self.export_policies()?;
+        }
+        bar.finish_and_clear();
+        if let (false, Some(first), Some(last)) = (self.quiet, &first, &last) {
+            print!("{}", summary_table(first, last, started.elapsed()));
+        }
+        Ok(())
+    }
+
+    /// Collect one iteration of experience and update every learner
+    pub fn train_iteration(&mut self) -> Result<IterationStats> {
+        let networks = LearnerNetworks {
+            policies: self.learners.iter().map(|l| l.model.valid()).collect(),
+            critics: self
+                .learners
+                .iter()
+                .map(|l| l.critic.as_ref().map(|critic| critic.model.valid()))
+                .collect(),
+        };
+        let input_size = self.config.encoder.input_size();
+        let global_size = if self.config.critic.centralized {
+            self.config.critic.global_size()
+        } else {
+            0
+        };
+        let mut batches: Vec<Batch> = networks
+            .policies
+            .iter()
+            .map(|policy| {
+                Batch::with_state_size(input_size, policy.state_size())
+                    .with_global_size(global_size)
+            })
+            .collect();
+        let mut raw_observations: Vec<Vec<f32>> =
+            self.learners.iter().map(|_| Vec::new()).collect();
+
+        let mut plans = Vec::with_capacity(self.config.episodes_per_iteration as usize);
+        for episode in 0..self.config.episodes_per_iteration {
+            plans.push(EpisodePlan {
+                seed: derive_seed(self.config.seed, &[self.iteration as u64, episode as u64]),
+                episode_id: self.iteration as u64 * self.config.episodes_per_iteration as u64
+                    + episode as u64,
+                opponent: self.pick_opponent()?,
+            });
+        }
+        let league_episodes = plans.iter().filter(|plan| plan.opponent.is_some()).count() as u32;
+        let stage = self
+            .curriculum
+            .current(&self.config.curriculum)
+            .map(|stage| stage.name.clone());
+        let context = RolloutContext {
+            config: &self.config,
+            env: self
+                .curriculum
+                .env(&self.config.curriculum, &self.config.env),
+            normalizers: self.learners.iter().map(|l| l.normalizer.clone()).collect(),
+            record: self.config.save_rollouts,
+        };
+        let started = Instant::now();
+        let outputs = collect_episodes(&context, &networks, plans, self.config.workers);
+        let collection = started.elapsed();
+
+        let mut rollouts = if self.config.save_rollouts {
+            let path =
+                rollouts_dir(&self.out_dir).join(format!("iter_{:06}.traj", self.iteration + 1));
+            Some(DatasetWriter::create(&path)?)
+        } else {
+            None
+        };
+        let mut agent_steps = 0;
+        let mut populations: Vec<(OrganismType, f32)> = OrganismType::all()
+            .iter()
+            .map(|organism_type| (*organism_type, 0.0))
+            .collect();
+        let episodes = outputs.len().max(1) as f32;
+        for output in outputs {
+            agent_steps += output.agent_steps;
+            for ((_, total), (_, count)) in populations.iter_mut().zip(&output.final_populations) {
+                *total += *count as f32 / episodes;
+            }
+            for (batch, episode) in batches.iter_mut().zip(output.batches) {
+                batch.extend(episode);
+            }
+            for (raw, episode) in raw_observations.iter_mut().zip(output.raw_observations) {
+                raw.extend(episode);
+            }
+            if let Some(writer) = &mut rollouts {
+                for record in &output.records {
+                    writer.write(record)?;
+                }
+            }
+        }
+        if let Some(writer) = &mut rollouts {
+            writer.flush()?;
+        }
+        self.global_step += agent_steps;
+
+        let mut species_stats = Vec::with_capacity(self.learners.len());
+        for ((learner, batch), raw) in self
+            .learners
+            .iter_mut()
+            .zip(&batches)
+            .zip(&raw_observations)
+        {
+            let model = learner.model.clone();
+            // A centralized critic replaces the policy's value head
+            let actor_config = match &learner.critic {
+                Some(_) => PpoConfig {
+                    value_coef: 0.0,
+                    ..self.config.ppo.clone()
+                },
+                None => self.config.ppo.clone(),
+            };
+            let (model, mut ppo) = ppo_update(
+                model,
+                &mut learner.optimizer,
+                batch,
+                &actor_config,
+                &mut self.rng,
+                &self.device,
+            );
+            learner.model = model;
+            if let Some(critic) = &mut learner.critic {
+                let (model, value_loss) = critic_update(
+                    critic.model.clone(),
+                    &mut critic.optimizer,
+                    batch,
+                    &self.config.ppo,
+                    &mut self.rng,
+                    &self.device,
+                );
+                critic.model = model;
+                ppo.value_loss = value_loss;
+            }
+            learner.normalizer.update(raw);
+            species_stats.push(SpeciesStats {
+                species: learner.species,
+                steps: batch.len(),
+                mean_reward: batch.mean_episode_reward(),
+                mean_intrinsic_reward: batch.mean_episode_intrinsic_reward(),
+                mean_episode_length: batch.mean_episode_length(),
+                ppo,
+            });
+        }
+
+        self.iteration += 1;
+        let promotion =
+            self.curriculum
+                .observe(&self.config.curriculum, self.iteration, &species_stats);
+        if let Some(promotion) = &promotion {
+            self.log_promotion(promotion)?;
+        }
+        let interval = self.config.league.snapshot_interval;
+        if interval > 0 && self.iteration.is_multiple_of(interval) {
+            for learner in &self.learners {
+                self.league.add(LeagueSnapshot {
+                    species: learner.species,
+                    iteration: self.iteration,
+                    weights: record_to_bytes::<TrainBackend, _>(
+                        learner.model.clone().into_record(),
+                    )?,
+                });
+            }
+        }
+
+        Ok(IterationStats {
+            iteration: self.iteration,
+            global_step: self.global_step,
+            species: species_stats,
+            league_episodes,
+            agent_steps_per_second: agent_steps as f32 / collection.as_secs_f32().max(1e-6),
+            stage,
+            promotion,
+            populations,
+        })
+    }
+
+    /// Append a stage change to `<run>/curriculum.jsonl`
+    fn log_promotion(&self, promotion: &StagePromotion) -> Result<()> {
+        let path = self.out_dir.join(CURRICULUM_LOG);
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("opening {}", path.display()))?;
+        writeln!(file, "{}", serde_json::to_string(promotion)?)
+            .with_context(|| format!("writing {}", path.display()))
+    }
+
+    /// Maybe replace one learner with a frozen league snapshot for an episode
+    fn pick_opponent(&mut self) -> Result<Option<(usize, PolicyNetwork<InferenceBackend>)>> {
+        if self.league.is_empty()
+            || self.rng.r#gen::<f32>() >= self.config.league.opponent_probability
+        {
+            return Ok(None);
+        }
+        let candidates: Vec<usize> = (0..self.learners.len())
+            .filter(|&index| self.league.has_species(self.learners[index].species))
+            .collect();
+        if candidates.is_empty() {
+            return Ok(None);
+        }
+        let index = candidates[self.rng.gen_range(0..candidates.len())];
+        let snapshot = self
+            .league
+            .sample(self.learners[index].species, &mut self.rng)
+            .expect("candidate species has snapshots");
+        let record =
+            record_from_bytes::<InferenceBackend, _>(snapshot.weights.clone(), &self.device)?;
+        let policy = policy_config(&self.config)
+            .init::<InferenceBackend>(&self.device)
+            .load_record(record);
+        Ok(Some((index, policy)))
+    }
+
+    /// Write a checkpoint of the current state
+    pub fn save_checkpoint(&self) -> Result<PathBuf> {
+        let mut species = Vec::with_capacity(self.learners.len());
+        for learner in &self.learners {
+            species.push(SpeciesCheckpoint {
+                species: learner.species,
+                normalizer: learner.normalizer.clone(),
+                policy: record_to_bytes::<TrainBackend, _>(learner.model.clone().into_record())?,
+                optimizer: record_to_bytes::<TrainBackend, _>(learner.optimizer.to_record())?,
+                critic: match &learner.critic {
+                    Some(critic) => Some((
+                        record_to_bytes::<TrainBackend, _>(critic.model.clone().into_record())?,
+                        record_to_bytes::<TrainBackend, _>(critic.optimizer.to_record())?,
+                    )),
+                    None => None,
+                },
+            });
+        }
+        let checkpoint = Checkpoint {
+            iteration: self.iteration,
+            global_step: self.global_step,
+            rng: self.rng.clone(),
+            config: self.config.clone(),
+            species,
+            league: self.league.clone(),
+            curriculum: self.curriculum.clone(),
+        };
+        let root = checkpoint_root(&self.out_dir);
+        fs::create_dir_all(&root)?;
+        checkpoint.save(&root, self.config.keep_checkpoints)
+    }
+
+    /// Identifier stamped into exported models (the run directory name)
+    pub fn run_id(&self) -> String {
+        self.out_dir
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "run".to_string())
+    }
+
+    /// Write one runtime model file per species into `<run>/models/`
+    pub fn export_policies(&self) -> Result<Vec<PathBuf>> {
+        let dir = models_dir(&self.out_dir);
+        fs::create_dir_all(&dir)?;
+        let hyperparameters = serde_json::to_value(&self.config)?;
+
+        let mut paths = Vec::with_capacity(self.learners.len());
+        for learner in &self.learners {
+            let metadata = ModelMetadata {
+                run_id: self.run_id(),
+                iteration: self.iteration,
+                hyperparameters: hyperparameters.clone(),
+                ..ModelMetadata::new(
+                    learner.species,
+                    self.config.encoder,
+                    self.config.action_space,
+                    policy_config(&self.config),
+                    learner.normalizer.clone(),
+                )
+            };
+            let path = dir.join(model_file_name(learner.species));
+            save_policy(&path, &metadata, &learner.model.valid())?;
+            paths.push(path);
+        }
+        Ok(paths)
+    }
+}
+
+/// File name of a species' exported model, e.g. `blue.policy`
+pub fn model_file_name(species: OrganismType) -> String {
+    format!("{}.policy", species.short_name().to_lowercase())
+}
+
+/// Where exported runtime models of a run live
+pub fn models_dir(out_dir: &Path) -> PathBuf {
+    out_dir.join("models")
+}
+
+/// Where saved rollout datasets of a run live
+pub fn rollouts_dir(out_dir: &Path) -> PathBuf {
+    out_dir.join("rollouts")
+}
+
+/// Where checkpoints of a run live
+pub fn checkpoint_root(out_dir: &Path) -> PathBuf {
+    out_dir.join("checkpoints")
+}
+
+/// Network architecture implied by a training config
+pub fn policy_config(config: &TrainingConfig) -> PolicyConfig {
+    PolicyConfig::new(config.encoder.input_size(), config.action_space.size())
+        .with_hidden_size(config.network.hidden_size)
+        .with_hidden_layers(config.network.hidden_layers)
+        .with_recurrent(config.network.recurrent)
+        .with_attention(config.network.attention.clone())
+        .with_self_features(config.encoder.traits.then(|| config.encoder.self_size()))
+}
+
+/// Empty input statistics for a run
+///
+/// Attention policies read entity slots raw (their presence flags double as
+/// the attention mask), so only the self features are normalized.
+pub fn initial_normalizer(config: &TrainingConfig) -> RunningNormalizer {
+    let input_size = config.encoder.input_size();
+    let self_size = config.encoder.self_size();
+    if config.network.attention.is_some() {
+        RunningNormalizer::new(self_size, config.normalizer_clip)
+            .with_passthrough(input_size - self_size)
+    } else {
+        RunningNormalizer::new(input_size, config.normalizer_clip)
+    }
+}
+
+/// Replace a fresh learner's weights and input statistics with those of
+/// `<dir>/<species>.policy`, if present
+///
+/// The model must have been trained with this run's architecture, encoder
+/// and action space.
+fn warm_start(
+    learner: &mut SpeciesLearner,
+    config: &TrainingConfig,
+    dir: &Path,
+    device: &<TrainBackend as Backend>::Device,
+) -> Result<()> {
+    let path = dir.join(model_file_name(learner.species));
+    if !path.exists() {
+        return Ok(());
+    }
+    let loaded = load_policy::<TrainBackend>(&path, &config.encoder, &config.action_space, device)
+        .with_context(|| format!("warm-starting from {}", path.display()))?;
+    if loaded.metadata.policy != policy_config(config) {
+        bail!(
+            "{} has a different network architecture than this run",
+            path.display()
+        );
+    }
+    learner.model = loaded.network;
+    learner.normalizer = loaded.metadata.normalizer;
+    Ok(())
+}
+
+/// Drop logged promotions after `iteration`, the one being resumed from
+fn truncate_curriculum_log(out_dir: &Path, iteration: u32) -> Result<()> {
+    let path = out_dir.join(CURRICULUM_LOG);
+    if !path.exists() {
+        return Ok(());
+    }
+    let log = fs::read_to_string(&path).with_context(|| format!("reading {}", path.display()))?;
+    let kept: String = log
+        .lines()
+        .filter(|line| {
+            serde_json::from_str::<StagePromotion>(line)
+                .is_ok_and(|promotion| promotion.iteration <= iteration)
+        })
+        .map(|line| format!("{line}\n"))
+        .collect();
+    fs::write(&path, kept).with_context(|| format!("writing {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::env::EnvConfig;
+    use crate::league::LeagueConfig;
+    use crate::ppo::PpoConfig;
+    use ecosystem_ai_shared::{
+        AttentionConfig, ObservationEncoder, RecurrentKind, SELF_FEATURES, TRAIT_FEATURES,
+    };
+
+    fn tiny_config() -> TrainingConfig {
+        TrainingConfig {
+            seed: 11,
+            iterations: 3,
+            episodes_per_iteration: 2,
+            episode_ticks: 15,
+            checkpoint_interval: 1,
+            env: EnvConfig {
+                half_extent: 80.0,
+                blue_count: 4,
+                red_count: 2,
+                plant_count: 6,
+                ..Default::default()
+            },
+            ppo: PpoConfig {
+                epochs: 2,
+                minibatch_size: 32,
+                ..Default::default()
+            },
+            league: LeagueConfig {
+                snapshot_interval: 1,
+                max_snapshots: 2,
+                opponent_probability: 0.5,
+            },
+            ..Default::default()
+        }
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("ecosystem-trainer-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn fingerprint(trainer: &Trainer) -> Vec<Vec<Vec<f32>>> {
+        let probe: Vec<f32> = (0..trainer.config.encoder.input_size() * 3)
+            .map(|i| (i as f32 * 0.37).sin())
+            .collect();
+        trainer
+            .learners()
+            .iter()
+            .map(|learner| {
+                learner
+                    .model
+                    .valid()
+                    .action_probabilities(&probe, &trainer.device)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_resume_is_bit_identical() {
+        let straight_dir = temp_dir("straight");
+        let mut straight = Trainer::new(tiny_config(), &straight_dir).unwrap();
+        straight.run().unwrap();
+
+        let resumed_dir = temp_dir("resumed");
+        let mut first_half = Trainer::new(tiny_config(), &resumed_dir).unwrap();
+        first_half.run_until(2).unwrap();
+        drop(first_half);
+        let mut resumed = Trainer::resume(&resumed_dir).unwrap();
+        assert_eq!(resumed.iteration(), 2);
+        resumed.run().unwrap();
+
+        assert_eq!(resumed.global_step(), straight.global_step());
+        assert_eq!(resumed.rng, straight.rng);
+        assert_eq!(resumed.league().len(), straight.league().len());
+        for (a, b) in resumed.learners().iter().zip(straight.learners()) {
+            assert_eq!(a.normalizer, b.normalizer);
+        }
+        assert_eq!(fingerprint(&resumed), fingerprint(&straight));
+        let metrics = fs::read_to_string(resumed_dir.join(crate::metrics::METRICS_FILE)).unwrap();
+        assert_eq!(metrics.lines().count(), 4);
+
+        fs::remove_dir_all(straight_dir).unwrap();
+        fs::remove_dir_all(resumed_dir).unwrap();
+    }
+
+    #[test]
+    fn test_worker_count_does_not_change_results() {
+        let single_dir = temp_dir("workers-1");
+        let mut single = Trainer::new(tiny_config(), &single_dir).unwrap();
+        single.run_until(2).unwrap();
+
+        let parallel_dir = temp_dir("workers-3");
+        let mut parallel = Trainer::new(
+            TrainingConfig {
+                workers: 3,
+                episodes_per_iteration: 2,
+                ..tiny_config()
+            },
+            &parallel_dir,
+        )
+        .unwrap();
+        parallel.run_until(2).unwrap();
+
+        assert_eq!(parallel.global_step(), single.global_step());
+        assert_eq!(fingerprint(&parallel), fingerprint(&single));
+        fs::remove_dir_all(single_dir).unwrap();
+        fs::remove_dir_all(parallel_dir).unwrap();
+    }
+
+    #[test]
+    fn test_curriculum_promotion_is_logged_and_resumed() {
+        use crate::curriculum::{CurriculumStage, Promotion, PromotionMetric};
+
+        let dir = temp_dir("curriculum");
+        let mut config = tiny_config();
+        config.curriculum.stages = vec![
+            CurriculumStage {
+                name: "no_predators".into(),
+                red_count: Some(0),
+                promotion: Some(Promotion {
+                    species: OrganismType::Blue,
+                    metric: PromotionMetric::MeanSurvival,
+                    threshold: 1.0,
+                    window: 1,
+                }),
+                ..Default::default()
+            },
+            CurriculumStage {
+                name: "full".into(),
+                ..Default::default()
+            },
+        ];
+        let mut trainer = Trainer::new(config, &dir).unwrap();
+        trainer.save_checkpoint().unwrap();
+        trainer.train_iteration().unwrap();
+        drop(trainer);
+
+        // Resuming from before the promotion replays it without logging twice
+        let mut trainer = Trainer::resume(&dir).unwrap();
+        assert_eq!(trainer.curriculum().stage, 0);
+        let first = trainer.train_iteration().unwrap();
+        assert_eq!(first.stage.as_deref(), Some("no_predators"));
+        // No predators: Red collects nothing
+        assert_eq!(first.species[1].steps, 0);
+        assert_eq!(first.promotion.unwrap().to, "full");
+        trainer.save_checkpoint().unwrap();
+
+        let mut resumed = Trainer::resume(&dir).unwrap();
+        assert_eq!(resumed.curriculum().stage, 1);
+        let second = resumed.train_iteration().unwrap();
+        assert_eq!(second.stage.as_deref(), Some("full"));
+        assert!(second.species[1].steps > 0);
+        let log = fs::read_to_string(dir.join("curriculum.jsonl")).unwrap();
+        assert_eq!(log.lines().count(), 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_checkpoints_are_pruned() {
+        let dir = temp_dir("prune");
+        let mut trainer = Trainer::new(
+            TrainingConfig {
+                keep_checkpoints: 2,
+                ..tiny_config()
+            },
+            &dir,
+        )
+        .unwrap();
+        trainer.run().unwrap();
+
+        let root = checkpoint_root(&dir);
+        let kept = fs::read_dir(&root)
+            .unwrap()
+            .filter(|entry| entry.as_ref().unwrap().path().is_dir())
+            .count();
+        assert_eq!(kept, 2);
+        assert!(
+            latest_checkpoint(&root)
+                .unwrap()
+                .unwrap()
+                .ends_with("iter_000003")
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_exported_models_load() {
+        let dir = temp_dir("export");
+        let config = tiny_config();
+        let mut trainer = Trainer::new(config.clone(), &dir).unwrap();
+        trainer.run_until(1).unwrap();
+
+        let path = models_dir(&dir).join(model_file_name(OrganismType::Red));
+        let model = ecosystem_ai_shared::load_policy::<InferenceBackend>(
+            &path,
+            &config.encoder,
+            &config.action_space,
+            &Default::default(),
+        )
+        .unwrap();
+        assert_eq!(model.metadata.species, OrganismType::Red);
+        assert_eq!(model.metadata.iteration, 1);
+        assert_eq!(model.metadata.hyperparameters["seed"], 11);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_saved_rollouts_match_iteration() {
+        let dir = temp_dir("rollouts");
+        let config = TrainingConfig {
+            save_rollouts: true,
+            ..tiny_config()
+        };
+        let mut trainer = Trainer::new(config, &dir).unwrap();
+        trainer.run_until(2).unwrap();
+
+        let reader =
+            ecosystem_ai_shared::DatasetReader::open(&rollouts_dir(&dir).join("iter_000002.traj"))
+                .unwrap();
+        assert_eq!(reader.episodes().unwrap(), vec![2, 3]);
+        let episode = reader.episode(3).unwrap();
+        assert!(!episode.is_empty());
+        assert!(episode.iter().all(|record| record.tick < 15));
+        assert!(episode.iter().any(|record| record.reward != 0.0));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_recurrent_policies_train_and_export() {
+        let dir = temp_dir("recurrent");
+        let mut config = tiny_config();
+        config.network.hidden_size = 16;
+        config.network.recurrent = Some(RecurrentKind::Lstm);
+        config.ppo.bptt_length = 4;
+        let mut trainer = Trainer::new(config.clone(), &dir).unwrap();
+        let before = fingerprint(&trainer);
+        trainer.run_until(2).unwrap();
+        assert_ne!(fingerprint(&trainer), before);
+
+        let path = models_dir(&dir).join(model_file_name(OrganismType::Blue));
+        let model = ecosystem_ai_shared::load_policy::<InferenceBackend>(
+            &path,
+            &config.encoder,
+            &config.action_space,
+            &Default::default(),
+        )
+        .unwrap();
+        assert_eq!(model.metadata.policy.recurrent, Some(RecurrentKind::Lstm));
+        assert_eq!(model.network.state_size(), 32);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_centralized_critic_trains_and_resumes() {
+        let mut config = tiny_config();
+        config.critic.centralized = true;
+        config.critic.hidden_size = 16;
+
+        let straight_dir = temp_dir("critic-straight");
+        let mut straight = Trainer::new(config.clone(), &straight_dir).unwrap();
+        let stats = straight.train_iteration().unwrap();
+        assert!(stats.species.iter().all(|s| s.ppo.value_loss > 0.0));
+        straight.run_until(2).unwrap();
+
+        let resumed_dir = temp_dir("critic-resumed");
+        Trainer::new(config.clone(), &resumed_dir)
+            .unwrap()
+            .run_until(1)
+            .unwrap();
+        let mut resumed = Trainer::resume(&resumed_dir).unwrap();
+        assert!(resumed.learners().iter().all(|l| l.critic.is_some()));
+        resumed.run_until(2).unwrap();
+        assert_eq!(fingerprint(&resumed), fingerprint(&straight));
+
+        // Actors still export as plain policies over their own observation
+        let path = models_dir(&resumed_dir).join(model_file_name(OrganismType::Red));
+        ecosystem_ai_shared::load_policy::<InferenceBackend>(
+            &path,
+            &config.encoder,
+            &config.action_space,
+            &Default::default(),
+        )
+        .unwrap();
+        fs::remove_dir_all(straight_dir).unwrap();
+        fs::remove_dir_all(resumed_dir).unwrap();
+    }
+
+    #[test]
+    fn test_curiosity_is_logged_apart_from_reward() {
+        let dir = temp_dir("curiosity");
+        let plain = Trainer::new(tiny_config(), &dir)
+            .unwrap()
+            .train_iteration()
+            .unwrap();
+        let mut config = tiny_config();
+        config.curiosity.coefficient = 0.1;
+        let curious = Trainer::new(config, &dir)
+            .unwrap()
+            .train_iteration()
+            .unwrap();
+
+        let [blue, red] = [0, 1].map(|index| &curious.species[index]);
+        assert_eq!(blue.mean_intrinsic_reward, 0.0);
+        assert!(red.mean_intrinsic_reward > 0.0);
+        // Same episodes either way: the bonus only changes what PPO learns from
+        assert_eq!(red.mean_reward, plain.species[1].mean_reward);
+        assert_ne!(red.ppo, plain.species[1].ppo);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_attention_policies_train_and_export() {
+        let dir = temp_dir("attention");
+        let mut config = tiny_config();
+        config.network.hidden_size = 16;
+        config.network.attention = Some(AttentionConfig::new().with_embed_size(8));
+        let mut trainer = Trainer::new(config.clone(), &dir).unwrap();
+        let before = fingerprint(&trainer);
+        trainer.run_until(1).unwrap();
+        assert_ne!(fingerprint(&trainer), before);
+        assert_eq!(trainer.learners()[0].normalizer.size(), SELF_FEATURES);
+
+        let path = models_dir(&dir).join(model_file_name(OrganismType::Red));
+        let wider = ObservationEncoder {
+            max_visible: 16,
+            ..config.encoder
+        };
+        let model = ecosystem_ai_shared::load_policy::<InferenceBackend>(
+            &path,
+            &wider,
+            &config.action_space,
+            &Default::default(),
+        )
+        .unwrap();
+        assert_eq!(model.network.input_size(), wider.input_size());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_trait_conditioned_policies_train_and_export() {
+        let dir = temp_dir("traits");
+        let mut config = tiny_config();
+        config.encoder = config.encoder.with_traits(true);
+        config.network.hidden_size = 16;
+        config.network.attention = Some(AttentionConfig::new().with_embed_size(8));
+        let mut trainer = Trainer::new(config.clone(), &dir).unwrap();
+        trainer.run_until(1).unwrap();
+        assert_eq!(
+            trainer.learners()[0].normalizer.size(),
+            SELF_FEATURES + TRAIT_FEATURES
+        );
+
+        let path = models_dir(&dir).join(model_file_name(OrganismType::Blue));
+        let device = Default::default();
+        let model = ecosystem_ai_shared::load_policy::<InferenceBackend>(
+            &path,
+            &config.encoder,
+            &config.action_space,
+            &device,
+        )
+        .unwrap();
+        assert_eq!(model.network.input_size(), config.encoder.input_size());
+        // A model that reads traits can't run on observations without them
+        assert!(
+            ecosystem_ai_shared::load_policy::<InferenceBackend>(
+                &path,
+                &config.encoder.with_traits(false),
+                &config.action_space,
+                &device,
+            )
+            .is_err()
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
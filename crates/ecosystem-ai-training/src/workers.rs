@@ -0,0 +1,357 @@
+//! Parallel rollout workers
+//!
+//! Each episode of an iteration runs in its own headless world with its own
+//! seed. Episodes are handed out to a pool of worker threads, every worker
+//! holding a copy of the latest policy weights, and their experience is
+//! gathered in a shared buffer. Results are merged in episode order and each
+//! episode samples from its own RNG, so training is identical for any number
+//! of workers.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Mutex;
+use std::thread;
+
+use bevy_ecs::entity::Entity;
+use ecosystem_ai_runtime::HeuristicConfig;
+use ecosystem_ai_shared::{
+    InferenceBackend, PolicyNetwork, RunningNormalizer, TrajectoryRecord, sample_index,
+};
+use ecosystem_components::prelude::*;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+use crate::config::TrainingConfig;
+use crate::critic::{CentralCritic, global_state};
+use crate::curiosity::NoveltyCounter;
+use crate::env::{AgentObservation, EnvConfig, SimEnv, derive_seed};
+use crate::rollout::{Batch, RolloutBuffer};
+
+/// Read-only state shared by every worker during one iteration
+pub struct RolloutContext<'a> {
+    pub config: &'a TrainingConfig,
+    /// Episode setup, with any curriculum stage applied
+    pub env: EnvConfig,
+    /// Normalizer of each learner, in learner order
+    pub normalizers: Vec<RunningNormalizer>,
+    /// Keep every agent step as a [`TrajectoryRecord`]
+    pub record: bool,
+}
+
+/// One episode to collect
+pub struct EpisodePlan {
+    /// Seed of the episode's world and action sampling
+    pub seed: u64,
+    /// Id stamped into saved trajectory records
+    pub episode_id: u64,
+    /// Learner index played by a frozen league snapshot, and that snapshot
+    pub opponent: Option<(usize, PolicyNetwork<InferenceBackend>)>,
+}
+
+/// Experience from one episode, per learner
+pub struct EpisodeOutput {
+    pub batches: Vec<Batch>,
+    /// Encoded observations before normalization
+    pub raw_observations: Vec<Vec<f32>>,
+    /// Steps taken by learning agents
+    pub agent_steps: u64,
+    /// Every agent step, when the context asked for records
+    pub records: Vec<TrajectoryRecord>,
+    /// Living organisms of each type when the episode ended
+    pub final_populations: Vec<(OrganismType, usize)>,
+}
+
+/// Latest weights of every learner, in learner order
+#[derive(Clone)]
+pub struct LearnerNetworks {
+    pub policies: Vec<PolicyNetwork<InferenceBackend>>,
+    /// Centralized critics, when the run uses them
+    pub critics: Vec<Option<CentralCritic<InferenceBackend>>>,
+}
+
+/// Collect every planned episode on up to `workers` threads, returning the
+/// outputs in plan order
+pub fn collect_episodes(
+    context: &RolloutContext,
+    networks: &LearnerNetworks,
+    plans: Vec<EpisodePlan>,
+    workers: usize,
+) -> Vec<EpisodeOutput> {
+    parallel_ordered_map(
+        plans,
+        workers,
+        // Networks are not `Sync`: every worker gets its own copy
+        || networks.clone(),
+        |networks, plan| run_episode(context, networks, plan),
+    )
+}
+
+/// Map `f` over `items` on up to `workers` threads, returning the results in
+/// item order
+///
+/// Items are handed out from a shared queue. Every worker owns a state made
+/// by `init_per_worker` on the calling thread (e.g. a copy of networks that
+/// are not `Sync`) and passes it to each of its calls of `f`.
+pub(crate) fn parallel_ordered_map<T: Send, S: Send, R: Send>(
+    items: Vec<T>,
+    workers: usize,
+    mut init_per_worker: impl FnMut() -> S,
+    f: impl Fn(&mut S, T) -> R + Sync,
+) -> Vec<R> {
+    let workers = workers.clamp(1, items.len().max(1));
+    if workers == 1 {
+        let mut state = init_per_worker();
+        return items.into_iter().map(|item| f(&mut state, item)).collect();
+    }
+
+    let queue = Mutex::new(items.into_iter().enumerate().collect::<VecDeque<_>>());
+    let finished = Mutex::new(Vec::new());
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            let mut state = init_per_worker();
+            let (queue, finished, f) = (&queue, &finished, &f);
+            scope.spawn(move || {
+                loop {
+                    let Some((index, item)) = queue.lock().expect("queue poisoned").pop_front()
+                    else {
+                        break;
+                    };
+                    let result = f(&mut state, item);
+                    finished
+                        .lock()
+                        .expect("results poisoned")
+                        .push((index, result));
+                }
+            });
+        }
+    });
+    let mut finished = finished.into_inner().expect("results poisoned");
+    finished.sort_by_key(|(index, _)| *index);
+    finished.into_iter().map(|(_, result)| result).collect()
+}
+
+/// Play one episode with the given learner networks
+pub fn run_episode(
+    context: &RolloutContext,
+    networks: &LearnerNetworks,
+    plan: EpisodePlan,
+) -> EpisodeOutput {
+    let config = context.config;
+    let policies = &networks.policies;
+    let global_size = if config.critic.centralized {
+        config.critic.global_size()
+    } else {
+        0
+    };
+    let encoder = config.encoder;
+    let input_size = encoder.input_size();
+    let device = Default::default();
+    let mut rng = ChaCha8Rng::seed_from_u64(derive_seed(plan.seed, &[0xAC7]));
+    let mut env = SimEnv::new(context.env.clone(), config.rewards.clone(), plan.seed);
+    for (index, species) in config.trained_species.iter().enumerate() {
+        let policy = match &plan.opponent {
+            Some((frozen_index, policy)) if *frozen_index == index => policy,
+            _ => &policies[index],
+        };
+        env.set_brain_size(*species, policy.brain_size());
+    }
+    let mut buffers: Vec<RolloutBuffer> = policies
+        .iter()
+        .map(|policy| {
+            RolloutBuffer::with_state_size(input_size, policy.state_size())
+                .with_global_size(global_size)
+        })
+        .collect();
+    let mut raw_observations: Vec<Vec<f32>> = policies.iter().map(|_| Vec::new()).collect();
+    let mut records = Vec::new();
+    let mut agent_steps = 0;
+    // Recurrent state per agent; everyone is born at the episode start
+    let mut memories: Vec<BTreeMap<Entity, Vec<f32>>> =
+        policies.iter().map(|_| BTreeMap::new()).collect();
+    let frozen = plan.opponent.as_ref().map(|(index, _)| *index);
+    let mut novelty = NoveltyCounter::new(config.curiosity.cell_size);
+
+    for _ in 0..config.episode_ticks {
+        let agents = env.observe();
+        if agents.is_empty() {
+            break;
+        }
+
+        let global = config
+            .critic
+            .centralized
+            .then(|| global_state(env.world_mut(), &context.env, config.critic.grid_size));
+        let mut actions = Vec::with_capacity(agents.len());
+        let mut owners = Vec::with_capacity(agents.len());
+        for (index, species) in config.trained_species.iter().enumerate() {
+            let members: Vec<_> = agents
+                .iter()
+                .filter(|agent| agent.organism_type == *species)
+                .collect();
+            if members.is_empty() {
+                continue;
+            }
+
+            let mut inputs = Vec::with_capacity(members.len() * input_size);
+            for agent in &members {
+                encoder.encode_into(&agent.observation, &mut inputs);
+            }
+            raw_observations[index].extend_from_slice(&inputs);
+            context.normalizers[index].normalize(&mut inputs);
+
+            let policy = match &plan.opponent {
+                Some((frozen_index, policy)) if *frozen_index == index => policy,
+                _ => &policies[index],
+            };
+            let state_size = policy.state_size();
+            let states = gather_states(&memories[index], &members, state_size);
+            let (evaluation, next_states) = policy.evaluate_step(&inputs, &states, &device);
+            let values = match (&networks.critics[index], &global) {
+                (Some(critic), Some(global)) if frozen != Some(index) => {
+                    critic.values(&inputs, global, &device)
+                }
+                _ => evaluation.values,
+            };
+
+            for (row, agent) in members.iter().enumerate() {
+                let probabilities = &evaluation.probabilities[row];
+                let action = sample_index(probabilities, &mut rng);
+                if frozen != Some(index) {
+                    buffers[index].record_step(
+                        agent.entity,
+                        &inputs[row * input_size..(row + 1) * input_size],
+                        &states[row * state_size..(row + 1) * state_size],
+                        action,
+                        probabilities[action].max(1e-8).ln(),
+                        values[row],
+                    );
+                    if let Some(global) = &global {
+                        buffers[index].record_global_state(agent.entity, global);
+                    }
+                    owners.push(Some(index));
+                } else {
+                    owners.push(None);
+                }
+                actions.push((agent.entity, config.action_space.to_command(action)));
+            }
+            for (agent, state) in members
+                .iter()
+                .zip(next_states.chunks_exact(state_size.max(1)))
+            {
+                memories[index].insert(agent.entity, state.to_vec());
+            }
+        }
+        // Untrained species play the heuristic baseline
+        for agent in &agents {
+            if !config.trained_species.contains(&agent.organism_type) {
+                let previous = env
+                    .world()
+                    .get::<ActionCommand>(agent.entity)
+                    .cloned()
+                    .unwrap_or_default();
+                let command = HeuristicConfig::default().decide(
+                    agent.organism_type,
+                    &agent.observation,
+                    &previous,
+                    &mut rng,
+                );
+                actions.push((agent.entity, command));
+                owners.push(None);
+            }
+        }
+
+        agent_steps += owners.iter().filter(|owner| owner.is_some()).count() as u64;
+        let tick = env.tick() as u64;
+        let rewards = env.step(&actions);
+        if context.record {
+            let observed: BTreeMap<Entity, &AgentObservation> =
+                agents.iter().map(|agent| (agent.entity, agent)).collect();
+            for ((entity, action), reward) in actions.iter().zip(&rewards) {
+                let agent = observed[entity];
+                records.push(TrajectoryRecord {
+                    episode: plan.episode_id,
+                    entity: entity.to_bits(),
+                    tick,
+                    species: agent.organism_type,
+                    observation: agent.observation.clone(),
+                    action: action.clone(),
+                    reward: reward.reward,
+                    done: reward.done,
+                });
+            }
+        }
+        for (reward, owner) in rewards.into_iter().zip(&owners) {
+            if let Some(index) = owner {
+                buffers[*index].record_reward(reward.entity, reward.reward, reward.done);
+                if !reward.done
+                    && config.curiosity.applies_to(config.trained_species[*index])
+                    && let Some(position) = env.world().get::<Position>(reward.entity)
+                {
+                    let bonus = novelty.visit(reward.entity, position);
+                    buffers[*index]
+                        .add_intrinsic_reward(reward.entity, config.curiosity.coefficient * bonus);
+                }
+            }
+        }
+    }
+
+    // Bootstrap agents still alive when the episode was cut off
+    let survivors = env.observe();
+    let global = config
+        .critic
+        .centralized
+        .then(|| global_state(env.world_mut(), &context.env, config.critic.grid_size));
+    for (index, species) in config.trained_species.iter().enumerate() {
+        if frozen == Some(index) {
+            continue;
+        }
+        let members: Vec<_> = survivors
+            .iter()
+            .filter(|agent| agent.organism_type == *species)
+            .collect();
+        let mut inputs = Vec::with_capacity(members.len() * input_size);
+        for agent in &members {
+            encoder.encode_into(&agent.observation, &mut inputs);
+        }
+        context.normalizers[index].normalize(&mut inputs);
+        let states = gather_states(&memories[index], &members, policies[index].state_size());
+        let (evaluation, _) = policies[index].evaluate_step(&inputs, &states, &device);
+        let values = match (&networks.critics[index], &global) {
+            (Some(critic), Some(global)) => critic.values(&inputs, global, &device),
+            _ => evaluation.values,
+        };
+        for (agent, value) in members.iter().zip(values) {
+            buffers[index].set_bootstrap(agent.entity, value);
+        }
+    }
+
+    let final_populations = OrganismType::all()
+        .iter()
+        .map(|organism_type| (*organism_type, env.population(*organism_type)))
+        .collect();
+    EpisodeOutput {
+        final_populations,
+        batches: buffers
+            .into_iter()
+            .map(|buffer| buffer.into_batch(config.ppo.gamma, config.ppo.gae_lambda))
+            .collect(),
+        raw_observations,
+        agent_steps,
+        records,
+    }
+}
+
+/// Row-major recurrent states of `members`, zeros for agents without one yet
+pub(crate) fn gather_states(
+    memory: &BTreeMap<Entity, Vec<f32>>,
+    members: &[&AgentObservation],
+    state_size: usize,
+) -> Vec<f32> {
+    let mut states = Vec::with_capacity(members.len() * state_size);
+    for agent in members {
+        match memory.get(&agent.entity) {
+            Some(state) => states.extend_from_slice(state),
+            None => states.extend(std::iter::repeat_n(0.0, state_size)),
+        }
+    }
+    states
+}
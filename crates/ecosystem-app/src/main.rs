@@ -1,3 +1,456 @@
-fn main() {
-    println!("Hello, world!");
+//! Headless ecosystem simulation
+//!
+//! Species with a model file in `--models` are driven by their policy, those
+//! with a tree in `--behaviors` by the tree, and the rest fall back to the
+//! heuristic brain, so the sim runs without any training. `--record` logs
+//! every decision for behavior cloning (`ecosystem-train clone`).
+//! `--onnx-blue`/`--onnx-red` run policies exported from other frameworks.
+//! `--adapt-blue`/`--adapt-red` keep training a species' policy on live
+//! experience; run the same seed with and without them to compare adaptive
+//! and frozen populations. `--int8` trades a little accuracy for faster
+//! inference (see `ecosystem-train distill` for smaller policies).
+//! `--challenger-blue`/`--challenger-red` load a second policy that competes
+//! with the first inside the same species, split by `--split`; population
+//! stats per policy are reported alongside the totals.
+//! `--inspect N` explains every policy decision of the first N Blue and Red
+//! organisms and writes them to `--inspect-out`.
+
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use bevy_app::prelude::*;
+use bevy_ecs::prelude::*;
+use clap::{ArgGroup, Parser, ValueEnum};
+use ecosystem_ai_runtime::{
+    AiRuntimePlugin, AiSet, AssignmentRule, BehaviorLibrary, DecisionInspector, DecisionInterval,
+    DecisionRates, DistanceLod, InferenceTimings, Inspected, LifetimeLearners, LifetimeLearning,
+    ModelHotReloadPlugin, PolicyAssignment, PolicyRegistry, PolicyReloadFailed, PolicySwapped,
+    PolicyVariant, Precision, TrajectoryRecorder, VariantPopulations,
+};
+use ecosystem_components::prelude::*;
+use ecosystem_physics::{PhysicsConfig, PhysicsPlugin, PhysicsSet};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+#[derive(Parser, Debug)]
+#[command(name = "ecosystem", about = "Run the ecosystem simulation headlessly")]
+#[command(group(
+    ArgGroup::new("policies")
+        .multiple(true)
+        .args(["models", "onnx_blue", "onnx_red", "challenger_blue", "challenger_red"])
+))]
+struct Cli {
+    /// Number of ticks to simulate
+    #[arg(long, default_value_t = 2000)]
+    ticks: u64,
+    /// Print populations every N ticks
+    #[arg(long, default_value_t = 100)]
+    report_every: u64,
+    /// Directory with `<species>.policy` files (e.g. `runs/default/models`)
+    #[arg(long)]
+    models: Option<PathBuf>,
+    /// Keep watching `--models` and swap in updated model files while running
+    #[arg(long, requires = "models")]
+    watch: bool,
+    /// ONNX policy for Blue (dense Tanh MLP over the encoded observation);
+    /// takes precedence over `--models`
+    #[arg(long)]
+    onnx_blue: Option<PathBuf>,
+    /// ONNX policy for Red; takes precedence over `--models`
+    #[arg(long)]
+    onnx_red: Option<PathBuf>,
+    /// Model file competing with Blue's policy (or with its fallback brain)
+    #[arg(long)]
+    challenger_blue: Option<PathBuf>,
+    /// Model file competing with Red's policy (or with its fallback brain)
+    #[arg(long)]
+    challenger_red: Option<PathBuf>,
+    /// How organisms are split between a species' policy and its challenger
+    #[arg(long, value_enum, default_value_t = Split::Ratio)]
+    split: Split,
+    /// Share of organisms given the challenger with `--split ratio`
+    #[arg(long, default_value_t = 0.5)]
+    challenger_share: f32,
+    /// Behavior tree library (e.g. `crates/ecosystem-ai-runtime/behaviors/default.json`)
+    /// for species without a model
+    #[arg(long)]
+    behaviors: Option<PathBuf>,
+    /// Record every Blue and Red decision to this trajectory dataset
+    /// (JSON lines for `.jsonl`, binary otherwise)
+    #[arg(long)]
+    record: Option<PathBuf>,
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+    #[arg(long, default_value_t = 20)]
+    blue: usize,
+    #[arg(long, default_value_t = 5)]
+    red: usize,
+    #[arg(long, default_value_t = 40)]
+    plants: usize,
+    /// Blue decides every N ticks
+    #[arg(long, default_value_t = 2)]
+    blue_every: u32,
+    /// Red decides every N ticks
+    #[arg(long, default_value_t = 1)]
+    red_every: u32,
+    /// Keep training the Blue policy from live experience instead of freezing it
+    #[arg(long, requires = "policies")]
+    adapt_blue: bool,
+    /// Keep training the Red policy from live experience instead of freezing it
+    #[arg(long, requires = "policies")]
+    adapt_red: bool,
+    /// Learning rate of `--adapt-blue`/`--adapt-red`
+    #[arg(long, default_value_t = 1e-4)]
+    adapt_learning_rate: f64,
+    /// Run plain MLP policies with int8 weights (others stay f32)
+    #[arg(long, requires = "policies")]
+    int8: bool,
+    /// Explain the policy decisions of the first N Blue and N Red organisms
+    #[arg(long, requires = "policies")]
+    inspect: Option<usize>,
+    /// Where `--inspect` writes its decisions as JSON
+    #[arg(long, default_value = "decisions.json")]
+    inspect_out: PathBuf,
+    /// Charge basal metabolism by body size and upkeep by policy size
+    #[arg(long)]
+    metabolism: bool,
+    /// Organisms with no other agent within this distance decide 4x less often
+    #[arg(long)]
+    lod_distance: Option<f32>,
+    /// Half the side length of the square world
+    #[arg(long, default_value_t = 300.0)]
+    half_extent: f32,
+}
+
+/// How `--split` assigns organisms to a policy and its challenger
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum Split {
+    /// `--challenger-share` of each species, interleaved
+    Ratio,
+    /// West half of the world runs the policy, east half the challenger
+    Halves,
+}
+
+/// Keeps the plant population topped up
+#[derive(Resource, Debug, Clone, Copy)]
+struct PlantGrowth {
+    target: usize,
+    chance: f32,
+}
+
+#[derive(Resource)]
+struct SimRng(ChaCha8Rng);
+
+fn regrow_plants(
+    mut commands: Commands,
+    growth: Res<PlantGrowth>,
+    bounds: Res<WorldBounds>,
+    mut rng: ResMut<SimRng>,
+    plants: Query<&OrganismType, With<Alive>>,
+) {
+    let count = plants
+        .iter()
+        .filter(|organism_type| **organism_type == OrganismType::Plant)
+        .count();
+    if count < growth.target && rng.0.gen_bool(growth.chance.clamp(0.0, 1.0) as f64) {
+        let position = bounds.random_position(&mut rng.0);
+        OrganismFactory::spawn_with_commands(&mut commands, OrganismType::Plant, position);
+    }
+}
+
+fn report_model_swaps(
+    mut swapped: EventReader<PolicySwapped>,
+    mut failed: EventReader<PolicyReloadFailed>,
+) {
+    for swap in swapped.read() {
+        println!(
+            "{:?}: policy {} (run {}, iteration {})",
+            swap.species,
+            swap.path.display(),
+            swap.run_id,
+            swap.iteration
+        );
+    }
+    for failure in failed.read() {
+        println!("kept previous policy, {}", failure.error);
+    }
+}
+
+fn decision_rates(cli: &Cli) -> DecisionRates {
+    let rates = DecisionRates::default()
+        .with_interval(OrganismType::Blue, DecisionInterval::Every(cli.blue_every))
+        .with_interval(OrganismType::Red, DecisionInterval::Every(cli.red_every));
+    match cli.lod_distance {
+        Some(distance) => rates.with_distance_lod(DistanceLod {
+            distance,
+            multiplier: 4,
+        }),
+        None => rates,
+    }
+}
+
+fn physics_config(cli: &Cli) -> PhysicsConfig {
+    let mut config = PhysicsConfig::default();
+    config.metabolism.enabled = cli.metabolism;
+    config
+}
+
+fn lifetime_learning(cli: &Cli) -> Option<LifetimeLearning> {
+    let species: Vec<OrganismType> = [
+        (OrganismType::Blue, cli.adapt_blue),
+        (OrganismType::Red, cli.adapt_red),
+    ]
+    .into_iter()
+    .filter_map(|(species, adapt)| adapt.then_some(species))
+    .collect();
+    (!species.is_empty()).then(|| LifetimeLearning {
+        learning_rate: cli.adapt_learning_rate,
+        ..LifetimeLearning::new(species)
+    })
+}
+
+fn policy_assignment(cli: &Cli, bounds: &WorldBounds) -> Option<PolicyAssignment> {
+    let share = cli.challenger_share.clamp(0.0, 1.0);
+    let rule = match cli.split {
+        Split::Ratio => AssignmentRule::Ratio(vec![
+            (PolicyVariant::PRIMARY, 1.0 - share),
+            (PolicyVariant::CHALLENGER, share),
+        ]),
+        Split::Halves => AssignmentRule::Region {
+            regions: vec![(
+                Rect::new(0.0, bounds.min.y, bounds.max.x, bounds.max.y),
+                PolicyVariant::CHALLENGER,
+            )],
+            fallback: PolicyVariant::PRIMARY,
+        },
+    }
+    .inherited();
+    [
+        (OrganismType::Blue, &cli.challenger_blue),
+        (OrganismType::Red, &cli.challenger_red),
+    ]
+    .into_iter()
+    .filter(|(_, path)| path.is_some())
+    .fold(
+        None,
+        |assignment: Option<PolicyAssignment>, (species, _)| {
+            Some(
+                assignment
+                    .unwrap_or_default()
+                    .with_rule(species, rule.clone()),
+            )
+        },
+    )
+}
+
+fn population(world: &mut World) -> [usize; 3] {
+    let mut counts = [0; 3];
+    let mut query = world.query_filtered::<&OrganismType, With<Alive>>();
+    for organism_type in query.iter(world) {
+        counts[*organism_type as usize] += 1;
+    }
+    counts
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let bounds = WorldBounds::centered(cli.half_extent);
+
+    let mut app = App::new();
+    app.add_plugins((
+        PhysicsPlugin {
+            config: physics_config(&cli),
+            bounds,
+        },
+        AiRuntimePlugin {
+            seed: cli.seed,
+            decision_rates: decision_rates(&cli),
+            lifetime_learning: lifetime_learning(&cli),
+            policy_assignment: policy_assignment(&cli, &bounds),
+            precision: if cli.int8 {
+                Precision::Int8
+            } else {
+                Precision::F32
+            },
+            ..Default::default()
+        },
+    ))
+    .configure_sets(Update, AiSet.before(PhysicsSet))
+    .insert_resource(PlantGrowth {
+        target: cli.plants,
+        chance: 0.3,
+    })
+    .add_systems(Update, regrow_plants.after(PhysicsSet));
+
+    if let Some(path) = &cli.behaviors {
+        app.insert_resource(BehaviorLibrary::load(path)?);
+    }
+    if let (true, Some(dir)) = (cli.watch, &cli.models) {
+        app.add_plugins(ModelHotReloadPlugin::new(dir.clone()))
+            .add_systems(Update, report_model_swaps.before(AiSet));
+    } else if let Some(dir) = &cli.models {
+        let (encoder, action_space) = {
+            let settings = app
+                .world
+                .resource::<ecosystem_ai_runtime::RuntimeSettings>();
+            (settings.encoder, settings.action_space)
+        };
+        let mut registry = app.world.resource_mut::<PolicyRegistry>();
+        for species in [OrganismType::Blue, OrganismType::Red] {
+            let path = dir.join(format!("{}.policy", species.short_name().to_lowercase()));
+            if path.exists() {
+                registry
+                    .load(&path, &encoder, &action_space)
+                    .with_context(|| format!("loading {}", path.display()))?;
+                println!("{species:?}: policy {}", path.display());
+            }
+        }
+    }
+
+    for (species, path) in [
+        (OrganismType::Blue, &cli.onnx_blue),
+        (OrganismType::Red, &cli.onnx_red),
+    ] {
+        if let Some(path) = path {
+            let (encoder, action_space) = {
+                let settings = app
+                    .world
+                    .resource::<ecosystem_ai_runtime::RuntimeSettings>();
+                (settings.encoder, settings.action_space)
+            };
+            app.world
+                .resource_mut::<PolicyRegistry>()
+                .load_onnx(path, species, &encoder, &action_space)
+                .with_context(|| format!("loading {}", path.display()))?;
+            println!("{species:?}: ONNX policy {}", path.display());
+        }
+    }
+
+    for (species, path) in [
+        (OrganismType::Blue, &cli.challenger_blue),
+        (OrganismType::Red, &cli.challenger_red),
+    ] {
+        if let Some(path) = path {
+            let (encoder, action_space) = {
+                let settings = app
+                    .world
+                    .resource::<ecosystem_ai_runtime::RuntimeSettings>();
+                (settings.encoder, settings.action_space)
+            };
+            let loaded = app
+                .world
+                .resource_mut::<PolicyRegistry>()
+                .load_variant(path, PolicyVariant::CHALLENGER, &encoder, &action_space)
+                .with_context(|| format!("loading {}", path.display()))?;
+            anyhow::ensure!(
+                loaded == species,
+                "{} holds a {loaded:?} policy, not {species:?}",
+                path.display()
+            );
+            println!("{species:?}: challenger policy {}", path.display());
+        }
+    }
+
+    if let Some(path) = &cli.record {
+        let recorder = TrajectoryRecorder::create(path, &[OrganismType::Blue, OrganismType::Red])
+            .with_context(|| format!("creating {}", path.display()))?
+            .with_episode(cli.seed);
+        app.insert_resource(recorder);
+    }
+
+    if cli.inspect.is_some() {
+        app.insert_resource(DecisionInspector::default());
+    }
+
+    let mut rng = ChaCha8Rng::seed_from_u64(cli.seed);
+    for (organism_type, count) in [
+        (OrganismType::Plant, cli.plants),
+        (OrganismType::Blue, cli.blue),
+        (OrganismType::Red, cli.red),
+    ] {
+        for index in 0..count {
+            let position = bounds.random_position(&mut rng);
+            let entity = OrganismFactory::spawn(&mut app.world, organism_type, position);
+            if cli.inspect.is_some_and(|inspect| index < inspect)
+                && organism_type != OrganismType::Plant
+            {
+                app.world.entity_mut(entity).insert(Inspected);
+            }
+        }
+    }
+    app.insert_resource(SimRng(rng));
+
+    for tick in 1..=cli.ticks {
+        app.update();
+        let [blue, red, plants] = population(&mut app.world);
+        if tick % cli.report_every.max(1) == 0 || blue == 0 || red == 0 {
+            let mut line =
+                format!("tick {tick:>6}  blue {blue:>4}  red {red:>4}  plants {plants:>4}");
+            if app
+                .world
+                .resource::<PolicyRegistry>()
+                .species()
+                .next()
+                .is_some()
+            {
+                line.push_str("  ");
+                line.push_str(&app.world.resource::<InferenceTimings>().summary());
+            }
+            if let Some(learners) = app.world.get_resource::<LifetimeLearners>() {
+                line.push_str("  ");
+                line.push_str(&learners.summary());
+            }
+            if let Some(populations) = app.world.get_resource::<VariantPopulations>() {
+                line.push_str("  ");
+                line.push_str(&populations.summary());
+            }
+            println!("{line}");
+        }
+        if blue == 0 || red == 0 {
+            println!("extinction at tick {tick}");
+            break;
+        }
+    }
+    if let Some(populations) = app.world.get_resource::<VariantPopulations>() {
+        for (species, variant, stats) in populations.iter() {
+            let name = if variant == PolicyVariant::PRIMARY {
+                "policy"
+            } else {
+                "challenger"
+            };
+            println!(
+                "{species:?} {name}: {} assigned, {} alive (peak {}), {} dead, \
+                 mean lifespan {:.0} ticks, mean energy {:.2}",
+                stats.assigned,
+                stats.alive,
+                stats.peak,
+                stats.deaths,
+                stats.mean_lifespan(),
+                stats.mean_energy
+            );
+        }
+    }
+    if let Some(mut recorder) = app.world.remove_resource::<TrajectoryRecorder>() {
+        recorder.flush()?;
+        if let Some(error) = recorder.error() {
+            anyhow::bail!("recording stopped early: {error}");
+        }
+        println!(
+            "recorded {} decisions to {}",
+            recorder.records(),
+            recorder.path().display()
+        );
+    }
+    if let Some(inspector) = app.world.get_resource::<DecisionInspector>() {
+        inspector
+            .dump_json(&cli.inspect_out)
+            .with_context(|| format!("writing {}", cli.inspect_out.display()))?;
+        println!(
+            "explained {} decisions of {} organisms in {}",
+            inspector.len(),
+            inspector.entities().count(),
+            cli.inspect_out.display()
+        );
+    }
+    Ok(())
 }
@@ -4,6 +4,8 @@ use bevy_math::Vec2;
 #[cfg(feature = "serde-support")]
 use serde::{Deserialize, Serialize};
 
+use crate::components::behavioral::{Movement, Vision};
+use crate::components::physical::{Collision, Energy};
 use crate::organisms::OrganismType;
 
 /// Complete observation data passed to AI systems
@@ -22,6 +24,12 @@ impl ObservationData {
         }
     }
 
+    /// Attach the observer's body traits
+    pub fn with_traits(mut self, traits: BodyTraits) -> Self {
+        self.self_state.traits = traits;
+        self
+    }
+
     pub fn add_observation(&mut self, observation: EntityObservation) {
         self.visible_entities.push(observation);
     }
@@ -65,6 +73,9 @@ pub struct SelfState {
     pub health_ratio: f32, // 0.0 to 1.0
     pub energy_ratio: f32, // 0.0 to 1.0
     pub current_position: Vec2,
+    /// Fixed body parameters; all zero unless the observer attached them
+    #[cfg_attr(feature = "serde-support", serde(default))]
+    pub traits: BodyTraits,
 }
 
 impl SelfState {
@@ -73,6 +84,81 @@ impl SelfState {
             health_ratio: health_ratio.clamp(0.0, 1.0),
             energy_ratio: energy_ratio.clamp(0.0, 1.0),
             current_position,
+            traits: BodyTraits::default(),
+        }
+    }
+}
+
+/// Body parameters of an organism - its genome as far as the AI can tell
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "serde-support", derive(Serialize, Deserialize))]
+pub struct BodyTraits {
+    pub vision_range: f32,
+    pub collision_radius: f32,
+    pub max_speed: f32,
+    pub max_energy: f32,
+    pub movement_cost: f32,
+    pub regen_rate: f32,
+}
+
+impl BodyTraits {
+    /// Number of values in [`BodyTraits::to_array`]
+    pub const LEN: usize = 6;
+
+    /// Field names in [`BodyTraits::to_array`] order
+    pub const NAMES: [&'static str; Self::LEN] = [
+        "vision_range",
+        "collision_radius",
+        "max_speed",
+        "max_energy",
+        "movement_cost",
+        "regen_rate",
+    ];
+
+    pub fn new(
+        vision: &Vision,
+        collision: &Collision,
+        movement: &Movement,
+        energy: &Energy,
+    ) -> Self {
+        Self {
+            vision_range: vision.range,
+            collision_radius: collision.radius(),
+            max_speed: movement.max_speed,
+            max_energy: energy.max(),
+            movement_cost: energy.movement_cost(),
+            regen_rate: energy.regen_rate(),
+        }
+    }
+
+    /// Traits in declaration order
+    pub fn to_array(&self) -> [f32; Self::LEN] {
+        [
+            self.vision_range,
+            self.collision_radius,
+            self.max_speed,
+            self.max_energy,
+            self.movement_cost,
+            self.regen_rate,
+        ]
+    }
+
+    pub fn from_array(values: [f32; Self::LEN]) -> Self {
+        let [
+            vision_range,
+            collision_radius,
+            max_speed,
+            max_energy,
+            movement_cost,
+            regen_rate,
+        ] = values;
+        Self {
+            vision_range,
+            collision_radius,
+            max_speed,
+            max_energy,
+            movement_cost,
+            regen_rate,
         }
     }
 }
@@ -27,22 +27,17 @@ impl ActionCommand {
 }
 
 /// Types of actions an entity can perform (minimal set for MVP)
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Default)]
 #[cfg_attr(feature = "serde-support", derive(Serialize, Deserialize))]
 pub enum ActionType {
     /// Move in specified direction (vector will be constrained by movement capabilities)
     Move(Vec2),
 
     /// Rest to recover energy (no movement)
+    #[default]
     Rest,
 }
 
-impl Default for ActionType {
-    fn default() -> Self {
-        ActionType::Rest
-    }
-}
-
 impl Default for ActionCommand {
     fn default() -> Self {
         Self::new(ActionType::default())
@@ -28,3 +28,52 @@ impl Default for Vision {
         Self::new(100.0) // Default vision range
     }
 }
+
+/// Movement capability - how fast an entity can travel per tick
+#[derive(Component, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde-support", derive(Serialize, Deserialize))]
+pub struct Movement {
+    pub max_speed: f32,
+}
+
+impl Movement {
+    pub fn new(max_speed: f32) -> Self {
+        Self {
+            max_speed: max_speed.max(0.0),
+        }
+    }
+
+    pub fn set_max_speed(&mut self, max_speed: f32) {
+        self.max_speed = max_speed.max(0.0);
+    }
+
+    /// Whether this entity can move at all (plants cannot)
+    pub fn is_mobile(&self) -> bool {
+        self.max_speed > 0.0
+    }
+}
+
+impl Default for Movement {
+    fn default() -> Self {
+        Self::new(2.0) // Default speed in world units per tick
+    }
+}
+
+/// Size of the neural controller driving an entity (zero for scripted brains)
+#[derive(Component, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde-support", derive(Serialize, Deserialize))]
+pub struct BrainSize {
+    /// Trainable parameters
+    pub parameters: u32,
+    /// Multiply-adds of one forward pass
+    pub operations: u32,
+}
+
+impl BrainSize {
+    pub fn new(parameters: u32, operations: u32) -> Self {
+        Self {
+            parameters,
+            operations,
+        }
+    }
+}
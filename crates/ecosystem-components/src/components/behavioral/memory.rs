@@ -0,0 +1,31 @@
+//! Per-organism memory carried between decisions
+
+use bevy_ecs::prelude::*;
+#[cfg(feature = "serde-support")]
+use serde::{Deserialize, Serialize};
+
+/// Hidden state of a recurrent policy
+///
+/// Opaque to everything except the policy that wrote it. Empty until the
+/// organism's first recurrent decision; cleared on birth.
+#[derive(Component, Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde-support", derive(Serialize, Deserialize))]
+pub struct RecurrentState {
+    pub values: Vec<f32>,
+}
+
+impl RecurrentState {
+    pub fn clear(&mut self) {
+        self.values.clear();
+    }
+
+    /// The stored state if it has the expected length
+    pub fn get(&self, size: usize) -> Option<&[f32]> {
+        (self.values.len() == size).then_some(self.values.as_slice())
+    }
+
+    pub fn set(&mut self, values: &[f32]) {
+        self.values.clear();
+        self.values.extend_from_slice(values);
+    }
+}
@@ -1,4 +1,4 @@
-//! Simple activity tracking - current state only
+//! Simple activity tracking - current state and lineage
 
 use bevy_ecs::prelude::*;
 #[cfg(feature = "serde-support")]
@@ -22,20 +22,15 @@ impl CurrentActivity {
 }
 
 /// Basic activity types (minimal set for MVP)
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 #[cfg_attr(feature = "serde-support", derive(Serialize, Deserialize))]
 pub enum ActivityType {
     /// Entity is currently moving
     Moving,
 
     /// Entity is resting to recover energy
-    Resting,
-}
-
-impl Default for ActivityType {
-    fn default() -> Self {
-        ActivityType::Resting // Default to resting (matches ActionType::Rest default)
-    }
+    #[default]
+    Resting, // Default to resting (matches ActionType::Rest default)
 }
 
 impl Default for CurrentActivity {
@@ -43,3 +38,12 @@ impl Default for CurrentActivity {
         Self::new(ActivityType::default())
     }
 }
+
+/// Link from an organism to the one it was bred from
+///
+/// Set by [`crate::organisms::OrganismFactory::spawn_offspring`], so other
+/// systems (e.g. policy assignment) can pass traits down a lineage.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Offspring {
+    pub parent: Entity,
+}
@@ -0,0 +1,7 @@
+//! Organism configuration system
+
+pub mod organism_config;
+pub mod presets;
+
+pub use organism_config::*;
+pub use presets::*;
@@ -0,0 +1,51 @@
+//! Sealed trait system for per-species organism configuration
+//!
+//! Every organism type has exactly one configuration preset. The trait is
+//! sealed so the set of presets stays in lockstep with [`OrganismType`].
+
+#[cfg(feature = "serde-support")]
+use serde::{Deserialize, Serialize};
+
+use crate::organisms::OrganismType;
+
+pub(crate) mod sealed {
+    pub trait Sealed {}
+}
+
+/// Compile-time configuration for one organism type
+pub trait OrganismConfig: sealed::Sealed + Send + Sync + 'static {
+    const ORGANISM_TYPE: OrganismType;
+    const MAX_HEALTH: f32;
+    const MAX_ENERGY: f32;
+    const MOVEMENT_COST: f32;
+    const REGEN_RATE: f32;
+    const VISION_RANGE: f32;
+    const MAX_SPEED: f32;
+    const COLLISION_RADIUS: f32;
+
+    /// Runtime view of the constants above
+    fn stats() -> OrganismStats {
+        OrganismStats {
+            max_health: Self::MAX_HEALTH,
+            max_energy: Self::MAX_ENERGY,
+            movement_cost: Self::MOVEMENT_COST,
+            regen_rate: Self::REGEN_RATE,
+            vision_range: Self::VISION_RANGE,
+            max_speed: Self::MAX_SPEED,
+            collision_radius: Self::COLLISION_RADIUS,
+        }
+    }
+}
+
+/// Base stats used when spawning an organism
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde-support", derive(Serialize, Deserialize))]
+pub struct OrganismStats {
+    pub max_health: f32,
+    pub max_energy: f32,
+    pub movement_cost: f32,
+    pub regen_rate: f32,
+    pub vision_range: f32,
+    pub max_speed: f32,
+    pub collision_radius: f32,
+}
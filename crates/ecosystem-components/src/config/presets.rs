@@ -0,0 +1,77 @@
+//! Predefined organism configurations
+
+use super::organism_config::{OrganismConfig, OrganismStats, sealed::Sealed};
+use crate::organisms::OrganismType;
+
+/// Prey: fast to regenerate, moderate vision
+pub struct BlueConfig;
+
+/// Predator: larger, sees further, slightly faster
+pub struct RedConfig;
+
+/// Plant: stationary food source
+pub struct PlantConfig;
+
+impl Sealed for BlueConfig {}
+impl Sealed for RedConfig {}
+impl Sealed for PlantConfig {}
+
+impl OrganismConfig for BlueConfig {
+    const ORGANISM_TYPE: OrganismType = OrganismType::Blue;
+    const MAX_HEALTH: f32 = 100.0;
+    const MAX_ENERGY: f32 = 100.0;
+    const MOVEMENT_COST: f32 = 0.5;
+    const REGEN_RATE: f32 = 2.0;
+    const VISION_RANGE: f32 = 120.0;
+    const MAX_SPEED: f32 = 3.0;
+    const COLLISION_RADIUS: f32 = 8.0;
+}
+
+impl OrganismConfig for RedConfig {
+    const ORGANISM_TYPE: OrganismType = OrganismType::Red;
+    const MAX_HEALTH: f32 = 150.0;
+    const MAX_ENERGY: f32 = 150.0;
+    const MOVEMENT_COST: f32 = 0.8;
+    const REGEN_RATE: f32 = 1.0;
+    const VISION_RANGE: f32 = 160.0;
+    const MAX_SPEED: f32 = 3.5;
+    const COLLISION_RADIUS: f32 = 12.0;
+}
+
+impl OrganismConfig for PlantConfig {
+    const ORGANISM_TYPE: OrganismType = OrganismType::Plant;
+    const MAX_HEALTH: f32 = 20.0;
+    const MAX_ENERGY: f32 = 40.0;
+    const MOVEMENT_COST: f32 = 0.0;
+    const REGEN_RATE: f32 = 0.0;
+    const VISION_RANGE: f32 = 0.0;
+    const MAX_SPEED: f32 = 0.0;
+    const COLLISION_RADIUS: f32 = 6.0;
+}
+
+impl OrganismType {
+    /// Base stats for this organism type
+    pub fn stats(&self) -> OrganismStats {
+        match self {
+            OrganismType::Blue => BlueConfig::stats(),
+            OrganismType::Red => RedConfig::stats(),
+            OrganismType::Plant => PlantConfig::stats(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_presets_match_types() {
+        for organism_type in OrganismType::all() {
+            let stats = organism_type.stats();
+            assert!(stats.max_health > 0.0);
+            assert_eq!(stats.collision_radius, organism_type.size());
+        }
+        assert_eq!(OrganismType::Plant.stats().max_speed, 0.0);
+        assert!(RedConfig::stats().vision_range > BlueConfig::stats().vision_range);
+    }
+}
@@ -0,0 +1,143 @@
+//! Organism spawning logic
+
+use bevy_ecs::prelude::*;
+
+use crate::components::*;
+use crate::config::OrganismStats;
+use crate::organisms::OrganismType;
+
+/// Full set of components every organism is spawned with
+#[derive(Bundle, Debug, Clone)]
+pub struct OrganismBundle {
+    pub organism: Organism,
+    pub organism_type: OrganismType,
+    pub alive: Alive,
+    pub newborn: Newborn,
+    pub position: Position,
+    pub health: Health,
+    pub energy: Energy,
+    pub collision: Collision,
+    pub vision: Vision,
+    pub movement: Movement,
+    pub action: ActionCommand,
+    pub activity: CurrentActivity,
+    pub memory: RecurrentState,
+    pub brain: BrainSize,
+}
+
+impl OrganismBundle {
+    /// Build a bundle using the preset stats for `organism_type`
+    pub fn new(organism_type: OrganismType, position: Position) -> Self {
+        Self::with_stats(organism_type, position, organism_type.stats())
+    }
+
+    /// Build a bundle from explicit stats (e.g. mutated offspring)
+    pub fn with_stats(
+        organism_type: OrganismType,
+        position: Position,
+        stats: OrganismStats,
+    ) -> Self {
+        Self {
+            organism: Organism,
+            organism_type,
+            alive: Alive,
+            newborn: Newborn,
+            position,
+            health: Health::new(stats.max_health),
+            energy: Energy::new(stats.max_energy, stats.movement_cost, stats.regen_rate),
+            collision: Collision::new(stats.collision_radius),
+            vision: Vision::new(stats.vision_range),
+            movement: Movement::new(stats.max_speed),
+            action: ActionCommand::default(),
+            activity: CurrentActivity::default(),
+            memory: RecurrentState::default(),
+            brain: BrainSize::default(),
+        }
+    }
+}
+
+/// Spawns organisms with the right markers for their type
+pub struct OrganismFactory;
+
+impl OrganismFactory {
+    /// Whether organisms of this type can be eaten
+    pub fn is_edible(organism_type: OrganismType) -> bool {
+        matches!(organism_type, OrganismType::Blue | OrganismType::Plant)
+    }
+
+    /// Spawn directly into a world (headless simulation, tests)
+    pub fn spawn(world: &mut World, organism_type: OrganismType, position: Position) -> Entity {
+        let mut entity = world.spawn(OrganismBundle::new(organism_type, position));
+        if Self::is_edible(organism_type) {
+            entity.insert(Edible);
+        }
+        entity.id()
+    }
+
+    /// Spawn through deferred commands (inside systems)
+    pub fn spawn_with_commands(
+        commands: &mut Commands,
+        organism_type: OrganismType,
+        position: Position,
+    ) -> Entity {
+        let mut entity = commands.spawn(OrganismBundle::new(organism_type, position));
+        if Self::is_edible(organism_type) {
+            entity.insert(Edible);
+        }
+        entity.id()
+    }
+
+    /// Spawn a newborn of `parent`'s type linked to it by [`Offspring`], or
+    /// `None` if `parent` is not an organism
+    pub fn spawn_offspring(
+        world: &mut World,
+        parent: Entity,
+        position: Position,
+    ) -> Option<Entity> {
+        let organism_type = *world.get::<OrganismType>(parent)?;
+        let child = Self::spawn(world, organism_type, position);
+        world.entity_mut(child).insert(Offspring { parent });
+        Some(child)
+    }
+
+    /// Like [`OrganismFactory::spawn_offspring`], through deferred commands
+    pub fn spawn_offspring_with_commands(
+        commands: &mut Commands,
+        parent: Entity,
+        organism_type: OrganismType,
+        position: Position,
+    ) -> Entity {
+        let child = Self::spawn_with_commands(commands, organism_type, position);
+        commands.entity(child).insert(Offspring { parent });
+        child
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spawn_markers() {
+        let mut world = World::new();
+        let blue = OrganismFactory::spawn(&mut world, OrganismType::Blue, Position::zero());
+        let red = OrganismFactory::spawn(&mut world, OrganismType::Red, Position::new(5.0, 5.0));
+
+        assert!(world.get::<Edible>(blue).is_some());
+        assert!(world.get::<Edible>(red).is_none());
+        assert_eq!(world.get::<OrganismType>(red), Some(&OrganismType::Red));
+        assert_eq!(world.get::<Vision>(red).unwrap().range, 160.0);
+
+        let child = OrganismFactory::spawn_offspring(&mut world, blue, Position::zero()).unwrap();
+        assert_eq!(world.get::<OrganismType>(child), Some(&OrganismType::Blue));
+        assert!(world.get::<Edible>(child).is_some());
+        assert_eq!(
+            world.get::<Offspring>(child),
+            Some(&Offspring { parent: blue })
+        );
+        assert!(
+            OrganismFactory::spawn_offspring(&mut world, Entity::PLACEHOLDER, Position::zero())
+                .is_none()
+        );
+    }
+}
@@ -1,14 +1,19 @@
 //! Organism type definitions for the ecosystem simulation
 
+use bevy_ecs::prelude::*;
 use bevy_math::Vec2;
 #[cfg(feature = "serde-support")]
 use serde::{Deserialize, Serialize};
 
 /// Types of organisms in the ecosystem simulation
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+///
+/// Also attached to every spawned organism as a component so systems can
+/// filter and group entities by species.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
 #[cfg_attr(feature = "serde-support", derive(Serialize, Deserialize))]
 pub enum OrganismType {
     /// Blue organisms (prey)
+    #[default]
     Blue,
 
     /// Red organisms (predators)
@@ -36,9 +41,10 @@ pub struct SpriteStats {
 }
 
 /// Shape types for organism rendering
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 #[cfg_attr(feature = "serde-support", derive(Serialize, Deserialize))]
 pub enum SpriteShape {
+    #[default]
     Circle,
     Square,
     Triangle,
@@ -125,18 +131,6 @@ impl SpriteStats {
     }
 }
 
-impl Default for OrganismType {
-    fn default() -> Self {
-        OrganismType::Blue
-    }
-}
-
-impl Default for SpriteShape {
-    fn default() -> Self {
-        SpriteShape::Circle
-    }
-}
-
 impl std::fmt::Display for OrganismType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.display_name())
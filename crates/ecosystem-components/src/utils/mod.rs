@@ -0,0 +1,5 @@
+//! Data structure utilities
+
+pub mod spatial;
+
+pub use spatial::*;
@@ -0,0 +1,61 @@
+//! Spatial math helpers
+
+use bevy_ecs::prelude::*;
+use bevy_math::Vec2;
+use rand::Rng;
+#[cfg(feature = "serde-support")]
+use serde::{Deserialize, Serialize};
+
+use crate::components::Position;
+
+/// Rectangular world boundaries
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde-support", derive(Serialize, Deserialize))]
+pub struct WorldBounds {
+    pub min: Vec2,
+    pub max: Vec2,
+}
+
+impl WorldBounds {
+    pub fn new(min: Vec2, max: Vec2) -> Self {
+        Self {
+            min: min.min(max),
+            max: min.max(max),
+        }
+    }
+
+    /// Square world centered on the origin
+    pub fn centered(half_extent: f32) -> Self {
+        let half = half_extent.abs();
+        Self::new(Vec2::splat(-half), Vec2::splat(half))
+    }
+
+    pub fn size(&self) -> Vec2 {
+        self.max - self.min
+    }
+
+    pub fn contains(&self, position: &Position) -> bool {
+        position.x >= self.min.x
+            && position.x <= self.max.x
+            && position.y >= self.min.y
+            && position.y <= self.max.y
+    }
+
+    pub fn clamp(&self, position: &mut Position) {
+        position.clamp_to_bounds(self.min.x, self.max.x, self.min.y, self.max.y);
+    }
+
+    /// Uniformly random position inside the bounds
+    pub fn random_position<R: Rng + ?Sized>(&self, rng: &mut R) -> Position {
+        Position::new(
+            rng.gen_range(self.min.x..=self.max.x),
+            rng.gen_range(self.min.y..=self.max.y),
+        )
+    }
+}
+
+impl Default for WorldBounds {
+    fn default() -> Self {
+        Self::centered(500.0)
+    }
+}
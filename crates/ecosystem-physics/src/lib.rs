@@ -1,14 +1,443 @@
-pub fn add(left: u64, right: u64) -> u64 {
-    left + right
+//! Physics systems for the ecosystem simulation
+//!
+//! Turns `ActionCommand`s into movement and resolves the consequences:
+//! energy spending, optional metabolic upkeep, feeding on collision,
+//! starvation and death. Systems are
+//! exposed both as a bevy plugin and as a plain [`Schedule`] for headless
+//! worlds (training, evaluation).
+
+use bevy_app::prelude::*;
+use bevy_ecs::prelude::*;
+use bevy_ecs::schedule::{ScheduleLabel, SystemConfigs};
+use ecosystem_components::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Tunable physics parameters
+#[derive(Resource, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PhysicsConfig {
+    /// Simulated seconds per tick (drives energy regeneration)
+    pub tick_seconds: f32,
+    /// Health lost per tick while energy is depleted
+    pub starvation_damage: f32,
+    /// Energy a predator gains from eating prey
+    pub predation_energy_gain: f32,
+    /// Energy prey gains from eating a plant
+    pub plant_energy_gain: f32,
+    /// Upkeep of bodies and brains
+    pub metabolism: MetabolismConfig,
+}
+
+impl Default for PhysicsConfig {
+    fn default() -> Self {
+        Self {
+            tick_seconds: 0.1,
+            starvation_damage: 2.0,
+            predation_energy_gain: 80.0,
+            plant_energy_gain: 30.0,
+            metabolism: MetabolismConfig::default(),
+        }
+    }
+}
+
+/// Which size of a [`BrainSize`] costs energy
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BrainMeasure {
+    #[default]
+    Parameters,
+    Operations,
+}
+
+/// Energy drained every tick just for staying alive
+///
+/// Off by default. When enabled, every mobile organism pays a basal cost
+/// proportional to its collision radius plus a cost proportional to the size
+/// of its neural controller, so bigger bodies and bigger brains both have to
+/// earn their keep.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MetabolismConfig {
+    pub enabled: bool,
+    /// Energy per second per unit of collision radius
+    pub basal_rate: f32,
+    /// Energy per second per thousand parameters or operations
+    pub brain_rate: f32,
+    pub brain_measure: BrainMeasure,
+}
+
+impl Default for MetabolismConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            basal_rate: 0.1,
+            brain_rate: 0.02,
+            brain_measure: BrainMeasure::Parameters,
+        }
+    }
+}
+
+impl MetabolismConfig {
+    /// Energy one organism spends per second
+    pub fn drain(&self, collision: &Collision, brain: &BrainSize) -> f32 {
+        let size = match self.brain_measure {
+            BrainMeasure::Parameters => brain.parameters,
+            BrainMeasure::Operations => brain.operations,
+        };
+        self.basal_rate * collision.radius() + self.brain_rate * size as f32 / 1000.0
+    }
+}
+
+/// Why an organism died
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DeathCause {
+    Eaten,
+    Starvation,
+}
+
+/// Sent when an organism eats another one
+#[derive(Event, Debug, Clone, Copy, PartialEq)]
+pub struct Consumed {
+    pub eater: Entity,
+    pub eater_type: OrganismType,
+    pub food: Entity,
+    pub food_type: OrganismType,
+}
+
+/// Sent when an organism dies (the entity is despawned the same tick)
+#[derive(Event, Debug, Clone, Copy, PartialEq)]
+pub struct Died {
+    pub entity: Entity,
+    pub organism_type: OrganismType,
+    pub cause: DeathCause,
+}
+
+/// System set containing every physics system, in execution order
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PhysicsSet;
+
+/// Label for the standalone physics schedule used by headless worlds
+#[derive(ScheduleLabel, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PhysicsSchedule;
+
+/// Which organism type `eater` may consume, if any
+pub fn food_of(eater: OrganismType) -> Option<OrganismType> {
+    match eater {
+        OrganismType::Red => Some(OrganismType::Blue),
+        OrganismType::Blue => Some(OrganismType::Plant),
+        OrganismType::Plant => None,
+    }
+}
+
+/// Newborn lasts exactly one tick so other systems can react to births
+pub fn clear_newborn(mut commands: Commands, query: Query<Entity, With<Newborn>>) {
+    for entity in &query {
+        commands.entity(entity).remove::<Newborn>();
+    }
+}
+
+/// Execute the current `ActionCommand` of every living organism
+pub fn apply_actions(
+    config: Res<PhysicsConfig>,
+    bounds: Res<WorldBounds>,
+    mut query: Query<
+        (
+            &ActionCommand,
+            &Movement,
+            &mut Position,
+            &mut Energy,
+            &mut CurrentActivity,
+        ),
+        With<Alive>,
+    >,
+) {
+    for (command, movement, mut position, mut energy, mut activity) in &mut query {
+        let moved = match command.action_type {
+            ActionType::Move(direction)
+                if movement.is_mobile() && direction != Vec2::ZERO && energy.consume_movement() =>
+            {
+                let step = direction.clamp_length_max(1.0) * movement.max_speed;
+                position.x += step.x;
+                position.y += step.y;
+                bounds.clamp(&mut position);
+                true
+            }
+            _ => false,
+        };
+
+        if moved {
+            activity.set_activity(ActivityType::Moving);
+        } else {
+            energy.regenerate(config.tick_seconds);
+            activity.set_activity(ActivityType::Resting);
+        }
+    }
+}
+
+/// Drain the metabolic cost of every mobile organism, if enabled
+pub fn apply_metabolism(
+    config: Res<PhysicsConfig>,
+    mut query: Query<(&Movement, &Collision, &BrainSize, &mut Energy), With<Alive>>,
+) {
+    let metabolism = &config.metabolism;
+    if !metabolism.enabled {
+        return;
+    }
+    for (movement, collision, brain, mut energy) in &mut query {
+        if movement.is_mobile() {
+            let drain = metabolism.drain(collision, brain) * config.tick_seconds;
+            let left = energy.current() - drain;
+            energy.set_current(left);
+        }
+    }
+}
+
+/// Organisms without energy lose health
+pub fn apply_starvation(
+    config: Res<PhysicsConfig>,
+    mut query: Query<(&Energy, &mut Health), With<Alive>>,
+) {
+    for (energy, mut health) in &mut query {
+        if energy.is_depleted() {
+            health.take_damage(config.starvation_damage);
+        }
+    }
+}
+
+/// Despawn organisms whose health reached zero
+pub fn remove_starved(
+    mut commands: Commands,
+    mut died: EventWriter<Died>,
+    query: Query<(Entity, &OrganismType, &Health), With<Alive>>,
+) {
+    for (entity, organism_type, health) in &query {
+        if !health.is_alive() {
+            died.send(Died {
+                entity,
+                organism_type: *organism_type,
+                cause: DeathCause::Starvation,
+            });
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+type FeedingQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static OrganismType,
+        &'static Position,
+        &'static Collision,
+        &'static mut Energy,
+        &'static mut Health,
+    ),
+    With<Alive>,
+>;
+
+/// Eaters consume the first touching food item; food is despawned
+pub fn resolve_feeding(
+    config: Res<PhysicsConfig>,
+    mut commands: Commands,
+    mut consumed: EventWriter<Consumed>,
+    mut died: EventWriter<Died>,
+    mut query: FeedingQuery,
+) {
+    let snapshot: Vec<(Entity, OrganismType, Position, f32)> = query
+        .iter()
+        .filter(|(.., health)| health.is_alive())
+        .map(|(entity, organism_type, position, collision, ..)| {
+            (entity, *organism_type, *position, collision.radius())
+        })
+        .collect();
+
+    let mut eaten: HashSet<Entity> = HashSet::default();
+    let mut meals = Vec::new();
+    for &(eater, eater_type, eater_pos, eater_radius) in &snapshot {
+        let Some(food_type) = food_of(eater_type) else {
+            continue;
+        };
+        if eaten.contains(&eater) {
+            continue;
+        }
+        let meal = snapshot
+            .iter()
+            .find(|(food, organism_type, position, radius)| {
+                *organism_type == food_type
+                    && !eaten.contains(food)
+                    && eater_pos.distance_to(position) <= eater_radius + radius
+            });
+        if let Some(&(food, ..)) = meal {
+            eaten.insert(food);
+            meals.push((eater, eater_type, food, food_type));
+        }
+    }
+
+    for (eater, eater_type, food, food_type) in meals {
+        if let Ok((.., mut health)) = query.get_mut(food) {
+            health.set_current(0.0);
+        }
+        if let Ok((.., mut energy, _)) = query.get_mut(eater) {
+            let gain = match food_type {
+                OrganismType::Plant => config.plant_energy_gain,
+                _ => config.predation_energy_gain,
+            };
+            energy.add_energy(gain);
+        }
+        consumed.send(Consumed {
+            eater,
+            eater_type,
+            food,
+            food_type,
+        });
+        died.send(Died {
+            entity: food,
+            organism_type: food_type,
+            cause: DeathCause::Eaten,
+        });
+        commands.entity(food).despawn();
+    }
+}
+
+/// The physics systems, chained in execution order, in [`PhysicsSet`]
+pub fn physics_systems() -> SystemConfigs {
+    (
+        clear_newborn,
+        apply_actions,
+        apply_metabolism,
+        apply_starvation,
+        remove_starved,
+        resolve_feeding,
+    )
+        .chain()
+        .in_set(PhysicsSet)
+}
+
+/// Add the physics systems to a schedule
+pub fn add_physics_systems(schedule: &mut Schedule) {
+    schedule.add_systems(physics_systems());
+}
+
+/// Insert the resources the physics systems need into a headless world
+pub fn init_physics_world(world: &mut World, config: PhysicsConfig, bounds: WorldBounds) {
+    world.insert_resource(config);
+    world.insert_resource(bounds);
+    world.init_resource::<Events<Consumed>>();
+    world.init_resource::<Events<Died>>();
+}
+
+/// Standalone schedule running one physics tick
+pub fn physics_schedule() -> Schedule {
+    let mut schedule = Schedule::new(PhysicsSchedule);
+    add_physics_systems(&mut schedule);
+    schedule
+}
+
+/// Bevy plugin running the physics systems every `Update`
+#[derive(Default)]
+pub struct PhysicsPlugin {
+    pub config: PhysicsConfig,
+    pub bounds: WorldBounds,
+}
+
+impl Plugin for PhysicsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(self.config.clone())
+            .insert_resource(self.bounds)
+            .add_event::<Consumed>()
+            .add_event::<Died>()
+            .add_systems(Update, physics_systems());
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn world() -> (World, Schedule) {
+        let mut world = World::new();
+        init_physics_world(&mut world, PhysicsConfig::default(), WorldBounds::default());
+        (world, physics_schedule())
+    }
+
+    #[test]
+    fn test_move_spends_energy() {
+        let (mut world, mut schedule) = world();
+        let blue = OrganismFactory::spawn(&mut world, OrganismType::Blue, Position::zero());
+        world
+            .entity_mut(blue)
+            .insert(ActionCommand::move_to(Vec2::new(10.0, 0.0)));
+
+        schedule.run(&mut world);
+
+        let position = world.get::<Position>(blue).unwrap();
+        assert_eq!(position.x, BlueConfig::MAX_SPEED);
+        assert!(world.get::<Energy>(blue).unwrap().ratio() < 1.0);
+        assert_eq!(
+            world.get::<CurrentActivity>(blue).unwrap().activity,
+            ActivityType::Moving
+        );
+    }
+
+    #[test]
+    fn test_predator_eats_prey() {
+        let (mut world, mut schedule) = world();
+        let red = OrganismFactory::spawn(&mut world, OrganismType::Red, Position::zero());
+        let blue = OrganismFactory::spawn(&mut world, OrganismType::Blue, Position::new(5.0, 0.0));
+        world.get_mut::<Energy>(red).unwrap().set_current(10.0);
+
+        schedule.run(&mut world);
+
+        assert!(world.get_entity(blue).is_none());
+        assert!(world.get::<Energy>(red).unwrap().current() > 10.0);
+        let deaths: Vec<Died> = world.resource_mut::<Events<Died>>().drain().collect();
+        assert_eq!(deaths.len(), 1);
+        assert_eq!(deaths[0].cause, DeathCause::Eaten);
+    }
+
+    #[test]
+    fn test_starvation_kills() {
+        let (mut world, mut schedule) = world();
+        let blue = OrganismFactory::spawn(&mut world, OrganismType::Blue, Position::zero());
+        let mut energy = world.get_mut::<Energy>(blue).unwrap();
+        energy.set_current(0.0);
+        energy.set_regen_rate(0.0);
+        world.get_mut::<Health>(blue).unwrap().set_current(1.0);
+        world
+            .entity_mut(blue)
+            .insert(ActionCommand::move_to(Vec2::X));
+
+        schedule.run(&mut world);
+
+        assert!(world.get_entity(blue).is_none());
+    }
+
     #[test]
-    fn it_works() {
-        let result = add(2, 2);
-        assert_eq!(result, 4);
+    fn test_metabolism_charges_bodies_and_brains() {
+        let mut world = World::new();
+        let mut config = PhysicsConfig::default();
+        config.metabolism.enabled = true;
+        init_physics_world(&mut world, config, WorldBounds::default());
+        let mut schedule = physics_schedule();
+        let small = OrganismFactory::spawn(&mut world, OrganismType::Blue, Position::zero());
+        let large =
+            OrganismFactory::spawn(&mut world, OrganismType::Blue, Position::new(50.0, 0.0));
+        let smart =
+            OrganismFactory::spawn(&mut world, OrganismType::Blue, Position::new(0.0, 50.0));
+        world.get_mut::<Collision>(large).unwrap().set_radius(20.0);
+        *world.get_mut::<BrainSize>(smart).unwrap() = BrainSize::new(50_000, 50_000);
+        for entity in [small, large, smart] {
+            let mut energy = world.get_mut::<Energy>(entity).unwrap();
+            energy.set_regen_rate(0.0);
+            energy.set_current(50.0);
+        }
+
+        schedule.run(&mut world);
+
+        let energy = |entity| world.get::<Energy>(entity).unwrap().current();
+        assert!(energy(small) < 50.0);
+        assert!(energy(large) < energy(small));
+        assert!(energy(smart) < energy(small));
     }
 }